@@ -85,6 +85,11 @@ impl PhysFrame {
     }
 }
 
+/// Number of physical frames managed by the allocator, and of those not allocated.
+pub fn frame_stats() -> (usize, usize) {
+    KHANDLER.frame_stats()
+}
+
 impl Drop for PhysFrame {
     fn drop(&mut self) {
         if self.allocated {
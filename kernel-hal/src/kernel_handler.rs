@@ -19,6 +19,11 @@ pub trait KernelHandler: Send + Sync + 'static {
         unimplemented!()
     }
 
+    /// Number of physical frames managed by the allocator, and of those not allocated.
+    fn frame_stats(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
     /// Handle kernel mode page fault.
     fn handle_page_fault(&self, _fault_vaddr: VirtAddr, _access_flags: MMUFlags) {
         // do nothing
@@ -1,13 +1,20 @@
 use bitmap_allocator::BitAlloc;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use super::mem::FRAME_ALLOCATOR;
+use super::mem::{FRAME_ALLOCATOR, PMEM_SIZE};
 use crate::kernel_handler::{DummyKernelHandler, KernelHandler};
 use crate::{PhysAddr, PAGE_SIZE};
 
+/// Number of allocated frames
+static USED_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
 impl KernelHandler for DummyKernelHandler {
     fn frame_alloc(&self) -> Option<PhysAddr> {
         let ret = FRAME_ALLOCATOR.lock().alloc().map(|id| id * PAGE_SIZE);
         trace!("Allocate frame: {:x?}", ret);
+        if ret.is_some() {
+            USED_FRAMES.fetch_add(1, Ordering::Relaxed);
+        }
         ret
     }
 
@@ -21,11 +28,21 @@ impl KernelHandler for DummyKernelHandler {
             ret,
             ret.map(|x| x + frame_count)
         );
+        if ret.is_some() {
+            USED_FRAMES.fetch_add(frame_count, Ordering::Relaxed);
+        }
         ret
     }
 
     fn frame_dealloc(&self, paddr: PhysAddr) {
         trace!("Deallocate frame: {:x}", paddr);
         FRAME_ALLOCATOR.lock().dealloc(paddr / PAGE_SIZE);
+        USED_FRAMES.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn frame_stats(&self) -> (usize, usize) {
+        // frame 0 is never allocated
+        let total = PMEM_SIZE / PAGE_SIZE - 1;
+        (total, total - USED_FRAMES.load(Ordering::Relaxed))
     }
 }
@@ -0,0 +1,548 @@
+//! Linux process credentials
+//!
+//! A process has a real, an effective and a saved set-user-ID, a filesystem user ID used for
+//! file access, the same four group IDs, and a list of supplementary groups.
+//! A process whose effective user ID is 0 is privileged, as if it had every capability.
+
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use rcore_fs::vfs::{FileType, Metadata};
+
+use crate::error::{LxError, LxResult};
+
+/// Set-user-ID bit of a file mode
+pub const S_ISUID: u32 = 0o4000;
+/// Set-group-ID bit of a file mode
+pub const S_ISGID: u32 = 0o2000;
+/// Sticky bit of a file mode, only owners may remove entries of such a directory
+pub const S_ISVTX: u32 = 0o1000;
+
+/// Max number of supplementary groups
+pub const NGROUPS_MAX: usize = 65536;
+
+bitflags! {
+    /// Access to a file, the bits are the same as `R_OK`, `W_OK` and `X_OK`
+    pub struct Access: u32 {
+        /// read the file, or list the directory
+        const READ = 4;
+        /// write the file, or create and remove entries of the directory
+        const WRITE = 2;
+        /// execute the file, or search the directory
+        const EXECUTE = 1;
+    }
+}
+
+/// User and group identity of a process
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// real user ID
+    pub uid: u32,
+    /// effective user ID
+    pub euid: u32,
+    /// saved set-user-ID
+    pub suid: u32,
+    /// filesystem user ID
+    pub fsuid: u32,
+    /// real group ID
+    pub gid: u32,
+    /// effective group ID
+    pub egid: u32,
+    /// saved set-group-ID
+    pub sgid: u32,
+    /// filesystem group ID
+    pub fsgid: u32,
+    /// supplementary group IDs
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// Whether the process may bypass permission checks and change its identity freely
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// Whether `gid` is the filesystem group or one of the supplementary groups
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.fsgid == gid || self.groups.contains(&gid)
+    }
+
+    /// The credentials `access` and `faccessat` check with: the real IDs stand in for the
+    /// filesystem IDs, so a set-user-ID program can ask what its caller may do.
+    pub fn real_access(&self) -> Self {
+        let mut cred = self.clone();
+        cred.fsuid = self.uid;
+        cred.fsgid = self.gid;
+        cred
+    }
+
+    /// Check `access` to a file with metadata `meta`.
+    ///
+    /// The owner bits apply to the owner, the group bits to members of the file group and the
+    /// other bits to everyone else. A privileged process may read and write anything, and
+    /// execute anything that has an execute bit or is a directory.
+    pub fn check_access(&self, meta: &Metadata, access: Access) -> LxResult {
+        let mode = meta.mode as u32;
+        if self.fsuid == 0 {
+            let executable = meta.type_ == FileType::Dir || mode & 0o111 != 0;
+            if !access.contains(Access::EXECUTE) || executable {
+                return Ok(());
+            }
+            return Err(LxError::EACCES);
+        }
+        let granted = if self.fsuid == meta.uid as u32 {
+            mode >> 6
+        } else if self.in_group(meta.gid as u32) {
+            mode >> 3
+        } else {
+            mode
+        };
+        if Access::from_bits_truncate(granted & 0o7).contains(access) {
+            Ok(())
+        } else {
+            Err(LxError::EACCES)
+        }
+    }
+
+    /// Check that the entry `file` may be removed from or renamed in directory `dir`
+    pub fn check_delete(&self, dir: &Metadata, file: &Metadata) -> LxResult {
+        self.check_access(dir, Access::WRITE | Access::EXECUTE)?;
+        let sticky = dir.mode as u32 & S_ISVTX != 0;
+        if sticky
+            && self.fsuid != 0
+            && self.fsuid != file.uid as u32
+            && self.fsuid != dir.uid as u32
+        {
+            return Err(LxError::EPERM);
+        }
+        Ok(())
+    }
+
+    /// Whether the process owns the file, or may act as its owner
+    pub fn owns(&self, meta: &Metadata) -> bool {
+        self.fsuid == 0 || self.fsuid == meta.uid as u32
+    }
+
+    /// Change the mode of a file, see [linux man chmod(2)](https://www.man7.org/linux/man-pages/man2/chmod.2.html)
+    pub fn chmod(&self, meta: &mut Metadata, mode: u32) -> LxResult {
+        if !self.owns(meta) {
+            return Err(LxError::EPERM);
+        }
+        let mut mode = mode & 0o7777;
+        // only members of the file group may make it set-group-ID
+        if self.fsuid != 0 && !self.in_group(meta.gid as u32) {
+            mode &= !S_ISGID;
+        }
+        meta.mode = mode as _;
+        Ok(())
+    }
+
+    /// Change the owner and group of a file, `None` keeps the old one,
+    /// see [linux man chown(2)](https://www.man7.org/linux/man-pages/man2/chown.2.html).
+    ///
+    /// Only a privileged process may give a file away. The owner may change the group to
+    /// one of its own groups. Changing a regular file drops its set-user-ID and set-group-ID bits.
+    pub fn chown(&self, meta: &mut Metadata, uid: Option<u32>, gid: Option<u32>) -> LxResult {
+        let old_uid = meta.uid as u32;
+        let old_gid = meta.gid as u32;
+        if self.fsuid != 0 {
+            if uid.map_or(false, |uid| uid != old_uid) {
+                return Err(LxError::EPERM);
+            }
+            if let Some(gid) = gid {
+                if self.fsuid != old_uid || (gid != old_gid && !self.in_group(gid)) {
+                    return Err(LxError::EPERM);
+                }
+            }
+        }
+        if uid.is_none() && gid.is_none() {
+            return Ok(());
+        }
+        meta.uid = uid.unwrap_or(old_uid) as _;
+        meta.gid = gid.unwrap_or(old_gid) as _;
+        if meta.type_ == FileType::File {
+            let mut mode = meta.mode as u32 & !S_ISUID;
+            // without group execute, set-group-ID marks mandatory locking and is kept
+            if mode & 0o010 != 0 {
+                mode &= !S_ISGID;
+            }
+            meta.mode = mode as _;
+        }
+        Ok(())
+    }
+
+    /// Owner and group of a file created by the process
+    pub fn new_file_owner(&self, dir: &Metadata) -> (u32, u32) {
+        // files created in a set-group-ID directory inherit its group
+        let gid = if dir.mode as u32 & S_ISGID != 0 {
+            dir.gid as u32
+        } else {
+            self.fsgid
+        };
+        (self.fsuid, gid)
+    }
+
+    /// Whether a process may send a signal to a process with the credentials `target`:
+    /// it must be privileged, or its real or effective user ID must be the real or saved
+    /// user ID of the target.
+    pub fn may_signal(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || [self.uid, self.euid].contains(&target.uid)
+            || [self.uid, self.euid].contains(&target.suid)
+    }
+
+    /// Apply the set-user-ID and set-group-ID bits of an executed file,
+    /// then save the effective IDs as by execve.
+    pub fn exec(&mut self, meta: &Metadata) {
+        let mode = meta.mode as u32;
+        if mode & S_ISUID != 0 {
+            self.euid = meta.uid as u32;
+        }
+        // set-group-ID without group execute marks mandatory locking
+        if mode & S_ISGID != 0 && mode & 0o010 != 0 {
+            self.egid = meta.gid as u32;
+        }
+        self.suid = self.euid;
+        self.fsuid = self.euid;
+        self.sgid = self.egid;
+        self.fsgid = self.egid;
+    }
+
+    /// Set user IDs as by [setuid(2)](https://www.man7.org/linux/man-pages/man2/setuid.2.html).
+    ///
+    /// A privileged process sets all user IDs, otherwise only the effective user ID may be
+    /// set to the real or saved one.
+    pub fn setuid(&mut self, uid: u32) -> LxResult {
+        if self.is_privileged() {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(LxError::EPERM);
+        }
+        self.euid = uid;
+        self.fsuid = uid;
+        Ok(())
+    }
+
+    /// Set group IDs as by [setgid(2)](https://www.man7.org/linux/man-pages/man2/setgid.2.html)
+    pub fn setgid(&mut self, gid: u32) -> LxResult {
+        if self.is_privileged() {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(LxError::EPERM);
+        }
+        self.egid = gid;
+        self.fsgid = gid;
+        Ok(())
+    }
+
+    /// Set real and effective user IDs as by [setreuid(2)](https://www.man7.org/linux/man-pages/man2/setreuid.2.html),
+    /// `None` keeps the old one.
+    ///
+    /// The saved set-user-ID follows the effective one if the real ID is set,
+    /// or the effective ID is set to anything but the old real ID.
+    pub fn setreuid(&mut self, ruid: Option<u32>, euid: Option<u32>) -> LxResult {
+        if !self.is_privileged() {
+            let ruid_ok = ruid.map_or(true, |id| id == self.uid || id == self.euid);
+            let euid_ok = euid.map_or(true, |id| {
+                id == self.uid || id == self.euid || id == self.suid
+            });
+            if !ruid_ok || !euid_ok {
+                return Err(LxError::EPERM);
+            }
+        }
+        let old_uid = self.uid;
+        if let Some(ruid) = ruid {
+            self.uid = ruid;
+        }
+        if let Some(euid) = euid {
+            self.euid = euid;
+        }
+        if ruid.is_some() || euid.map_or(false, |id| id != old_uid) {
+            self.suid = self.euid;
+        }
+        self.fsuid = self.euid;
+        Ok(())
+    }
+
+    /// Set real and effective group IDs as by [setregid(2)](https://www.man7.org/linux/man-pages/man2/setregid.2.html)
+    pub fn setregid(&mut self, rgid: Option<u32>, egid: Option<u32>) -> LxResult {
+        if !self.is_privileged() {
+            let rgid_ok = rgid.map_or(true, |id| id == self.gid || id == self.egid);
+            let egid_ok = egid.map_or(true, |id| {
+                id == self.gid || id == self.egid || id == self.sgid
+            });
+            if !rgid_ok || !egid_ok {
+                return Err(LxError::EPERM);
+            }
+        }
+        let old_gid = self.gid;
+        if let Some(rgid) = rgid {
+            self.gid = rgid;
+        }
+        if let Some(egid) = egid {
+            self.egid = egid;
+        }
+        if rgid.is_some() || egid.map_or(false, |id| id != old_gid) {
+            self.sgid = self.egid;
+        }
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// Set real, effective and saved user IDs as by [setresuid(2)](https://www.man7.org/linux/man-pages/man2/setresuid.2.html).
+    ///
+    /// An unprivileged process may set each of them to one of its current user IDs.
+    pub fn setresuid(
+        &mut self,
+        ruid: Option<u32>,
+        euid: Option<u32>,
+        suid: Option<u32>,
+    ) -> LxResult {
+        let current = [self.uid, self.euid, self.suid];
+        if !self.is_privileged()
+            && [ruid, euid, suid]
+                .iter()
+                .flatten()
+                .any(|id| !current.contains(id))
+        {
+            return Err(LxError::EPERM);
+        }
+        self.uid = ruid.unwrap_or(self.uid);
+        self.euid = euid.unwrap_or(self.euid);
+        self.suid = suid.unwrap_or(self.suid);
+        self.fsuid = self.euid;
+        Ok(())
+    }
+
+    /// Set real, effective and saved group IDs as by [setresgid(2)](https://www.man7.org/linux/man-pages/man2/setresgid.2.html)
+    pub fn setresgid(
+        &mut self,
+        rgid: Option<u32>,
+        egid: Option<u32>,
+        sgid: Option<u32>,
+    ) -> LxResult {
+        let current = [self.gid, self.egid, self.sgid];
+        if !self.is_privileged()
+            && [rgid, egid, sgid]
+                .iter()
+                .flatten()
+                .any(|id| !current.contains(id))
+        {
+            return Err(LxError::EPERM);
+        }
+        self.gid = rgid.unwrap_or(self.gid);
+        self.egid = egid.unwrap_or(self.egid);
+        self.sgid = sgid.unwrap_or(self.sgid);
+        self.fsgid = self.egid;
+        Ok(())
+    }
+
+    /// Set the filesystem user ID as by [setfsuid(2)](https://www.man7.org/linux/man-pages/man2/setfsuid.2.html),
+    /// returns the old one whether or not it changed.
+    pub fn setfsuid(&mut self, fsuid: u32) -> u32 {
+        let old = self.fsuid;
+        let allowed = [self.uid, self.euid, self.suid, self.fsuid].contains(&fsuid);
+        if self.is_privileged() || allowed {
+            self.fsuid = fsuid;
+        }
+        old
+    }
+
+    /// Set the filesystem group ID as by [setfsgid(2)](https://www.man7.org/linux/man-pages/man2/setfsgid.2.html)
+    pub fn setfsgid(&mut self, fsgid: u32) -> u32 {
+        let old = self.fsgid;
+        let allowed = [self.gid, self.egid, self.sgid, self.fsgid].contains(&fsgid);
+        if self.is_privileged() || allowed {
+            self.fsgid = fsgid;
+        }
+        old
+    }
+
+    /// Replace the supplementary groups, only a privileged process may do so
+    pub fn set_groups(&mut self, groups: Vec<u32>) -> LxResult {
+        if !self.is_privileged() {
+            return Err(LxError::EPERM);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return Err(LxError::EINVAL);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcore_fs::vfs::Timespec;
+
+    fn user(uid: u32, gid: u32) -> Credentials {
+        Credentials {
+            uid,
+            euid: uid,
+            suid: uid,
+            fsuid: uid,
+            gid,
+            egid: gid,
+            sgid: gid,
+            fsgid: gid,
+            groups: Vec::new(),
+        }
+    }
+
+    fn file(type_: FileType, mode: u32, uid: u32, gid: u32) -> Metadata {
+        Metadata {
+            dev: 0,
+            inode: 0,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_,
+            mode: mode as _,
+            nlinks: 1,
+            uid: uid as _,
+            gid: gid as _,
+            rdev: 0,
+        }
+    }
+
+    #[test]
+    fn access_classes() {
+        let meta = file(FileType::File, 0o640, 1000, 100);
+        let owner = user(1000, 1000);
+        assert!(owner
+            .check_access(&meta, Access::READ | Access::WRITE)
+            .is_ok());
+        assert!(matches!(
+            owner.check_access(&meta, Access::EXECUTE),
+            Err(LxError::EACCES)
+        ));
+
+        // the group bits apply to members only
+        let member = user(1001, 100);
+        assert!(member.check_access(&meta, Access::READ).is_ok());
+        assert!(matches!(
+            member.check_access(&meta, Access::WRITE),
+            Err(LxError::EACCES)
+        ));
+        let other = user(1002, 1002);
+        assert!(matches!(
+            other.check_access(&meta, Access::READ),
+            Err(LxError::EACCES)
+        ));
+
+        // the owner bits win even when the group or other bits grant more
+        let meta = file(FileType::File, 0o077, 1000, 1000);
+        assert!(matches!(
+            owner.check_access(&meta, Access::READ),
+            Err(LxError::EACCES)
+        ));
+    }
+
+    #[test]
+    fn access_supplementary_groups() {
+        let meta = file(FileType::Dir, 0o750, 0, 100);
+        let mut cred = user(1000, 1000);
+        assert!(matches!(
+            cred.check_access(&meta, Access::EXECUTE),
+            Err(LxError::EACCES)
+        ));
+        cred.groups = vec![20, 100];
+        assert!(cred
+            .check_access(&meta, Access::READ | Access::EXECUTE)
+            .is_ok());
+        assert!(matches!(
+            cred.check_access(&meta, Access::WRITE),
+            Err(LxError::EACCES)
+        ));
+    }
+
+    #[test]
+    fn access_root_override() {
+        let root = user(0, 0);
+        let meta = file(FileType::File, 0o000, 1000, 1000);
+        assert!(root
+            .check_access(&meta, Access::READ | Access::WRITE)
+            .is_ok());
+        // execute needs an execute bit somewhere, except on directories
+        assert!(matches!(
+            root.check_access(&meta, Access::EXECUTE),
+            Err(LxError::EACCES)
+        ));
+        let meta = file(FileType::File, 0o001, 1000, 1000);
+        assert!(root.check_access(&meta, Access::EXECUTE).is_ok());
+        let meta = file(FileType::Dir, 0o000, 1000, 1000);
+        assert!(root.check_access(&meta, Access::EXECUTE).is_ok());
+
+        // the filesystem user ID decides, not the effective one
+        let mut cred = root.clone();
+        cred.setfsuid(1000);
+        let meta = file(FileType::File, 0o000, 0, 0);
+        assert!(matches!(
+            cred.check_access(&meta, Access::READ),
+            Err(LxError::EACCES)
+        ));
+    }
+
+    #[test]
+    fn setuid_saved_id() {
+        // a privileged process gives up all of its user IDs
+        let mut cred = user(0, 0);
+        cred.setuid(1000).unwrap();
+        assert_eq!(
+            (cred.uid, cred.euid, cred.suid, cred.fsuid),
+            (1000, 1000, 1000, 1000)
+        );
+        assert!(matches!(cred.setuid(0), Err(LxError::EPERM)));
+
+        // a set-user-ID program may switch between its real and saved IDs
+        let mut cred = user(1000, 1000);
+        cred.exec(&file(FileType::File, 0o4755, 2000, 0));
+        assert_eq!((cred.uid, cred.euid, cred.suid), (1000, 2000, 2000));
+        cred.setuid(1000).unwrap();
+        assert_eq!((cred.uid, cred.euid, cred.suid), (1000, 1000, 2000));
+        cred.setuid(2000).unwrap();
+        assert_eq!((cred.uid, cred.euid, cred.suid), (1000, 2000, 2000));
+        assert!(matches!(cred.setuid(3000), Err(LxError::EPERM)));
+    }
+
+    #[test]
+    fn setresuid_saved_id() {
+        let mut cred = user(1000, 1000);
+        cred.euid = 2000;
+        cred.suid = 3000;
+        // an unprivileged process may only shuffle its current IDs
+        cred.setresuid(Some(3000), None, Some(1000)).unwrap();
+        assert_eq!(
+            (cred.uid, cred.euid, cred.suid, cred.fsuid),
+            (3000, 2000, 1000, 2000)
+        );
+        assert!(matches!(
+            cred.setresuid(None, Some(4000), None),
+            Err(LxError::EPERM)
+        ));
+        assert_eq!((cred.uid, cred.euid, cred.suid), (3000, 2000, 1000));
+
+        // a privileged process may set anything, and keep its saved ID to get back
+        let mut cred = user(0, 0);
+        cred.setresuid(Some(1000), Some(1000), None).unwrap();
+        assert_eq!((cred.uid, cred.euid, cred.suid), (1000, 1000, 0));
+        assert!(!cred.is_privileged());
+        cred.setresuid(None, Some(0), None).unwrap();
+        assert!(cred.is_privileged());
+    }
+
+    #[test]
+    fn signal_permission() {
+        let sender = user(1000, 1000);
+        let mut target = user(2000, 2000);
+        assert!(!sender.may_signal(&target));
+        target.suid = 1000;
+        assert!(sender.may_signal(&target));
+        assert!(user(0, 0).may_signal(&user(2000, 2000)));
+    }
+}
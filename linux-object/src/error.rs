@@ -97,20 +97,32 @@ pub enum LxError {
     ELOOP = 40,
     /// Identifier removed
     EIDRM = 43,
+    /// Protocol error
+    EPROTO = 71,
     /// Socket operation on non-socket
     ENOTSOCK = 88,
+    /// Destination address required
+    EDESTADDRREQ = 89,
     /// Protocol not available
     ENOPROTOOPT = 92,
+    /// Protocol not supported
+    EPROTONOSUPPORT = 93,
+    /// Operation not supported on transport endpoint
+    EOPNOTSUPP = 95,
     /// Protocol family not supported
     EPFNOSUPPORT = 96,
     /// Address family not supported by protocol
     EAFNOSUPPORT = 97,
+    /// Address already in use
+    EADDRINUSE = 98,
     /// No buffer space available
     ENOBUFS = 105,
     /// Transport endpoint is already connected
     EISCONN = 106,
     /// Transport endpoint is not connected
     ENOTCONN = 107,
+    /// Connection timed out
+    ETIMEDOUT = 110,
     /// Connection refused
     ECONNREFUSED = 111,
 }
@@ -161,13 +173,19 @@ impl fmt::Display for LxError {
             ENOTEMPTY => "Directory not empty",
             ELOOP => "Too many symbolic links encountered",
             EIDRM => "Identifier removed",
+            EPROTO => "Protocol error",
             ENOTSOCK => "Socket operation on non-socket",
+            EDESTADDRREQ => "Destination address required",
             ENOPROTOOPT => "Protocol not available",
+            EPROTONOSUPPORT => "Protocol not supported",
+            EOPNOTSUPP => "Operation not supported on transport endpoint",
             EPFNOSUPPORT => "Protocol family not supported",
             EAFNOSUPPORT => "Address family not supported by protocol",
+            EADDRINUSE => "Address already in use",
             ENOBUFS => "No buffer space available",
             EISCONN => "Transport endpoint is already connected",
             ENOTCONN => "Transport endpoint is not connected",
+            ETIMEDOUT => "Connection timed out",
             ECONNREFUSED => "Connection refused",
             _ => "Unknown error",
         };
@@ -184,6 +202,9 @@ impl From<ZxError> for LxError {
             ZxError::SHOULD_WAIT => LxError::EAGAIN,
             ZxError::PEER_CLOSED => LxError::EPIPE,
             ZxError::BAD_HANDLE => LxError::EBADF,
+            ZxError::NO_MEMORY => LxError::ENOMEM,
+            ZxError::ACCESS_DENIED => LxError::EACCES,
+            ZxError::NOT_FOUND => LxError::ENOENT,
             _ => unimplemented!("unknown error type: {:?}", e),
         }
     }
@@ -0,0 +1,75 @@
+use crate::fs::{Keystone, OpenFlags};
+use alloc::sync::Arc;
+use core::any::Any;
+use rcore_fs::vfs::{make_rdev, FileType, FsError, INode, Metadata, PollStatus, Result, Timespec};
+use rcore_fs_devfs::DevFS;
+use zircon_object::task::Process;
+
+/// Keystone enclave driver at `/dev/keystone`.
+///
+/// The node holds no enclaves itself: every open of it creates a [`Keystone`]
+/// with enclaves of its own.
+pub struct KeystoneDev {
+    inode_id: usize,
+}
+
+impl KeystoneDev {
+    pub fn new() -> Self {
+        Self {
+            inode_id: DevFS::new_inode_id(),
+        }
+    }
+
+    /// Open the driver for `proc`, which the new enclaves are charged to.
+    pub fn open(&self, proc: &Arc<Process>, flags: OpenFlags) -> Arc<Keystone> {
+        Keystone::new(proc, flags)
+    }
+}
+
+impl Default for KeystoneDev {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl INode for KeystoneDev {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o666,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            // a misc device, like the Linux driver
+            rdev: make_rdev(10, 0),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
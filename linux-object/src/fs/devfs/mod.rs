@@ -1,9 +1,11 @@
 mod fbdev;
 mod input;
+mod keystone;
 mod random;
 mod uartdev;
 
 pub use fbdev::FbDev;
 pub use input::{EventDev, MiceDev};
+pub use keystone::KeystoneDev;
 pub use random::RandomINode;
 pub use uartdev::UartDev;
@@ -1,4 +1,5 @@
-﻿use alloc::sync::Arc;
+﻿use crate::fs::tty::Tty;
+use alloc::sync::Arc;
 use core::any::Any;
 use rcore_fs::vfs::{make_rdev, FileType, FsError, INode, Metadata, PollStatus, Result, Timespec};
 use rcore_fs_devfs::DevFS;
@@ -9,16 +10,23 @@ pub struct UartDev {
     index: usize,
     port: Arc<dyn UartScheme>,
     inode_id: usize,
+    tty: Arc<Tty>,
 }
 
 impl UartDev {
-    pub fn new(index: usize, port: Arc<dyn UartScheme>) -> Self {
+    pub fn new(index: usize, port: Arc<dyn UartScheme>, tty: Arc<Tty>) -> Self {
         Self {
             index,
             port,
             inode_id: DevFS::new_inode_id(),
+            tty,
         }
     }
+
+    /// The terminal of the port.
+    pub fn tty(&self) -> &Arc<Tty> {
+        &self.tty
+    }
 }
 
 impl INode for UartDev {
@@ -30,11 +38,13 @@ impl INode for UartDev {
         );
 
         let mut len = 0;
-        for b in buf.iter_mut() {
+        while len < buf.len() {
             match self.port.try_recv() {
-                Ok(Some(b_)) => {
-                    *b = b_;
-                    len += 1;
+                Ok(Some(b)) => {
+                    if self.tty.receive(b) {
+                        buf[len] = b;
+                        len += 1;
+                    }
                 }
                 Ok(None) => break,
                 Err(e) => return Err(convert_error(e)),
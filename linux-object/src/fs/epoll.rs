@@ -0,0 +1,555 @@
+//! I/O event notification facility
+#![deny(missing_docs)]
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use kernel_hal::timer;
+use numeric_enum_macro::numeric_enum;
+use rcore_fs::vfs::PollStatus;
+use spin::{Mutex, RwLock};
+use zircon_object::impl_kobject;
+use zircon_object::object::{KObjectBase, KernelObject};
+use zircon_object::vm::VmObject;
+
+use super::{FileDesc, FileLike, OpenFlags};
+use crate::error::{LxError, LxResult};
+use crate::net::{poll_ifaces, Socket};
+use crate::time::TimeVal;
+
+bitflags::bitflags! {
+    /// Events of epoll_event
+    pub struct EpollEvents: u32 {
+        /// There is data to read
+        const IN = 0x0001;
+        /// There is urgent data to read
+        const PRI = 0x0002;
+        /// Writing is now possible
+        const OUT = 0x0004;
+        /// Error condition, always reported
+        const ERR = 0x0008;
+        /// Hang up, always reported
+        const HUP = 0x0010;
+        /// Normal data may be read
+        const RDNORM = 0x0040;
+        /// Priority data may be read
+        const RDBAND = 0x0080;
+        /// Normal data may be written
+        const WRNORM = 0x0100;
+        /// Priority data may be written
+        const WRBAND = 0x0200;
+        /// Unused
+        const MSG = 0x0400;
+        /// Stream socket peer closed connection
+        const RDHUP = 0x2000;
+        /// Wake up only one of the epoll instances waiting on the same target
+        const EXCLUSIVE = 1 << 28;
+        /// Keep the system awake while the event is pending
+        const WAKEUP = 1 << 29;
+        /// Disable the interest after one event is reported
+        const ONESHOT = 1 << 30;
+        /// Edge-triggered: report readiness only when it changes
+        const ET = 1 << 31;
+    }
+}
+
+/// The events that are reported whether or not they are asked for
+const ALWAYS_REPORTED: EpollEvents =
+    EpollEvents::from_bits_truncate(EpollEvents::ERR.bits() | EpollEvents::HUP.bits());
+
+/// The events that describe the readiness of a target, rather than how to report it
+const READINESS: EpollEvents = EpollEvents::from_bits_truncate(
+    !EpollEvents::ET.bits()
+        & !EpollEvents::ONESHOT.bits()
+        & !EpollEvents::WAKEUP.bits()
+        & !EpollEvents::EXCLUSIVE.bits(),
+);
+
+/// epoll_event, packed on x86_64 as in the Linux ABI
+#[cfg_attr(target_arch = "x86_64", repr(C, packed))]
+#[cfg_attr(not(target_arch = "x86_64"), repr(C))]
+#[derive(Copy, Clone)]
+pub struct EpollEvent {
+    /// Epoll events
+    pub events: EpollEvents,
+    /// User data variable
+    pub data: u64,
+}
+
+numeric_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+    /// Operations of epoll_ctl
+    pub enum EpollCtlOp {
+        /// Register the target file descriptor
+        ADD = 1,
+        /// Deregister the target file descriptor
+        DEL = 2,
+        /// Change the event associated with the target file descriptor
+        MOD = 3,
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of epoll_create1
+    pub struct EpollCreateFlags: usize {
+        /// close on exec
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+/// The object an epoll instance watches
+pub enum EpollTarget {
+    /// a file in the file table
+    File(Arc<dyn FileLike>),
+    /// a socket in the socket table
+    Socket(Arc<Mutex<dyn Socket>>),
+}
+
+/// Watched objects are held weakly, so closing them drops the interest
+enum WeakTarget {
+    File(Weak<dyn FileLike>),
+    Socket(Weak<Mutex<dyn Socket>>),
+}
+
+struct EpollInterest {
+    target: WeakTarget,
+    events: EpollEvents,
+    data: u64,
+    /// Readiness seen by the last wait, for edge-triggered interests
+    reported: EpollEvents,
+    /// Set when the events of the target changed since the last wait, which makes
+    /// all its readiness a new edge
+    edge: Arc<AtomicBool>,
+    /// Set once a oneshot interest fired, until it is rearmed by `MOD`
+    disabled: bool,
+}
+
+impl EpollInterest {
+    /// Current readiness of the target, or `None` if it has been closed.
+    ///
+    /// With a context the target also registers the waker, so that the wait is resumed
+    /// when its state changes.
+    fn readiness(&self, cx: Option<&mut Context>) -> Option<EpollEvents> {
+        let status = match &self.target {
+            WeakTarget::File(file) => {
+                let file = file.upgrade()?;
+                let polled =
+                    cx.and_then(|cx| match Box::pin(file.async_poll()).as_mut().poll(cx) {
+                        Poll::Ready(status) => Some(status),
+                        Poll::Pending => None,
+                    });
+                match polled.unwrap_or_else(|| file.poll()) {
+                    Ok(status) => status,
+                    Err(_) => PollStatus {
+                        read: false,
+                        write: false,
+                        error: true,
+                    },
+                }
+            }
+            WeakTarget::Socket(socket) => {
+                let socket = socket.upgrade()?;
+                poll_ifaces();
+                let (read, write, error) = socket.lock().poll();
+                PollStatus { read, write, error }
+            }
+        };
+        let mut ready = EpollEvents::empty();
+        if status.read {
+            ready |= EpollEvents::IN | EpollEvents::RDNORM;
+        }
+        if status.write {
+            ready |= EpollEvents::OUT | EpollEvents::WRNORM;
+        }
+        if status.error {
+            ready |= EpollEvents::ERR;
+        }
+        Some(ready & (self.events | ALWAYS_REPORTED))
+    }
+
+    /// The events to report for `ready`, the readiness of the target.
+    ///
+    /// Edge-triggered interests only report what was not ready at the last wait,
+    /// unless the target changed since then (`edge`).
+    fn pending(&self, ready: EpollEvents, edge: bool) -> EpollEvents {
+        if self.events.contains(EpollEvents::ET) && !edge {
+            ready & !self.reported
+        } else {
+            ready
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        match &self.target {
+            WeakTarget::File(file) => file.strong_count() == 0,
+            WeakTarget::Socket(socket) => socket.strong_count() == 0,
+        }
+    }
+
+    fn is_socket(&self) -> bool {
+        matches!(self.target, WeakTarget::Socket(_))
+    }
+}
+
+type Interests = BTreeMap<FileDesc, EpollInterest>;
+
+/// An epoll instance, see [linux man epoll(7)](https://www.man7.org/linux/man-pages/man7/epoll.7.html)
+pub struct EpollInstance {
+    base: KObjectBase,
+    flags: RwLock<OpenFlags>,
+    /// The interest list, shared by duplicated descriptors
+    interests: Arc<Mutex<Interests>>,
+}
+
+impl_kobject!(EpollInstance);
+
+impl EpollInstance {
+    /// Create an epoll instance with an empty interest list
+    pub fn new(flags: EpollCreateFlags) -> Arc<Self> {
+        let mut open_flags = OpenFlags::RDWR;
+        open_flags.set(
+            OpenFlags::CLOEXEC,
+            flags.contains(EpollCreateFlags::CLOEXEC),
+        );
+        Arc::new(EpollInstance {
+            base: KObjectBase::new(),
+            flags: RwLock::new(open_flags),
+            interests: Arc::new(Mutex::new(BTreeMap::new())),
+        })
+    }
+
+    /// Add, modify or remove the interest on `fd`
+    pub fn control(
+        &self,
+        op: EpollCtlOp,
+        fd: FileDesc,
+        target: EpollTarget,
+        event: Option<EpollEvent>,
+    ) -> LxResult {
+        if let EpollTarget::File(file) = &target {
+            if let Some(epoll) = file.downcast_ref::<EpollInstance>() {
+                if Arc::ptr_eq(&epoll.interests, &self.interests) {
+                    return Err(LxError::EINVAL);
+                }
+            }
+        }
+        let mut interests = self.interests.lock();
+        match op {
+            EpollCtlOp::ADD => {
+                if interests.contains_key(&fd) {
+                    return Err(LxError::EEXIST);
+                }
+                let event = event.ok_or(LxError::EFAULT)?;
+                let edge = Arc::new(AtomicBool::new(false));
+                let target = match target {
+                    EpollTarget::File(file) => {
+                        let weak = Arc::downgrade(&edge);
+                        file.subscribe(Box::new(move |_| match weak.upgrade() {
+                            Some(edge) => {
+                                edge.store(true, Ordering::Release);
+                                false
+                            }
+                            // the interest was removed
+                            None => true,
+                        }));
+                        WeakTarget::File(Arc::downgrade(&file))
+                    }
+                    EpollTarget::Socket(socket) => WeakTarget::Socket(Arc::downgrade(&socket)),
+                };
+                interests.insert(
+                    fd,
+                    EpollInterest {
+                        target,
+                        events: event.events,
+                        data: event.data,
+                        reported: EpollEvents::empty(),
+                        edge,
+                        disabled: false,
+                    },
+                );
+            }
+            EpollCtlOp::MOD => {
+                let event = event.ok_or(LxError::EFAULT)?;
+                let interest = interests.get_mut(&fd).ok_or(LxError::ENOENT)?;
+                interest.events = event.events;
+                interest.data = event.data;
+                interest.reported = EpollEvents::empty();
+                interest.disabled = false;
+            }
+            EpollCtlOp::DEL => {
+                interests.remove(&fd).ok_or(LxError::ENOENT)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for at most `maxevents` events, `timeout_msecs` < 0 waits forever
+    pub fn wait(
+        &self,
+        maxevents: usize,
+        timeout_msecs: isize,
+    ) -> impl Future<Output = LxResult<Vec<EpollEvent>>> {
+        EpollWaitFuture {
+            interests: self.interests.clone(),
+            maxevents,
+            timeout_msecs,
+            begin_time_ms: TimeVal::now().to_msec(),
+        }
+    }
+
+    /// Whether some interest would be reported by a wait, without consuming it
+    fn has_events(&self, mut cx: Option<&mut Context>) -> bool {
+        let mut interests = self.interests.lock();
+        interests.retain(|_, interest| !interest.is_closed());
+        let mut found = false;
+        for interest in interests.values().filter(|i| !i.disabled) {
+            let edge = interest.edge.load(Ordering::Acquire);
+            let ready = interest
+                .readiness(cx.as_deref_mut())
+                .unwrap_or_else(EpollEvents::empty);
+            found |= !interest.pending(ready, edge).is_empty();
+        }
+        found
+    }
+}
+
+/// Scan the interest list and collect the events to report
+fn collect_events(
+    interests: &mut Interests,
+    maxevents: usize,
+    cx: &mut Context,
+) -> Vec<EpollEvent> {
+    let mut events = Vec::new();
+    let mut closed = Vec::new();
+    for (&fd, interest) in interests.iter_mut() {
+        if events.len() >= maxevents {
+            break;
+        }
+        if interest.disabled {
+            continue;
+        }
+        // taken before the readiness is read, so that a later change is a new edge
+        let edge = interest.edge.swap(false, Ordering::AcqRel);
+        let ready = match interest.readiness(Some(&mut *cx)) {
+            Some(ready) => ready,
+            None => {
+                closed.push(fd);
+                continue;
+            }
+        };
+        let report = interest.pending(ready, edge);
+        interest.reported = ready;
+        if report.is_empty() {
+            continue;
+        }
+        if interest.events.contains(EpollEvents::ONESHOT) {
+            interest.disabled = true;
+        }
+        events.push(EpollEvent {
+            events: report & READINESS,
+            data: interest.data,
+        });
+    }
+    for fd in closed {
+        interests.remove(&fd);
+    }
+    events
+}
+
+/// Sockets have no wakers, so a wait on them is rechecked at this interval
+const SOCKET_POLL_INTERVAL_MS: usize = 10;
+
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct EpollWaitFuture {
+    interests: Arc<Mutex<Interests>>,
+    maxevents: usize,
+    timeout_msecs: isize,
+    begin_time_ms: usize,
+}
+
+impl Future for EpollWaitFuture {
+    type Output = LxResult<Vec<EpollEvent>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut interests = self.interests.lock();
+        let events = collect_events(&mut interests, self.maxevents, cx);
+        if !events.is_empty() {
+            return Poll::Ready(Ok(events));
+        }
+        let has_socket = interests.values().any(|i| !i.disabled && i.is_socket());
+        drop(interests);
+
+        let current_time_ms = TimeVal::now().to_msec();
+        let mut wake_time_ms = match self.timeout_msecs {
+            // no timeout, return now
+            0 => return Poll::Ready(Ok(Vec::new())),
+            1.. => {
+                let deadline = self.begin_time_ms + self.timeout_msecs as usize;
+                if current_time_ms >= deadline {
+                    return Poll::Ready(Ok(Vec::new()));
+                }
+                Some(deadline)
+            }
+            _ => None,
+        };
+        if has_socket {
+            let recheck = current_time_ms + SOCKET_POLL_INTERVAL_MS;
+            wake_time_ms = Some(wake_time_ms.map_or(recheck, |t| t.min(recheck)));
+        }
+        if let Some(wake_time_ms) = wake_time_ms {
+            let waker = cx.waker().clone();
+            timer::timer_set(
+                Duration::from_millis(wake_time_ms as u64),
+                Box::new(move |_| waker.wake_by_ref()),
+            );
+        }
+        Poll::Pending
+    }
+}
+
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct EpollReadyFuture<'a> {
+    epoll: &'a EpollInstance,
+}
+
+impl Future for EpollReadyFuture<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.epoll.has_events(Some(cx)) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[async_trait]
+impl FileLike for EpollInstance {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.read()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.write();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        Arc::new(EpollInstance {
+            base: KObjectBase::new(),
+            flags: RwLock::new(self.flags()),
+            interests: self.interests.clone(),
+        })
+    }
+
+    async fn read(&self, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    /// An epoll instance is readable when a wait on it would return events
+    fn poll(&self) -> LxResult<PollStatus> {
+        Ok(PollStatus {
+            read: self.has_events(None),
+            write: false,
+            error: false,
+        })
+    }
+
+    async fn async_poll(&self) -> LxResult<PollStatus> {
+        EpollReadyFuture { epoll: self }.await;
+        self.poll()
+    }
+
+    fn ioctl(&self, _request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        Err(LxError::ENOTTY)
+    }
+
+    fn get_vmo(&self, _offset: usize, _len: usize) -> LxResult<Arc<VmObject>> {
+        Err(LxError::EINVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{File, Pipe};
+    use alloc::task::Wake;
+    use core::task::Waker;
+    use rcore_fs::vfs::INode;
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn collect(epoll: &EpollInstance) -> Vec<EpollEvent> {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        collect_events(&mut epoll.interests.lock(), 16, &mut cx)
+    }
+
+    #[test]
+    fn edge_triggered_pipe_refill() {
+        let (read, write) = Pipe::create_pair();
+        let (read, write) = (Arc::new(read), Arc::new(write));
+        let file = File::new(read.clone(), OpenFlags::RDONLY, "pipe".into());
+        let epoll = EpollInstance::new(EpollCreateFlags::empty());
+        let event = EpollEvent {
+            events: EpollEvents::IN | EpollEvents::ET,
+            data: 7,
+        };
+        epoll
+            .control(
+                EpollCtlOp::ADD,
+                3.into(),
+                EpollTarget::File(file.clone()),
+                Some(event),
+            )
+            .unwrap();
+        assert!(collect(&epoll).is_empty());
+
+        write.write_at(0, b"hello").unwrap();
+        let events = collect(&epoll);
+        assert_eq!(events.len(), 1);
+        assert_eq!({ events[0].data }, 7);
+        // still readable, but not a new edge
+        assert!(collect(&epoll).is_empty());
+
+        // drained and refilled without a wait in between
+        let mut buf = [0u8; 8];
+        assert_eq!(read.read_at(0, &mut buf).unwrap(), 5);
+        write.write_at(0, b"world").unwrap();
+        assert!(epoll.has_events(None));
+        assert_eq!(collect(&epoll).len(), 1);
+        assert!(collect(&epoll).is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! Event notification file
+#![deny(missing_docs)]
+
+use alloc::{boxed::Box, sync::Arc};
+use async_trait::async_trait;
+use rcore_fs::vfs::PollStatus;
+use spin::{Mutex, RwLock};
+use zircon_object::impl_kobject;
+use zircon_object::object::{KObjectBase, KernelObject};
+use zircon_object::vm::VmObject;
+
+use super::{FileLike, OpenFlags};
+use crate::error::{LxError, LxResult};
+use crate::sync::{wait_for_event, Event, EventBus, EventHandler};
+
+bitflags::bitflags! {
+    /// Flags of eventfd2
+    pub struct EventFdFlags: usize {
+        /// read returns 1 and decrements the counter by 1
+        const SEMAPHORE = 1;
+        /// non block open
+        const NON_BLOCK = 1 << 11;
+        /// close on exec
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+/// The largest value the counter can hold
+const MAX_COUNT: u64 = u64::MAX - 1;
+
+/// A 64-bit counter which can be waited on, see [linux man eventfd(2)](https://www.man7.org/linux/man-pages/man2/eventfd.2.html)
+pub struct EventFd {
+    base: KObjectBase,
+    flags: RwLock<OpenFlags>,
+    semaphore: bool,
+    count: Arc<Mutex<u64>>,
+    /// `READABLE` while the counter is not zero, `WRITABLE` while it can be incremented
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+impl_kobject!(EventFd);
+
+impl EventFd {
+    /// Create an eventfd whose counter starts at `initval`
+    pub fn new(initval: u64, flags: EventFdFlags) -> Arc<Self> {
+        let mut open_flags = OpenFlags::RDWR;
+        open_flags.set(
+            OpenFlags::NON_BLOCK,
+            flags.contains(EventFdFlags::NON_BLOCK),
+        );
+        open_flags.set(OpenFlags::CLOEXEC, flags.contains(EventFdFlags::CLOEXEC));
+        let eventfd = EventFd {
+            base: KObjectBase::new(),
+            flags: RwLock::new(open_flags),
+            semaphore: flags.contains(EventFdFlags::SEMAPHORE),
+            count: Arc::new(Mutex::new(initval)),
+            eventbus: EventBus::new(),
+        };
+        eventfd.update_events(initval);
+        Arc::new(eventfd)
+    }
+
+    fn update_events(&self, count: u64) {
+        let mut eventbus = self.eventbus.lock();
+        eventbus.clear(Event::READABLE | Event::WRITABLE);
+        if count > 0 {
+            eventbus.set(Event::READABLE);
+        }
+        if count < MAX_COUNT {
+            eventbus.set(Event::WRITABLE);
+        }
+    }
+
+    /// Take the counter, or 1 in semaphore mode, if it is not zero
+    fn try_read(&self) -> Option<u64> {
+        let mut count = self.count.lock();
+        if *count == 0 {
+            return None;
+        }
+        let value = if self.semaphore { 1 } else { *count };
+        *count -= value;
+        self.update_events(*count);
+        Some(value)
+    }
+
+    /// Add `value` to the counter if it does not overflow
+    fn try_write(&self, value: u64) -> bool {
+        let mut count = self.count.lock();
+        if MAX_COUNT - *count < value {
+            // the next read makes room and sets it again
+            self.eventbus.lock().clear(Event::WRITABLE);
+            return false;
+        }
+        *count += value;
+        self.update_events(*count);
+        true
+    }
+}
+
+/// The value written by `buf`
+fn write_value(buf: &[u8]) -> LxResult<u64> {
+    if buf.len() < 8 {
+        return Err(LxError::EINVAL);
+    }
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[..8]);
+    let value = u64::from_ne_bytes(bytes);
+    if value == u64::MAX {
+        return Err(LxError::EINVAL);
+    }
+    Ok(value)
+}
+
+#[async_trait]
+impl FileLike for EventFd {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.read()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.write();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        Arc::new(EventFd {
+            base: KObjectBase::new(),
+            flags: RwLock::new(self.flags()),
+            semaphore: self.semaphore,
+            count: self.count.clone(),
+            eventbus: self.eventbus.clone(),
+        })
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LxError::EINVAL);
+        }
+        loop {
+            if let Some(value) = self.try_read() {
+                buf[..8].copy_from_slice(&value.to_ne_bytes());
+                return Ok(8);
+            }
+            if self.flags().non_block() {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    /// Add a value to the counter, an overflowing write fails with `EAGAIN`
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        if self.try_write(write_value(buf)?) {
+            Ok(8)
+        } else {
+            Err(LxError::EAGAIN)
+        }
+    }
+
+    /// Add a value to the counter, waiting for a read to make room in blocking mode
+    async fn async_write(&self, buf: &[u8]) -> LxResult<usize> {
+        let value = write_value(buf)?;
+        loop {
+            if self.try_write(value) {
+                return Ok(8);
+            }
+            if self.flags().non_block() {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.eventbus.clone(), Event::WRITABLE).await;
+        }
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self) -> LxResult<PollStatus> {
+        let count = *self.count.lock();
+        Ok(PollStatus {
+            read: count > 0,
+            write: count < MAX_COUNT,
+            error: false,
+        })
+    }
+
+    /// Wait until the counter is readable or writable
+    async fn async_poll(&self) -> LxResult<PollStatus> {
+        wait_for_event(self.eventbus.clone(), Event::READABLE | Event::WRITABLE).await;
+        self.poll()
+    }
+
+    fn subscribe(&self, callback: EventHandler) -> bool {
+        self.eventbus.lock().subscribe(callback);
+        true
+    }
+
+    fn ioctl(&self, _request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        Err(LxError::ENOTTY)
+    }
+
+    fn get_vmo(&self, _offset: usize, _len: usize) -> LxResult<Arc<VmObject>> {
+        Err(LxError::EINVAL)
+    }
+}
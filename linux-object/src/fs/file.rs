@@ -9,8 +9,10 @@ use rcore_fs::vfs::{FileType, FsError, INode, Metadata, PollStatus};
 use zircon_object::object::*;
 use zircon_object::vm::{pages, VmObject};
 
-use super::FileLike;
+use super::stdio::Stdin;
+use super::{FileLike, Pipe};
 use crate::error::{LxError, LxResult};
+use crate::sync::EventHandler;
 
 
 bitflags::bitflags! {
@@ -32,6 +34,10 @@ bitflags::bitflags! {
         const APPEND = 1 << 10;
         /// non block open
         const NON_BLOCK = 1 << 11;
+        /// fail if the path is not a directory
+        const DIRECTORY = 1 << 16;
+        /// fail if the last component of the path is a symbolic link
+        const NOFOLLOW = 1 << 17;
         /// close on exec
         const CLOEXEC = 1 << 19;
     }
@@ -177,6 +183,16 @@ impl File {
         Ok(inner.offset)
     }
 
+    /// The offset where the next `write` starts, the end of the file in append mode
+    pub fn write_offset(&self) -> LxResult<u64> {
+        let inner = self.inner.read();
+        if inner.flags.is_append() {
+            Ok(inner.inode.metadata()?.size as u64)
+        } else {
+            Ok(inner.offset)
+        }
+    }
+
     /// resize the file
     pub fn set_len(&self, len: u64) -> LxResult {
         let inner = self.inner.write();
@@ -273,6 +289,18 @@ impl FileLike for File {
         Ok(self.inner.read().inode.async_poll().await?)
     }
 
+    fn subscribe(&self, callback: EventHandler) -> bool {
+        let inner = self.inner.read();
+        if let Some(pipe) = inner.inode.downcast_ref::<Pipe>() {
+            pipe.subscribe(callback);
+        } else if let Some(stdin) = inner.inode.downcast_ref::<Stdin>() {
+            stdin.subscribe(callback);
+        } else {
+            return false;
+        }
+        true
+    }
+
     fn ioctl(&self, request: usize, arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
         // ioctl syscall
         self.inner.read().inode.io_control(request as u32, arg1)?;
@@ -289,6 +317,7 @@ impl FileLike for File {
                 let len = inner.inode.read_at(offset, &mut buf)?;
                 let vmo = VmObject::new_paged(pages(len));
                 vmo.write(0, &buf[..len])?;
+                vmo.set_name(&self.path);
                 Ok(vmo)
             }
             FileType::CharDevice => {
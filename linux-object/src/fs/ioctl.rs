@@ -21,6 +21,21 @@ pub const TIOCSPGRP: usize = 0x5410;
 #[cfg(target_arch = "mips")]
 pub const TIOCSPGRP: usize = 0x8_004_74_76;
 
+#[cfg(not(target_arch = "mips"))]
+pub const TIOCSCTTY: usize = 0x540E;
+#[cfg(target_arch = "mips")]
+pub const TIOCSCTTY: usize = 0x5480;
+
+#[cfg(not(target_arch = "mips"))]
+pub const TIOCNOTTY: usize = 0x5422;
+#[cfg(target_arch = "mips")]
+pub const TIOCNOTTY: usize = 0x5471;
+
+#[cfg(not(target_arch = "mips"))]
+pub const TIOCGSID: usize = 0x5429;
+#[cfg(target_arch = "mips")]
+pub const TIOCGSID: usize = 0x7416;
+
 #[cfg(not(target_arch = "mips"))]
 pub const TIOCGWINSZ: usize = 0x5413;
 // _IOR('t', 104, struct winsize)
@@ -0,0 +1,92 @@
+use core::mem::size_of;
+use core::slice::{from_raw_parts, from_raw_parts_mut};
+use kernel_hal::mem::{pmem_read, pmem_write};
+use kernel_hal::PhysAddr;
+
+pub const CALL_STATUS_OK: u32 = 0;
+pub const CALL_STATUS_ERROR: u32 = 5;
+
+// Edge calls of the attestation relay, served by the kernel without returning to the host:
+// the eapp gets the nonce the host asked to attest, then passes the report of its runtime
+pub const CALL_ID_ATTEST_NONCE: u64 = 0xa408_0001;
+pub const CALL_ID_ATTEST_REPORT: u64 = 0xa408_0002;
+
+/// `struct edge_call` at the start of the shared buffer (UTM)
+#[repr(C)]
+#[derive(Default)]
+pub struct EdgeCall {
+    pub call_id: u64,
+    pub call_arg_offset: u64,
+    pub call_arg_size: u64,
+    pub call_status: u32,
+    _pad: u32,
+    pub call_ret_offset: u64,
+    pub call_ret_size: u64
+}
+
+/// Part of the shared buffer of `size` bytes at `paddr` used by the thread `tid` out of `threads`
+pub fn thread_buffer(paddr: PhysAddr, size: usize, threads: usize, tid: usize) -> (PhysAddr, usize) {
+    let slot = (size / threads) & !(size_of::<u64>() - 1);
+    (paddr + tid * slot, slot)
+}
+
+fn in_buffer(offset: u64, size: u64, buffer_size: usize) -> bool {
+    offset as usize >= size_of::<EdgeCall>()
+        && offset.checked_add(size).map_or(false, |end| end as usize <= buffer_size)
+}
+
+impl EdgeCall {
+    pub fn new(call_id: usize, data_len: usize) -> Self {
+        EdgeCall {
+            call_id: call_id as u64,
+            call_arg_offset: size_of::<Self>() as u64,
+            call_arg_size: data_len as u64,
+            ..Default::default()
+        }
+    }
+
+    /// Read the header from the shared buffer at `paddr`
+    pub fn read(paddr: PhysAddr) -> Self {
+        let mut call = EdgeCall::default();
+        pmem_read(paddr, unsafe {
+            from_raw_parts_mut(&mut call as *mut Self as *mut u8, size_of::<Self>())
+        });
+        call
+    }
+
+    pub fn write(&self, paddr: PhysAddr) {
+        pmem_write(paddr, unsafe {
+            from_raw_parts(self as *const Self as *const u8, size_of::<Self>())
+        });
+    }
+
+    /// Copy the call arguments out of the shared buffer at `paddr`, which must be valid
+    pub fn read_args(&self, paddr: PhysAddr, buf: &mut [u8]) {
+        pmem_read(paddr + self.call_arg_offset as usize, buf);
+    }
+
+    /// Return `data` right after the header in the shared buffer at `paddr`,
+    /// or fail the call if it does not fit in `buffer_size` bytes
+    pub fn reply(&mut self, paddr: PhysAddr, buffer_size: usize, data: &[u8]) {
+        let offset = size_of::<Self>();
+        if offset + data.len() > buffer_size {
+            self.call_status = CALL_STATUS_ERROR;
+            return;
+        }
+        pmem_write(paddr + offset, data);
+        self.call_status = CALL_STATUS_OK;
+        self.call_ret_offset = offset as u64;
+        self.call_ret_size = data.len() as u64;
+    }
+
+    /// Whether the call arguments lie in a shared buffer of `buffer_size` bytes
+    pub fn args_valid(&self, buffer_size: usize) -> bool {
+        buffer_size >= size_of::<Self>() && in_buffer(self.call_arg_offset, self.call_arg_size, buffer_size)
+    }
+
+    /// Whether the return data filled by the host lies in a shared buffer of `buffer_size` bytes
+    pub fn ret_valid(&self, buffer_size: usize) -> bool {
+        self.call_status != CALL_STATUS_OK || self.call_ret_size == 0
+            || in_buffer(self.call_ret_offset, self.call_ret_size, buffer_size)
+    }
+}
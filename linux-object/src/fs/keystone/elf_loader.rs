@@ -9,11 +9,15 @@ use zircon_object::util::elf_loader::FlagsExt;
 use zircon_object::vm::{PAGE_SIZE, pages, VmAddressRegion, VmObject};
 use crate::error::{LxError, LxResult};
 use crate::fs::keystone::{MemoryRegion};
+use super::measure::{hash_enclave, MEASUREMENT_SIZE};
+use super::sbi::SbiCreate;
 
 pub trait EnclaveVmar {
     /// Create `VMObject` from all LOAD segments of `elf` and map them to this VMAR.
     /// Return the first `VMObject`.
     fn load_elf_to_epm(&self, elf: &ElfFile, epm: Arc<Mutex<MemoryRegion>>, user: bool) -> LxResult<PhysAddr>;
+    /// Hash the pages laid out by `load_elf_to_epm` as the security monitor will measure them.
+    fn measure_epm(&self, args: &SbiCreate) -> LxResult<[u8; MEASUREMENT_SIZE]>;
 }
 
 impl EnclaveVmar for VmAddressRegion {
@@ -38,6 +42,13 @@ impl EnclaveVmar for VmAddressRegion {
         }
         Ok(first_paddr.unwrap())
     }
+
+    fn measure_epm(&self, args: &SbiCreate) -> LxResult<[u8; MEASUREMENT_SIZE]> {
+        hash_enclave(self.table_phys(), args).ok_or_else(|| {
+            error!("Illegal enclave page table, cannot measure");
+            LxError::EINVAL
+        })
+    }
 }
 
 fn make_vmo(elf: &ElfFile, ph: ProgramHeader, epm: Arc<Mutex<MemoryRegion>>) -> LxResult<(Arc<VmObject>, PhysAddr)> {
@@ -60,4 +71,4 @@ fn make_vmo(elf: &ElfFile, ph: ProgramHeader, epm: Arc<Mutex<MemoryRegion>>) ->
     //调用 VMObjectTrait.write, 分配物理内存，后写入程序数据
     vmo.write(page_offset, data)?;
     Ok((vmo, base_paddr))
-}
\ No newline at end of file
+}
\ No newline at end of file
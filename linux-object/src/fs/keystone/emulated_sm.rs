@@ -0,0 +1,479 @@
+//! In-kernel stand-in for the Keystone security monitor.
+//!
+//! Used when the firmware does not implement the Keystone SBI extension (libos, plain QEMU).
+//! The monitor keeps the enclave lifecycle and regions as the real SM does, and plays the
+//! role of the Eyrie runtime itself: the eapp mapped in the EPM page table runs in an isolated
+//! zCore process, one zCore thread per enclave thread, and its runtime syscalls are served here.
+
+use alloc::boxed::Box;
+use async_trait::async_trait;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::future::Future;
+use core::mem::size_of;
+use core::pin::Pin;
+use core::slice::from_raw_parts;
+use core::time::Duration;
+use hashbrown::HashMap;
+use spin::Mutex;
+use kernel_hal::context::{TrapReason, UserContext, UserContextField};
+use kernel_hal::mem::{pmem_read, pmem_write};
+use kernel_hal::{MMUFlags, PAGE_SIZE, PhysAddr};
+use zircon_object::object::{KernelObject, Signal};
+use zircon_object::signal::Event;
+use zircon_object::task::{CurrentThread, Job, Process, Thread, ThreadState};
+use zircon_object::vm::{VmAddressRegion, VmObject};
+use zircon_object::{ZxError, ZxResult};
+use crate::error::LxError;
+use super::edge_call::{thread_buffer, EdgeCall, CALL_STATUS_OK};
+use super::ioctl::{thread_stack_top, ENCLAVE_THREADS_MAX};
+use super::measure::{hash_enclave, MEASUREMENT_SIZE};
+use super::page::{PTE_R, PTE_U, PTE_V, PTE_W, PTE_X};
+use super::sbi::*;
+
+// Eyrie runtime syscalls (rt/syscall_nums.h)
+const RUNTIME_SYSCALL_OCALL: usize = 1001;
+const RUNTIME_SYSCALL_SHAREDCOPY: usize = 1002;
+const RUNTIME_SYSCALL_ATTEST_ENCLAVE: usize = 1003;
+const RUNTIME_SYSCALL_EXIT: usize = 1101;
+// Linux exit/exit_group, for eapps whose libc exits the usual way
+const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+
+const ENCLAVE_ID_MAX: usize = 32;
+// Room for argc/argv/envp/auxv at the top of the eapp stack, all left empty
+const INITIAL_STACK_FRAME: usize = 128;
+
+enum EnclaveState {
+    Fresh,
+    Running,
+    // `reported` is set once the host has been told about the edge call
+    StoppedEdgeCall { reported: bool },
+    Exited(usize)
+}
+
+struct EmulatedThread {
+    state: Mutex<EnclaveState>,
+    // USER_SIGNAL_0 wakes the enclave thread blocked in an edge call,
+    // USER_SIGNAL_1 is set while the thread is stopped and the host has to be told
+    wakeup: Arc<Event>,
+    // Part of the UTM holding the edge calls of this thread
+    buffer: SbiRegion
+}
+
+struct EmulatedEnclave {
+    epm: SbiRegion,
+    utm: SbiRegion,
+    user_entry: usize,
+    hash: [u8; MEASUREMENT_SIZE],
+    threads: Vec<EmulatedThread>,
+    // Created by the first run of any thread
+    proc: Mutex<Option<Arc<Process>>>
+}
+
+/// Security monitor emulated by the kernel
+pub struct EmulatedMonitor {
+    enclaves: Mutex<HashMap<usize, Arc<EmulatedEnclave>>>
+}
+
+fn overlaps(a: &SbiRegion, b: &SbiRegion) -> bool {
+    a.paddr < b.paddr + b.size && b.paddr < a.paddr + a.size
+}
+
+fn contains(region: &SbiRegion, paddr: usize) -> bool {
+    paddr >= region.paddr && paddr < region.paddr + region.size
+}
+
+impl EmulatedMonitor {
+    pub fn new() -> Self {
+        EmulatedMonitor {
+            enclaves: Mutex::new(HashMap::new())
+        }
+    }
+
+    fn get(&self, eid: usize) -> Option<Arc<EmulatedEnclave>> {
+        self.enclaves.lock().get(&eid).cloned()
+    }
+
+    fn get_thread(&self, eid: usize, tid: usize) -> Result<Arc<EmulatedEnclave>, Sbiret> {
+        let enclave = self.get(eid).ok_or_else(|| Sbiret::error(SBI_ERR_SM_ENCLAVE_INVALID_ID))?;
+        if tid >= enclave.threads.len() {
+            return Err(Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT));
+        }
+        Ok(enclave)
+    }
+}
+
+#[async_trait]
+impl SecurityMonitor for EmulatedMonitor {
+    fn create_enclave(&self, args: &SbiCreate) -> Sbiret {
+        let epm = &args.epm_region;
+        let utm = &args.utm_region;
+        if epm.size == 0 || epm.paddr % PAGE_SIZE != 0 || epm.size % PAGE_SIZE != 0
+            || utm.paddr % PAGE_SIZE != 0 || utm.size % PAGE_SIZE != 0 {
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT);
+        }
+        if !contains(epm, args.runtime_paddr) || !contains(epm, args.user_paddr)
+            || !(contains(epm, args.free_paddr) || args.free_paddr == epm.paddr + epm.size) {
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT);
+        }
+        if args.threads == 0 || args.threads > ENCLAVE_THREADS_MAX {
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT);
+        }
+        if utm.size != 0 && overlaps(epm, utm) {
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_REGION_OVERLAPS);
+        }
+        // The EPM starts with the root page table
+        let hash = match hash_enclave(epm.paddr, args) {
+            Some(hash) => hash,
+            None => return Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_PTE)
+        };
+        let mut enclaves = self.enclaves.lock();
+        for enclave in enclaves.values() {
+            if overlaps(&enclave.epm, epm) || (utm.size != 0 && overlaps(&enclave.utm, utm)) {
+                return Sbiret::error(SBI_ERR_SM_ENCLAVE_REGION_OVERLAPS);
+            }
+        }
+        let eid = match (0..ENCLAVE_ID_MAX).find(|id| !enclaves.contains_key(id)) {
+            Some(eid) => eid,
+            None => return Sbiret::error(SBI_ERR_SM_ENCLAVE_NO_FREE_RESOURCE)
+        };
+        let threads = (0..args.threads).map(|tid| {
+            let (paddr, size) = thread_buffer(utm.paddr, utm.size, args.threads, tid);
+            EmulatedThread {
+                state: Mutex::new(EnclaveState::Fresh),
+                wakeup: Event::new(),
+                buffer: SbiRegion { paddr, size }
+            }
+        }).collect();
+        enclaves.insert(eid, Arc::new(EmulatedEnclave {
+            epm: SbiRegion { paddr: epm.paddr, size: epm.size },
+            utm: SbiRegion { paddr: utm.paddr, size: utm.size },
+            user_entry: args.runtime_params.user_entry,
+            hash,
+            threads,
+            proc: Mutex::new(None)
+        }));
+        Sbiret::new(SBI_ERR_SM_ENCLAVE_SUCCESS, eid as i32)
+    }
+
+    fn destroy_enclave(&self, eid: usize) -> Sbiret {
+        let enclave = match self.enclaves.lock().remove(&eid) {
+            Some(enclave) => enclave,
+            None => return Sbiret::error(SBI_ERR_SM_ENCLAVE_INVALID_ID)
+        };
+        if let Some(proc) = enclave.proc.lock().take() {
+            proc.exit(-1);
+        }
+        // Hosts still waiting on a thread see it exited
+        enclave.stop_all(usize::MAX);
+        Sbiret::error(SBI_ERR_SM_ENCLAVE_SUCCESS)
+    }
+
+    fn run_enclave(&self, eid: usize, tid: usize) -> Sbiret {
+        let enclave = match self.get_thread(eid, tid) {
+            Ok(enclave) => enclave,
+            Err(ret) => return ret
+        };
+        let thread = &enclave.threads[tid];
+        let mut state = thread.state.lock();
+        if !matches!(*state, EnclaveState::Fresh) {
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_NOT_RUNNABLE);
+        }
+        if let Err(err) = start_enclave_thread(&enclave, tid) {
+            error!("emulated sm: cannot start thread {} of enclave {}: {:?}", tid, eid, err);
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_NOT_RUNNABLE);
+        }
+        thread.wakeup.signal_clear(Signal::USER_SIGNAL_1);
+        *state = EnclaveState::Running;
+        Sbiret::error(SBI_ERR_SM_ENCLAVE_INTERRUPTED)
+    }
+
+    fn resume_enclave(&self, eid: usize, tid: usize) -> Sbiret {
+        let enclave = match self.get_thread(eid, tid) {
+            Ok(enclave) => enclave,
+            Err(ret) => return ret
+        };
+        let thread = &enclave.threads[tid];
+        let mut state = thread.state.lock();
+        match *state {
+            EnclaveState::Fresh => Sbiret::error(SBI_ERR_SM_ENCLAVE_NOT_RESUMABLE),
+            EnclaveState::Running => Sbiret::error(SBI_ERR_SM_ENCLAVE_INTERRUPTED),
+            EnclaveState::StoppedEdgeCall { reported: false } => {
+                *state = EnclaveState::StoppedEdgeCall { reported: true };
+                Sbiret::error(SBI_ERR_SM_ENCLAVE_EDGE_CALL_HOST)
+            }
+            EnclaveState::StoppedEdgeCall { reported: true } => {
+                thread.wakeup.signal_change(Signal::USER_SIGNAL_1, Signal::USER_SIGNAL_0);
+                *state = EnclaveState::Running;
+                Sbiret::error(SBI_ERR_SM_ENCLAVE_INTERRUPTED)
+            }
+            EnclaveState::Exited(value) => Sbiret::new(SBI_ERR_SM_ENCLAVE_SUCCESS, value as i32)
+        }
+    }
+
+    /// Sleep until the thread stops instead of polling it
+    async fn wait_thread(&self, eid: usize, tid: usize) {
+        let wakeup: Arc<dyn KernelObject> = match self.get_thread(eid, tid) {
+            Ok(enclave) => enclave.threads[tid].wakeup.clone(),
+            Err(_) => return
+        };
+        wakeup.wait_signal(Signal::USER_SIGNAL_1).await;
+    }
+
+    fn attest_enclave(&self, eid: usize, report: &mut Report, data: &[u8]) -> Sbiret {
+        match self.get(eid) {
+            Some(enclave) => fill_report(report, &enclave.hash, data),
+            None => Sbiret::error(SBI_ERR_SM_ENCLAVE_INVALID_ID)
+        }
+    }
+}
+
+/// There is no device key to sign with, so the report carries zeroed signatures
+fn fill_report(report: &mut Report, hash: &[u8; MEASUREMENT_SIZE], data: &[u8]) -> Sbiret {
+    if data.len() > ATTEST_DATA_MAXLEN {
+        return Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT);
+    }
+    *report = Report::empty();
+    report.enclave.hash[..MEASUREMENT_SIZE].copy_from_slice(hash);
+    report.enclave.data[..data.len()].copy_from_slice(data);
+    report.enclave.data_len = data.len() as u64;
+    Sbiret::error(SBI_ERR_SM_ENCLAVE_SUCCESS)
+}
+
+/// Start the thread `tid` of the eapp, creating the isolated process holding it on first use
+///
+/// Every thread enters at the eapp entry with its id in the first argument.
+fn start_enclave_thread(enclave: &Arc<EmulatedEnclave>, tid: usize) -> ZxResult {
+    let proc = {
+        let mut proc = enclave.proc.lock();
+        if proc.is_none() {
+            let new_proc = Process::create(&Job::root(), "enclave")?;
+            map_user_pages(&new_proc.vmar(), enclave.epm.paddr, 2, 0)?;
+            *proc = Some(new_proc);
+        }
+        proc.clone().unwrap()
+    };
+    let sp = thread_stack_top(tid) - INITIAL_STACK_FRAME;
+    proc.vmar().write_memory(sp, &[0u8; INITIAL_STACK_FRAME])?;
+    let thread = Thread::create_with_ext(&proc, "enclave", (enclave.clone(), tid))?;
+    thread.start_with_entry(enclave.user_entry, sp, tid, 0, enclave_thread_fn)
+}
+
+/// Walk the Sv39 table built in the EPM and map every user leaf into `vmar`
+fn map_user_pages(vmar: &Arc<VmAddressRegion>, table: PhysAddr, level: usize, va_base: usize) -> ZxResult {
+    let mut buf = vec![0u8; PAGE_SIZE];
+    pmem_read(table, &mut buf);
+    for (index, entry) in buf.chunks_exact(size_of::<u64>()).enumerate() {
+        let pte = u64::from_le_bytes(entry.try_into().unwrap()) as usize;
+        // Upper half of the address space belongs to the runtime
+        if pte & PTE_V == 0 || (level == 2 && index >= 256) {
+            continue;
+        }
+        let va = va_base | (index << (12 + 9 * level));
+        let pa = (pte >> 10) << 12;
+        if pte & (PTE_R | PTE_W | PTE_X) == 0 {
+            if level == 0 {
+                return Err(ZxError::BAD_STATE);
+            }
+            map_user_pages(vmar, pa, level - 1, va)?;
+        } else if pte & PTE_U != 0 {
+            let pages = 1 << (9 * level);
+            let mut flags = MMUFlags::USER;
+            if pte & PTE_R != 0 { flags |= MMUFlags::READ; }
+            if pte & PTE_W != 0 { flags |= MMUFlags::WRITE; }
+            if pte & PTE_X != 0 { flags |= MMUFlags::EXECUTE; }
+            if va < vmar.addr() {
+                warn!("emulated sm: skip user page {:#x} below the user address space", va);
+                continue;
+            }
+            let vmo = VmObject::new_physical(pa, pages);
+            vmar.map_at(va - vmar.addr(), vmo, 0, pages * PAGE_SIZE, flags)?;
+        }
+    }
+    Ok(())
+}
+
+fn enclave_thread_fn(thread: CurrentThread) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+    Box::pin(run_enclave_thread(thread))
+}
+
+async fn run_enclave_thread(thread: CurrentThread) {
+    let (enclave, tid) = thread.ext().downcast_ref::<(Arc<EmulatedEnclave>, usize)>().unwrap().clone();
+    loop {
+        let mut ctx = thread.wait_for_run().await;
+        if thread.state() == ThreadState::Dying {
+            break;
+        }
+        ctx.enter_uspace();
+        let reason = ctx.trap_reason();
+        match reason {
+            TrapReason::Syscall => {
+                let (num, args) = match syscall_num_args(&ctx) {
+                    Ok(call) => call,
+                    Err(ret) => {
+                        thread.put_context(ctx);
+                        error!("emulated sm: enclave syscalls are not supported on this target ({})", ret.error);
+                        enclave.exit(&thread, usize::MAX);
+                        continue;
+                    }
+                };
+                ctx.advance_pc(reason);
+                thread.put_context(ctx);
+                if let Some(ret) = enclave.handle_syscall(&thread, tid, num, args).await {
+                    thread.with_context(|ctx| ctx.set_field(UserContextField::ReturnValue, ret)).ok();
+                }
+            }
+            TrapReason::Interrupt(vector) => {
+                thread.put_context(ctx);
+                kernel_hal::interrupt::handle_irq(vector);
+                kernel_hal::thread::yield_now().await;
+            }
+            _ => {
+                thread.put_context(ctx);
+                error!("emulated sm: enclave thread {} trapped with {:x?}", tid, reason);
+                enclave.exit(&thread, usize::MAX);
+            }
+        }
+    }
+}
+
+fn syscall_num_args(ctx: &UserContext) -> Result<(usize, [usize; 6]), Sbiret> {
+    let regs = ctx.general();
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            Ok((regs.rax, [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9]))
+        } else if #[cfg(target_arch = "aarch64")] {
+            Ok((regs.x8, [regs.x0, regs.x1, regs.x2, regs.x3, regs.x4, regs.x5]))
+        } else if #[cfg(target_arch = "riscv64")] {
+            Ok((regs.a7, [regs.a0, regs.a1, regs.a2, regs.a3, regs.a4, regs.a5]))
+        } else {
+            let _ = regs;
+            Err(Sbiret::error(SBI_ERR_SM_NOT_IMPLEMENTED))
+        }
+    }
+}
+
+impl EmulatedEnclave {
+    /// Serve a runtime syscall, `None` once the enclave has exited
+    async fn handle_syscall(&self, thread: &CurrentThread, tid: usize, num: usize, args: [usize; 6]) -> Option<usize> {
+        let vmar = thread.proc().vmar();
+        let ret = match num {
+            RUNTIME_SYSCALL_OCALL => {
+                match self.ocall(thread, tid, &vmar, args[0], args[1], args[2], args[3], args[4]).await {
+                    Ok(ret) => ret,
+                    Err(ZxError::STOP) => return None,
+                    Err(_) => 1
+                }
+            }
+            RUNTIME_SYSCALL_SHAREDCOPY => {
+                match self.copy_from_shared(&vmar, args[0], args[1], args[2]) {
+                    Ok(()) => 0,
+                    Err(_) => 1
+                }
+            }
+            RUNTIME_SYSCALL_ATTEST_ENCLAVE => {
+                let mut data = vec![0u8; args[2].min(ATTEST_DATA_MAXLEN + 1)];
+                let mut report = Box::new(Report::empty());
+                if vmar.read_memory(args[1], &mut data).is_err()
+                    || fill_report(&mut report, &self.hash, &data).error != SBI_ERR_SM_ENCLAVE_SUCCESS {
+                    return Some(usize::MAX);
+                }
+                let bytes = unsafe { from_raw_parts(report.as_ref() as *const Report as *const u8, size_of::<Report>()) };
+                match vmar.write_memory(args[0], bytes) {
+                    Ok(_) => 0,
+                    Err(_) => usize::MAX
+                }
+            }
+            SYSCALL_EXIT => {
+                self.exit_thread(thread, tid, args[0]);
+                return None;
+            }
+            RUNTIME_SYSCALL_EXIT | SYSCALL_EXIT_GROUP => {
+                self.exit(thread, args[0]);
+                return None;
+            }
+            _ => {
+                warn!("emulated sm: unsupported enclave syscall {}", num);
+                -(LxError::ENOSYS as isize) as usize
+            }
+        };
+        Some(ret)
+    }
+
+    /// Forward an edge call to the host through the buffer of thread `tid` and wait for its answer
+    #[allow(clippy::too_many_arguments)]
+    async fn ocall(&self, thread: &CurrentThread, tid: usize, vmar: &Arc<VmAddressRegion>, call_id: usize, data: usize,
+                   data_len: usize, ret_buf: usize, ret_len: usize) -> ZxResult<usize> {
+        let emulated = &self.threads[tid];
+        let buffer = &emulated.buffer;
+        let header = size_of::<EdgeCall>();
+        if header.checked_add(data_len).map_or(true, |end| end > buffer.size) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let mut buf = vec![0u8; data_len];
+        vmar.read_memory(data, &mut buf)?;
+        pmem_write(buffer.paddr + header, &buf);
+        EdgeCall::new(call_id, data_len).write(buffer.paddr);
+
+        let wakeup: Arc<dyn KernelObject> = emulated.wakeup.clone();
+        wakeup.signal_clear(Signal::USER_SIGNAL_0);
+        *emulated.state.lock() = EnclaveState::StoppedEdgeCall { reported: false };
+        wakeup.signal_set(Signal::USER_SIGNAL_1);
+        thread.blocking_run(
+            wakeup.wait_signal(Signal::USER_SIGNAL_0),
+            ThreadState::BlockedWaitOne,
+            Duration::MAX,
+            None,
+        ).await?;
+
+        let call = EdgeCall::read(buffer.paddr);
+        if call.call_status != CALL_STATUS_OK {
+            return Ok(1);
+        }
+        if !call.ret_valid(buffer.size) {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
+        if ret_len != 0 {
+            let offset = call.call_ret_offset as usize;
+            let size = (call.call_ret_size as usize).min(ret_len);
+            let mut buf = vec![0u8; size];
+            pmem_read(buffer.paddr + offset, &mut buf);
+            vmar.write_memory(ret_buf, &buf)?;
+        }
+        Ok(0)
+    }
+
+    fn copy_from_shared(&self, vmar: &Arc<VmAddressRegion>, dst: usize, offset: usize, size: usize) -> ZxResult {
+        if offset.checked_add(size).map_or(true, |end| end > self.utm.size) {
+            return Err(ZxError::OUT_OF_RANGE);
+        }
+        let mut buf = vec![0u8; size];
+        pmem_read(self.utm.paddr + offset, &mut buf);
+        vmar.write_memory(dst, &buf)?;
+        Ok(())
+    }
+
+    fn stop(&self, tid: usize, value: usize) {
+        let emulated = &self.threads[tid];
+        *emulated.state.lock() = EnclaveState::Exited(value);
+        emulated.wakeup.signal_set(Signal::USER_SIGNAL_1);
+    }
+
+    fn stop_all(&self, value: usize) {
+        (0..self.threads.len()).for_each(|tid| self.stop(tid, value));
+    }
+
+    /// Exit the thread `tid` only, the others keep running
+    fn exit_thread(&self, thread: &CurrentThread, tid: usize, value: usize) {
+        self.stop(tid, value);
+        thread.exit();
+    }
+
+    /// Exit every thread of the enclave
+    fn exit(&self, thread: &CurrentThread, value: usize) {
+        self.stop_all(value);
+        thread.proc().exit(value as i64);
+    }
+}
@@ -1,10 +1,14 @@
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use spin::Mutex;
+use crate::error::LxResult;
 use zircon_object::vm::VmAddressRegion;
 use crate::fs::keystone::{EnclaveParams, MemoryRegion};
 use crate::fs::keystone::page::EnclavePageTable;
-use super::Enclave;
+use kernel_hal::PhysAddr;
+use super::{Attestation, Enclave, EnclaveThread};
+use super::edge_call::thread_buffer;
+use super::enclave_manager::EnclaveCharge;
 
 
 impl EnclaveParams {
@@ -19,10 +23,11 @@ impl EnclaveParams {
 }
 
 impl Enclave {
-    pub fn new(min_pages: usize) -> Self {
-        let epm = Arc::new(Mutex::new(MemoryRegion::new(min_pages)));
-        Enclave {
+    pub fn new(min_pages: usize, charge: EnclaveCharge) -> LxResult<Self> {
+        let epm = Arc::new(Mutex::new(MemoryRegion::new(min_pages)?));
+        Ok(Enclave {
             eid: -1,
+            _charge: charge,
             // close_on_pexit: 1,
             epm: epm.clone(),
             utm: Arc::new(Mutex::new(MemoryRegion {
@@ -31,14 +36,26 @@ impl Enclave {
                 size: 0,
                 order: 0,
                 pa: 0,
-                frames: Vec::new()
+                used: Vec::new(),
+                frames: Vec::new(),
+                reserved: false,
+                vmo: None
             })),
             vmar:VmAddressRegion::new_root_with_pt(Arc::new(Mutex::new(EnclavePageTable::new(epm.clone())))),
             params: EnclaveParams::empty(),
-            is_init: true
+            is_init: true,
+            threads: vec![EnclaveThread::default()],
+            hash: None,
+            attestation: Attestation::None
+        })
+    }
+
+    /// Edge call buffer of the thread `tid` in the UTM
+    pub fn thread_buffer(&self, tid: usize) -> Option<(PhysAddr, usize)> {
+        if tid >= self.threads.len() {
+            return None;
         }
+        let utm = self.utm.lock();
+        Some(thread_buffer(utm.pa, utm.size, self.threads.len(), tid))
     }
 }
-
-
-
@@ -1,86 +1,169 @@
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use hashbrown::HashMap;
-use spin::RwLock;
+use spin::{Mutex, RwLock};
+use zircon_object::task::Process;
 use crate::error::{LxError, LxResult};
 use crate::fs::keystone::Enclave;
+use crate::process::{ProcessExt, RLimit};
+use crate::sync::{Event, EventBus};
+use super::sbi::sbi_sm_destroy_enclave;
 
 const ENCLAVE_IDR_MIN: usize = 0x1000;
 const ENCLAVE_IDR_MAX: usize = 0xffff;
 
 struct EnclaveManagerInner {
     avail: Vec<usize>,
-    enclave_map: HashMap<usize, Enclave>
+    enclave_map: HashMap<usize, Enclave>,
+    // Enclave whose UTM `mmap` maps, the last one given a UTM
+    current: Option<usize>
 }
 
+/// Enclaves created through one open of the keystone device, ids are local to it
 pub struct EnclaveManager {
-    inner: RwLock<EnclaveManagerInner>
+    proc: Weak<Process>,
+    inner: RwLock<EnclaveManagerInner>,
+    // READABLE while a thread run in the background has stopped
+    events: Arc<Mutex<EventBus>>
+}
+
+/// Quota taken from the owning process by an enclave, given back on drop
+pub struct EnclaveCharge {
+    proc: Weak<Process>,
+    epm_pages: usize
+}
+
+impl Drop for EnclaveCharge {
+    fn drop(&mut self) {
+        if let Some(proc) = self.proc.upgrade() {
+            proc.linux().uncharge_enclave(self.epm_pages);
+        }
+    }
 }
 
 impl EnclaveManager {
-    pub fn new() -> Self {
+    pub fn new(proc: Weak<Process>) -> Self {
         let mut avail: Vec<usize> = Vec::new();
         (ENCLAVE_IDR_MIN..ENCLAVE_IDR_MAX).for_each(|id| { avail.push(id); } );
         EnclaveManager {
+            proc,
             inner: RwLock::new(EnclaveManagerInner {
                 avail,
-                enclave_map: HashMap::new()
-            })
+                enclave_map: HashMap::new(),
+                current: None
+            }),
+            events: EventBus::new()
         }
     }
-}
 
-impl EnclaveManagerInner {
+    /// Account a new enclave of `epm_pages` against the limits of the owning process
+    pub fn charge(&self, epm_pages: usize) -> LxResult<EnclaveCharge> {
+        let proc = self.proc.upgrade().ok_or(LxError::ESRCH)?;
+        proc.linux().charge_enclave(epm_pages)?;
+        Ok(EnclaveCharge {
+            proc: self.proc.clone(),
+            epm_pages
+        })
+    }
+
+    /// Limits on the enclaves of the owning process, of their number and of their EPM pages
+    pub fn limits(&self) -> LxResult<(RLimit, RLimit)> {
+        let proc = self.proc.upgrade().ok_or(LxError::ESRCH)?;
+        let limits = proc.linux().enclave_limits();
+        Ok(limits)
+    }
+
+    pub fn set_limits(&self, enclave_limit: RLimit, epm_limit: RLimit) -> LxResult {
+        let proc = self.proc.upgrade().ok_or(LxError::ESRCH)?;
+        proc.linux().set_enclave_limits(enclave_limit, epm_limit)?;
+        Ok(())
+    }
+
     pub fn get_enclave_sbi_eid(&self, id: usize) -> LxResult<isize> {
-        if let Some(enclave) = self.enclave_map.get(&id) {
-            Ok(enclave.eid)
-        } else {
-            Err(LxError::EINVAL)
-        }
+        self.inner.read().enclave_map.get(&id).map(|enclave| enclave.eid).ok_or(LxError::EINVAL)
     }
 
-    pub fn modify_enclave_by_id<F, T>(&mut self, id: usize, mut f: F) -> LxResult<T>
+    pub fn modify_enclave_by_id<F, T>(&self, id: usize, mut f: F) -> LxResult<T>
         where
             F: FnMut(&mut Enclave) -> LxResult<T>, {
-        if let Some(enclave) = self.enclave_map.get_mut(&id) {
+        let mut inner = self.inner.write();
+        if let Some(enclave) = inner.enclave_map.get_mut(&id) {
             f(enclave)
         } else {
             Err(LxError::EINVAL)
         }
     }
 
-    pub fn alloc(&mut self, enclave: Enclave) -> Option<usize> {
-        if let Some(id) = self.avail.pop() {
-            self.enclave_map.insert(id, enclave);
-            Some(id)
-        } else {
-            None
+    pub fn alloc(&self, enclave: Enclave) -> LxResult<usize> {
+        let mut inner = self.inner.write();
+        let id = inner.avail.pop().ok_or(LxError::ENOMEM)?;
+        inner.enclave_map.insert(id, enclave);
+        Ok(id)
+    }
+
+    pub fn remove_by_id(&self, id: usize) -> LxResult<Enclave> {
+        let mut inner = self.inner.write();
+        let enclave = inner.enclave_map.remove(&id).ok_or(LxError::EINVAL)?;
+        inner.avail.push(id);
+        if inner.current == Some(id) {
+            inner.current = None;
         }
+        Ok(enclave)
     }
 
-    pub fn remove(&mut self, id: usize) {
-        self.enclave_map.remove(&id).unwrap();
+    pub fn current(&self) -> LxResult<usize> {
+        self.inner.read().current.ok_or(LxError::EINVAL)
     }
-}
 
-lazy_static::lazy_static! {
-    pub static ref ENCLAVE_MANAGER: EnclaveManager = EnclaveManager::new();
-}
+    pub fn set_current(&self, id: usize) {
+        self.inner.write().current = Some(id);
+    }
 
-pub fn get_enclave_sbi_eid(id: usize) -> LxResult<isize> {
-    ENCLAVE_MANAGER.inner.read().get_enclave_sbi_eid(id)
-}
+    pub fn events(&self) -> Arc<Mutex<EventBus>> {
+        self.events.clone()
+    }
 
-pub fn modify_enclave_by_id<F, T>(id: usize, f: F) -> LxResult<T>
-    where
-        F: FnMut(&mut Enclave) -> LxResult<T>, {
-    ENCLAVE_MANAGER.inner.write().modify_enclave_by_id(id, f)
-}
+    /// Whether a thread run in the background has stopped and not been collected yet
+    pub fn has_stopped(&self) -> bool {
+        self.inner.read().enclave_map.values()
+            .any(|enclave| enclave.threads.iter().any(|thread| thread.stopped.is_some()))
+    }
 
-pub fn alloc(enclave: Enclave) -> Option<usize> {
-    ENCLAVE_MANAGER.inner.write().alloc(enclave)
-}
+    /// Bring the readiness of the device up to date with the enclave threads
+    pub fn update_events(&self) {
+        let stopped = self.has_stopped();
+        let mut events = self.events.lock();
+        if stopped {
+            events.set(Event::READABLE);
+        } else {
+            events.clear(Event::READABLE);
+        }
+    }
 
-pub fn remove_by_id(id: usize) {
-    ENCLAVE_MANAGER.inner.write().remove(id)
+    /// Destroy every enclave and give their memory back
+    pub fn release(&self) {
+        let enclaves: Vec<Enclave> = {
+            let mut inner = self.inner.write();
+            let ids: Vec<usize> = inner.enclave_map.keys().cloned().collect();
+            inner.avail.extend(ids.iter());
+            inner.current = None;
+            inner.enclave_map.drain().map(|(_, enclave)| enclave).collect()
+        };
+        for enclave in enclaves {
+            if enclave.eid >= 0 {
+                let ret = sbi_sm_destroy_enclave(enclave.eid as usize);
+                if ret.error != 0 {
+                    error!("cannot destroy enclave: SBI failed with error code {}", ret.error);
+                }
+            }
+        }
+        self.update_events();
+    }
 }
 
+impl Drop for EnclaveManager {
+    fn drop(&mut self) {
+        // The last fd of this open is closed
+        self.release();
+    }
+}
@@ -1,17 +1,25 @@
+use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::mem::size_of;
+use core::slice::from_raw_parts_mut;
 use spin::Mutex;
 use xmas_elf::ElfFile;
 use kernel_hal::addr::page_count;
-use kernel_hal::{MMUFlags, PAGE_SIZE};
-use kernel_hal::user::{UserInOutPtr, UserInPtr};
+use kernel_hal::{MMUFlags, PAGE_SIZE, PhysAddr};
+use kernel_hal::user::{UserInOutPtr, UserInPtr, UserOutPtr};
 use zircon_object::vm::VmObject;
 use crate::error::{LxError, LxResult};
+use crate::process::RLimit;
 use crate::fs::keystone::elf_loader::EnclaveVmar;
 use crate::fs::keystone::MemoryRegion;
-use super::enclave_manager::*;
-use super::Enclave;
+use crate::fs::keystone::page::region_pages;
+use super::enclave_manager::EnclaveManager;
+use super::{Attestation, Enclave, EnclaveThread};
 use super::sbi::*;
+use super::measure::MEASUREMENT_SIZE;
+use super::edge_call::{EdgeCall, CALL_ID_ATTEST_NONCE, CALL_ID_ATTEST_REPORT, CALL_STATUS_ERROR, CALL_STATUS_OK};
+use super::run::EnclaveRun;
 
 pub const IOC_MAGIC: usize = 0xa4 << 8;
 pub const CREATE_ENCLAVE: usize = IOC_MAGIC | 0x00;
@@ -20,10 +28,24 @@ pub const RUN_ENCLAVE: usize = IOC_MAGIC | 0x04;
 pub const RESUME_ENCLAVE: usize = IOC_MAGIC | 0x05;
 pub const FINALIZE_ENCLAVE: usize = IOC_MAGIC | 0x06;
 pub const UTM_INIT: usize = IOC_MAGIC | 0x07;
+pub const ATTEST_ENCLAVE: usize = IOC_MAGIC | 0x08;
+pub const GET_MEASUREMENT: usize = IOC_MAGIC | 0x09;
+pub const SET_THREADS: usize = IOC_MAGIC | 0x0a;
+pub const GET_LIMITS: usize = IOC_MAGIC | 0x0b;
+pub const SET_LIMITS: usize = IOC_MAGIC | 0x0c;
 
 pub const DEFAULT_STACK_SIZE: usize = 1024 * 16;
 pub const DEFAULT_STACK_START: usize = 0x0000000040000000;
 pub const DEFAULT_STACK_PAGES: usize = DEFAULT_STACK_SIZE / PAGE_SIZE;
+/// Max entry threads of an enclave
+pub const ENCLAVE_THREADS_MAX: usize = 8;
+// Distance between the stack tops of two threads, leaving a guard gap
+const THREAD_STACK_STRIDE: usize = DEFAULT_STACK_SIZE * 4;
+
+/// Top of the stack of the enclave thread `tid`, thread 0 uses the default stack
+pub fn thread_stack_top(tid: usize) -> usize {
+    DEFAULT_STACK_START + tid * THREAD_STACK_STRIDE
+}
 
 pub struct Cmd(pub usize);
 
@@ -52,10 +74,10 @@ impl Cmd {
 }
 
 pub struct RuntimeParams {
-    runtime_entry: usize,
-    user_entry: usize,
-    untrusted_ptr: usize,
-    untrusted_size: usize
+    pub runtime_entry: usize,
+    pub user_entry: usize,
+    pub untrusted_ptr: usize,
+    pub untrusted_size: usize
 }
 
 pub struct CreateParams {
@@ -73,23 +95,65 @@ pub struct CreateParams {
     params: RuntimeParams
 }
 
+/// Why `RUN_ENCLAVE`/`RESUME_ENCLAVE` returned to the host
+#[repr(usize)]
+#[derive(Copy, Clone, Debug)]
+pub enum ExitReason {
+    /// The enclave exited, `value` holds its return value
+    Exited = 0,
+    /// The enclave stopped on an edge call described by the header at the start of the UTM part of the thread
+    EdgeCall = 1,
+    /// The security monitor refused to run the enclave, `error` holds its error code
+    SmError = 2,
+}
+
 pub struct RunParams {
     eid: usize,
     error: usize,
-    value: usize
+    value: usize,
+    // One of `ExitReason`
+    exit_reason: usize,
+    // Enclave thread to run, below the count given by `SET_THREADS`
+    tid: usize
+}
+
+pub struct ThreadsParams {
+    eid: usize,
+    threads: usize
+}
+
+pub struct AttestParams {
+    eid: usize,
+    // Nonce bound into the enclave report
+    nonce_ptr: usize,
+    nonce_size: usize,
+    // User buffer receiving the signed `Report`
+    report_ptr: usize
+}
+
+/// Limits on the enclaves of the process owning the device, checked like its resource limits
+pub struct LimitsParams {
+    enclaves: RLimit,
+    // EPM pages of all enclaves
+    epm_pages: RLimit
+}
+
+pub struct MeasureParams {
+    eid: usize,
+    hash: [u8; MEASUREMENT_SIZE]
 }
 
-pub fn ioctl(cmd: Cmd, base: usize) -> LxResult<usize> {
+pub fn ioctl(manager: &EnclaveManager, cmd: Cmd, base: usize) -> LxResult<usize> {
     match cmd.match_field() {
         CREATE_ENCLAVE | DESTROY_ENCLAVE | FINALIZE_ENCLAVE | UTM_INIT => {
             if cmd.ioc_size() >= size_of::<CreateParams>() {
                 let mut ptr: UserInOutPtr<CreateParams> = base.into();
                 if let Ok(mut data) = ptr.read() {
                     let ret = match cmd.match_field() {
-                        CREATE_ENCLAVE => { create_enclave(&mut data) },
-                        DESTROY_ENCLAVE => { destroy_enclave(&data) },
-                        FINALIZE_ENCLAVE => { finalize_enclave(&data) },
-                        UTM_INIT => { utm_init_ioctl(&mut data) },
+                        CREATE_ENCLAVE => { create_enclave(manager, &mut data) },
+                        DESTROY_ENCLAVE => { destroy_enclave(manager, &data) },
+                        FINALIZE_ENCLAVE => { finalize_enclave(manager, &data) },
+                        UTM_INIT => { utm_init_ioctl(manager, &mut data) },
                         _ => { Err(LxError::ENOSYS) }
                     };
                     if let Ok(_) = ptr.write(data) {
@@ -99,14 +163,31 @@ pub fn ioctl(cmd: Cmd, base: usize) -> LxResult<usize> {
             }
             Err(LxError::EFAULT)
         },
-        RUN_ENCLAVE | RESUME_ENCLAVE => {
-            if cmd.ioc_size() >= size_of::<RunParams>() {
-                let mut ptr: UserInOutPtr<RunParams> = base.into();
+        ATTEST_ENCLAVE => {
+            if cmd.ioc_size() >= size_of::<AttestParams>() {
+                let ptr: UserInPtr<AttestParams> = base.into();
+                if let Ok(data) = ptr.read() {
+                    return attest_enclave(manager, &data);
+                }
+            }
+            Err(LxError::EFAULT)
+        }
+        SET_THREADS => {
+            if cmd.ioc_size() >= size_of::<ThreadsParams>() {
+                let ptr: UserInPtr<ThreadsParams> = base.into();
+                if let Ok(data) = ptr.read() {
+                    return set_threads(manager, &data);
+                }
+            }
+            Err(LxError::EFAULT)
+        }
+        GET_LIMITS | SET_LIMITS => {
+            if cmd.ioc_size() >= size_of::<LimitsParams>() {
+                let mut ptr: UserInOutPtr<LimitsParams> = base.into();
                 if let Ok(mut data) = ptr.read() {
                     let ret = match cmd.match_field() {
-                        RUN_ENCLAVE => { run_enclave(&mut data) },
-                        RESUME_ENCLAVE => { resume_enclave(&mut data) },
-                        _ => { Err(LxError::ENOSYS) }
+                        GET_LIMITS => { get_limits(manager, &mut data) },
+                        _ => { set_limits(manager, &data) }
                     };
                     if let Ok(_) = ptr.write(data) {
                         return ret;
@@ -115,12 +196,48 @@ pub fn ioctl(cmd: Cmd, base: usize) -> LxResult<usize> {
             }
             Err(LxError::EFAULT)
         }
+        GET_MEASUREMENT => {
+            if cmd.ioc_size() >= size_of::<MeasureParams>() {
+                let mut ptr: UserInOutPtr<MeasureParams> = base.into();
+                if let Ok(mut data) = ptr.read() {
+                    let ret = get_measurement(manager, &mut data);
+                    if let Ok(_) = ptr.write(data) {
+                        return ret;
+                    }
+                }
+            }
+            Err(LxError::EFAULT)
+        }
         _ => { Err(LxError::ENOSYS) }
     }
 }
 
-fn create_enclave(params: &mut CreateParams) -> LxResult<usize> {
-    let mut enclave = Enclave::new(params.min_pages);
+/// Commands which may wait for the enclave, other commands complete at once.
+///
+/// With `nonblock`, a run goes on in the background and fails with `EAGAIN` until the
+/// enclave thread stops, which makes the device readable.
+pub async fn async_ioctl(manager: &Arc<EnclaveManager>, cmd: Cmd, base: usize, nonblock: bool) -> LxResult<usize> {
+    match cmd.match_field() {
+        RUN_ENCLAVE | RESUME_ENCLAVE => {
+            if cmd.ioc_size() >= size_of::<RunParams>() {
+                let mut ptr: UserInOutPtr<RunParams> = base.into();
+                if let Ok(mut data) = ptr.read() {
+                    let resume = cmd.match_field() == RESUME_ENCLAVE;
+                    let ret = run_enclave(manager, &mut data, resume, nonblock).await;
+                    if let Ok(_) = ptr.write(data) {
+                        return ret;
+                    }
+                }
+            }
+            Err(LxError::EFAULT)
+        }
+        _ => ioctl(manager, cmd, base)
+    }
+}
+
+fn create_enclave(manager: &EnclaveManager, params: &mut CreateParams) -> LxResult<usize> {
+    let charge = manager.charge(region_pages(params.min_pages))?;
+    let mut enclave = Enclave::new(params.min_pages, charge)?;
     let runtime_ptr: UserInPtr<u8> = params.runtime_vaddr.into();
     if let Ok(data) = runtime_ptr.read_array(params.runtime_size) {
         let elf = ElfFile::new(data.as_slice()).map_err(|_| LxError::EFAULT)?;
@@ -154,12 +271,50 @@ fn create_enclave(params: &mut CreateParams) -> LxResult<usize> {
     enclave.vmar.map_at(DEFAULT_STACK_START - DEFAULT_STACK_SIZE, user_stack_vmo.clone(), 0, user_stack_vmo.len(),
                      MMUFlags::USER | MMUFlags::READ | MMUFlags::WRITE)?;
 
-    params.eid = alloc(enclave).unwrap();
+    params.eid = manager.alloc(enclave)?;
     Ok(0)
 }
 
-fn finalize_enclave(data: &CreateParams) -> LxResult<usize> {
-    modify_enclave_by_id(data.eid, |enclave| {
+/// Give the enclave `data.threads` entry threads, each with its own stack, before it is finalized
+fn set_threads(manager: &EnclaveManager, data: &ThreadsParams) -> LxResult<usize> {
+    if data.threads == 0 || data.threads > ENCLAVE_THREADS_MAX {
+        return Err(LxError::EINVAL);
+    }
+    if data.threads > sbi_sm_max_threads() {
+        error!("the security monitor runs enclaves with at most {} threads", sbi_sm_max_threads());
+        return Err(LxError::EOPNOTSUPP);
+    }
+    manager.modify_enclave_by_id(data.eid, |enclave| {
+        if !enclave.is_init || enclave.threads.len() != 1 {
+            return Err(LxError::EINVAL);
+        }
+        for tid in 1..data.threads {
+            let frames = enclave.epm.lock().alloc(DEFAULT_STACK_PAGES).ok_or(LxError::ENOMEM)?;
+            let stack_vmo = VmObject::new_with_frames(DEFAULT_STACK_PAGES, frames);
+            enclave.vmar.map_at(thread_stack_top(tid) - DEFAULT_STACK_SIZE, stack_vmo.clone(), 0, stack_vmo.len(),
+                             MMUFlags::USER | MMUFlags::READ | MMUFlags::WRITE)?;
+            enclave.threads.push(EnclaveThread::default());
+        }
+        // The stacks belong to the user part of the EPM
+        enclave.params.free_paddr = enclave.epm.lock().free_paddr().ok_or(LxError::ENOMEM)?;
+        Ok(0)
+    })
+}
+
+fn get_limits(manager: &EnclaveManager, data: &mut LimitsParams) -> LxResult<usize> {
+    let (enclaves, epm_pages) = manager.limits()?;
+    data.enclaves = enclaves;
+    data.epm_pages = epm_pages;
+    Ok(0)
+}
+
+fn set_limits(manager: &EnclaveManager, data: &LimitsParams) -> LxResult<usize> {
+    manager.set_limits(data.enclaves, data.epm_pages)?;
+    Ok(0)
+}
+
+fn finalize_enclave(manager: &EnclaveManager, data: &CreateParams) -> LxResult<usize> {
+    manager.modify_enclave_by_id(data.eid, |enclave| {
         enclave.is_init = false;
         let epm = enclave.epm.lock();
         let utm = enclave.utm.lock();
@@ -180,33 +335,36 @@ fn finalize_enclave(data: &CreateParams) -> LxResult<usize> {
                 user_entry: data.params.user_entry,
                 untrusted_ptr: data.params.untrusted_ptr,
                 untrusted_size: data.params.untrusted_size
-            }
+            },
+            threads: enclave.threads.len()
         };
         drop(epm);
         drop(utm);
-        let ret: Sbiret = sbi_sm_create_enclave(&sbi_create as *const SbiCreate as usize).into();
+        let hash = enclave.vmar.measure_epm(&sbi_create)?;
+        let ret = sbi_sm_create_enclave(&sbi_create);
         if ret.error == 0 {
             enclave.eid = ret.value as isize;
+            enclave.hash = Some(hash);
             Ok(0)
         } else {
             Err(LxError::EINVAL)
         }
     }).map_err(|_| {
         error!("Invalid enclave id");
-        remove_by_id(data.eid);
+        manager.remove_by_id(data.eid).ok();
         LxError::EINVAL
     })
 }
 
-fn destroy_enclave(data: &CreateParams) -> LxResult<usize> {
-    if let Ok(sbi_eid) = get_enclave_sbi_eid(data.eid) {
+fn destroy_enclave(manager: &EnclaveManager, data: &CreateParams) -> LxResult<usize> {
+    if let Ok(sbi_eid) = manager.get_enclave_sbi_eid(data.eid) {
         if sbi_eid >= 0 {
-            let ret: Sbiret = sbi_sm_destroy_enclave(sbi_eid as usize).into();
+            let ret = sbi_sm_destroy_enclave(sbi_eid as usize);
             if ret.error > 0 {
                 error!("cannot destroy enclave: SBI failed with error code {}", ret.error);
                 Err(LxError::EINVAL)
             } else {
-                remove_by_id(data.eid);
+                manager.remove_by_id(data.eid)?;
                 warn!("kernel remove enclave completed!");
                 Ok(0)
             }
@@ -219,28 +377,197 @@ fn destroy_enclave(data: &CreateParams) -> LxResult<usize> {
     }
 }
 
-fn run_enclave(data: &mut RunParams) -> LxResult<usize> {
-    if let Ok(sbi_eid) = get_enclave_sbi_eid(data.eid) {
-        if sbi_eid >= 0 {
-            let ret: Sbiret = sbi_sm_run_enclave(sbi_eid as usize).into();
-            data.error = ret.error as usize;
-            data.value = ret.value as usize;
-            Ok(0)
-        } else {
+/// Marks an enclave thread as run by a host thread, until the run returns or is cancelled
+struct RunningGuard {
+    manager: Arc<EnclaveManager>,
+    eid: usize,
+    tid: usize
+}
+
+impl RunningGuard {
+    /// Keep the stop of a background run for the next run call of the thread to collect
+    fn stop(self, ret: Sbiret) {
+        let tid = self.tid;
+        self.manager.modify_enclave_by_id(self.eid, |enclave| {
+            enclave.threads[tid].stopped = Some(ret);
+            Ok(())
+        }).ok();
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        let tid = self.tid;
+        self.manager.modify_enclave_by_id(self.eid, |enclave| {
+            enclave.threads[tid].running = false;
+            Ok(())
+        }).ok();
+        self.manager.update_events();
+    }
+}
+
+async fn run_enclave(manager: &Arc<EnclaveManager>, data: &mut RunParams, resume: bool, nonblock: bool) -> LxResult<usize> {
+    let tid = data.tid;
+    let (sbi_eid, (buf_pa, buf_size), stopped) = manager.modify_enclave_by_id(data.eid, |enclave| {
+        if enclave.eid < 0 {
             error!("real enclave does not exist");
-            Err(LxError::EINVAL)
+            return Err(LxError::EINVAL);
         }
-    } else {
-        Err(LxError::EINVAL)
+        let buffer = enclave.thread_buffer(tid).ok_or(LxError::EINVAL)?;
+        let thread = &mut enclave.threads[tid];
+        // A run in the background stopped, hand its result over
+        if let Some(ret) = thread.stopped.take() {
+            return Ok((enclave.eid as usize, buffer, Some(ret)));
+        }
+        if thread.running {
+            return Err(if nonblock { LxError::EAGAIN } else { LxError::EBUSY });
+        }
+        if resume && thread.edge_call_pending && !EdgeCall::read(buffer.0).ret_valid(buffer.1) {
+            error!("malformed edge call return data");
+            return Err(LxError::EPROTO);
+        }
+        thread.running = true;
+        Ok((enclave.eid as usize, buffer, None))
+    })?;
+    let ret = match stopped {
+        Some(ret) => {
+            manager.update_events();
+            ret
+        }
+        None => {
+            let running = RunningGuard { manager: manager.clone(), eid: data.eid, tid };
+            // Only come back to the host for an edge call or when the enclave is gone,
+            // other tasks keep running meanwhile
+            let run = run_to_host(manager.clone(), data.eid, sbi_eid, tid, resume, (buf_pa, buf_size));
+            if nonblock {
+                kernel_hal::thread::spawn(async move {
+                    let ret = run.await;
+                    running.stop(ret);
+                });
+                return Err(LxError::EAGAIN);
+            }
+            let ret = run.await;
+            drop(running);
+            ret
+        }
+    };
+    let reason = match ret.error {
+        SBI_ERR_SM_ENCLAVE_SUCCESS => ExitReason::Exited,
+        SBI_ERR_SM_ENCLAVE_EDGE_CALL_HOST => ExitReason::EdgeCall,
+        _ => ExitReason::SmError
+    };
+    manager.modify_enclave_by_id(data.eid, |enclave| {
+        enclave.threads[tid].edge_call_pending = matches!(reason, ExitReason::EdgeCall);
+        Ok(0)
+    })?;
+    data.error = ret.error as usize;
+    data.value = ret.value as usize;
+    data.exit_reason = reason as usize;
+    if matches!(reason, ExitReason::EdgeCall) && !EdgeCall::read(buf_pa).args_valid(buf_size) {
+        error!("malformed edge call header from enclave");
+        return Err(LxError::EPROTO);
     }
+    Ok(0)
 }
 
-fn resume_enclave(data: &mut RunParams) -> LxResult<usize> {
-    if let Ok(sbi_eid) = get_enclave_sbi_eid(data.eid) {
+/// Run the enclave thread until it leaves for the host,
+/// serving the edge calls of the attestation relay on the way
+async fn run_to_host(manager: Arc<EnclaveManager>, eid: usize, sbi_eid: usize, tid: usize, resume: bool,
+                     (buf_pa, buf_size): (PhysAddr, usize)) -> Sbiret {
+    let mut resume = resume;
+    loop {
+        let ret = EnclaveRun::new(sbi_eid, tid, resume).await;
+        if ret.error != SBI_ERR_SM_ENCLAVE_EDGE_CALL_HOST || !serve_attest_call(&manager, eid, buf_pa, buf_size) {
+            return ret;
+        }
+        resume = true;
+    }
+}
+
+/// Serve an edge call of the attestation relay in the shared buffer, return false for other calls
+fn serve_attest_call(manager: &EnclaveManager, eid: usize, buf_pa: PhysAddr, buf_size: usize) -> bool {
+    let mut call = EdgeCall::read(buf_pa);
+    if !call.args_valid(buf_size) {
+        return false;
+    }
+    let served = manager.modify_enclave_by_id(eid, |enclave| {
+        match call.call_id {
+            CALL_ID_ATTEST_NONCE => match &enclave.attestation {
+                Attestation::Requested(nonce) => call.reply(buf_pa, buf_size, nonce),
+                _ => call.call_status = CALL_STATUS_ERROR
+            },
+            CALL_ID_ATTEST_REPORT => {
+                let mut report = Box::new(Report::empty());
+                let requested = match &enclave.attestation {
+                    Attestation::Requested(nonce) => nonce.as_slice(),
+                    _ => &[]
+                };
+                call.call_status = CALL_STATUS_ERROR;
+                if call.call_arg_size as usize == size_of::<Report>() {
+                    call.read_args(buf_pa, unsafe {
+                        from_raw_parts_mut(report.as_mut() as *mut Report as *mut u8, size_of::<Report>())
+                    });
+                    // Only a report binding the nonce of the host answers its request
+                    if !requested.is_empty() && report_nonce(&report) == requested {
+                        enclave.attestation = Attestation::Reported(report);
+                        call.call_status = CALL_STATUS_OK;
+                        call.call_ret_size = 0;
+                    }
+                }
+            }
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }).unwrap_or(false);
+    if served {
+        call.write(buf_pa);
+    }
+    served
+}
+
+fn report_nonce(report: &Report) -> &[u8] {
+    let len = (report.enclave.data_len as usize).min(ATTEST_DATA_MAXLEN);
+    &report.enclave.data[..len]
+}
+
+/// Get the report the eapp relayed for `nonce`, which only the enclave can ask the firmware monitor for.
+///
+/// Fails with `EAGAIN` and records the request until the eapp has passed the report.
+fn relayed_report(manager: &EnclaveManager, eid: usize, nonce: Vec<u8>) -> LxResult<Box<Report>> {
+    manager.modify_enclave_by_id(eid, |enclave| {
+        if let Attestation::Reported(report) = &enclave.attestation {
+            if report_nonce(report) == nonce.as_slice() {
+                let report = report.clone();
+                enclave.attestation = Attestation::None;
+                return Ok(report);
+            }
+        }
+        enclave.attestation = Attestation::Requested(nonce.clone());
+        Err(LxError::EAGAIN)
+    })
+}
+
+fn attest_enclave(manager: &EnclaveManager, data: &AttestParams) -> LxResult<usize> {
+    // An empty nonce could not tell a relayed report from a stale one
+    if data.nonce_size == 0 || data.nonce_size > ATTEST_DATA_MAXLEN {
+        return Err(LxError::EINVAL);
+    }
+    let nonce_ptr: UserInPtr<u8> = data.nonce_ptr.into();
+    let nonce = nonce_ptr.read_array(data.nonce_size)?;
+    if let Ok(sbi_eid) = manager.get_enclave_sbi_eid(data.eid) {
         if sbi_eid >= 0 {
-            let ret: Sbiret = sbi_sm_resume_enclave(sbi_eid as usize).into();
-            data.error = ret.error as usize;
-            data.value = ret.value as usize;
+            let mut report = Box::new(Report::empty());
+            let ret = sbi_sm_attest_enclave(sbi_eid as usize, report.as_mut(), nonce.as_slice());
+            let report = match ret.error {
+                SBI_ERR_SM_ENCLAVE_SUCCESS => report,
+                SBI_ERR_SM_NOT_IMPLEMENTED => relayed_report(manager, data.eid, nonce)?,
+                error => {
+                    error!("cannot attest enclave: SBI failed with error code {}", error);
+                    return Err(LxError::EINVAL);
+                }
+            };
+            let mut report_ptr: UserOutPtr<Report> = data.report_ptr.into();
+            report_ptr.write(*report)?;
             Ok(0)
         } else {
             error!("real enclave does not exist");
@@ -251,13 +578,26 @@ fn resume_enclave(data: &mut RunParams) -> LxResult<usize> {
     }
 }
 
-fn utm_init_ioctl(data: &mut CreateParams) -> LxResult<usize> {
-    modify_enclave_by_id(data.eid, |enclave| {
-        enclave.utm = Arc::new(Mutex::new(MemoryRegion::new(page_count(data.params.untrusted_size))));
-        // let utm = enclave.utm.lock();
-        // data.utm_free_ptr = utm.pa;
-        Ok(0)
+fn get_measurement(manager: &EnclaveManager, data: &mut MeasureParams) -> LxResult<usize> {
+    manager.modify_enclave_by_id(data.eid, |enclave| {
+        if let Some(hash) = enclave.hash {
+            data.hash = hash;
+            Ok(0)
+        } else {
+            error!("enclave is not finalized yet");
+            Err(LxError::EINVAL)
+        }
     })
 }
 
+fn utm_init_ioctl(manager: &EnclaveManager, data: &mut CreateParams) -> LxResult<usize> {
+    manager.modify_enclave_by_id(data.eid, |enclave| {
+        let utm = MemoryRegion::new_shared(page_count(data.params.untrusted_size))?;
+        data.utm_free_ptr = utm.pa;
+        enclave.utm = Arc::new(Mutex::new(utm));
+        Ok(0)
+    })?;
+    manager.set_current(data.eid);
+    Ok(0)
+}
 
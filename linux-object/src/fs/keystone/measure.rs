@@ -0,0 +1,181 @@
+//! Enclave measurement, computed the way Keystone's `validate_and_hash_enclave` walks the EPM.
+//!
+//! The hash covers the runtime parameters, then every leaf page of the Sv39 table in
+//! virtual address order, each contiguous range prefixed by its first virtual address.
+//! Like the security monitor, the digest is SHA3-512.
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::slice::from_raw_parts_mut;
+use sha3::{Digest, Sha3_512};
+use kernel_hal::mem::pmem_read;
+use kernel_hal::{PAGE_SIZE, PhysAddr};
+use super::page::PTE_U;
+use super::sbi::{SbiCreate, MDSIZE};
+
+pub const MEASUREMENT_SIZE: usize = MDSIZE;
+
+const PTE_PPN_SHIFT: usize = 10;
+const PGSHIFT: usize = 12;
+const PGLEVEL_BITS: usize = 9;
+const PGLEVEL_MASK: usize = (1 << PGLEVEL_BITS) - 1;
+const PGLEVEL_TOP: usize = 3;
+const PGTABLE_HIGHEST_BIT: usize = 1 << (PGLEVEL_BITS - 1);
+
+struct Walker<'a> {
+    hasher: Sha3_512,
+    args: &'a SbiCreate,
+    page: Vec<u8>,
+    runtime_max_seen: usize,
+    user_max_seen: usize
+}
+
+impl Walker<'_> {
+    fn in_range(paddr: usize, base: usize, end: usize) -> bool {
+        paddr >= base && paddr < end
+    }
+
+    /// Returns the new `contiguous` state, `None` on an illegal mapping
+    fn walk(&mut self, level: usize, table: PhysAddr, vaddr: usize, mut contiguous: bool) -> Option<bool> {
+        let mut entries: Vec<usize> = vec![0; PAGE_SIZE / size_of::<usize>()];
+        pmem_read(table, unsafe {
+            from_raw_parts_mut(entries.as_mut_ptr() as *mut u8, PAGE_SIZE)
+        });
+        let args = self.args;
+        let params = &args.runtime_params;
+        let (runtime_base, user_base, free_base) = (args.runtime_paddr, args.user_paddr, args.free_paddr);
+        let (epm, utm) = (&args.epm_region, &args.utm_region);
+        for (i, &pte) in entries.iter().enumerate() {
+            if pte == 0 {
+                contiguous = false;
+                continue;
+            }
+            let phys_addr = (pte >> PTE_PPN_SHIFT) << PGSHIFT;
+            let map_in_epm = Self::in_range(phys_addr, epm.paddr, epm.paddr + epm.size);
+            let map_in_utm = Self::in_range(phys_addr, utm.paddr, utm.paddr + utm.size);
+            // EPM may map anything, UTM may not map page tables
+            if !map_in_epm && (!map_in_utm || level != 1) {
+                return None;
+            }
+            // Propagate the highest bit of the virtual address
+            let vpn = if level == PGLEVEL_TOP && i & PGTABLE_HIGHEST_BIT != 0 {
+                (usize::MAX << PGLEVEL_BITS) | (i & PGLEVEL_MASK)
+            } else {
+                (vaddr << PGLEVEL_BITS) | (i & PGLEVEL_MASK)
+            };
+            let va_start = vpn << PGSHIFT;
+            if level != 1 {
+                contiguous = self.walk(level - 1, phys_addr, vpn, contiguous)?;
+                continue;
+            }
+            // Include the first virtual address of a contiguous range
+            if !contiguous {
+                self.hasher.update(va_start.to_le_bytes());
+                contiguous = true;
+            }
+            let in_runtime = Self::in_range(phys_addr, runtime_base, user_base);
+            let in_user = Self::in_range(phys_addr, user_base, free_base);
+            if in_user && pte & PTE_U == 0 {
+                return None;
+            }
+            if Self::in_range(va_start, params.untrusted_ptr, params.untrusted_ptr + params.untrusted_size) && !map_in_utm {
+                return None;
+            }
+            // Each physical page is mapped at most once, in increasing order
+            if in_runtime {
+                if phys_addr <= self.runtime_max_seen {
+                    return None;
+                }
+                self.runtime_max_seen = phys_addr;
+            } else if in_user {
+                if phys_addr <= self.user_max_seen {
+                    return None;
+                }
+                self.user_max_seen = phys_addr;
+            } else if !map_in_utm {
+                return None;
+            }
+            pmem_read(phys_addr, &mut self.page);
+            self.hasher.update(&self.page);
+        }
+        Some(contiguous)
+    }
+}
+
+/// Measure the enclave whose page table root is `root`, `None` if the EPM layout is invalid
+pub fn hash_enclave(root: PhysAddr, args: &SbiCreate) -> Option<[u8; MEASUREMENT_SIZE]> {
+    if root % PAGE_SIZE != 0 {
+        return None;
+    }
+    let mut walker = Walker {
+        hasher: Sha3_512::new(),
+        args,
+        page: vec![0; PAGE_SIZE],
+        runtime_max_seen: 0,
+        user_max_seen: 0
+    };
+    // `struct runtime_va_params_t`
+    let params = &args.runtime_params;
+    for field in [params.runtime_entry, params.user_entry, params.untrusted_ptr, params.untrusted_size] {
+        walker.hasher.update(field.to_le_bytes());
+    }
+    walker.walk(PGLEVEL_TOP, root, 0, false)?;
+    let mut hash = [0u8; MEASUREMENT_SIZE];
+    hash.copy_from_slice(&walker.hasher.finalize());
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+    use kernel_hal::mem::{pmem_write, PhysFrame};
+    use super::super::ioctl::RuntimeParams;
+    use super::super::page::{PTE_R, PTE_V, PTE_X};
+    use super::super::sbi::SbiRegion;
+
+    fn set_pte(table: PhysAddr, index: usize, pte: usize) {
+        pmem_write(table + index * size_of::<usize>(), &pte.to_le_bytes());
+    }
+
+    fn table_pte(paddr: PhysAddr) -> usize {
+        ((paddr >> PGSHIFT) << PTE_PPN_SHIFT) | PTE_V
+    }
+
+    #[test]
+    fn hash_like_security_monitor() {
+        // Root, level 2 and level 1 tables, then one runtime page mapped at 0x1000
+        let mut frames = PhysFrame::new_contiguous(4, 2);
+        assert!(!frames.is_empty());
+        frames.iter_mut().for_each(|frame| frame.zero());
+        let pa = frames[0].paddr;
+        let data = pa + 3 * PAGE_SIZE;
+        set_pte(pa, 0, table_pte(pa + PAGE_SIZE));
+        set_pte(pa + PAGE_SIZE, 0, table_pte(pa + 2 * PAGE_SIZE));
+        set_pte(pa + 2 * PAGE_SIZE, 1, table_pte(data) | PTE_R | PTE_X);
+        pmem_write(data, b"keystone");
+        let args = SbiCreate {
+            epm_region: SbiRegion { paddr: pa, size: 4 * PAGE_SIZE },
+            utm_region: SbiRegion { paddr: 0, size: 0 },
+            runtime_paddr: data,
+            user_paddr: data + PAGE_SIZE,
+            free_paddr: data + PAGE_SIZE,
+            runtime_params: RuntimeParams {
+                runtime_entry: 0x1000,
+                user_entry: 0,
+                untrusted_ptr: 0x4000_0000,
+                untrusted_size: 0
+            },
+            threads: 1
+        };
+        // SHA3-512 of the parameters, the range start and the page
+        let hash = hash_enclave(pa, &args).unwrap();
+        let hex: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(hex, "c99edd628440df523590ccbe11a06622d9398f56d9201ec10fdb14f8f60edcb3\
+                         bc0894e614af50baf2f504c24b727d69c22ecd79b8e6e752bfa472a318c5eb75");
+
+        // A physical page may only be mapped once
+        set_pte(pa + 2 * PAGE_SIZE, 2, table_pte(data) | PTE_R);
+        assert!(hash_enclave(pa, &args).is_none());
+    }
+}
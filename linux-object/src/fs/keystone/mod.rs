@@ -4,41 +4,41 @@ mod page;
 mod enclave_manager;
 mod sbi;
 mod elf_loader;
+mod emulated_sm;
+mod measure;
+mod edge_call;
+mod reserve;
+mod run;
 
 use alloc::boxed::Box;
 use async_trait::async_trait;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use lazy_static::lazy_static;
 use kernel_hal::{MMUFlags, PAGE_SIZE, PhysAddr};
 use rcore_fs::vfs::PollStatus;
-use spin::Mutex;
+use spin::{Mutex, RwLock};
 use kernel_hal::mem::PhysFrame;
 
 use zircon_object::{impl_kobject};
-use zircon_object::object::{KObjectBase, KoID, Signal};
+use zircon_object::object::{KernelObject, KObjectBase, Signal};
+use zircon_object::task::Process;
 use zircon_object::vm::{VmAddressRegion, VmObject};
 
 use crate::error::{LxError, LxResult};
-use crate::fs::keystone::enclave_manager::modify_enclave_by_id;
-use crate::fs::keystone::ioctl::ioctl;
+use crate::fs::keystone::enclave_manager::{EnclaveCharge, EnclaveManager};
+use crate::fs::keystone::ioctl::{async_ioctl, ioctl};
 use crate::fs::OpenFlags;
+use crate::sync::{wait_for_event, Event};
 use super::FileLike;
 
+pub use reserve::init_epm_reserve;
+
 /// Abstract fd for keystone driver
 pub struct Keystone {
     base: KObjectBase,
-    // path: String,
-}
-
-lazy_static! {
-    /// global fd
-    pub static ref KEYSTONE: Arc<Keystone> = {
-        Arc::new(Keystone {
-            base: KObjectBase::new(),
-            // path: "/mnt/keystone".into()
-        })
-    };
+    manager: Arc<EnclaveManager>,
+    // `NON_BLOCK` makes runs go on in the background
+    flags: RwLock<OpenFlags>,
 }
 
 pub struct MemoryRegion {
@@ -47,7 +47,14 @@ pub struct MemoryRegion {
     pub size: usize,
     pub order: usize,
     pub pa: PhysAddr,
-    pub frames: Vec<PhysFrame>
+    // Pages handed out by `alloc`
+    pub used: Vec<bool>,
+    // Owner of the pages when taken from the frame allocator
+    pub frames: Vec<PhysFrame>,
+    // Taken from the EPM reserve, given back on drop
+    pub reserved: bool,
+    // Owner of the frames when backed by a VMO, which the host can map
+    pub vmo: Option<Arc<VmObject>>
 }
 
 pub struct EnclaveParams {
@@ -59,12 +66,38 @@ pub struct EnclaveParams {
 
 pub struct Enclave {
     eid: isize,
-    // close_on_pexit: i32,
+    // Quota taken from the owning process
+    _charge: EnclaveCharge,
     epm: Arc<Mutex<MemoryRegion>>, // enclave private memory
     utm: Arc<Mutex<MemoryRegion>>, // untrusted share page
     vmar: Arc<VmAddressRegion>,
     params: EnclaveParams,
-    is_init: bool
+    is_init: bool,
+    // Entry threads, each with its own stack and part of the UTM
+    threads: Vec<EnclaveThread>,
+    // Expected measurement, known once finalized
+    hash: Option<[u8; measure::MEASUREMENT_SIZE]>,
+    attestation: Attestation
+}
+
+/// Report relayed by the eapp, when the security monitor only attests the enclave calling it
+pub enum Attestation {
+    None,
+    // The host asked for a report binding this nonce
+    Requested(Vec<u8>),
+    // The eapp passed the report for the nonce requested
+    Reported(Box<sbi::Report>)
+}
+
+/// Kernel state of one enclave thread
+#[derive(Default)]
+pub struct EnclaveThread {
+    // A host thread is running it
+    running: bool,
+    // Stopped on an edge call the host is serving
+    edge_call_pending: bool,
+    // Where a run in the background stopped, until a run call collects it
+    stopped: Option<sbi::Sbiret>
 }
 
 impl_kobject!(Keystone);
@@ -72,17 +105,21 @@ impl_kobject!(Keystone);
 #[async_trait]
 impl FileLike for Keystone {
     fn flags(&self) -> OpenFlags {
-        OpenFlags::RDWR
+        *self.flags.read()
     }
 
-    fn set_flags(&self, _f: OpenFlags) -> LxResult {
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.write();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
         Ok(())
     }
 
     fn dup(&self) -> Arc<dyn FileLike> {
         Arc::new(Keystone {
             base: KObjectBase::new(),
-            // path: "/mnt/keystone".into()
+            manager: self.manager.clone(),
+            flags: RwLock::new(self.flags()),
         })
     }
 
@@ -102,32 +139,63 @@ impl FileLike for Keystone {
         Ok(0)
     }
 
+    /// Readable while an enclave thread run in the background has stopped
     fn poll(&self) -> LxResult<PollStatus> {
         Ok(PollStatus{
-            read: false,
+            read: self.manager.has_stopped(),
             write: false,
             error: false
         })
     }
 
     async fn async_poll(&self) -> LxResult<PollStatus> {
-        Ok(PollStatus{
-            read: false,
-            write: false,
-            error: false
-        })
+        wait_for_event(self.manager.events(), Event::READABLE).await;
+        self.poll()
     }
 
     fn ioctl(&self, request: usize, arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
-        ioctl(request.into(),arg1.into())
+        ioctl(&self.manager, request.into(),arg1.into())
+    }
+
+    async fn async_ioctl(&self, request: usize, arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        async_ioctl(&self.manager, request.into(), arg1, self.flags().non_block()).await
     }
 
-    fn get_vmo(&self, _offset: usize, _len: usize) -> LxResult<Arc<VmObject>> {
-        Err(LxError::EINVAL)
+    /// The UTM of the enclave given one last on this open
+    fn get_vmo(&self, offset: usize, len: usize) -> LxResult<Arc<VmObject>> {
+        let enclave_id = self.manager.current()?;
+        self.manager.modify_enclave_by_id(enclave_id, |enclave| {
+            let utm = enclave.utm.lock();
+            let vmo = utm.vmo.as_ref().ok_or(LxError::EINVAL)?;
+            vmo.create_slice(offset, len).map_err(|_| LxError::EINVAL)
+        })
     }
 }
 
 impl Keystone {
+    /// Open the keystone device for `proc`.
+    ///
+    /// Enclaves created through it are destroyed when `proc` terminates or the last fd is closed.
+    pub fn new(proc: &Arc<Process>, flags: OpenFlags) -> Arc<Self> {
+        let manager = Arc::new(EnclaveManager::new(Arc::downgrade(proc)));
+        let weak = Arc::downgrade(&manager);
+        proc.add_signal_callback(Box::new(move |signal| {
+            if signal.contains(Signal::PROCESS_TERMINATED) {
+                if let Some(manager) = weak.upgrade() {
+                    manager.release();
+                }
+                return true;
+            }
+            false
+        }));
+        Arc::new(Keystone {
+            base: KObjectBase::new(),
+            manager,
+            flags: RwLock::new(flags),
+        })
+    }
+
+    /// Map `len` bytes of the UTM of the enclave encoded in the upper bits of `len`
     pub fn mmap(
         &self,
         addr: Option<usize>,
@@ -138,13 +206,14 @@ impl Keystone {
         let enclave_id = len >> 48;
         let align_len = (len & ((1 << 48) - 1)) / PAGE_SIZE;
         // let offset = offset / PAGE_SIZE;
-        modify_enclave_by_id(enclave_id, |enclave| {
+        self.manager.modify_enclave_by_id(enclave_id, |enclave| {
             let mut memory = enclave.utm.lock();
-            let alloc_frames = memory.alloc(align_len).unwrap();
-            // let alloc_frames: Vec<PhysFrame> = Vec::from(&memory.frames[offset..(offset + align_len)]);
+            let utm_vmo = memory.vmo.clone().ok_or(LxError::EINVAL)?;
+            // The pages stay owned by the UTM VMO, only their place is taken
+            let offset = memory.alloc_offset(align_len).ok_or(LxError::ENOMEM)?;
+            warn!("Begin to keystone map from {:x}...", memory.pa + offset);
             drop(memory);
-            warn!("Begin to keystone map from {:x}...", alloc_frames[0].paddr);
-            let vmo = VmObject::new_with_frames(align_len, alloc_frames);
+            let vmo = utm_vmo.create_slice(offset, align_len * PAGE_SIZE).map_err(|_| LxError::EINVAL)?;
             let mmu_flags = if user { MMUFlags::USER | MMUFlags::READ | MMUFlags::WRITE } else { MMUFlags::READ | MMUFlags::WRITE };
             enclave.vmar.map(addr, vmo.clone(), 0, vmo.len(), mmu_flags)?;
             warn!("Keystone map success!");
@@ -153,8 +222,3 @@ impl Keystone {
     }
 }
 
-impl Drop for Keystone {
-    fn drop(&mut self) {
-
-    }
-}
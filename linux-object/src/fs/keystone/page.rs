@@ -5,8 +5,10 @@ use spin::Mutex;
 use kernel_hal::mem::PhysFrame;
 use kernel_hal::MMUFlags;
 use kernel_hal::vm::{GenericPageTable, Page, PageSize, PagingError, PagingResult};
-use zircon_object::vm::{PAGE_SIZE, PhysAddr, VirtAddr};
+use zircon_object::vm::{PAGE_SIZE, PAGE_SIZE_LOG2, PhysAddr, VirtAddr, VmObject};
+use crate::error::{LxError, LxResult};
 use crate::fs::keystone::MemoryRegion;
+use super::reserve;
 
 
 pub const PTE_V: usize = 0x001;
@@ -27,62 +29,99 @@ fn log2(mut x: usize) -> usize {
     count
 }
 
+/// Number of pages `MemoryRegion::new(min_pages)` allocates
+pub fn region_pages(min_pages: usize) -> usize {
+    1 << (log2(min_pages) + 1)
+}
+
 impl MemoryRegion {
-    pub fn new(min_pages: usize) -> Self {
+    /// Allocate from the EPM reserve, then from the frame allocator
+    pub fn new(min_pages: usize) -> LxResult<Self> {
         let order = log2(min_pages) + 1;
-        let count = 1 << order;
-        let mut frames = PhysFrame::new_contiguous(count, order);
-        frames.iter_mut().for_each(|frame| {frame.allocated = false; } );
-        warn!("new memory region from {:x} to {:x}", frames[0].paddr, frames[frames.len()-1].paddr);
-        Self {
+        let count = region_pages(min_pages);
+        let (pa, frames, reserved) = match reserve::alloc(count, count * PAGE_SIZE) {
+            Some(pa) => (pa, Vec::new(), true),
+            None => {
+                // Owned by the region, the frames it hands out are not
+                let frames = PhysFrame::new_contiguous(count, order);
+                if frames.is_empty() {
+                    error!("no {} contiguous pages left for the memory region", count);
+                    return Err(LxError::ENOMEM);
+                }
+                (frames[0].paddr, frames, false)
+            }
+        };
+        warn!("new memory region from {:x} to {:x}", pa, pa + (count - 1) * PAGE_SIZE);
+        // May hold data of a previous enclave
+        kernel_hal::mem::pmem_zero(pa, count * PAGE_SIZE);
+        Ok(Self {
             // root_page_table: epm_vaddr,
             // ptr: epm_vaddr,
             size: count * PAGE_SIZE,
             order,
-            pa: frames[0].paddr().into(),
-            frames
-        }
+            pa,
+            used: vec![false; count],
+            frames,
+            reserved,
+            vmo: None
+        })
     }
 
-    pub fn alloc(&mut self, pages: usize) -> Option<Vec<PhysFrame>> {
-        let mut ptr = 0;
-        while ptr < self.frames.len() - pages {
-            let mut contiguous = true;
-            for index in ptr..(ptr + pages) {
-                if self.frames[index].allocated {
-                    ptr = index + 1;
-                    contiguous = false;
-                    break;
-                }
-            }
-            if contiguous {
-                let mut frames = Vec::new();
-                self.frames[ptr..(ptr + pages)].iter_mut().for_each(|frame| {
-                    frames.push(frame.clone());
-                    frame.allocated = true;
-                });
-                return Some(frames);
+    /// Same as `new`, but the frames are owned by a VMO which outlives the region while mapped
+    pub fn new_shared(min_pages: usize) -> LxResult<Self> {
+        let order = log2(min_pages) + 1;
+        let count = region_pages(min_pages);
+        let vmo = VmObject::new_contiguous(count, order + PAGE_SIZE_LOG2).map_err(|_| LxError::ENOMEM)?;
+        let pa = vmo.commit_page(0, MMUFlags::READ).map_err(|_| LxError::ENOMEM)?;
+        Ok(Self {
+            size: count * PAGE_SIZE,
+            order,
+            pa,
+            used: vec![false; count],
+            frames: Vec::new(),
+            reserved: false,
+            vmo: Some(vmo)
+        })
+    }
+
+    /// Take `pages` contiguous free pages of the region, return the offset of the first one
+    pub fn alloc_offset(&mut self, pages: usize) -> Option<usize> {
+        let last = self.used.len().checked_sub(pages);
+        let start = last.and_then(|last| (0..=last).find(|&i| self.used[i..i + pages].iter().all(|used| !used)));
+        let start = match start {
+            Some(start) => start,
+            None => {
+                error!("Unable to alloc frames with size {}!", pages);
+                return None;
             }
-        }
-        error!("Unable to alloc frames with size {}!", pages);
-        None
+        };
+        self.used[start..start + pages].iter_mut().for_each(|used| *used = true);
+        Some(start * PAGE_SIZE)
+    }
+
+    /// Take `pages` contiguous free pages of the region.
+    ///
+    /// The frames do not own their pages, which are freed with the region only.
+    pub fn alloc(&mut self, pages: usize) -> Option<Vec<PhysFrame>> {
+        let base = self.pa + self.alloc_offset(pages)?;
+        Some((0..pages).map(|i| PhysFrame {
+            paddr: base + i * PAGE_SIZE,
+            allocated: false
+        }).collect())
     }
 
     pub fn free_paddr(&self) -> Option<PhysAddr> {
-        for frame in &self.frames {
-            if !frame.allocated {
-                return Some(frame.paddr);
-            }
-        }
-        None
+        let index = self.used.iter().position(|used| !used)?;
+        Some(self.pa + index * PAGE_SIZE)
     }
 }
 
 impl Drop for MemoryRegion {
     fn drop(&mut self) {
-        self.frames.iter_mut().for_each(|frame| {
-            frame.allocated = true;
-        });
+        // Owned frames and the VMO free the other regions themselves
+        if self.reserved {
+            reserve::dealloc(self.pa..self.pa + self.size);
+        }
     }
 }
 
@@ -254,3 +293,36 @@ impl GenericPageTable for EnclavePageTable {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_region_freed_once() {
+        // Hand some frames over to the reserve, as done at boot
+        let mut frames = PhysFrame::new_contiguous(64, 6);
+        assert!(!frames.is_empty());
+        frames.iter_mut().for_each(|frame| frame.allocated = false);
+        reserve::init_epm_reserve(frames[0].paddr..frames[0].paddr + 64 * PAGE_SIZE);
+        let start = reserve::free_pages();
+
+        // Create an enclave memory with VMOs on its pages, then destroy it
+        let epm = Arc::new(Mutex::new(MemoryRegion::new(8).unwrap()));
+        let pa = epm.lock().pa;
+        assert!(epm.lock().reserved);
+        assert_eq!(reserve::free_pages(), start - region_pages(8));
+        let stack = epm.lock().alloc(4).unwrap();
+        assert!(stack.iter().all(|frame| !frame.allocated));
+        let vmo = VmObject::new_with_frames(4, stack);
+        vmo.write(0, b"enclave").unwrap();
+        let code = epm.lock().alloc(2).unwrap();
+        assert_eq!(code[0].paddr, pa + 4 * PAGE_SIZE);
+        assert_eq!(epm.lock().free_paddr(), Some(pa + 6 * PAGE_SIZE));
+        drop(code);
+        drop(vmo);
+        assert_eq!(reserve::free_pages(), start - region_pages(8));
+        drop(epm);
+        assert_eq!(reserve::free_pages(), start);
+    }
+}
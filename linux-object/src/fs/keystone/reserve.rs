@@ -0,0 +1,60 @@
+//! Contiguous physical memory set aside at boot for enclave private memory.
+//!
+//! Large EPM regions are hard to find once the frame allocator is fragmented,
+//! so they are carved from this reserve first.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use kernel_hal::{PAGE_SIZE, PhysAddr};
+use spin::Mutex;
+
+/// Free ranges of the reserve, sorted and never adjacent
+static EPM_RESERVE: Mutex<Vec<Range<PhysAddr>>> = Mutex::new(Vec::new());
+
+/// Hand `region` over to the EPM reserve, it must not be known to the frame allocator
+pub fn init_epm_reserve(region: Range<PhysAddr>) {
+    info!("keystone: EPM reserve {:#x?}", region);
+    dealloc(region);
+    info!("keystone: {} pages in the EPM reserve", free_pages());
+}
+
+/// Allocate `pages` contiguous pages aligned to `align` bytes
+pub fn alloc(pages: usize, align: usize) -> Option<PhysAddr> {
+    let size = pages * PAGE_SIZE;
+    let mut free = EPM_RESERVE.lock();
+    for i in 0..free.len() {
+        let range = free[i].clone();
+        let start = (range.start + align - 1) & !(align - 1);
+        if start < range.start || start + size > range.end {
+            continue;
+        }
+        free.remove(i);
+        if start + size < range.end {
+            free.insert(i, start + size..range.end);
+        }
+        if range.start < start {
+            free.insert(i, range.start..start);
+        }
+        return Some(start);
+    }
+    None
+}
+
+/// Number of free pages in the reserve
+pub fn free_pages() -> usize {
+    EPM_RESERVE.lock().iter().map(|r| (r.end - r.start) / PAGE_SIZE).sum()
+}
+
+/// Give `region` back to the reserve
+pub fn dealloc(region: Range<PhysAddr>) {
+    let mut free = EPM_RESERVE.lock();
+    let i = free.iter().position(|r| r.start > region.start).unwrap_or(free.len());
+    free.insert(i, region);
+    // Merge with the neighbours
+    if i + 1 < free.len() && free[i].end == free[i + 1].start {
+        free[i].end = free.remove(i + 1).end;
+    }
+    if i > 0 && free[i - 1].end == free[i].start {
+        free[i - 1].end = free.remove(i).end;
+    }
+}
@@ -0,0 +1,60 @@
+//! Enclave execution as a future.
+//!
+//! Each poll enters the enclave thread once. When a timer interrupt ends the time slice the
+//! future stays pending until the security monitor lets the thread make progress, so other
+//! zCore tasks run on the hart meanwhile.
+
+use alloc::boxed::Box;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use super::sbi::*;
+
+/// Runs the thread `tid` of the enclave `eid` until it leaves for the host
+#[must_use = "future does nothing unless polled/`await`-ed"]
+pub struct EnclaveRun {
+    eid: usize,
+    tid: usize,
+    resume: bool,
+    // Pending while the thread cannot make progress
+    wait: Option<Pin<Box<dyn Future<Output = ()> + Send>>>
+}
+
+impl EnclaveRun {
+    /// Start the thread, or resume it after an edge call if `resume`
+    pub fn new(eid: usize, tid: usize, resume: bool) -> Self {
+        EnclaveRun {
+            eid,
+            tid,
+            resume,
+            wait: None
+        }
+    }
+}
+
+impl Future for EnclaveRun {
+    type Output = Sbiret;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        loop {
+            if let Some(wait) = self.wait.as_mut() {
+                if wait.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.wait = None;
+            }
+            let ret = if self.resume {
+                sbi_sm_resume_enclave(self.eid, self.tid)
+            } else {
+                sbi_sm_run_enclave(self.eid, self.tid)
+            };
+            // Once started, the thread is only ever resumed
+            self.resume = true;
+            if ret.error != SBI_ERR_SM_ENCLAVE_INTERRUPTED {
+                return Poll::Ready(ret);
+            }
+            handle_enclave_interrupt();
+            self.wait = Some(Box::pin(wait_enclave_thread(self.eid, self.tid)));
+        }
+    }
+}
@@ -1,10 +1,39 @@
+use alloc::boxed::Box;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use super::emulated_sm::EmulatedMonitor;
 use super::ioctl::RuntimeParams;
 
-const KEYSTONE_SBI_EXT_ID: usize = 0x08424b45;
-const SBI_SM_CREATE_ENCLAVE: usize = 2001;
-const SBI_SM_DESTROY_ENCLAVE: usize = 2002;
-const SBI_SM_RUN_ENCLAVE: usize = 2003;
-const SBI_SM_RESUME_ENCLAVE: usize = 2005;
+#[cfg(all(target_arch = "riscv64", target_os = "none"))]
+mod fid {
+    pub const KEYSTONE_SBI_EXT_ID: usize = 0x08424b45;
+    pub const SBI_SM_CREATE_ENCLAVE: usize = 2001;
+    pub const SBI_SM_DESTROY_ENCLAVE: usize = 2002;
+    pub const SBI_SM_RUN_ENCLAVE: usize = 2003;
+    pub const SBI_SM_RESUME_ENCLAVE: usize = 2005;
+}
+#[cfg(all(target_arch = "riscv64", target_os = "none"))]
+use fid::*;
+
+pub const MDSIZE: usize = 64;
+pub const SIGNATURE_SIZE: usize = 64;
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const ATTEST_DATA_MAXLEN: usize = 1024;
+
+// Error codes of the security monitor (sm_err.h)
+pub const SBI_ERR_SM_ENCLAVE_SUCCESS: i32 = 0;
+pub const SBI_ERR_SM_ENCLAVE_UNKNOWN_ERROR: i32 = 100000;
+pub const SBI_ERR_SM_ENCLAVE_INVALID_ID: i32 = 100001;
+pub const SBI_ERR_SM_ENCLAVE_INTERRUPTED: i32 = 100002;
+pub const SBI_ERR_SM_ENCLAVE_NOT_RUNNABLE: i32 = 100004;
+pub const SBI_ERR_SM_ENCLAVE_NOT_DESTROYABLE: i32 = 100005;
+pub const SBI_ERR_SM_ENCLAVE_REGION_OVERLAPS: i32 = 100006;
+pub const SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT: i32 = 100008;
+pub const SBI_ERR_SM_ENCLAVE_NOT_RESUMABLE: i32 = 100010;
+pub const SBI_ERR_SM_ENCLAVE_EDGE_CALL_HOST: i32 = 100011;
+pub const SBI_ERR_SM_ENCLAVE_NO_FREE_RESOURCE: i32 = 100013;
+pub const SBI_ERR_SM_ENCLAVE_ILLEGAL_PTE: i32 = 100015;
+pub const SBI_ERR_SM_NOT_IMPLEMENTED: i32 = 100100;
 
 pub struct Sbiret {
     pub error: i32,
@@ -25,7 +54,66 @@ pub struct SbiCreate {
     pub user_paddr: usize,
     pub free_paddr: usize,
     // Parameters
-    pub runtime_params: RuntimeParams
+    pub runtime_params: RuntimeParams,
+    // Entry threads, each given an equal part of the UTM for edge calls
+    pub threads: usize
+}
+
+/// Enclave part of the attestation report, signed by the security monitor
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct EnclaveReport {
+    pub hash: [u8; MDSIZE],
+    pub data_len: u64,
+    pub data: [u8; ATTEST_DATA_MAXLEN],
+    pub signature: [u8; SIGNATURE_SIZE]
+}
+
+/// Security monitor part of the attestation report, signed by the device key
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SmReport {
+    pub hash: [u8; MDSIZE],
+    pub public_key: [u8; PUBLIC_KEY_SIZE],
+    pub signature: [u8; SIGNATURE_SIZE]
+}
+
+/// Signed report returned by `SBI_SM_ATTEST_ENCLAVE`, laid out as `struct report` of Keystone
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Report {
+    pub enclave: EnclaveReport,
+    pub sm: SmReport,
+    pub dev_public_key: [u8; PUBLIC_KEY_SIZE]
+}
+
+impl Report {
+    pub fn empty() -> Self {
+        Report {
+            enclave: EnclaveReport {
+                hash: [0; MDSIZE],
+                data_len: 0,
+                data: [0; ATTEST_DATA_MAXLEN],
+                signature: [0; SIGNATURE_SIZE]
+            },
+            sm: SmReport {
+                hash: [0; MDSIZE],
+                public_key: [0; PUBLIC_KEY_SIZE],
+                signature: [0; SIGNATURE_SIZE]
+            },
+            dev_public_key: [0; PUBLIC_KEY_SIZE]
+        }
+    }
+}
+
+impl Sbiret {
+    pub fn new(error: i32, value: i32) -> Self {
+        Sbiret { error, value }
+    }
+
+    pub fn error(error: i32) -> Self {
+        Sbiret { error, value: 0 }
+    }
 }
 
 impl From<u64> for Sbiret {
@@ -37,14 +125,97 @@ impl From<u64> for Sbiret {
     }
 }
 
+/// Backend of the `sbi_sm_*` calls issued by the keystone driver
+///
+/// Run and resume act on one thread `tid` of the enclave, several threads may run at once.
+#[async_trait]
+pub trait SecurityMonitor: Send + Sync {
+    fn create_enclave(&self, args: &SbiCreate) -> Sbiret;
+    fn destroy_enclave(&self, eid: usize) -> Sbiret;
+    fn run_enclave(&self, eid: usize, tid: usize) -> Sbiret;
+    fn resume_enclave(&self, eid: usize, tid: usize) -> Sbiret;
+    /// Most entry threads an enclave may be created with
+    fn max_threads(&self) -> usize {
+        usize::MAX
+    }
+    /// Sign a report of the enclave `eid` for the host, binding `data` to it
+    fn attest_enclave(&self, eid: usize, report: &mut Report, data: &[u8]) -> Sbiret;
+    /// Called when a run stopped with `SBI_ERR_SM_ENCLAVE_INTERRUPTED`, before resuming
+    fn handle_interrupt(&self) {}
+    /// Wait until resuming the thread `tid` may make progress, letting other tasks run
+    async fn wait_thread(&self, _eid: usize, _tid: usize) {
+        kernel_hal::thread::yield_now().await
+    }
+}
+
+/// Security monitor reached through the Keystone SBI extension of the firmware
+#[cfg(all(target_arch = "riscv64", target_os = "none"))]
+pub struct SbiMonitor;
+
+#[cfg(all(target_arch = "riscv64", target_os = "none"))]
+#[async_trait]
+impl SecurityMonitor for SbiMonitor {
+    fn create_enclave(&self, args: &SbiCreate) -> Sbiret {
+        sbi_call(KEYSTONE_SBI_EXT_ID,
+        SBI_SM_CREATE_ENCLAVE,
+        args as *const SbiCreate as usize, 0, 0, 0).into()
+    }
+
+    fn destroy_enclave(&self, eid: usize) -> Sbiret {
+        sbi_call(KEYSTONE_SBI_EXT_ID,
+        SBI_SM_DESTROY_ENCLAVE,
+        eid, 0, 0, 0).into()
+    }
+
+    /// Keystone enclaves have a single thread
+    fn max_threads(&self) -> usize {
+        1
+    }
+
+    fn run_enclave(&self, eid: usize, tid: usize) -> Sbiret {
+        if tid != 0 {
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT);
+        }
+        sbi_call(KEYSTONE_SBI_EXT_ID,
+        SBI_SM_RUN_ENCLAVE,
+        eid, 0, 0, 0).into()
+    }
+
+    fn resume_enclave(&self, eid: usize, tid: usize) -> Sbiret {
+        if tid != 0 {
+            return Sbiret::error(SBI_ERR_SM_ENCLAVE_ILLEGAL_ARGUMENT);
+        }
+        sbi_call(KEYSTONE_SBI_EXT_ID,
+        SBI_SM_RESUME_ENCLAVE,
+        eid, 0, 0, 0).into()
+    }
+
+    /// `SBI_SM_ATTEST_ENCLAVE` (3002) is an enclave call: the monitor attests the enclave
+    /// running on the hart, taking `(report, data, size)` as enclave physical addresses
+    /// inside its EPM, which the host cannot provide. The eapp gets the report through
+    /// the `attest_enclave` syscall of its runtime and relays it by the attestation edge calls.
+    fn attest_enclave(&self, _eid: usize, _report: &mut Report, _data: &[u8]) -> Sbiret {
+        Sbiret::error(SBI_ERR_SM_NOT_IMPLEMENTED)
+    }
+
+    fn handle_interrupt(&self) {
+        // Let the kernel take the timer interrupt that stopped the enclave
+        unsafe {
+            core::arch::asm!("csrsi sstatus, 2", "csrci sstatus, 2");
+        }
+    }
+}
+
+#[cfg(all(target_arch = "riscv64", target_os = "none"))]
 #[inline(always)]
-fn sbi_call(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> u64 {
+fn sbi_call(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> u64 {
     let ret: u64;
     unsafe {
         core::arch::asm!("ecall",
         in("a0") arg0,
         in("a1") arg1,
         in("a2") arg2,
+        in("a3") arg3,
         in("a6") fid,
         in("a7") eid,
         lateout("a0") ret,
@@ -53,26 +224,66 @@ fn sbi_call(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> u6
     ret
 }
 
-pub fn sbi_sm_create_enclave(args: usize) -> u64 {
-    sbi_call(KEYSTONE_SBI_EXT_ID,
-    SBI_SM_CREATE_ENCLAVE,
-    args, 0, 0)
+/// Whether the firmware implements the Keystone SBI extension (`sbi_probe_extension`)
+#[cfg(all(target_arch = "riscv64", target_os = "none"))]
+fn keystone_sbi_available() -> bool {
+    const SBI_EXT_BASE: usize = 0x10;
+    const SBI_EXT_BASE_PROBE_EXT: usize = 3;
+    let error: isize;
+    let value: usize;
+    unsafe {
+        core::arch::asm!("ecall",
+        inlateout("a0") KEYSTONE_SBI_EXT_ID => error,
+        lateout("a1") value,
+        in("a6") SBI_EXT_BASE_PROBE_EXT,
+        in("a7") SBI_EXT_BASE,
+        );
+    };
+    error == 0 && value != 0
+}
+
+fn select_monitor() -> Box<dyn SecurityMonitor> {
+    #[cfg(all(target_arch = "riscv64", target_os = "none"))]
+    if keystone_sbi_available() {
+        info!("keystone: using the firmware security monitor");
+        return Box::new(SbiMonitor);
+    }
+    warn!("keystone: no Keystone SBI extension, using the emulated security monitor");
+    Box::new(EmulatedMonitor::new())
+}
+
+lazy_static! {
+    static ref SECURITY_MONITOR: Box<dyn SecurityMonitor> = select_monitor();
+}
+
+pub fn sbi_sm_create_enclave(args: &SbiCreate) -> Sbiret {
+    SECURITY_MONITOR.create_enclave(args)
+}
+
+pub fn sbi_sm_run_enclave(eid: usize, tid: usize) -> Sbiret {
+    SECURITY_MONITOR.run_enclave(eid, tid)
+}
+
+pub fn sbi_sm_destroy_enclave(eid: usize) -> Sbiret {
+    SECURITY_MONITOR.destroy_enclave(eid)
+}
+
+pub fn sbi_sm_resume_enclave(eid: usize, tid: usize) -> Sbiret {
+    SECURITY_MONITOR.resume_enclave(eid, tid)
+}
+
+pub fn sbi_sm_attest_enclave(eid: usize, report: &mut Report, data: &[u8]) -> Sbiret {
+    SECURITY_MONITOR.attest_enclave(eid, report, data)
 }
 
-pub fn sbi_sm_run_enclave(eid: usize) -> u64 {
-    sbi_call(KEYSTONE_SBI_EXT_ID,
-    SBI_SM_RUN_ENCLAVE,
-    eid, 0, 0)
+pub fn sbi_sm_max_threads() -> usize {
+    SECURITY_MONITOR.max_threads()
 }
 
-pub fn sbi_sm_destroy_enclave(eid: usize) -> u64 {
-    sbi_call(KEYSTONE_SBI_EXT_ID,
-    SBI_SM_DESTROY_ENCLAVE,
-    eid, 0, 0)
+pub fn handle_enclave_interrupt() {
+    SECURITY_MONITOR.handle_interrupt()
 }
 
-pub fn sbi_sm_resume_enclave(eid: usize) -> u64 {
-    sbi_call(KEYSTONE_SBI_EXT_ID,
-    SBI_SM_RESUME_ENCLAVE,
-    eid, 0, 0)
+pub async fn wait_enclave_thread(eid: usize, tid: usize) {
+    SECURITY_MONITOR.wait_thread(eid, tid).await
 }
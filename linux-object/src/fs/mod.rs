@@ -1,41 +1,63 @@
 //! Linux file objects
 
 mod devfs;
+mod epoll;
+mod eventfd;
 mod keystone;
 mod file;
 mod ioctl;
+mod mount;
 mod pipe;
+mod procfs;
 mod pseudo;
 mod stdio;
+mod timerfd;
+mod tty;
 
 pub mod rcore_fs_wrapper;
 
-use alloc::{boxed::Box, string::ToString, sync::Arc, vec::Vec};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use core::convert::TryFrom;
 
 use async_trait::async_trait;
 use downcast_rs::impl_downcast;
 
 use kernel_hal::drivers;
-use rcore_fs::vfs::{FileSystem, FileType, INode, PollStatus, Result};
+use rcore_fs::vfs::{FileType, INode, PollStatus, Result};
 use rcore_fs_devfs::{
     DevFS,
     special::{NullINode, ZeroINode},
 };
-use rcore_fs_mountfs::MountFS;
-use rcore_fs_ramfs::RamFS;
 use zircon_object::{object::KernelObject, vm::VmObject};
 
+use crate::cred::Access;
 use crate::error::{LxError, LxResult};
 use crate::process::LinuxProcess;
+use crate::sync::EventHandler;
 use devfs::RandomINode;
 use pseudo::Pseudo;
 
+pub use devfs::KeystoneDev;
+pub use epoll::{
+    EpollCreateFlags, EpollCtlOp, EpollEvent, EpollEvents, EpollInstance, EpollTarget,
+};
+pub use eventfd::{EventFd, EventFdFlags};
 pub use file::{File, OpenFlags, SeekFrom};
+pub use mount::{
+    mount_flags, register_filesystem, FileSystemType, FsConstructor, Mount, MountFlags,
+    MountTable,
+};
 pub use pipe::Pipe;
 pub use rcore_fs::vfs;
 pub use stdio::{STDIN, STDOUT};
-pub use keystone::KEYSTONE;
+pub use timerfd::{TimerFd, TimerFdFlags, TimerFdSetFlags};
+pub use tty::{Tty, CONSOLE_TTY};
+pub use keystone::{init_epm_reserve, Keystone};
 
 #[async_trait]
 /// Generic file interface
@@ -54,6 +76,10 @@ pub trait FileLike: KernelObject {
     async fn read(&self, buf: &mut [u8]) -> LxResult<usize>;
     /// write from buffer
     fn write(&self, buf: &[u8]) -> LxResult<usize>;
+    /// write from buffer, may wait until the file can take it
+    async fn async_write(&self, buf: &[u8]) -> LxResult<usize> {
+        self.write(buf)
+    }
     /// read to buffer at given offset
     async fn read_at(&self, offset: u64, buf: &mut [u8]) -> LxResult<usize>;
     /// write from buffer at given offset
@@ -62,8 +88,18 @@ pub trait FileLike: KernelObject {
     fn poll(&self) -> LxResult<PollStatus>;
     /// wait for some event on a file descriptor use async
     async fn async_poll(&self) -> LxResult<PollStatus>;
+    /// Call `callback` each time the events of the file change, until it returns true.
+    ///
+    /// Returns false if the file does not report its changes.
+    fn subscribe(&self, _callback: EventHandler) -> bool {
+        false
+    }
     /// manipulates the underlying device parameters of special files
     fn ioctl(&self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> LxResult<usize>;
+    /// manipulates the underlying device parameters of special files, may wait for the device
+    async fn async_ioctl(&self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> LxResult<usize> {
+        self.ioctl(request, arg1, arg2, arg3)
+    }
     /// Returns the [`VmObject`] representing the file with given `offset` and `len`.
     fn get_vmo(&self, offset: usize, len: usize) -> LxResult<Arc<VmObject>>;
 }
@@ -111,12 +147,8 @@ impl From<FileDesc> for i32 {
     }
 }
 
-/// create root filesystem, mount DevFS and RamFS
-pub fn create_root_fs(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
-    let rootfs = MountFS::new(rootfs);
-    let root = rootfs.mountpoint_root_inode();
-
-    // create DevFS
+/// create DevFS with the device files
+fn create_devfs() -> Arc<DevFS> {
     let devfs = DevFS::new();
     let devfs_root = devfs.root();
     devfs_root
@@ -131,6 +163,9 @@ pub fn create_root_fs(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
     devfs_root
         .add("urandom", Arc::new(RandomINode::new(true)))
         .expect("failed to mknod /dev/urandom");
+    devfs_root
+        .add("keystone", Arc::new(KeystoneDev::new()))
+        .expect("failed to mknod /dev/keystone");
 
     if let Some(display) = drivers::all_display().first() {
         use devfs::{EventDev, FbDev, MiceDev};
@@ -161,30 +196,20 @@ pub fn create_root_fs(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
         }
     }
 
-    // Add uart devices at `/dev/ttyS{i}`
+    // Add uart devices at `/dev/ttyS{i}`, the first one is the console
     for (i, uart) in drivers::all_uart().as_vec().iter().enumerate() {
         let fname = format!("ttyS{}", i);
-        if let Err(e) = devfs_root.add(&fname, Arc::new(devfs::UartDev::new(i, uart.clone()))) {
+        let tty = match i {
+            0 => CONSOLE_TTY.clone(),
+            _ => Arc::default(),
+        };
+        let uart = devfs::UartDev::new(i, uart.clone(), tty);
+        if let Err(e) = devfs_root.add(&fname, Arc::new(uart)) {
             warn!("failed to mknod /dev/{}: {:?}", &fname, e);
         }
     }
 
-    // mount DevFS at /dev
-    let dev = root.find(true, "dev").unwrap_or_else(|_| {
-        root.create("dev", FileType::Dir, 0o666)
-            .expect("failed to mkdir /dev")
-    });
-    dev.mount(devfs).expect("failed to mount DevFS");
-
-    // mount RamFS at /tmp
-    let ramfs = RamFS::new();
-    let tmp = root.find(true, "tmp").unwrap_or_else(|_| {
-        root.create("tmp", FileType::Dir, 0o666)
-            .expect("failed to mkdir /tmp")
-    });
-    tmp.mount(ramfs).expect("failed to mount RamFS");
-
-    root
+    devfs
 }
 
 /// extension for INode
@@ -224,6 +249,18 @@ impl LinuxProcess {
         path: &str,
         follow: bool,
     ) -> LxResult<Arc<dyn INode>> {
+        self.lookup_inode_path_at(dirfd, path, follow)
+            .map(|(inode, _)| inode)
+    }
+
+    /// Lookup INode from the process like `lookup_inode_at`, also returning its absolute
+    /// path with the symbolic links resolved.
+    pub fn lookup_inode_path_at(
+        &self,
+        dirfd: FileDesc,
+        path: &str,
+        follow: bool,
+    ) -> LxResult<(Arc<dyn INode>, String)> {
         debug!(
             "lookup_inode_at: dirfd: {:?}, cwd: {:?}, path: {:?}, follow: {:?}",
             dirfd,
@@ -231,30 +268,82 @@ impl LinuxProcess {
             path,
             follow
         );
-        // hard code special path
-        if path == "/proc/self/exe" {
-            return Ok(Arc::new(Pseudo::new(
-                &self.execute_path(),
-                FileType::SymLink,
-            )));
-        }
-        let (fd_dir_path, fd_name) = split_path(path);
-        if fd_dir_path == "/proc/self/fd" {
-            let fd = FileDesc::try_from(fd_name)?;
-            let file = self.get_file(fd)?;
-            return Ok(Arc::new(Pseudo::new(file.path(), FileType::SymLink)));
-        }
-
-        let follow_max_depth = if follow { FOLLOW_MAX_DEPTH } else { 0 };
-        if dirfd == FileDesc::CWD {
-            Ok(self
-                .root_inode()
-                .lookup(&self.current_working_directory())?
-                .lookup_follow(path, follow_max_depth)?)
+        // the absolute path of the directory being walked, to tell where /proc/self is
+        let mut at = Vec::new();
+        let start = if path.starts_with('/') {
+            self.root_inode().clone()
+        } else if dirfd == FileDesc::CWD {
+            let cwd = self.current_working_directory();
+            self.walk_path(self.root_inode().clone(), &mut at, &cwd, true, &mut 0)?
         } else {
             let file = self.get_file(dirfd)?;
-            Ok(file.lookup_follow(path, follow_max_depth)?)
+            let dir_path = self.absolute_path(file.path());
+            let names = dir_path.split('/').filter(|name| !name.is_empty());
+            at.extend(names.map(String::from));
+            file.inode()
+        };
+        let inode = self.walk_path(start, &mut at, path, follow, &mut 0)?;
+        Ok((inode, format!("/{}", at.join("/"))))
+    }
+
+    /// Walk `path` component by component from the directory `dir`, whose absolute path is `at`.
+    ///
+    /// Symbolic links met on the way are always followed, the last component only
+    /// if `follow` is true or `path` ends with a slash, in which case it must also be
+    /// a directory. `hops` counts the links followed so far in the whole lookup.
+    /// Every directory searched needs execute permission.
+    fn walk_path(
+        &self,
+        mut dir: Arc<dyn INode>,
+        at: &mut Vec<String>,
+        path: &str,
+        follow: bool,
+        hops: &mut usize,
+    ) -> LxResult<Arc<dyn INode>> {
+        if path.starts_with('/') {
+            dir = self.root_inode().clone();
+            at.clear();
         }
+        let trailing_slash = path.len() > 1 && path.ends_with('/');
+        let follow = follow || trailing_slash;
+        let cred = self.credentials();
+        let mut names = path.split('/').filter(|name| !name.is_empty()).peekable();
+        while let Some(name) = names.next() {
+            let dir_info = dir.metadata()?;
+            if dir_info.type_ == FileType::Dir {
+                cred.check_access(&dir_info, Access::EXECUTE)?;
+            }
+            let inode: Arc<dyn INode> = if name == "self" && at.len() == 1 && at[0] == "proc" {
+                // /proc/self links to the directory of the calling process in procfs
+                let pid = self.pid().to_string();
+                Arc::new(Pseudo::new(&pid, FileType::SymLink))
+            } else {
+                dir.find(name)?
+            };
+            let is_last = names.peek().is_none();
+            if inode.metadata()?.type_ == FileType::SymLink && (follow || !is_last) {
+                *hops += 1;
+                if *hops > MAX_SYMLINK_HOPS {
+                    return Err(LxError::ELOOP);
+                }
+                let target = read_link(&inode)?;
+                // a relative target is resolved from the directory holding the link
+                dir = self.walk_path(dir, at, &target, true, hops)?;
+            } else {
+                dir = inode;
+                match name {
+                    "." => {}
+                    ".." => {
+                        at.pop();
+                    }
+                    _ => at.push(String::from(name)),
+                }
+            }
+        }
+        if trailing_slash && dir.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        Ok(dir)
     }
 
     /// Lookup INode from the process.
@@ -276,5 +365,19 @@ pub fn split_path(path: &str) -> (&str, &str) {
     (dir_path, file_name)
 }
 
-/// the max depth for following a link
-const FOLLOW_MAX_DEPTH: usize = 1;
+/// Read the target of the symbolic link `inode`.
+pub fn read_link(inode: &Arc<dyn INode>) -> LxResult<String> {
+    let mut buf = vec![0; PATH_MAX];
+    let len = inode.read_at(0, &mut buf)?;
+    if len == 0 {
+        return Err(LxError::ENOENT);
+    }
+    buf.truncate(len);
+    String::from_utf8(buf).map_err(|_| LxError::ENOENT)
+}
+
+/// the max length of a path, including the terminating null byte
+const PATH_MAX: usize = 4096;
+
+/// the max number of symbolic links followed in a single lookup
+const MAX_SYMLINK_HOPS: usize = 40;
@@ -0,0 +1,472 @@
+//! Mount table and filesystem types
+#![deny(missing_docs)]
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use kernel_hal::drivers;
+use lazy_static::lazy_static;
+use rcore_fs::dev::Device;
+use rcore_fs::vfs::{FileSystem, FileType, FsInfo, INode, Result};
+use rcore_fs_mountfs::{MNode, MountFS};
+use rcore_fs_ramfs::RamFS;
+use rcore_fs_sfs::SimpleFileSystem;
+use spin::RwLock;
+use zircon_object::task::Job;
+
+use super::create_devfs;
+use super::procfs::create_procfs;
+use super::rcore_fs_wrapper::{Block, BlockCache};
+use crate::error::{LxError, LxResult};
+
+bitflags::bitflags! {
+    /// Flags of mount(2), the per-mount ones are reported by statfs(2)
+    pub struct MountFlags: usize {
+        /// Mount read-only
+        const RDONLY = 1;
+        /// Ignore set-user-ID and set-group-ID bits
+        const NOSUID = 1 << 1;
+        /// Disallow access to device special files
+        const NODEV = 1 << 2;
+        /// Disallow program execution
+        const NOEXEC = 1 << 3;
+        /// Writes are synced at once
+        const SYNCHRONOUS = 1 << 4;
+        /// Alter flags of a mounted filesystem
+        const REMOUNT = 1 << 5;
+        /// Allow mandatory locks
+        const MANDLOCK = 1 << 6;
+        /// Directory modifications are synchronous
+        const DIRSYNC = 1 << 7;
+        /// Do not update access times
+        const NOATIME = 1 << 10;
+        /// Do not update directory access times
+        const NODIRATIME = 1 << 11;
+        /// Bind directory at different place
+        const BIND = 1 << 12;
+        /// Move a subtree
+        const MOVE = 1 << 13;
+        /// Suppress some warning messages
+        const SILENT = 1 << 15;
+        /// Update access times relative to modification times
+        const RELATIME = 1 << 21;
+    }
+}
+
+impl MountFlags {
+    /// Flags kept by a mount
+    const PER_MOUNT: Self = Self {
+        bits: Self::RDONLY.bits
+            | Self::NOSUID.bits
+            | Self::NODEV.bits
+            | Self::NOEXEC.bits
+            | Self::SYNCHRONOUS.bits
+            | Self::MANDLOCK.bits
+            | Self::DIRSYNC.bits
+            | Self::NOATIME.bits
+            | Self::NODIRATIME.bits
+            | Self::RELATIME.bits,
+    };
+
+    /// The options column of /proc/mounts
+    fn options(&self) -> String {
+        let mut options = String::from(if self.contains(Self::RDONLY) {
+            "ro"
+        } else {
+            "rw"
+        });
+        for (flag, name) in [
+            (Self::NOSUID, "nosuid"),
+            (Self::NODEV, "nodev"),
+            (Self::NOEXEC, "noexec"),
+            (Self::SYNCHRONOUS, "sync"),
+            (Self::MANDLOCK, "mand"),
+            (Self::DIRSYNC, "dirsync"),
+            (Self::NOATIME, "noatime"),
+            (Self::NODIRATIME, "nodiratime"),
+            (Self::RELATIME, "relatime"),
+        ] {
+            if self.contains(flag) {
+                options += ",";
+                options += name;
+            }
+        }
+        options
+    }
+}
+
+/// Magic number in the upper bits of mount(2) flags, which used to be required
+const MS_MGC_VAL: usize = 0xc0ed_0000;
+/// Mask of the magic number
+const MS_MGC_MSK: usize = 0xffff_0000;
+
+/// Create a filesystem to mount from the `source` and `data` arguments of mount(2)
+pub type FsConstructor =
+    fn(mounts: &Arc<MountTable>, source: &str, data: &str) -> LxResult<Arc<dyn FileSystem>>;
+
+/// A type of filesystem which can be mounted
+#[derive(Clone)]
+pub struct FileSystemType {
+    /// name given to mount(2)
+    pub name: &'static str,
+    /// magic number reported by statfs(2)
+    pub magic: i64,
+    /// whether it is not backed by a device
+    pub nodev: bool,
+    /// create an instance of the filesystem
+    pub mount: FsConstructor,
+}
+
+lazy_static! {
+    static ref FS_TYPES: RwLock<Vec<FileSystemType>> = RwLock::new(vec![
+        FileSystemType {
+            name: "ramfs",
+            magic: 0x8584_58f6,
+            nodev: true,
+            mount: |_, _, _| Ok(RamFS::new()),
+        },
+        FileSystemType {
+            name: "tmpfs",
+            magic: 0x0102_1994,
+            nodev: true,
+            mount: |_, _, _| Ok(RamFS::new()),
+        },
+        FileSystemType {
+            name: "devfs",
+            magic: 0x1373,
+            nodev: true,
+            mount: |_, _, _| Ok(create_devfs()),
+        },
+        FileSystemType {
+            name: "proc",
+            magic: 0x9fa0,
+            nodev: true,
+            mount: |mounts, _, _| Ok(create_procfs(mounts)),
+        },
+        FileSystemType {
+            name: "sfs",
+            magic: 0x2f8d_be2a,
+            nodev: false,
+            mount: |_, source, _| {
+                let block = block_device(source)?;
+                let device: Arc<dyn Device> = Arc::new(BlockCache::new(Block::new(block), 0x100));
+                Ok(SimpleFileSystem::open(device)?)
+            },
+        },
+    ]);
+}
+
+/// Register a type of filesystem, replacing the one with the same name
+pub fn register_filesystem(fs_type: FileSystemType) {
+    let mut fs_types = FS_TYPES.write();
+    fs_types.retain(|t| t.name != fs_type.name);
+    fs_types.push(fs_type);
+}
+
+/// Get a registered type of filesystem
+fn filesystem_type(name: &str) -> Option<FileSystemType> {
+    FS_TYPES.read().iter().find(|t| t.name == name).cloned()
+}
+
+/// The content of /proc/filesystems
+pub fn proc_filesystems() -> String {
+    let mut content = String::new();
+    for fs_type in FS_TYPES.read().iter() {
+        let nodev = if fs_type.nodev { "nodev" } else { "" };
+        writeln!(content, "{}\t{}", nodev, fs_type.name).unwrap();
+    }
+    content
+}
+
+/// Get the block device `/dev/blkN`, the N-th one found by the drivers
+fn block_device(source: &str) -> LxResult<Arc<dyn drivers::scheme::BlockScheme>> {
+    let index: usize = source
+        .strip_prefix("/dev/blk")
+        .ok_or(LxError::ENOTBLK)?
+        .parse()
+        .map_err(|_| LxError::ENOTBLK)?;
+    drivers::all_block()
+        .as_vec()
+        .get(index)
+        .cloned()
+        .ok_or(LxError::ENXIO)
+}
+
+/// A filesystem mounted on a directory, which shows the directory again once unmounted
+struct Mounted {
+    fs: RwLock<Option<Arc<dyn FileSystem>>>,
+    covered: Arc<dyn INode>,
+}
+
+impl FileSystem for Mounted {
+    fn sync(&self) -> Result<()> {
+        match &*self.fs.read() {
+            Some(fs) => fs.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        match &*self.fs.read() {
+            Some(fs) => fs.root_inode(),
+            None => self.covered.clone(),
+        }
+    }
+
+    fn info(&self) -> FsInfo {
+        match &*self.fs.read() {
+            Some(fs) => fs.info(),
+            None => self.covered.fs().info(),
+        }
+    }
+}
+
+/// An entry of the mount table
+#[derive(Clone)]
+pub struct Mount {
+    /// unique ID of the mount
+    pub id: usize,
+    /// device or name of the mounted filesystem
+    pub source: String,
+    /// absolute path of the mount point
+    pub target: String,
+    /// filesystem type
+    pub fstype: String,
+    /// magic number of the filesystem type
+    pub magic: i64,
+    /// per-mount flags
+    pub flags: MountFlags,
+    /// filesystem specific options
+    pub data: String,
+    /// the view of the filesystem in the tree, `fs()` of the inodes in it
+    vfs: Arc<MountFS>,
+    /// `None` for the root filesystem
+    mounted: Option<Arc<Mounted>>,
+}
+
+/// Filesystems mounted in a directory tree, see [linux man mount(2)](https://www.man7.org/linux/man-pages/man2/mount.2.html)
+pub struct MountTable {
+    root: Arc<MountFS>,
+    mounts: RwLock<Vec<Mount>>,
+    /// job of the processes shown in procfs
+    job: Weak<Job>,
+}
+
+impl MountTable {
+    /// Create a tree on `rootfs`, with DevFS at /dev, RamFS at /tmp and procfs at /proc
+    /// showing the processes in `job`
+    pub fn new(rootfs: Arc<dyn FileSystem>, job: &Arc<Job>) -> Arc<Self> {
+        let root = MountFS::new(rootfs);
+        let table = Arc::new(MountTable {
+            root: root.clone(),
+            mounts: RwLock::new(vec![Mount {
+                id: 0,
+                source: String::from("rootfs"),
+                target: String::from("/"),
+                fstype: String::from("rootfs"),
+                magic: 0,
+                flags: MountFlags::empty(),
+                data: String::new(),
+                vfs: root,
+                mounted: None,
+            }]),
+            job: Arc::downgrade(job),
+        });
+        for (source, target, fstype) in [
+            ("devfs", "/dev", "devfs"),
+            ("tmpfs", "/tmp", "tmpfs"),
+            ("proc", "/proc", "proc"),
+        ] {
+            table.create_dir(target);
+            if let Err(e) = table.mount(source, target, fstype, MountFlags::empty(), "") {
+                warn!("failed to mount {} at {}: {:?}", fstype, target, e);
+            }
+        }
+        table
+    }
+
+    /// Get the root INode of the tree.
+    pub fn root_inode(&self) -> Arc<dyn INode> {
+        self.root.root_inode()
+    }
+
+    /// Get the job of the processes shown in procfs.
+    pub(super) fn job(&self) -> Weak<Job> {
+        self.job.clone()
+    }
+
+    /// Create the directory of a default mount point if it does not exist
+    fn create_dir(&self, path: &str) {
+        let root = self.root_inode();
+        let name = path.trim_start_matches('/');
+        if root.find(name).is_err() {
+            if let Err(e) = root.create(name, FileType::Dir, 0o755) {
+                warn!("failed to mkdir {}: {:?}", path, e);
+            }
+        }
+    }
+
+    /// Mount a filesystem of type `fstype` at the absolute path `target`.
+    ///
+    /// With [`MountFlags::REMOUNT`], the flags and data of the mount at `target` are changed.
+    pub fn mount(
+        self: &Arc<Self>,
+        source: &str,
+        target: &str,
+        fstype: &str,
+        flags: MountFlags,
+        data: &str,
+    ) -> LxResult {
+        if flags.contains(MountFlags::REMOUNT) {
+            let mut mounts = self.mounts.write();
+            let mount = mounts
+                .iter_mut()
+                .rev()
+                .find(|m| m.target == target)
+                .ok_or(LxError::EINVAL)?;
+            mount.flags = flags & MountFlags::PER_MOUNT;
+            mount.data = data.to_string();
+            return Ok(());
+        }
+        if flags.intersects(MountFlags::BIND | MountFlags::MOVE) {
+            warn!("mount: bind and move mounts are not supported");
+            return Err(LxError::EINVAL);
+        }
+        let fs_type = filesystem_type(fstype).ok_or(LxError::ENODEV)?;
+        if !fs_type.nodev && self.mounts.read().iter().any(|m| m.source == source) {
+            return Err(LxError::EBUSY);
+        }
+        let dir = self.root_inode().lookup(target)?;
+        if dir.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        let fs = (fs_type.mount)(self, source, data)?;
+        let mounted = Arc::new(Mounted {
+            fs: RwLock::new(Some(fs)),
+            covered: dir.clone(),
+        });
+        let mnode = dir
+            .as_any_ref()
+            .downcast_ref::<MNode>()
+            .ok_or(LxError::EINVAL)?;
+        let vfs = mnode.mount(mounted.clone())?;
+        let mut mounts = self.mounts.write();
+        let id = mounts.iter().map(|m| m.id).max().unwrap_or(0) + 1;
+        mounts.push(Mount {
+            id,
+            source: source.to_string(),
+            target: target.to_string(),
+            fstype: fstype.to_string(),
+            magic: fs_type.magic,
+            flags: flags & MountFlags::PER_MOUNT,
+            data: data.to_string(),
+            vfs,
+            mounted: Some(mounted),
+        });
+        Ok(())
+    }
+
+    /// Unmount the filesystem last mounted at the absolute path `target`.
+    ///
+    /// Files opened in it keep working, as with a lazy unmount.
+    pub fn umount(&self, target: &str) -> LxResult {
+        let mut mounts = self.mounts.write();
+        let index = mounts
+            .iter()
+            .rposition(|m| m.target == target)
+            .ok_or(LxError::EINVAL)?;
+        let mounted = mounts[index].mounted.clone().ok_or(LxError::EBUSY)?;
+        let prefix = if target.ends_with('/') {
+            target.to_string()
+        } else {
+            target.to_string() + "/"
+        };
+        if mounts[index + 1..]
+            .iter()
+            .any(|m| m.target == target || m.target.starts_with(&prefix))
+        {
+            return Err(LxError::EBUSY);
+        }
+        let fs = mounted.fs.write().take();
+        if let Some(fs) = fs {
+            fs.sync()?;
+        }
+        mounts.remove(index);
+        Ok(())
+    }
+
+    /// Get the mount an INode is in, from its `fs()`.
+    pub fn find(&self, fs: &Arc<dyn FileSystem>) -> Option<Mount> {
+        let fs = Arc::as_ptr(fs) as *const u8;
+        self.mounts
+            .read()
+            .iter()
+            .rev()
+            .find(|m| Arc::as_ptr(&m.vfs) as *const u8 == fs)
+            .cloned()
+    }
+
+    /// Get the flags of the mount an INode is in.
+    pub fn flags_of(&self, inode: &Arc<dyn INode>) -> MountFlags {
+        self.find(&inode.fs())
+            .map_or(MountFlags::empty(), |mount| mount.flags)
+    }
+
+    /// Check that an INode can be modified, or created and removed in it if it is a directory,
+    /// which fails with `EROFS` in a read-only mount.
+    pub fn check_writable(&self, inode: &Arc<dyn INode>) -> LxResult {
+        if self.flags_of(inode).contains(MountFlags::RDONLY) {
+            return Err(LxError::EROFS);
+        }
+        Ok(())
+    }
+
+    /// Get all mounts, in the order they were mounted.
+    pub fn mounts(&self) -> Vec<Mount> {
+        self.mounts.read().clone()
+    }
+
+    /// Flush all mounted filesystems.
+    pub fn sync(&self) -> LxResult {
+        for mount in self.mounts.read().iter() {
+            match &mount.mounted {
+                Some(mounted) => mounted.sync()?,
+                None => self.root.sync()?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The content of /proc/mounts
+    pub fn proc_mounts(&self) -> String {
+        let mut content = String::new();
+        for m in self.mounts.read().iter() {
+            let mut options = m.flags.options();
+            if !m.data.is_empty() {
+                options += ",";
+                options += &m.data;
+            }
+            writeln!(
+                content,
+                "{} {} {} {} 0 0",
+                m.source, m.target, m.fstype, options
+            )
+            .unwrap();
+        }
+        content
+    }
+}
+
+/// Split the magic number off the flags of mount(2)
+pub fn mount_flags(flags: usize) -> MountFlags {
+    let flags = if flags & MS_MGC_MSK == MS_MGC_VAL {
+        flags & !MS_MGC_MSK
+    } else {
+        flags
+    };
+    MountFlags::from_bits_truncate(flags)
+}
@@ -1,7 +1,7 @@
 //! Implement INode for Pipe
 #![deny(missing_docs)]
 
-use crate::{sync::Event, sync::EventBus};
+use crate::sync::{Event, EventBus, EventHandler};
 use alloc::{boxed::Box, collections::vec_deque::VecDeque, sync::Arc};
 use core::{any::Any, cmp::min};
 use core::{
@@ -69,6 +69,11 @@ impl Pipe {
             },
         )
     }
+    /// Call `callback` each time the events of the pipe change, until it returns true
+    pub fn subscribe(&self, callback: EventHandler) {
+        self.data.lock().eventbus.subscribe(callback);
+    }
+
     /// whether the pipe struct is readable
     fn can_read(&self) -> bool {
         if let PipeEnd::Read = self.direction {
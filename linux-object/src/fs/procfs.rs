@@ -0,0 +1,745 @@
+//! Process information pseudo-filesystem
+//!
+//! The entries are generated on each access from the live state of the processes,
+//! see [linux man proc(5)](https://www.man7.org/linux/man-pages/man5/proc.5.html).
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use core::fmt::Write;
+use core::time::Duration;
+
+use rcore_fs::vfs::*;
+use spin::RwLock;
+use zircon_object::{
+    object::{KernelObject, KoID},
+    task::{Process, Status},
+    vm::{MMUFlags, PAGE_SIZE},
+};
+
+use super::mount::{proc_filesystems, MountTable};
+use super::{File, FileLike, Pipe};
+use crate::loader::{
+    binfmt, binfmt_enabled, binfmt_names, clear_binfmts, register_binfmt, set_binfmt_enabled,
+    unregister_binfmt, BinaryFormat,
+};
+use crate::process::{linux_processes, ProcessExt};
+
+/// Generate the content of a file
+type Content = Box<dyn Fn() -> Result<String> + Send + Sync>;
+
+/// Handle the data written to a file
+type Writer = Box<dyn Fn(&[u8]) -> Result<usize> + Send + Sync>;
+
+/// Names and INodes in a directory
+type EntryList = Vec<(String, Arc<dyn INode>)>;
+
+/// Generate the entries of a directory, given the directory itself
+type Entries = Box<dyn Fn(&Arc<ProcDir>) -> Result<EntryList> + Send + Sync>;
+
+/// A file or symbolic link whose content is generated on each access
+struct ProcFile {
+    inode_id: usize,
+    type_: FileType,
+    content: Content,
+    /// `None` for a read-only file
+    writer: Option<Writer>,
+}
+
+impl ProcFile {
+    /// create a file showing the output of `content`
+    fn new(
+        inode_id: usize,
+        content: impl Fn() -> Result<String> + Send + Sync + 'static,
+    ) -> Arc<dyn INode> {
+        Arc::new(ProcFile {
+            inode_id,
+            type_: FileType::File,
+            content: Box::new(content),
+            writer: None,
+        })
+    }
+
+    /// create a file showing the output of `content`, passing the writes to `writer`
+    fn writable(
+        inode_id: usize,
+        content: impl Fn() -> Result<String> + Send + Sync + 'static,
+        writer: impl Fn(&[u8]) -> Result<usize> + Send + Sync + 'static,
+    ) -> Arc<dyn INode> {
+        Arc::new(ProcFile {
+            inode_id,
+            type_: FileType::File,
+            content: Box::new(content),
+            writer: Some(Box::new(writer)),
+        })
+    }
+
+    /// create a symbolic link to the output of `target`
+    fn symlink(
+        inode_id: usize,
+        target: impl Fn() -> Result<String> + Send + Sync + 'static,
+    ) -> Arc<dyn INode> {
+        Arc::new(ProcFile {
+            inode_id,
+            type_: FileType::SymLink,
+            content: Box::new(target),
+            writer: None,
+        })
+    }
+}
+
+impl INode for ProcFile {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let content = (self.content)()?;
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return Ok(0);
+        }
+        let len = (content.len() - offset).min(buf.len());
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        match &self.writer {
+            Some(writer) => writer(buf),
+            None => Err(FsError::NotSupported),
+        }
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: self.writer.is_some(),
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let mode = match self.type_ {
+            FileType::SymLink => 0o777,
+            _ if self.writer.is_some() => 0o644,
+            _ => 0o444,
+        };
+        Ok(metadata(
+            self.inode_id,
+            self.type_,
+            mode,
+            (self.content)()?.len(),
+        ))
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A directory whose entries are generated on each access
+struct ProcDir {
+    inode_id: usize,
+    /// `None` for the root directory
+    parent: Option<Arc<dyn INode>>,
+    this: RwLock<Weak<ProcDir>>,
+    entries: Entries,
+}
+
+impl ProcDir {
+    fn new(
+        inode_id: usize,
+        parent: Option<Arc<dyn INode>>,
+        entries: impl Fn(&Arc<ProcDir>) -> Result<EntryList> + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        let dir = Arc::new(ProcDir {
+            inode_id,
+            parent,
+            this: RwLock::new(Weak::new()),
+            entries: Box::new(entries),
+        });
+        *dir.this.write() = Arc::downgrade(&dir);
+        dir
+    }
+
+    fn this(&self) -> Arc<ProcDir> {
+        self.this.read().upgrade().unwrap()
+    }
+
+    fn entries(&self) -> Result<EntryList> {
+        (self.entries)(&self.this())
+    }
+}
+
+impl INode for ProcDir {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let mut metadata = metadata(self.inode_id, FileType::Dir, 0o555, 0);
+        metadata.nlinks = 2;
+        Ok(metadata)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        match name {
+            "." => Ok(self.this()),
+            ".." => Ok(self.parent.clone().unwrap_or_else(|| self.this())),
+            _ => self
+                .entries()?
+                .into_iter()
+                .find(|(entry, _)| entry == name)
+                .map(|(_, inode)| inode)
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            _ => self
+                .entries()?
+                .into_iter()
+                .nth(id - 2)
+                .map(|(name, _)| name)
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// An entry of /proc/[pid], owned by the effective user and group of the process.
+///
+/// A private entry keeps only its owner permission bits: the open files and the address
+/// space of a process are only shown to its owner.
+struct ProcessEntry {
+    inode: Arc<dyn INode>,
+    proc: Weak<Process>,
+    private: bool,
+}
+
+impl ProcessEntry {
+    fn new(inode: Arc<dyn INode>, proc: Weak<Process>, private: bool) -> Arc<dyn INode> {
+        Arc::new(ProcessEntry {
+            inode,
+            proc,
+            private,
+        })
+    }
+}
+
+impl INode for ProcessEntry {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.inode.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.inode.write_at(offset, buf)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        self.inode.poll()
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let proc = self.proc.upgrade().ok_or(FsError::EntryNotFound)?;
+        let cred = proc.linux().credentials();
+        let mut metadata = self.inode.metadata()?;
+        metadata.uid = cred.euid as _;
+        metadata.gid = cred.egid as _;
+        if self.private {
+            metadata.mode &= 0o700;
+        }
+        Ok(metadata)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        self.inode.find(name)
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        self.inode.get_entry(id)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Metadata of a procfs entry
+fn metadata(inode_id: usize, type_: FileType, mode: u16, size: usize) -> Metadata {
+    Metadata {
+        dev: 0,
+        inode: inode_id,
+        size,
+        blk_size: 0,
+        blocks: 0,
+        atime: Timespec { sec: 0, nsec: 0 },
+        mtime: Timespec { sec: 0, nsec: 0 },
+        ctime: Timespec { sec: 0, nsec: 0 },
+        type_,
+        mode,
+        nlinks: 1,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+    }
+}
+
+/// The process information pseudo-filesystem, usually mounted at /proc
+pub struct ProcFS {
+    root: Arc<ProcDir>,
+}
+
+impl FileSystem for ProcFS {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.root.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: PAGE_SIZE,
+            frsize: PAGE_SIZE,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        }
+    }
+}
+
+/// Create a procfs instance showing the mounts of `mounts` and the processes in its job
+pub fn create_procfs(mounts: &Arc<MountTable>) -> Arc<ProcFS> {
+    let table = Arc::downgrade(mounts);
+    let job = mounts.job();
+    let root = ProcDir::new(1, None, move |root| {
+        let table = table.clone();
+        let mut entries: EntryList = vec![
+            (String::from("cpuinfo"), ProcFile::new(2, || Ok(cpuinfo()))),
+            (String::from("meminfo"), ProcFile::new(3, || Ok(meminfo()))),
+            (String::from("uptime"), ProcFile::new(4, || Ok(uptime()))),
+            (
+                String::from("mounts"),
+                ProcFile::new(5, move || {
+                    let table = table.upgrade().ok_or(FsError::EntryNotFound)?;
+                    Ok(table.proc_mounts())
+                }),
+            ),
+            (
+                String::from("filesystems"),
+                ProcFile::new(6, || Ok(proc_filesystems())),
+            ),
+            (String::from("sys"), sys_dir(root)),
+        ];
+        if let Some(job) = job.upgrade() {
+            for proc in linux_processes(&job) {
+                entries.push((proc.id().to_string(), process_dir(root, &proc)));
+            }
+        }
+        Ok(entries)
+    });
+    Arc::new(ProcFS { root })
+}
+
+/// The directory /proc/sys, only holding fs/binfmt_misc
+fn sys_dir(root: &Arc<ProcDir>) -> Arc<dyn INode> {
+    ProcDir::new(7, Some(root.clone()), |sys| {
+        let fs: Arc<dyn INode> = ProcDir::new(8, Some(sys.clone()), |fs| {
+            Ok(vec![(String::from("binfmt_misc"), binfmt_misc_dir(fs))])
+        });
+        Ok(vec![(String::from("fs"), fs)])
+    })
+}
+
+/// The directory /proc/sys/fs/binfmt_misc, the interface of the executable formats
+/// run by an interpreter, see [`crate::loader::register_binfmt`]
+///
+/// A format is added by writing its description to `register`. Writing `1` or `0`
+/// enables or disables a format or the whole table through `status`, `-1` removes it.
+fn binfmt_misc_dir(parent: &Arc<ProcDir>) -> Arc<dyn INode> {
+    ProcDir::new(9, Some(parent.clone()), |_| {
+        let register = ProcFile::writable(10, || Ok(String::new()), register_binfmt_write);
+        let status =
+            ProcFile::writable(11, || Ok(binfmt_status()), |buf| binfmt_control(None, buf));
+        let mut entries: EntryList = vec![
+            (String::from("register"), register),
+            (String::from("status"), status),
+        ];
+        for (i, name) in binfmt_names().into_iter().enumerate() {
+            let (content_name, writer_name) = (name.clone(), name.clone());
+            let inode = ProcFile::writable(
+                12 + i,
+                move || {
+                    let format = binfmt(&content_name).ok_or(FsError::EntryNotFound)?;
+                    Ok(format.status())
+                },
+                move |buf| binfmt_control(Some(&writer_name), buf),
+            );
+            entries.push((name, inode));
+        }
+        Ok(entries)
+    })
+}
+
+/// The content of binfmt_misc/status
+fn binfmt_status() -> String {
+    match binfmt_enabled() {
+        true => String::from("enabled\n"),
+        false => String::from("disabled\n"),
+    }
+}
+
+/// Register the format described by the data written to binfmt_misc/register
+fn register_binfmt_write(buf: &[u8]) -> Result<usize> {
+    let spec = core::str::from_utf8(buf).map_err(|_| FsError::InvalidParam)?;
+    let format = BinaryFormat::parse(spec).map_err(|_| FsError::InvalidParam)?;
+    register_binfmt(format).map_err(|_| FsError::EntryExist)?;
+    Ok(buf.len())
+}
+
+/// Enable, disable or remove the format `name`, or the whole table if `None`,
+/// for `1`, `0` or `-1` written to its file in binfmt_misc
+fn binfmt_control(name: Option<&str>, buf: &[u8]) -> Result<usize> {
+    let command = core::str::from_utf8(buf).map_err(|_| FsError::InvalidParam)?;
+    let result = match (command.trim_end_matches('\n'), name) {
+        ("1", name) => set_binfmt_enabled(name, true),
+        ("0", name) => set_binfmt_enabled(name, false),
+        ("-1", Some(name)) => unregister_binfmt(name),
+        ("-1", None) => {
+            clear_binfmts();
+            Ok(())
+        }
+        _ => return Err(FsError::InvalidParam),
+    };
+    result.map_err(|_| FsError::EntryNotFound)?;
+    Ok(buf.len())
+}
+
+/// Clock ticks per second of the times in /proc/[pid]/stat, as `sysconf(_SC_CLK_TCK)`
+const USER_HZ: u128 = 100;
+
+/// Inode ID of the `n`-th entry of the directory of process `pid`, 0 for the directory itself
+fn pid_inode_id(pid: KoID, n: usize) -> usize {
+    ((pid as usize) << 32) | n
+}
+
+/// The directory /proc/[pid]
+fn process_dir(root: &Arc<ProcDir>, proc: &Arc<Process>) -> Arc<dyn INode> {
+    let pid = proc.id();
+    let proc = Arc::downgrade(proc);
+    let owner = proc.clone();
+    let dir = ProcDir::new(pid_inode_id(pid, 0), Some(root.clone()), move |dir| {
+        let owned = |inode, private| ProcessEntry::new(inode, proc.clone(), private);
+        let file = |n, f: fn(&Process) -> String| {
+            let proc = proc.clone();
+            ProcFile::new(pid_inode_id(pid, n), move || {
+                proc.upgrade().map(|p| f(&p)).ok_or(FsError::EntryNotFound)
+            })
+        };
+        let symlink = |n, f: fn(&Process) -> String| {
+            let proc = proc.clone();
+            ProcFile::symlink(pid_inode_id(pid, n), move || {
+                proc.upgrade().map(|p| f(&p)).ok_or(FsError::EntryNotFound)
+            })
+        };
+        Ok(vec![
+            (String::from("stat"), owned(file(1, process_stat), false)),
+            (
+                String::from("status"),
+                owned(file(2, process_status), false),
+            ),
+            (
+                String::from("cmdline"),
+                owned(file(3, process_cmdline), false),
+            ),
+            (String::from("maps"), owned(file(4, process_maps), true)),
+            (
+                String::from("exe"),
+                owned(symlink(5, |p| p.linux().execute_path()), false),
+            ),
+            (
+                String::from("cwd"),
+                owned(symlink(6, |p| p.linux().current_working_directory()), false),
+            ),
+            (
+                String::from("root"),
+                owned(symlink(7, |_| String::from("/")), false),
+            ),
+            (
+                String::from("fd"),
+                owned(fd_dir(dir, pid, proc.clone()), true),
+            ),
+        ])
+    });
+    ProcessEntry::new(dir, owner, false)
+}
+
+/// The directory /proc/[pid]/fd, with a symbolic link to each opened file
+fn fd_dir(parent: &Arc<ProcDir>, pid: KoID, proc: Weak<Process>) -> Arc<dyn INode> {
+    ProcDir::new(pid_inode_id(pid, 8), Some(parent.clone()), move |_| {
+        let proc = proc.upgrade().ok_or(FsError::EntryNotFound)?;
+        let files = proc
+            .linux()
+            .get_files()
+            .map_err(|_| FsError::EntryNotFound)?;
+        let mut files: Vec<_> = files.into_iter().collect();
+        files.sort_unstable_by_key(|(fd, _)| usize::from(*fd));
+        Ok(files
+            .into_iter()
+            .map(|(fd, file)| {
+                let fd = usize::from(fd);
+                let target = fd_target(&file);
+                let inode =
+                    ProcFile::symlink(pid_inode_id(pid, 0x1000 + fd), move || Ok(target.clone()));
+                (fd.to_string(), inode)
+            })
+            .collect())
+    })
+}
+
+/// What /proc/[pid]/fd/[fd] links to
+fn fd_target(file: &Arc<dyn FileLike>) -> String {
+    if let Some(file) = file.downcast_ref::<File>() {
+        file.path().clone()
+    } else if file.is::<Pipe>() {
+        format!("pipe:[{}]", file.id())
+    } else {
+        format!("anon_inode:[{}]", file.type_name())
+    }
+}
+
+/// Name of the program run by the process, as in /proc/[pid]/comm
+fn process_comm(proc: &Process) -> String {
+    let path = proc.linux().execute_path();
+    path.rsplit('/')
+        .next()
+        .unwrap_or("")
+        .chars()
+        .take(15)
+        .collect()
+}
+
+/// State of the process and its description
+fn process_state(proc: &Process) -> (char, &'static str) {
+    if let Status::Exited(_) = proc.status() {
+        ('Z', "zombie")
+    } else if proc.linux().is_stopped() {
+        ('T', "stopped")
+    } else {
+        ('R', "running")
+    }
+}
+
+/// Size of the address space and of the memory committed to it, in bytes
+fn process_memory(proc: &Process) -> (usize, usize) {
+    let vmar = proc.vmar();
+    if vmar.is_dead() {
+        return (0, 0);
+    }
+    let size = vmar
+        .mappings_info()
+        .iter()
+        .map(|mapping| mapping.size)
+        .sum();
+    let resident = vmar.get_task_stats().committed_bytes() as usize;
+    (size, resident)
+}
+
+/// The content of /proc/[pid]/stat
+fn process_stat(proc: &Process) -> String {
+    let (state, _) = process_state(proc);
+    let linux = proc.linux();
+    let ppid = linux.parent().map_or(0, |p| p.id());
+    let tpgid = linux
+        .terminal()
+        .and_then(|tty| tty.foreground())
+        .map_or(-1, |pgid| pgid as i64);
+    let (vsize, rss) = process_memory(proc);
+    let (utime, stime) = linux.cpu_time();
+    let (cutime, cstime) = linux.children_cpu_time();
+    let ticks = |time: Duration| time.as_millis() * USER_HZ / 1000;
+    let mut stat = String::new();
+    // pid (comm) state ppid pgrp session tty_nr tpgid flags
+    write!(
+        stat,
+        "{} ({}) {} {} {} {} 0 {} 0",
+        proc.id(),
+        process_comm(proc),
+        state,
+        ppid,
+        linux.pgid(),
+        linux.sid(),
+        tpgid
+    )
+    .unwrap();
+    // minflt cminflt majflt cmajflt utime stime cutime cstime priority nice
+    write!(
+        stat,
+        " 0 0 0 0 {} {} {} {} 20 0",
+        ticks(utime),
+        ticks(stime),
+        ticks(cutime),
+        ticks(cstime)
+    )
+    .unwrap();
+    // num_threads itrealvalue starttime vsize rss rsslim
+    write!(
+        stat,
+        " {} 0 0 {} {} {}",
+        proc.thread_ids().len(),
+        vsize,
+        rss / PAGE_SIZE,
+        u64::MAX
+    )
+    .unwrap();
+    // startcode endcode startstack kstkesp kstkeip signal blocked sigignore sigcatch
+    stat += " 0 0 0 0 0 0 0 0 0";
+    // wchan nswap cnswap
+    stat += " 0 0 0";
+    // exit_signal processor rt_priority policy delayacct_blkio_ticks guest_time cguest_time
+    stat += " 17 0 0 0 0 0 0";
+    // start_data end_data start_brk arg_start arg_end env_start env_end exit_code
+    stat += " 0 0 0 0 0 0 0 0\n";
+    stat
+}
+
+/// The content of /proc/[pid]/status
+fn process_status(proc: &Process) -> String {
+    let linux = proc.linux();
+    let (state, state_name) = process_state(proc);
+    let cred = linux.credentials();
+    let (vsize, rss) = process_memory(proc);
+    let mut status = String::new();
+    writeln!(status, "Name:\t{}", process_comm(proc)).unwrap();
+    writeln!(status, "State:\t{} ({})", state, state_name).unwrap();
+    writeln!(status, "Tgid:\t{}", proc.id()).unwrap();
+    writeln!(status, "Pid:\t{}", proc.id()).unwrap();
+    writeln!(status, "PPid:\t{}", linux.parent().map_or(0, |p| p.id())).unwrap();
+    writeln!(
+        status,
+        "Uid:\t{}\t{}\t{}\t{}",
+        cred.uid, cred.euid, cred.suid, cred.fsuid
+    )
+    .unwrap();
+    writeln!(
+        status,
+        "Gid:\t{}\t{}\t{}\t{}",
+        cred.gid, cred.egid, cred.sgid, cred.fsgid
+    )
+    .unwrap();
+    status += "Groups:\t";
+    for gid in cred.groups.iter() {
+        write!(status, "{} ", gid).unwrap();
+    }
+    status += "\n";
+    writeln!(status, "VmSize:\t{:>8} kB", vsize / 1024).unwrap();
+    writeln!(status, "VmRSS:\t{:>8} kB", rss / 1024).unwrap();
+    writeln!(status, "Threads:\t{}", proc.thread_ids().len()).unwrap();
+    status
+}
+
+/// The content of /proc/[pid]/cmdline, the arguments each ended by a null byte
+fn process_cmdline(proc: &Process) -> String {
+    let mut cmdline = String::new();
+    for arg in proc.linux().cmdline() {
+        cmdline += &arg;
+        cmdline.push('\0');
+    }
+    cmdline
+}
+
+/// The content of /proc/[pid]/maps
+fn process_maps(proc: &Process) -> String {
+    let vmar = proc.vmar();
+    let mut maps = String::new();
+    if vmar.is_dead() {
+        return maps;
+    }
+    for mapping in vmar.mappings_info() {
+        let perm = |flag, c| if mapping.flags.contains(flag) { c } else { '-' };
+        let line = format!(
+            "{:08x}-{:08x} {}{}{}p {:08x} 00:00 0",
+            mapping.addr,
+            mapping.addr + mapping.size,
+            perm(MMUFlags::READ, 'r'),
+            perm(MMUFlags::WRITE, 'w'),
+            perm(MMUFlags::EXECUTE, 'x'),
+            mapping.vmo_offset,
+        );
+        let name = mapping.vmo.name();
+        if name.is_empty() {
+            writeln!(maps, "{}", line).unwrap();
+        } else {
+            writeln!(maps, "{:<72} {}", line, name).unwrap();
+        }
+    }
+    maps
+}
+
+/// The content of /proc/cpuinfo
+fn cpuinfo() -> String {
+    let mut cpuinfo = String::new();
+    writeln!(cpuinfo, "processor\t: {}", kernel_hal::cpu::cpu_id()).unwrap();
+    #[cfg(target_arch = "riscv64")]
+    {
+        cpuinfo += "isa\t\t: rv64imafdc\n";
+        cpuinfo += "mmu\t\t: sv39\n";
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        cpuinfo += "model name\t: x86_64\n";
+    }
+    writeln!(
+        cpuinfo,
+        "cpu MHz\t\t: {}.000",
+        kernel_hal::cpu::cpu_frequency()
+    )
+    .unwrap();
+    cpuinfo += "\n";
+    cpuinfo
+}
+
+/// The content of /proc/meminfo
+fn meminfo() -> String {
+    let (total, free) = kernel_hal::mem::frame_stats();
+    let kb = |frames: usize| frames * PAGE_SIZE / 1024;
+    let mut meminfo = String::new();
+    for (name, value) in [
+        ("MemTotal:", kb(total)),
+        ("MemFree:", kb(free)),
+        ("MemAvailable:", kb(free)),
+        ("Buffers:", 0),
+        ("Cached:", 0),
+        ("SwapTotal:", 0),
+        ("SwapFree:", 0),
+    ] {
+        writeln!(meminfo, "{:<16}{:>8} kB", name, value).unwrap();
+    }
+    meminfo
+}
+
+/// The content of /proc/uptime
+fn uptime() -> String {
+    let now = kernel_hal::timer::timer_now();
+    format!("{}.{:02} 0.00\n", now.as_secs(), now.subsec_millis() / 10)
+}
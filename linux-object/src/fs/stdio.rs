@@ -2,7 +2,8 @@
 #![allow(unsafe_code)]
 
 use super::ioctl::*;
-use crate::{sync::Event, sync::EventBus};
+use super::tty::CONSOLE_TTY;
+use crate::sync::{Event, EventBus, EventHandler};
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
@@ -24,7 +25,9 @@ lazy_static! {
             uart.clone().subscribe(
                 Box::new(move |_| {
                     while let Some(c) = uart.try_recv().unwrap_or(None) {
-                        cloned.push(c as char);
+                        if CONSOLE_TTY.receive(c) {
+                            cloned.push(c as char);
+                        }
                     }
                 }),
                 false,
@@ -58,6 +61,10 @@ impl Stdin {
         }
         c
     }
+    /// Call `callback` each time the Stdin buffer becomes readable or empty, until it returns true
+    pub fn subscribe(&self, callback: EventHandler) {
+        self.eventbus.lock().subscribe(callback);
+    }
     /// specify whether the Stdin buffer is readable
     pub fn can_read(&self) -> bool {
         self.buf.lock().len() > 0
@@ -124,18 +131,11 @@ impl INode for Stdin {
                 unsafe { *winsize = console::console_win_size() };
                 Ok(0)
             }
-            TCGETS | TIOCSPGRP => {
-                warn!("stdin TCGETS | TIOCSPGRP, pretend to be tty.");
+            TCGETS => {
+                warn!("stdin TCGETS, pretend to be tty.");
                 // pretend to be tty
                 Ok(0)
             }
-            TIOCGPGRP => {
-                warn!("stdin TIOCGPGRP, pretend to be have a tty process group.");
-                // pretend to be have a tty process group
-                // TODO: verify pointer
-                unsafe { *(data as *mut u32) = 0 };
-                Ok(0)
-            }
             _ => Err(FsError::NotSupported),
         }
     }
@@ -169,17 +169,11 @@ impl INode for Stdout {
                 unsafe { *winsize = console::console_win_size() };
                 Ok(0)
             }
-            TCGETS | TIOCSPGRP => {
-                warn!("stdout TCGETS | TIOCSPGRP, pretend to be tty.");
+            TCGETS => {
+                warn!("stdout TCGETS, pretend to be tty.");
                 // pretend to be tty
                 Ok(0)
             }
-            TIOCGPGRP => {
-                // pretend to be have a tty process group
-                // TODO: verify pointer
-                unsafe { *(data as *mut u32) = 0 };
-                Ok(0)
-            }
             _ => Err(FsError::NotSupported),
         }
     }
@@ -0,0 +1,182 @@
+//! Timer notification file
+#![deny(missing_docs)]
+
+use alloc::{boxed::Box, sync::Arc};
+use async_trait::async_trait;
+use rcore_fs::vfs::PollStatus;
+use spin::{Mutex, RwLock};
+use zircon_object::impl_kobject;
+use zircon_object::object::{KObjectBase, KernelObject};
+use zircon_object::vm::VmObject;
+
+use super::{FileLike, OpenFlags};
+use crate::error::{LxError, LxResult};
+use crate::sync::{wait_for_event, Event, EventBus, EventHandler};
+use crate::time::{IntervalTimer, TimerValue};
+
+bitflags::bitflags! {
+    /// Flags of timerfd_create
+    pub struct TimerFdFlags: usize {
+        /// non block open
+        const NON_BLOCK = 1 << 11;
+        /// close on exec
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of timerfd_settime
+    pub struct TimerFdSetFlags: usize {
+        /// the expiration time is absolute instead of relative to now
+        const ABSTIME = 1;
+        /// cancel reads when the realtime clock is changed, the clock is never changed here
+        const CANCEL_ON_SET = 2;
+    }
+}
+
+/// A timer read as a file, see [linux man timerfd_create(2)](https://www.man7.org/linux/man-pages/man2/timerfd_create.2.html)
+pub struct TimerFd {
+    base: KObjectBase,
+    flags: RwLock<OpenFlags>,
+    timer: Arc<IntervalTimer>,
+    /// expirations since the last read
+    ticks: Arc<Mutex<u64>>,
+    /// `READABLE` while the timer has expired since the last read
+    eventbus: Arc<Mutex<EventBus>>,
+}
+
+impl_kobject!(TimerFd);
+
+impl TimerFd {
+    /// Create a disarmed timerfd
+    pub fn new(flags: TimerFdFlags) -> Arc<Self> {
+        let mut open_flags = OpenFlags::RDWR;
+        open_flags.set(
+            OpenFlags::NON_BLOCK,
+            flags.contains(TimerFdFlags::NON_BLOCK),
+        );
+        open_flags.set(OpenFlags::CLOEXEC, flags.contains(TimerFdFlags::CLOEXEC));
+        let ticks = Arc::new(Mutex::new(0u64));
+        let eventbus = EventBus::new();
+        let timer = {
+            let ticks = ticks.clone();
+            let eventbus = eventbus.clone();
+            IntervalTimer::new(Box::new(move |expirations| {
+                let mut ticks = ticks.lock();
+                *ticks = ticks.saturating_add(expirations as u64);
+                eventbus.lock().set(Event::READABLE);
+            }))
+        };
+        Arc::new(TimerFd {
+            base: KObjectBase::new(),
+            flags: RwLock::new(open_flags),
+            timer,
+            ticks,
+            eventbus,
+        })
+    }
+
+    /// Get the current setting of the timer
+    pub fn get(&self) -> TimerValue {
+        self.timer.get()
+    }
+
+    /// Arm or disarm the timer, return its previous setting.
+    ///
+    /// The expirations not read yet are discarded.
+    pub fn set(&self, value: TimerValue) -> TimerValue {
+        let mut ticks = self.ticks.lock();
+        *ticks = 0;
+        self.eventbus.lock().clear(Event::READABLE);
+        self.timer.set(value)
+    }
+
+    /// Take the number of expirations if it is not zero
+    fn try_read(&self) -> Option<u64> {
+        let mut ticks = self.ticks.lock();
+        if *ticks == 0 {
+            return None;
+        }
+        self.eventbus.lock().clear(Event::READABLE);
+        Some(core::mem::take(&mut *ticks))
+    }
+}
+
+#[async_trait]
+impl FileLike for TimerFd {
+    fn flags(&self) -> OpenFlags {
+        *self.flags.read()
+    }
+
+    fn set_flags(&self, f: OpenFlags) -> LxResult {
+        let flags = &mut self.flags.write();
+        flags.set(OpenFlags::NON_BLOCK, f.contains(OpenFlags::NON_BLOCK));
+        flags.set(OpenFlags::CLOEXEC, f.contains(OpenFlags::CLOEXEC));
+        Ok(())
+    }
+
+    fn dup(&self) -> Arc<dyn FileLike> {
+        Arc::new(TimerFd {
+            base: KObjectBase::new(),
+            flags: RwLock::new(self.flags()),
+            timer: self.timer.clone(),
+            ticks: self.ticks.clone(),
+            eventbus: self.eventbus.clone(),
+        })
+    }
+
+    /// Read the number of expirations since the last read as a 64-bit integer
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        if buf.len() < 8 {
+            return Err(LxError::EINVAL);
+        }
+        loop {
+            if let Some(ticks) = self.try_read() {
+                buf[..8].copy_from_slice(&ticks.to_ne_bytes());
+                return Ok(8);
+            }
+            if self.flags().non_block() {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::EINVAL)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self) -> LxResult<PollStatus> {
+        Ok(PollStatus {
+            read: *self.ticks.lock() > 0,
+            write: false,
+            error: false,
+        })
+    }
+
+    async fn async_poll(&self) -> LxResult<PollStatus> {
+        wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        self.poll()
+    }
+
+    fn subscribe(&self, callback: EventHandler) -> bool {
+        self.eventbus.lock().subscribe(callback);
+        true
+    }
+
+    fn ioctl(&self, _request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        Err(LxError::ENOTTY)
+    }
+
+    fn get_vmo(&self, _offset: usize, _len: usize) -> LxResult<Arc<VmObject>> {
+        Err(LxError::EINVAL)
+    }
+}
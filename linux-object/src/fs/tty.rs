@@ -0,0 +1,196 @@
+//! Controlling terminals and job control
+
+use super::devfs::UartDev;
+use super::ioctl::*;
+use super::stdio::{Stdin, Stdout};
+use crate::error::{LxError, LxResult};
+use crate::process::{process_group, ProcessExt};
+use crate::signal::{SigInfo, Signal as LinuxSignal, SignalCode};
+use alloc::sync::{Arc, Weak};
+use kernel_hal::user::{UserInPtr, UserOutPtr};
+use lazy_static::lazy_static;
+use rcore_fs::vfs::INode;
+use spin::Mutex;
+use zircon_object::{
+    object::KoID,
+    task::{Job, Process},
+};
+
+lazy_static! {
+    /// The console terminal, behind stdin, stdout and `/dev/ttyS0`
+    pub static ref CONSOLE_TTY: Arc<Tty> = Default::default();
+}
+
+/// Job control state of a terminal
+#[derive(Default)]
+pub struct Tty {
+    inner: Mutex<TtyInner>,
+}
+
+#[derive(Default)]
+struct TtyInner {
+    /// Job of the processes in the session
+    job: Weak<Job>,
+    /// The session controlled by the terminal
+    session: Option<KoID>,
+    /// The foreground process group of the session
+    foreground: KoID,
+}
+
+impl Tty {
+    /// The terminal behind `inode`, if it is one
+    pub fn of(inode: &dyn INode) -> Option<Arc<Tty>> {
+        if inode.downcast_ref::<Stdin>().is_some() || inode.downcast_ref::<Stdout>().is_some() {
+            return Some(CONSOLE_TTY.clone());
+        }
+        inode
+            .downcast_ref::<UartDev>()
+            .map(|uart| uart.tty().clone())
+    }
+
+    /// The foreground process group, if the terminal controls a session
+    pub fn foreground(&self) -> Option<KoID> {
+        let inner = self.inner.lock();
+        inner.session.map(|_| inner.foreground)
+    }
+
+    /// Make the terminal the controlling terminal of the session led by `leader`,
+    /// with the process group of `leader` in the foreground.
+    ///
+    /// The terminal is taken from another session only if `steal` is set.
+    pub fn set_controlling(self: &Arc<Self>, leader: &Arc<Process>, steal: bool) -> LxResult {
+        let linux = leader.linux();
+        let (sid, pgid) = (linux.sid(), linux.pgid());
+        let mut inner = self.inner.lock();
+        match inner.session {
+            Some(session) if session != sid && !steal => return Err(LxError::EPERM),
+            _ => {}
+        }
+        inner.job = Arc::downgrade(&leader.job());
+        inner.session = Some(sid);
+        inner.foreground = pgid;
+        drop(inner);
+        linux.set_terminal(Some(self.clone()));
+        Ok(())
+    }
+
+    /// Handle the job control requests of `ioctl` made by `proc` on the terminal,
+    /// `None` for the other requests.
+    pub fn ioctl(
+        self: &Arc<Self>,
+        proc: &Arc<Process>,
+        request: usize,
+        arg: usize,
+    ) -> Option<LxResult<usize>> {
+        let result = match request {
+            TIOCGPGRP => self.controlled_session(proc).and_then(|_| {
+                let foreground = self.inner.lock().foreground;
+                UserOutPtr::<i32>::from(arg).write(foreground as i32)?;
+                Ok(0)
+            }),
+            TIOCSPGRP => self.controlled_session(proc).and_then(|sid| {
+                let pgid = UserInPtr::<i32>::from(arg).read()?;
+                if pgid < 0 {
+                    return Err(LxError::EINVAL);
+                }
+                self.set_foreground(sid, pgid as KoID)?;
+                Ok(0)
+            }),
+            TIOCGSID => self.controlled_session(proc).and_then(|sid| {
+                UserOutPtr::<i32>::from(arg).write(sid as i32)?;
+                Ok(0)
+            }),
+            TIOCSCTTY => {
+                let linux = proc.linux();
+                match linux.terminal() {
+                    Some(tty) if Arc::ptr_eq(&tty, self) => Ok(0),
+                    _ if linux.sid() != proc.id() => Err(LxError::EPERM),
+                    Some(_) => Err(LxError::EPERM),
+                    None => {
+                        // only a privileged process may take the terminal of another session
+                        let steal = arg == 1 && linux.credentials().is_privileged();
+                        self.set_controlling(proc, steal).map(|_| 0)
+                    }
+                }
+            }
+            TIOCNOTTY => self.controlled_session(proc).map(|sid| {
+                if sid == proc.id() {
+                    // the session leader gives up the terminal for the whole session
+                    let foreground = self.foreground();
+                    self.release(sid);
+                    if let Some(pgid) = foreground {
+                        signal_group(&proc.job(), pgid, LinuxSignal::SIGHUP);
+                        signal_group(&proc.job(), pgid, LinuxSignal::SIGCONT);
+                    }
+                }
+                proc.linux().set_terminal(None);
+                0
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// Line discipline of the input: turns the interrupt characters into signals for the
+    /// foreground process group. Returns `false` if `c` is consumed that way.
+    pub fn receive(&self, c: u8) -> bool {
+        let signal = match c {
+            0x03 => LinuxSignal::SIGINT,  // Ctrl-C
+            0x1a => LinuxSignal::SIGTSTP, // Ctrl-Z
+            0x1c => LinuxSignal::SIGQUIT, // Ctrl-\
+            _ => return true,
+        };
+        let (job, foreground) = {
+            let inner = self.inner.lock();
+            match (inner.job.upgrade(), inner.session) {
+                (Some(job), Some(_)) => (job, inner.foreground),
+                _ => return true,
+            }
+        };
+        info!("tty: {:?} for process group {}", signal, foreground);
+        signal_group(&job, foreground, signal);
+        false
+    }
+
+    /// The session of `proc`, if the terminal is its controlling terminal
+    fn controlled_session(self: &Arc<Self>, proc: &Process) -> LxResult<KoID> {
+        let linux = proc.linux();
+        let sid = linux.sid();
+        match linux.terminal() {
+            Some(tty) if Arc::ptr_eq(&tty, self) && self.inner.lock().session == Some(sid) => {
+                Ok(sid)
+            }
+            _ => Err(LxError::ENOTTY),
+        }
+    }
+
+    /// Move the process group `pgid` of the session `sid` to the foreground
+    fn set_foreground(&self, sid: KoID, pgid: KoID) -> LxResult {
+        let job = self.inner.lock().job.upgrade().ok_or(LxError::ENOTTY)?;
+        let group = process_group(&job, pgid);
+        if group.is_empty() {
+            return Err(LxError::ESRCH);
+        }
+        if !group.iter().any(|proc| proc.linux().sid() == sid) {
+            return Err(LxError::EPERM);
+        }
+        self.inner.lock().foreground = pgid;
+        Ok(())
+    }
+
+    /// Detach the terminal from the session `sid`
+    fn release(&self, sid: KoID) {
+        let mut inner = self.inner.lock();
+        if inner.session == Some(sid) {
+            inner.session = None;
+            inner.job = Weak::new();
+        }
+    }
+}
+
+/// Send `signal` from the kernel to the process group `pgid` in `job`
+fn signal_group(job: &Arc<Job>, pgid: KoID, signal: LinuxSignal) {
+    for proc in process_group(job, pgid) {
+        proc.send_signal(SigInfo::kill(signal, SignalCode::KERNEL, 0));
+    }
+}
@@ -0,0 +1,120 @@
+//! Linux process heap
+#![deny(missing_docs)]
+
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use zircon_object::{object::KernelObject, vm::*, ZxResult};
+
+/// Address space reserved after the program image for the heap to grow into
+const HEAP_RESERVE: usize = 0x1000_0000;
+
+/// The program break of a process, see [linux man brk(2)](https://www.man7.org/linux/man-pages/man2/brk.2.html)
+///
+/// The heap starts right after the last loaded segment and is backed by a single resizable VMO,
+/// mapped into a region reserved for it so that the stack and mmaps do not get in its way.
+#[derive(Default)]
+pub struct ProgramBreak {
+    /// Lowest break, the end of the program image
+    start: VirtAddr,
+    /// Current break, not necessarily page aligned
+    current: VirtAddr,
+    /// Region reserved for the heap, `None` once it has been flattened by fork
+    region: Option<Arc<VmAddressRegion>>,
+    /// Resizable VMO mapped from the given address up to the page-rounded break
+    vmo: Option<(VirtAddr, Arc<VmObject>)>,
+}
+
+impl ProgramBreak {
+    /// Set up an empty heap at `start`, reserving room for it in `vmar` if the space is free
+    pub fn new(vmar: &Arc<VmAddressRegion>, start: VirtAddr) -> Self {
+        let start = roundup_pages(start);
+        let flags = VmarFlags::CAN_MAP_RXW | VmarFlags::CAN_MAP_SPECIFIC;
+        let region = vmar
+            .allocate_at(start - vmar.addr(), HEAP_RESERVE, flags, PAGE_SIZE)
+            .map_err(|err| warn!("heap: cannot reserve space at {:#x}: {:?}", start, err))
+            .ok();
+        ProgramBreak {
+            start,
+            current: start,
+            region,
+            vmo: None,
+        }
+    }
+
+    /// The heap of a forked child, whose pages were copied with the address space
+    pub fn fork(&self) -> Self {
+        ProgramBreak {
+            start: self.start,
+            current: self.current,
+            region: None,
+            vmo: None,
+        }
+    }
+
+    /// Get the lowest break, the start of the heap.
+    pub fn start(&self) -> VirtAddr {
+        self.start
+    }
+
+    /// Get the current break.
+    pub fn current(&self) -> VirtAddr {
+        self.current
+    }
+
+    /// Move the break to `addr`, growing or shrinking the heap, and return the new break.
+    ///
+    /// The break stays unchanged if `addr` is below the start of the heap or cannot be mapped.
+    pub fn set(&mut self, vmar: &Arc<VmAddressRegion>, addr: VirtAddr) -> VirtAddr {
+        if addr < self.start || addr.checked_add(PAGE_SIZE).is_none() {
+            return self.current;
+        }
+        let old_end = roundup_pages(self.current);
+        let new_end = roundup_pages(addr);
+        let target = self.region.clone().unwrap_or_else(|| vmar.clone());
+        let result = match new_end.cmp(&old_end) {
+            Ordering::Greater => self.grow(&target, old_end, new_end),
+            Ordering::Less => self.shrink(&target, new_end, old_end),
+            Ordering::Equal => Ok(()),
+        };
+        match result {
+            Ok(()) => self.current = addr,
+            Err(err) => warn!("heap: cannot move break to {:#x}: {:?}", addr, err),
+        }
+        self.current
+    }
+
+    /// Extend the VMO and map [begin, end) from it
+    fn grow(&mut self, target: &Arc<VmAddressRegion>, begin: VirtAddr, end: VirtAddr) -> ZxResult {
+        let (base, vmo) = self.vmo.get_or_insert_with(|| {
+            let vmo = VmObject::new_paged_with_resizable(true, 0);
+            vmo.set_name("[heap]");
+            (begin, vmo)
+        });
+        vmo.set_len(end - *base)?;
+        let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
+        let offset = begin - target.addr();
+        if let Err(err) = target.map_at(offset, vmo.clone(), begin - *base, end - begin, flags) {
+            vmo.set_len(begin - *base)?;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Unmap [begin, end) and release the pages of the VMO beyond `begin`
+    fn shrink(
+        &mut self,
+        target: &Arc<VmAddressRegion>,
+        begin: VirtAddr,
+        end: VirtAddr,
+    ) -> ZxResult {
+        target.unmap(begin, end - begin)?;
+        if let Some((base, vmo)) = &self.vmo {
+            if *base < begin {
+                return vmo.set_len(begin - *base);
+            }
+        }
+        // the pages below `begin` are not from the VMO, start a new one when growing again
+        self.vmo = None;
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@ use alloc::{collections::BTreeMap, sync::Arc, sync::Weak};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use spin::RwLock;
+use zircon_object::object::KernelObject;
 use zircon_object::vm::*;
 
 lazy_static! {
@@ -80,8 +81,11 @@ impl ShmIdentifier {
                 return Ok(guard);
             }
         }
+        let vmo = VmObject::new_paged(pages(memsize));
+        // named like Linux shows it in /proc/<pid>/maps
+        vmo.set_name(&format!("/SYSV{:08x}", key));
         let shared_guard = Arc::new(spin::Mutex::new(ShmGuard {
-            shared_guard: VmObject::new_paged(pages(memsize)),
+            shared_guard: vmo,
             shmid_ds: Mutex::new(ShmidDs {
                 perm: IpcPerm {
                     key,
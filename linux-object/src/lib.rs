@@ -18,7 +18,9 @@ extern crate log;
 pub mod error;
 
 // layer 1
+pub mod cred;
 pub mod fs;
+pub mod heap;
 
 // layer 2
 pub mod ipc;
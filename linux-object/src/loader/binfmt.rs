@@ -0,0 +1,387 @@
+//! Executable formats run by an interpreter, like
+//! [binfmt_misc](https://docs.kernel.org/admin-guide/binfmt-misc.html).
+//!
+//! A format is recognized by a magic number at some offset of the file or by the
+//! extension of its name, the registered interpreter is then run with the path of the
+//! file inserted in its arguments.
+
+use super::HEADER_SIZE;
+use crate::error::{LxError, LxResult};
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use spin::RwLock;
+
+lazy_static! {
+    /// The registered formats, matched in order of registration
+    static ref FORMATS: RwLock<Vec<BinaryFormat>> = RwLock::new(Vec::new());
+}
+
+/// Whether the registered formats are used at all
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// How the files of a format are recognized
+#[derive(Debug, Clone)]
+pub enum FormatMatch {
+    /// The bytes at `offset` equal `magic` once masked by `mask`
+    Magic {
+        /// position of the magic number in the file
+        offset: usize,
+        /// the magic number, already masked
+        magic: Vec<u8>,
+        /// bits of the file compared with the magic number
+        mask: Vec<u8>,
+    },
+    /// The file name ends with a dot and this extension
+    Extension(String),
+}
+
+/// An executable format run by an interpreter
+#[derive(Debug, Clone)]
+pub struct BinaryFormat {
+    /// Name of the format, unique among the registered ones
+    pub name: String,
+    /// How the files of the format are recognized
+    pub matcher: FormatMatch,
+    /// Path of the interpreter
+    pub interpreter: String,
+    /// Keep the original `argv[0]` after the path of the file (the `P` flag),
+    /// instead of replacing it
+    pub preserve_argv0: bool,
+    /// Whether the format is used
+    pub enabled: bool,
+}
+
+impl BinaryFormat {
+    /// Parse a format written to `register` in binfmt_misc:
+    /// `:name:type:offset:magic:mask:interpreter:flags`
+    ///
+    /// The first character is the field separator, `type` is `M` for a magic number or
+    /// `E` for an extension. `magic` and `mask` may contain `\xHH` escapes.
+    /// The flags `P` and `F` are supported, the interpreter being looked up on each
+    /// execution anyway.
+    pub fn parse(spec: &str) -> LxResult<Self> {
+        let spec = spec.trim_end_matches('\n');
+        let separator = spec.chars().next().ok_or(LxError::EINVAL)?;
+        let mut fields = spec[separator.len_utf8()..].split(separator);
+        let mut field = || fields.next().ok_or(LxError::EINVAL);
+        let (name, kind, offset) = (field()?, field()?, field()?);
+        let (magic, mask, interpreter) = (field()?, field()?, field()?);
+        let flags = fields.next().unwrap_or("");
+        if fields.next().is_some() {
+            return Err(LxError::EINVAL);
+        }
+        if name.is_empty()
+            || name.contains('/')
+            || [".", "..", "register", "status"].contains(&name)
+            || interpreter.is_empty()
+        {
+            return Err(LxError::EINVAL);
+        }
+        let matcher = match kind {
+            "M" => {
+                let offset = match offset {
+                    "" => 0,
+                    offset => offset.parse().map_err(|_| LxError::EINVAL)?,
+                };
+                let magic = unescape(magic)?;
+                let mask = match mask {
+                    "" => vec![0xff; magic.len()],
+                    mask => unescape(mask)?,
+                };
+                if magic.is_empty()
+                    || mask.len() != magic.len()
+                    || magic.len() > HEADER_SIZE
+                    || offset > HEADER_SIZE - magic.len()
+                {
+                    return Err(LxError::EINVAL);
+                }
+                let magic = magic.iter().zip(&mask).map(|(m, k)| m & k).collect();
+                FormatMatch::Magic {
+                    offset,
+                    magic,
+                    mask,
+                }
+            }
+            "E" if !magic.is_empty() && !magic.contains('/') => {
+                FormatMatch::Extension(String::from(magic))
+            }
+            _ => return Err(LxError::EINVAL),
+        };
+        let mut preserve_argv0 = false;
+        for flag in flags.chars() {
+            match flag {
+                'P' => preserve_argv0 = true,
+                'F' => {}
+                _ => return Err(LxError::EINVAL),
+            }
+        }
+        Ok(BinaryFormat {
+            name: String::from(name),
+            matcher,
+            interpreter: String::from(interpreter),
+            preserve_argv0,
+            enabled: true,
+        })
+    }
+
+    /// Whether the file at `path` starting with `data` is of this format
+    pub fn matches(&self, path: &str, data: &[u8]) -> bool {
+        match &self.matcher {
+            FormatMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => match data.get(*offset..*offset + magic.len()) {
+                Some(bytes) => bytes
+                    .iter()
+                    .zip(mask)
+                    .zip(magic)
+                    .all(|((byte, mask), magic)| byte & mask == *magic),
+                None => false,
+            },
+            FormatMatch::Extension(extension) => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                match name.rsplit_once('.') {
+                    Some((_, ext)) => ext == extension,
+                    None => false,
+                }
+            }
+        }
+    }
+
+    /// The content of the file of the format in binfmt_misc
+    pub fn status(&self) -> String {
+        let state = if self.enabled { "enabled" } else { "disabled" };
+        let mut status = format!("{}\n", state);
+        writeln!(status, "interpreter {}", self.interpreter).unwrap();
+        let flags = if self.preserve_argv0 { "P" } else { "" };
+        writeln!(status, "flags: {}", flags).unwrap();
+        match &self.matcher {
+            FormatMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                writeln!(status, "offset {}", offset).unwrap();
+                writeln!(status, "magic {}", hex(magic)).unwrap();
+                if mask.iter().any(|&byte| byte != 0xff) {
+                    writeln!(status, "mask {}", hex(mask)).unwrap();
+                }
+            }
+            FormatMatch::Extension(extension) => {
+                writeln!(status, "extension .{}", extension).unwrap();
+            }
+        }
+        status
+    }
+}
+
+/// Add `format` to the table, `EEXIST` if its name is taken
+pub fn register_binfmt(format: BinaryFormat) -> LxResult {
+    let mut formats = FORMATS.write();
+    if formats.iter().any(|f| f.name == format.name) {
+        return Err(LxError::EEXIST);
+    }
+    info!("binfmt: register {:?}", format);
+    formats.push(format);
+    Ok(())
+}
+
+/// Remove the format `name` from the table
+pub fn unregister_binfmt(name: &str) -> LxResult {
+    let mut formats = FORMATS.write();
+    let index = formats
+        .iter()
+        .position(|f| f.name == name)
+        .ok_or(LxError::ENOENT)?;
+    formats.remove(index);
+    Ok(())
+}
+
+/// Remove all the formats from the table
+pub fn clear_binfmts() {
+    FORMATS.write().clear();
+}
+
+/// Get the registered format `name`
+pub fn binfmt(name: &str) -> Option<BinaryFormat> {
+    FORMATS.read().iter().find(|f| f.name == name).cloned()
+}
+
+/// Names of the registered formats
+pub fn binfmt_names() -> Vec<String> {
+    FORMATS.read().iter().map(|f| f.name.clone()).collect()
+}
+
+/// Enable or disable the format `name`, or the whole table if `name` is `None`
+pub fn set_binfmt_enabled(name: Option<&str>, enabled: bool) -> LxResult {
+    match name {
+        Some(name) => {
+            let mut formats = FORMATS.write();
+            let format = formats
+                .iter_mut()
+                .find(|f| f.name == name)
+                .ok_or(LxError::ENOENT)?;
+            format.enabled = enabled;
+        }
+        None => ENABLED.store(enabled, Ordering::Relaxed),
+    }
+    Ok(())
+}
+
+/// Whether the table is used at all
+pub fn binfmt_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The first enabled format of the file at `path` starting with `data`
+pub(super) fn find_binfmt(path: &str, data: &[u8]) -> Option<BinaryFormat> {
+    if !binfmt_enabled() {
+        return None;
+    }
+    FORMATS
+        .read()
+        .iter()
+        .find(|f| f.enabled && f.matches(path, data))
+        .cloned()
+}
+
+/// Decode the `\xHH` and `\\` escapes of a magic number or a mask
+fn unescape(s: &str) -> LxResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match iter.next() {
+            Some(b'\\') => bytes.push(b'\\'),
+            Some(b'x') => {
+                let digits = [iter.next(), iter.next()];
+                let digits = match digits {
+                    [Some(high), Some(low)] => [high, low],
+                    _ => return Err(LxError::EINVAL),
+                };
+                let digits = core::str::from_utf8(&digits).map_err(|_| LxError::EINVAL)?;
+                bytes.push(u8::from_str_radix(digits, 16).map_err(|_| LxError::EINVAL)?);
+            }
+            _ => return Err(LxError::EINVAL),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Lower case hexadecimal digits of `bytes`
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_magic() {
+        let format =
+            BinaryFormat::parse(r":qemu:M:2:\x7fELF\x03:\xff\xff\xff\xff\xfe:/bin/qemu:P").unwrap();
+        assert_eq!(format.name, "qemu");
+        assert_eq!(format.interpreter, "/bin/qemu");
+        assert!(format.preserve_argv0);
+        assert!(format.enabled);
+        match format.matcher {
+            FormatMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                assert_eq!(offset, 2);
+                // the magic number is stored masked
+                assert_eq!(magic, b"\x7fELF\x02");
+                assert_eq!(mask, b"\xff\xff\xff\xff\xfe");
+            }
+            FormatMatch::Extension(_) => panic!("not a magic format"),
+        }
+    }
+
+    #[test]
+    fn parse_extension() {
+        let format = BinaryFormat::parse(",py,E,,py,,/bin/python,F\n").unwrap();
+        assert_eq!(format.name, "py");
+        assert_eq!(format.interpreter, "/bin/python");
+        assert!(!format.preserve_argv0);
+        assert!(matches!(format.matcher, FormatMatch::Extension(ext) if ext == "py"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let invalid = [
+            "",
+            ":x:M::ab",
+            ":x:M::ab::",
+            ":x:M::ab::/bin/i::extra",
+            ":x/y:M::ab::/bin/i:",
+            ":register:M::ab::/bin/i:",
+            ":x:Q::ab::/bin/i:",
+            ":x:M::::/bin/i:",
+            ":x:M::ab:\\xff:/bin/i:",
+            ":x:M::\\x4g::/bin/i:",
+            ":x:M:255:ab::/bin/i:",
+            ":x:M:-1:ab::/bin/i:",
+            ":x:E::a/b::/bin/i:",
+            ":x:M::ab::/bin/i:Z",
+        ];
+        for spec in invalid.iter() {
+            assert!(
+                matches!(BinaryFormat::parse(spec), Err(LxError::EINVAL)),
+                "{:?} is accepted",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn unescape_bytes() {
+        assert_eq!(unescape(r"a\\b\x41\xfF").unwrap(), b"a\\bA\xff");
+        assert!(unescape(r"\x4").is_err());
+        assert!(unescape(r"\xzz").is_err());
+        assert!(unescape(r"\q").is_err());
+        assert!(unescape("\\").is_err());
+    }
+
+    #[test]
+    fn match_magic_with_mask() {
+        let format = BinaryFormat::parse(r":m:M:1:\x10\x20:\xf0\xff:/bin/i:").unwrap();
+        assert!(format.matches("/a", b"\x00\x1f\x20"));
+        assert!(format.matches("/a", b"\x00\x10\x20\x30"));
+        assert!(!format.matches("/a", b"\x00\x20\x20"));
+        assert!(!format.matches("/a", b"\x00\x10\x21"));
+        // too short to hold the magic number
+        assert!(!format.matches("/a", b"\x00\x10"));
+    }
+
+    #[test]
+    fn match_extension() {
+        let format = BinaryFormat::parse(":e:E::py::/bin/python:").unwrap();
+        assert!(format.matches("/a/b.py", b""));
+        assert!(format.matches("b.tar.py", b""));
+        assert!(!format.matches("/a.py/b", b""));
+        assert!(!format.matches("/a/bpy", b""));
+        assert!(!format.matches("/a/b.pyc", b""));
+    }
+
+    #[test]
+    fn register_twice() {
+        let format = BinaryFormat::parse(":binfmt-test-dup:E::binfmt-test-dup::/bin/i:").unwrap();
+        register_binfmt(format.clone()).unwrap();
+        assert!(matches!(register_binfmt(format), Err(LxError::EEXIST)));
+        assert!(binfmt("binfmt-test-dup").is_some());
+        unregister_binfmt("binfmt-test-dup").unwrap();
+        assert!(matches!(
+            unregister_binfmt("binfmt-test-dup"),
+            Err(LxError::ENOENT)
+        ));
+    }
+}
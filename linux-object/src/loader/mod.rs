@@ -2,15 +2,33 @@
 #![deny(missing_docs)]
 
 use {
-    crate::error::LxResult,
+    crate::error::{LxError, LxResult},
     crate::fs::INodeExt,
+    crate::heap::ProgramBreak,
     alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec},
     rcore_fs::vfs::INode,
     xmas_elf::{program::ProgramHeader, ElfFile},
-    zircon_object::{util::elf_loader::*, vm::*, ZxError},
+    zircon_object::{object::KernelObject, util::elf_loader::*, vm::*},
 };
 
 mod abi;
+mod binfmt;
+
+use self::binfmt::find_binfmt;
+pub use self::binfmt::{
+    binfmt, binfmt_enabled, binfmt_names, clear_binfmts, register_binfmt, set_binfmt_enabled,
+    unregister_binfmt, BinaryFormat, FormatMatch,
+};
+
+/// Bytes at the start of a file examined to tell its format, like `BINPRM_BUF_SIZE` in Linux
+const HEADER_SIZE: usize = 256;
+
+/// Most interpreters run one for another before the ELF file,
+/// like `BINPRM_MAX_RECURSION` in Linux
+const MAX_INTERPRETER_DEPTH: usize = 4;
+
+/// Most symbolic links followed to find an interpreter
+const MAX_SYMLINK_FOLLOW: usize = 40;
 
 /// Linux ELF Program Loader.
 pub struct LinuxElfLoader {
@@ -23,7 +41,10 @@ pub struct LinuxElfLoader {
 }
 
 impl LinuxElfLoader {
-    /// load a Linux ElfFile and return a tuple of (entry,sp)
+    /// load a Linux ElfFile and return a tuple of (entry,sp,program break)
+    ///
+    /// A file of a format registered with [`register_binfmt`], or a script starting
+    /// with `#!`, is run by its interpreter with the path of the file in the arguments.
     pub fn load(
         &self,
         vmar: &Arc<VmAddressRegion>,
@@ -31,7 +52,21 @@ impl LinuxElfLoader {
         args: Vec<String>,
         envs: Vec<String>,
         path: String,
-    ) -> LxResult<(VirtAddr, VirtAddr)> {
+    ) -> LxResult<(VirtAddr, VirtAddr, ProgramBreak)> {
+        self.load_image(vmar, data, args, envs, &path, 0)
+    }
+
+    /// load the file at `image`, which is the executed file or the interpreter run for
+    /// the previous image, after `depth` interpreters
+    fn load_image(
+        &self,
+        vmar: &Arc<VmAddressRegion>,
+        data: &[u8],
+        args: Vec<String>,
+        envs: Vec<String>,
+        image: &str,
+        depth: usize,
+    ) -> LxResult<(VirtAddr, VirtAddr, ProgramBreak)> {
         debug!(
             "load: vmar.addr & size: {:#x?}, data {:#x?}, args: {:?}, envs: {:?}",
             vmar.get_info(),
@@ -40,23 +75,33 @@ impl LinuxElfLoader {
             envs
         );
 
-        let elf = ElfFile::new(data).map_err(|_| ZxError::INVALID_ARGS)?;
+        if let Some((interp, new_args)) = script_interpreter(image, data, &args)? {
+            info!("script interp: {:?}, args: {:?}", interp, new_args);
+            let data = self.read_interpreter(&interp, depth)?;
+            return self.load_image(vmar, &data, new_args, envs, &interp, depth + 1);
+        }
+
+        let elf = ElfFile::new(data).map_err(|_| LxError::ENOEXEC)?;
 
         debug!("elf info:  {:#x?}", elf.header.pt2);
 
         if let Ok(interp) = elf.get_interpreter() {
-            info!("interp: {:?}, path: {:?}", interp, path);
-            let inode = self.root_inode.lookup(interp)?;
-            let data = inode.read_as_vec()?;
-            let mut new_args = vec![interp.into(), path.clone()];
-            new_args.extend_from_slice(&args[1..]);
-            return self.load(vmar, &data, new_args, envs, path);
+            info!("interp: {:?}, path: {:?}", interp, image);
+            let data = self.read_interpreter(interp, depth)?;
+            let mut new_args = vec![interp.into(), image.into()];
+            new_args.extend(args.into_iter().skip(1));
+            return self.load_image(vmar, &data, new_args, envs, interp, depth + 1);
         }
 
         let size = elf.load_segment_size();
         let image_vmar = vmar.allocate(None, size, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)?;
         let mut base = image_vmar.addr();
         let vmo = image_vmar.load_from_elf(&elf)?;
+        for mapping in image_vmar.mappings_info() {
+            mapping.vmo.set_name(image);
+        }
+        // heap starts after the last loaded segment, reserved before the stack is mapped
+        let program_break = ProgramBreak::new(vmar, image_vmar.addr() + size);
         let entry = base + elf.header.pt2.entry_point() as usize;
 
         // for static exec program
@@ -83,6 +128,7 @@ impl LinuxElfLoader {
         }
 
         let stack_vmo = VmObject::new_paged(self.stack_pages);
+        stack_vmo.set_name("[stack]");
         let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
         let stack_bottom = vmar.map(None, stack_vmo.clone(), 0, stack_vmo.len(), flags)?;
         let mut sp = stack_bottom + stack_vmo.len();
@@ -118,6 +164,147 @@ impl LinuxElfLoader {
             info.auxv, entry, sp
         );
 
-        Ok((entry, sp))
+        Ok((entry, sp, program_break))
+    }
+
+    /// Read the interpreter at `path` to be run after `depth` others
+    fn read_interpreter(&self, path: &str, depth: usize) -> LxResult<Vec<u8>> {
+        if depth >= MAX_INTERPRETER_DEPTH {
+            warn!("too many interpreters, the last one is {:?}", path);
+            return Err(LxError::ELOOP);
+        }
+        let inode = self.root_inode.lookup_follow(path, MAX_SYMLINK_FOLLOW)?;
+        Ok(inode.read_as_vec()?)
+    }
+}
+
+/// Whether the file at `path` starting with `data` is run by an interpreter,
+/// as a `#!` script or a file of a registered format
+pub fn is_interpreted(path: &str, data: &[u8]) -> bool {
+    matches!(script_interpreter(path, data, &[]), Ok(Some(_)))
+}
+
+/// The interpreter of the file at `image` starting with `data` and its arguments,
+/// if the file has a registered format or is a `#!` script.
+///
+/// The interpreter gets `image` in place of `argv[0]`, after the optional argument
+/// of the `#!` line, so a script run by a script interpreter `/B` itself run by `/C`
+/// gets `/C /B A args`.
+fn script_interpreter(
+    image: &str,
+    data: &[u8],
+    args: &[String],
+) -> LxResult<Option<(String, Vec<String>)>> {
+    let header = &data[..data.len().min(HEADER_SIZE)];
+    let mut new_args = Vec::new();
+    let mut argv0 = None;
+    if let Some(format) = find_binfmt(image, header) {
+        new_args.push(format.interpreter);
+        if format.preserve_argv0 {
+            argv0 = args.first().cloned();
+        }
+    } else if let Some(line) = header.strip_prefix(b"#!") {
+        let (line, cut) = match line.iter().position(|&c| c == b'\n') {
+            Some(end) => (&line[..end], false),
+            None => (line, data.len() > HEADER_SIZE),
+        };
+        let is_blank = |c: char| c == ' ' || c == '\t';
+        let line = core::str::from_utf8(line).map_err(|_| LxError::ENOEXEC)?;
+        let mut parts = line.trim_matches(is_blank).splitn(2, is_blank);
+        let interp = parts.next().unwrap_or("");
+        let arg = parts.next().map(|arg| arg.trim_start_matches(is_blank));
+        // a line cut by the end of the header must hold the whole interpreter
+        if interp.is_empty() || (cut && arg.is_none()) {
+            return Err(LxError::ENOEXEC);
+        }
+        new_args.push(String::from(interp));
+        // the rest of the line is a single argument
+        new_args.extend(arg.filter(|arg| !arg.is_empty()).map(String::from));
+    } else {
+        return Ok(None);
+    }
+    let interp = new_args[0].clone();
+    new_args.push(String::from(image));
+    new_args.extend(argv0);
+    new_args.extend(args.iter().skip(1).cloned());
+    Ok(Some((interp, new_args)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| String::from(*s)).collect()
+    }
+
+    fn interpret(image: &str, data: &[u8], args: &[&str]) -> LxResult<Option<Vec<String>>> {
+        let result = script_interpreter(image, data, &strings(args))?;
+        Ok(result.map(|(interp, new_args)| {
+            assert_eq!(interp, new_args[0]);
+            new_args
+        }))
+    }
+
+    #[test]
+    fn shebang_line() {
+        let args = interpret("/a.sh", b"#!/bin/sh\necho\n", &["a.sh", "x"]).unwrap();
+        assert_eq!(args, Some(strings(&["/bin/sh", "/a.sh", "x"])));
+        // the rest of the line is a single argument
+        let data = b"#! \t/bin/sh \t-e -x \t\necho\n";
+        let args = interpret("/a.sh", data, &["a.sh"]).unwrap();
+        assert_eq!(args, Some(strings(&["/bin/sh", "-e -x", "/a.sh"])));
+        // a file ending with the line
+        let args = interpret("/a.sh", b"#!/bin/sh", &["a.sh"]).unwrap();
+        assert_eq!(args, Some(strings(&["/bin/sh", "/a.sh"])));
+    }
+
+    #[test]
+    fn not_a_script() {
+        assert!(matches!(
+            interpret("/a", b"\x7fELF\x02\x01", &["a"]),
+            Ok(None)
+        ));
+        assert!(matches!(interpret("/a", b"", &["a"]), Ok(None)));
+        assert!(!is_interpreted("/a", b"\x7fELF\x02\x01"));
+        assert!(is_interpreted("/a", b"#!/bin/sh\n"));
+    }
+
+    #[test]
+    fn empty_interpreter() {
+        for data in [&b"#!\n"[..], b"#! \t\n", b"#!"].iter() {
+            assert!(matches!(
+                interpret("/a.sh", data, &["a.sh"]),
+                Err(LxError::ENOEXEC)
+            ));
+        }
+    }
+
+    #[test]
+    fn line_cut_by_header() {
+        // only the argument is cut
+        let mut data = Vec::from(&b"#!/bin/sh "[..]);
+        data.resize(HEADER_SIZE + 10, b'a');
+        let args = interpret("/a.sh", &data, &["a.sh"]).unwrap().unwrap();
+        assert_eq!(args[0], "/bin/sh");
+        assert_eq!(args[1].len(), HEADER_SIZE - "#!/bin/sh ".len());
+        assert_eq!(args[2], "/a.sh");
+        // the interpreter is cut
+        let mut data = Vec::from(&b"#!/"[..]);
+        data.resize(HEADER_SIZE + 10, b'b');
+        assert!(matches!(
+            interpret("/a.sh", &data, &["a.sh"]),
+            Err(LxError::ENOEXEC)
+        ));
+    }
+
+    #[test]
+    fn registered_format() {
+        let spec = ":loader-test:E::loader-test::/bin/run:P";
+        register_binfmt(BinaryFormat::parse(spec).unwrap()).unwrap();
+        let args = interpret("/bin/prog.loader-test", b"", &["prog", "x"]);
+        unregister_binfmt("loader-test").unwrap();
+        let expected = ["/bin/run", "/bin/prog.loader-test", "prog", "x"];
+        assert_eq!(args.unwrap(), Some(strings(&expected)));
     }
 }
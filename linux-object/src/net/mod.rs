@@ -14,6 +14,10 @@ pub use tcp::*;
 pub mod udp;
 pub use udp::*;
 
+/// Unix domain sockets
+pub mod unix;
+pub use unix::*;
+
 use spin::Mutex;
 /// missing documentation
 // pub mod raw;
@@ -114,8 +118,8 @@ impl Drop for GlobalSocketHandle {
 
 use kernel_hal::net::get_net_device;
 
-/// miss doc
-fn poll_ifaces() {
+/// Poll all network interfaces, so that sockets see incoming packets
+pub(crate) fn poll_ifaces() {
     for iface in get_net_device().iter() {
         match iface.poll() {
             Ok(_) => {}
@@ -172,10 +176,94 @@ pub unsafe fn from_cstr(s: *const u8) -> &'static str {
 // ============= Util =============
 
 use crate::error::*;
+use crate::fs::FileLike;
+use crate::sync::EventBus;
 use alloc::boxed::Box;
 use alloc::fmt::Debug;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use async_trait::async_trait;
+use bitflags::bitflags;
+
+// ============= Message =============
+
+/// Credentials of a process passed with `SCM_CREDENTIALS`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UCred {
+    /// process ID of the sender
+    pub pid: u32,
+    /// user ID of the sender
+    pub uid: u32,
+    /// group ID of the sender
+    pub gid: u32,
+}
+
+/// An open file or socket passed with `SCM_RIGHTS`
+#[derive(Clone)]
+pub enum PassedFd {
+    /// a file, pipe, etc.
+    File(Arc<dyn FileLike>),
+    /// a socket
+    Socket(Arc<Mutex<dyn Socket>>),
+}
+
+/// Control messages sent along with the data by `sendmsg` and `recvmsg`
+#[derive(Clone, Default)]
+pub struct Ancillary {
+    /// file descriptors to install in the receiving process
+    pub rights: Vec<PassedFd>,
+    /// credentials of the sender
+    pub credentials: Option<UCred>,
+}
+
+impl Ancillary {
+    /// Whether no control message is present
+    pub fn is_empty(&self) -> bool {
+        self.rights.is_empty() && self.credentials.is_none()
+    }
+}
+
+bitflags! {
+    /// Flags of `sendmsg` and `recvmsg`
+    pub struct MsgFlags: usize {
+        /// look at the data without removing it
+        const PEEK = 2;
+        /// some control messages were discarded for lack of space
+        const CTRUNC = 8;
+        /// the datagram was longer than the buffer
+        const TRUNC = 0x20;
+        /// do not block
+        const DONTWAIT = 0x40;
+        /// do not raise SIGPIPE on a broken stream
+        const NOSIGNAL = 0x4000;
+    }
+}
+
+/// A message received by `recvmsg`
+pub struct ReceivedMsg {
+    /// number of bytes received
+    pub len: usize,
+    /// address of the sender
+    pub endpoint: Endpoint,
+    /// control messages received
+    pub ancillary: Ancillary,
+    /// `MSG_TRUNC` or `MSG_CTRUNC` reported back
+    pub flags: MsgFlags,
+}
+
+/// Result of [`Socket::try_recv_msg`]
+pub enum TryRecv {
+    /// A message was received
+    Received(ReceivedMsg),
+    /// Nothing to receive yet, `READABLE` is set on the event bus once something arrives
+    Wait(Arc<Mutex<EventBus>>),
+    /// The socket only receives with [`Socket::recv_msg`]
+    Unsupported,
+}
+
+// ============= Message =============
+
 // use core::ops::{Deref, DerefMut};
 /// Common methods that a socket must have
 #[async_trait]
@@ -227,4 +315,34 @@ pub trait Socket: Send + Sync + Debug {
         warn!("ioctl is unimplemented for this socket");
         Ok(0)
     }
+    /// Send data with control messages, only sockets passing file descriptors take them
+    fn send_msg(
+        &self,
+        data: &[u8],
+        sendto_endpoint: Option<Endpoint>,
+        ancillary: Ancillary,
+        _flags: MsgFlags,
+    ) -> SysResult {
+        if !ancillary.is_empty() {
+            return Err(LxError::EINVAL);
+        }
+        self.write(data, sendto_endpoint)
+    }
+    /// Receive data with control messages if some are ready, without waiting
+    ///
+    /// The caller waits on the returned event bus without holding the socket lock,
+    /// so other threads can use the socket meanwhile.
+    fn try_recv_msg(&self, _data: &mut [u8], _flags: MsgFlags) -> LxResult<TryRecv> {
+        Ok(TryRecv::Unsupported)
+    }
+    /// Receive data with control messages
+    async fn recv_msg(&self, data: &mut [u8], _flags: MsgFlags) -> LxResult<ReceivedMsg> {
+        let (result, endpoint) = self.read(data).await;
+        Ok(ReceivedMsg {
+            len: result?,
+            endpoint,
+            ancillary: Ancillary::default(),
+            flags: MsgFlags::empty(),
+        })
+    }
 }
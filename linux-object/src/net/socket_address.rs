@@ -4,7 +4,9 @@ use core::cmp::min;
 use core::mem::size_of;
 
 // crate
-use crate::error::LxError;
+use crate::error::{LxError, LxResult};
+use alloc::string::String;
+use alloc::vec::Vec;
 // use crate::net::Endpoint;
 
 // smoltcp
@@ -103,6 +105,25 @@ pub enum Endpoint {
     LinkLevel(LinkLevelEndpoint),
     /// missing documentation
     Netlink(NetlinkEndpoint),
+    /// Unix domain socket address
+    Unix(UnixEndpoint),
+}
+
+/// Address of a Unix domain socket, see [linux man unix(7)](https://www.man7.org/linux/man-pages/man7/unix.7.html)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnixEndpoint {
+    /// not bound to any name
+    Unnamed,
+    /// bound to a socket file, the path is absolute
+    Path(String),
+    /// bound to a name outside the filesystem, without the leading NUL byte
+    Abstract(Vec<u8>),
+}
+
+impl Default for UnixEndpoint {
+    fn default() -> Self {
+        UnixEndpoint::Unnamed
+    }
 }
 
 /// missing documentation
@@ -184,6 +205,25 @@ impl From<Endpoint> for SockAddr {
                     nl_groups: netlink.multicast_groups_mask,
                 },
             }
+        } else if let Endpoint::Unix(unix) = endpoint {
+            let mut sun_path = [0; 108];
+            match unix {
+                UnixEndpoint::Unnamed => {}
+                UnixEndpoint::Path(path) => {
+                    let len = path.len().min(sun_path.len() - 1);
+                    sun_path[..len].copy_from_slice(&path.as_bytes()[..len]);
+                }
+                UnixEndpoint::Abstract(name) => {
+                    let len = name.len().min(sun_path.len() - 1);
+                    sun_path[1..len + 1].copy_from_slice(&name[..len]);
+                }
+            }
+            SockAddr {
+                addr_un: SockAddrUn {
+                    sun_family: AddressFamily::Unix.into(),
+                    sun_path,
+                },
+            }
         } else {
             unimplemented!("not match");
         }
@@ -195,6 +235,10 @@ pub fn sockaddr_to_endpoint(addr: SockAddr, len: usize) -> Result<Endpoint, LxEr
     if len < size_of::<u16>() {
         return Err(LxError::EINVAL);
     }
+    #[allow(unsafe_code)]
+    if AddressFamily::from(unsafe { addr.family }) == AddressFamily::Unix {
+        return unix_endpoint(unsafe { &addr.addr_un }, len).map(Endpoint::Unix);
+    }
     // let addr = unsafe { vm.check_read_ptr(addr)? };
     if len < addr.len()? {
         return Err(LxError::EINVAL);
@@ -209,7 +253,6 @@ pub fn sockaddr_to_endpoint(addr: SockAddr, len: usize) -> Result<Endpoint, LxEr
                 ));
                 Ok(Endpoint::Ip((addr, port).into()))
             }
-            AddressFamily::Unix => Err(LxError::EINVAL),
             // AddressFamily::Packet => Ok(Endpoint::LinkLevel(LinkLevelEndpoint::new(
             //     addr.addr_ll.sll_ifindex as usize,
             // ))),
@@ -222,6 +265,21 @@ pub fn sockaddr_to_endpoint(addr: SockAddr, len: usize) -> Result<Endpoint, LxEr
     }
 }
 
+/// Parse a `sockaddr_un` of `len` bytes
+fn unix_endpoint(addr: &SockAddrUn, len: usize) -> Result<UnixEndpoint, LxError> {
+    let path_len = len.min(size_of::<SockAddrUn>()) - size_of::<u16>();
+    let path = &addr.sun_path[..path_len];
+    match path.first() {
+        None => Ok(UnixEndpoint::Unnamed),
+        Some(0) => Ok(UnixEndpoint::Abstract(path[1..].to_vec())),
+        Some(_) => {
+            let end = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+            let path = core::str::from_utf8(&path[..end]).map_err(|_| LxError::EINVAL)?;
+            Ok(UnixEndpoint::Path(path.into()))
+        }
+    }
+}
+
 impl SockAddr {
     fn len(&self) -> Result<usize, LxError> {
         #[allow(unsafe_code)]
@@ -229,7 +287,16 @@ impl SockAddr {
             AddressFamily::Internet => Ok(size_of::<SockAddrIn>()),
             AddressFamily::Packet => Ok(size_of::<SockAddrLl>()),
             AddressFamily::Netlink => Ok(size_of::<SockAddrNl>()),
-            AddressFamily::Unix => Err(LxError::EINVAL),
+            AddressFamily::Unix => {
+                #[allow(unsafe_code)]
+                let sun_path = unsafe { &self.addr_un.sun_path };
+                // a path is followed by its NUL byte, an abstract name ends at the last non-zero one
+                let len = match sun_path[0] {
+                    0 => sun_path.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1),
+                    _ => sun_path.iter().position(|&b| b == 0).map_or(108, |i| i + 1),
+                };
+                Ok(size_of::<u16>() + len)
+            }
             _ => Err(LxError::EINVAL),
         }
     }
@@ -249,9 +316,15 @@ impl SockAddr {
         }
 
         let max_addr_len = addr_len.read()? as usize;
-        let full_len = self.len()?;
+        let full_len = self.write_truncated(addr, max_addr_len)?;
+        addr_len.write(full_len as u32)?;
+        Ok(0)
+    }
 
-        let written_len = min(max_addr_len, full_len);
+    /// Write at most `max_len` bytes of the address to user memory, return its full length
+    pub fn write_truncated(self, addr: UserOutPtr<SockAddr>, max_len: usize) -> LxResult<usize> {
+        let full_len = self.len()?;
+        let written_len = min(max_len, full_len);
         if written_len > 0 {
             #[allow(unsafe_code)]
             let source = unsafe {
@@ -261,8 +334,7 @@ impl SockAddr {
             let mut addr: UserOutPtr<u8> = unsafe { core::mem::transmute(addr) };
             addr.write_array(source)?;
         }
-        addr_len.write(full_len as u32)?;
-        Ok(0)
+        Ok(full_len)
     }
 }
 
@@ -0,0 +1,568 @@
+//! Unix domain sockets, see [linux man unix(7)](https://www.man7.org/linux/man-pages/man7/unix.7.html)
+#![deny(missing_docs)]
+
+use crate::error::{LxError, LxResult};
+use crate::net::{
+    Ancillary, Endpoint, MsgFlags, PassedFd, ReceivedMsg, Socket, SysResult, TryRecv, UCred,
+    UnixEndpoint,
+};
+use crate::sync::{wait_for_event, Event, EventBus};
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use async_trait::async_trait;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Max number of connections waiting to be accepted by a listening socket
+const MAX_BACKLOG: usize = 128;
+
+/// `SOL_SOCKET` level of socket options
+const SOL_SOCKET: usize = 1;
+/// Option to receive the credentials of the sender with every message
+const SO_PASSCRED: usize = 16;
+
+/// Get the file status flags
+const F_GETFL: usize = 3;
+/// Set the file status flags
+const F_SETFL: usize = 4;
+/// `O_RDWR` reported by `F_GETFL`
+const O_RDWR: usize = 2;
+/// Non-blocking mode for `F_GETFL` and `F_SETFL`
+const O_NONBLOCK: usize = 0o4000;
+
+/// Names bound by sockets, both socket files and abstract ones
+static NAMES: Mutex<Vec<(UnixEndpoint, Weak<Shared>)>> = Mutex::new(Vec::new());
+
+/// Find the socket bound to `name`
+fn lookup(name: &UnixEndpoint) -> Option<Arc<Shared>> {
+    NAMES
+        .lock()
+        .iter()
+        .find(|(bound, _)| bound == name)
+        .and_then(|(_, shared)| shared.upgrade())
+}
+
+/// Type of a Unix domain socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnixSocketType {
+    /// `SOCK_STREAM`, a connected byte stream
+    Stream,
+    /// `SOCK_DGRAM`, messages which keep their boundaries
+    Datagram,
+}
+
+/// Data sent to a socket and not read yet
+#[derive(Clone)]
+struct Message {
+    data: Vec<u8>,
+    /// bytes of a stream message already read
+    read: usize,
+    /// file descriptors passed along
+    rights: Vec<PassedFd>,
+    /// identity of the sender
+    credentials: UCred,
+    /// name of the sender
+    from: UnixEndpoint,
+}
+
+/// Receiving side of a socket, shared with the sockets sending to it
+#[derive(Default)]
+struct Inbox {
+    messages: VecDeque<Message>,
+    /// connections waiting to be accepted, `Some` once listening
+    backlog: Option<VecDeque<UnixSocketState>>,
+    /// nothing more will arrive, the peer is gone or shut down
+    eof: bool,
+    /// the socket is gone or shut down, nothing can be sent to it anymore
+    closed: bool,
+}
+
+impl Inbox {
+    fn readable(&self) -> bool {
+        !self.messages.is_empty()
+            || self.eof
+            || self.backlog.as_ref().map_or(false, |b| !b.is_empty())
+    }
+}
+
+/// The part of a socket which other sockets reach by name or connection
+struct Shared {
+    inbox: Mutex<Inbox>,
+    eventbus: Arc<Mutex<EventBus>>,
+    /// identity of the creator of the socket, sent with its messages
+    credentials: UCred,
+}
+
+impl Shared {
+    fn new(credentials: UCred) -> Arc<Self> {
+        Arc::new(Shared {
+            inbox: Mutex::new(Inbox::default()),
+            eventbus: EventBus::new(),
+            credentials,
+        })
+    }
+
+    /// Update the readable event after `inbox` changed
+    fn notify(&self, inbox: &Inbox) {
+        let mut eventbus = self.eventbus.lock();
+        if inbox.readable() {
+            eventbus.set(Event::READABLE);
+        } else {
+            eventbus.clear(Event::READABLE);
+        }
+    }
+}
+
+/// Mutable state of a socket
+#[derive(Default)]
+struct UnixInner {
+    /// name the socket is bound to
+    local: UnixEndpoint,
+    /// name of the peer, `Some` once connected
+    remote: Option<UnixEndpoint>,
+    /// where the data sent goes by default
+    peer: Option<Arc<Shared>>,
+    /// `SO_PASSCRED`
+    pass_credentials: bool,
+    /// no more data can be sent after shutdown
+    shut_down: bool,
+}
+
+/// A Unix domain socket
+pub struct UnixSocketState {
+    type_: UnixSocketType,
+    shared: Arc<Shared>,
+    inner: Mutex<UnixInner>,
+    nonblock: AtomicBool,
+}
+
+impl fmt::Debug for UnixSocketState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.inner.lock();
+        f.debug_struct("UnixSocketState")
+            .field("type", &self.type_)
+            .field("local", &inner.local)
+            .field("remote", &inner.remote)
+            .finish()
+    }
+}
+
+impl UnixSocketState {
+    /// Create an unbound socket sending messages on behalf of `credentials`
+    pub fn new(type_: UnixSocketType, credentials: UCred, nonblock: bool) -> Self {
+        UnixSocketState {
+            type_,
+            shared: Shared::new(credentials),
+            inner: Mutex::new(UnixInner::default()),
+            nonblock: AtomicBool::new(nonblock),
+        }
+    }
+
+    /// Create a pair of unnamed sockets connected to each other, see `socketpair`
+    pub fn new_pair(type_: UnixSocketType, credentials: UCred, nonblock: bool) -> (Self, Self) {
+        let a = Self::new(type_, credentials, nonblock);
+        let b = Self::new(type_, credentials, nonblock);
+        for (this, other) in [(&a, &b), (&b, &a)] {
+            let mut inner = this.inner.lock();
+            inner.remote = Some(UnixEndpoint::Unnamed);
+            inner.peer = Some(other.shared.clone());
+        }
+        (a, b)
+    }
+
+    /// The socket the data sent to `endpoint`, or by default, goes to
+    fn target(&self, inner: &UnixInner, endpoint: Option<Endpoint>) -> LxResult<Arc<Shared>> {
+        match (self.type_, endpoint) {
+            (UnixSocketType::Stream, None) => inner.peer.clone().ok_or(LxError::ENOTCONN),
+            (UnixSocketType::Stream, Some(_)) if inner.peer.is_some() => Err(LxError::EISCONN),
+            (UnixSocketType::Stream, Some(_)) => Err(LxError::EOPNOTSUPP),
+            (UnixSocketType::Datagram, None) => inner.peer.clone().ok_or(LxError::EDESTADDRREQ),
+            (UnixSocketType::Datagram, Some(Endpoint::Unix(name))) => {
+                lookup(&name).ok_or(LxError::ECONNREFUSED)
+            }
+            (UnixSocketType::Datagram, Some(_)) => Err(LxError::EINVAL),
+        }
+    }
+
+    /// Queue a message to the peer or the socket bound to `endpoint`
+    fn send(&self, data: &[u8], endpoint: Option<Endpoint>, ancillary: Ancillary) -> SysResult {
+        let inner = self.inner.lock();
+        if inner.shut_down {
+            return Err(LxError::EPIPE);
+        }
+        let target = self.target(&inner, endpoint)?;
+        let mut inbox = target.inbox.lock();
+        if inbox.closed {
+            return match self.type_ {
+                UnixSocketType::Stream => Err(LxError::EPIPE),
+                UnixSocketType::Datagram => Err(LxError::ECONNREFUSED),
+            };
+        }
+        // a stream has no record of empty writes
+        let stream = self.type_ == UnixSocketType::Stream;
+        if stream && data.is_empty() && ancillary.rights.is_empty() {
+            return Ok(0);
+        }
+        inbox.messages.push_back(Message {
+            data: data.to_vec(),
+            read: 0,
+            rights: ancillary.rights,
+            credentials: ancillary.credentials.unwrap_or(self.shared.credentials),
+            from: inner.local.clone(),
+        });
+        target.notify(&inbox);
+        Ok(data.len())
+    }
+
+    /// Read the messages queued without blocking, `None` if there are none yet
+    fn try_recv(&self, data: &mut [u8], flags: MsgFlags) -> LxResult<Option<ReceivedMsg>> {
+        let (pass_credentials, remote) = {
+            let inner = self.inner.lock();
+            (inner.pass_credentials, inner.remote.clone())
+        };
+        let mut inbox = self.shared.inbox.lock();
+        if inbox.messages.is_empty() {
+            if inbox.eof {
+                return Ok(Some(ReceivedMsg {
+                    len: 0,
+                    endpoint: Endpoint::Unix(remote.unwrap_or_default()),
+                    ancillary: Ancillary::default(),
+                    flags: MsgFlags::empty(),
+                }));
+            }
+            if self.type_ == UnixSocketType::Stream && remote.is_none() {
+                return Err(LxError::ENOTCONN);
+            }
+            return Ok(None);
+        }
+        let msg = match self.type_ {
+            UnixSocketType::Stream => Self::recv_stream(&mut inbox, data, flags),
+            UnixSocketType::Datagram => Self::recv_datagram(&mut inbox, data, flags),
+        };
+        self.shared.notify(&inbox);
+        Ok(msg.map(|(mut msg, credentials)| {
+            if pass_credentials {
+                msg.ancillary.credentials = Some(credentials);
+            }
+            msg
+        }))
+    }
+
+    /// Read bytes from the stream, up to the next message carrying file descriptors
+    fn recv_stream(
+        inbox: &mut Inbox,
+        data: &mut [u8],
+        flags: MsgFlags,
+    ) -> Option<(ReceivedMsg, UCred)> {
+        let peek = flags.contains(MsgFlags::PEEK);
+        let mut len = 0;
+        let mut consumed = 0;
+        let mut rights = Vec::new();
+        let mut credentials = UCred::default();
+        let mut from = UnixEndpoint::Unnamed;
+        for (i, msg) in inbox.messages.iter_mut().enumerate() {
+            if i > 0 && (len == data.len() || !msg.rights.is_empty()) {
+                break;
+            }
+            if i == 0 {
+                rights = if peek {
+                    msg.rights.clone()
+                } else {
+                    core::mem::take(&mut msg.rights)
+                };
+                credentials = msg.credentials;
+                from = msg.from.clone();
+            }
+            let n = (data.len() - len).min(msg.data.len() - msg.read);
+            data[len..len + n].copy_from_slice(&msg.data[msg.read..msg.read + n]);
+            len += n;
+            if !peek {
+                msg.read += n;
+                if msg.read == msg.data.len() {
+                    consumed += 1;
+                }
+            }
+        }
+        inbox.messages.drain(..consumed);
+        let msg = ReceivedMsg {
+            len,
+            endpoint: Endpoint::Unix(from),
+            ancillary: Ancillary {
+                rights,
+                credentials: None,
+            },
+            flags: MsgFlags::empty(),
+        };
+        Some((msg, credentials))
+    }
+
+    /// Read the next datagram, whatever does not fit in `data` is lost
+    fn recv_datagram(
+        inbox: &mut Inbox,
+        data: &mut [u8],
+        flags: MsgFlags,
+    ) -> Option<(ReceivedMsg, UCred)> {
+        let msg = if flags.contains(MsgFlags::PEEK) {
+            inbox.messages.front().cloned()
+        } else {
+            inbox.messages.pop_front()
+        }?;
+        let len = data.len().min(msg.data.len());
+        data[..len].copy_from_slice(&msg.data[..len]);
+        let truncated = len < msg.data.len();
+        let credentials = msg.credentials;
+        let msg = ReceivedMsg {
+            // with MSG_TRUNC the real length of the datagram is returned
+            len: if truncated && flags.contains(MsgFlags::TRUNC) {
+                msg.data.len()
+            } else {
+                len
+            },
+            endpoint: Endpoint::Unix(msg.from),
+            ancillary: Ancillary {
+                rights: msg.rights,
+                credentials: None,
+            },
+            flags: if truncated {
+                MsgFlags::TRUNC
+            } else {
+                MsgFlags::empty()
+            },
+        };
+        Some((msg, credentials))
+    }
+
+    fn would_block(&self, flags: MsgFlags) -> bool {
+        flags.contains(MsgFlags::DONTWAIT) || self.nonblock.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for UnixSocketState {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut();
+        if let Some(peer) = &inner.peer {
+            let mut inbox = peer.inbox.lock();
+            inbox.eof = true;
+            peer.notify(&inbox);
+        }
+        let backlog = {
+            let mut inbox = self.shared.inbox.lock();
+            inbox.closed = true;
+            inbox.backlog.take()
+        };
+        // connections never accepted are refused
+        drop(backlog);
+        let shared = Arc::as_ptr(&self.shared);
+        NAMES
+            .lock()
+            .retain(|(_, bound)| bound.strong_count() > 0 && Weak::as_ptr(bound) != shared);
+    }
+}
+
+#[async_trait]
+impl Socket for UnixSocketState {
+    async fn read(&self, data: &mut [u8]) -> (SysResult, Endpoint) {
+        match self.recv_msg(data, MsgFlags::empty()).await {
+            Ok(msg) => (Ok(msg.len), msg.endpoint),
+            Err(err) => (Err(err), Endpoint::Unix(UnixEndpoint::Unnamed)),
+        }
+    }
+
+    fn write(&self, data: &[u8], sendto_endpoint: Option<Endpoint>) -> SysResult {
+        self.send(data, sendto_endpoint, Ancillary::default())
+    }
+
+    fn poll(&self) -> (bool, bool, bool) {
+        let inner = self.inner.lock();
+        let readable = self.shared.inbox.lock().readable();
+        let writable = match &inner.peer {
+            Some(peer) => !inner.shut_down && !peer.inbox.lock().closed,
+            None => self.type_ == UnixSocketType::Datagram,
+        };
+        (readable, writable, false)
+    }
+
+    async fn connect(&self, endpoint: Endpoint) -> SysResult {
+        let name = match endpoint {
+            Endpoint::Unix(name) => name,
+            _ => return Err(LxError::EINVAL),
+        };
+        let mut inner = self.inner.lock();
+        if self.type_ == UnixSocketType::Stream && inner.peer.is_some() {
+            return Err(LxError::EISCONN);
+        }
+        let target = lookup(&name).ok_or(LxError::ECONNREFUSED)?;
+        let mut inbox = target.inbox.lock();
+        if inbox.closed {
+            return Err(LxError::ECONNREFUSED);
+        }
+        if self.type_ == UnixSocketType::Stream {
+            let backlog = inbox.backlog.as_mut().ok_or(LxError::ECONNREFUSED)?;
+            if backlog.len() >= MAX_BACKLOG {
+                return Err(LxError::EAGAIN);
+            }
+            // the end of the connection handed out by accept
+            let server = UnixSocketState::new(self.type_, target.credentials, false);
+            {
+                let mut server_inner = server.inner.lock();
+                server_inner.local = name.clone();
+                server_inner.remote = Some(inner.local.clone());
+                server_inner.peer = Some(self.shared.clone());
+            }
+            inner.peer = Some(server.shared.clone());
+            backlog.push_back(server);
+            target.notify(&inbox);
+        } else {
+            inner.peer = Some(target.clone());
+        }
+        inner.remote = Some(name);
+        Ok(0)
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> SysResult {
+        let name = match endpoint {
+            Endpoint::Unix(name) => name,
+            _ => return Err(LxError::EINVAL),
+        };
+        let inner = self.inner.get_mut();
+        if inner.local != UnixEndpoint::Unnamed {
+            return Err(LxError::EINVAL);
+        }
+        let name = match name {
+            // autobind to a unique abstract name
+            UnixEndpoint::Unnamed => {
+                static NEXT_AUTOBIND: AtomicUsize = AtomicUsize::new(0);
+                let id = NEXT_AUTOBIND.fetch_add(1, Ordering::Relaxed) & 0xf_ffff;
+                UnixEndpoint::Abstract(format!("{:05x}", id).into_bytes())
+            }
+            name => name,
+        };
+        let mut names = NAMES.lock();
+        names.retain(|(_, bound)| bound.strong_count() > 0);
+        if names.iter().any(|(bound, _)| *bound == name) {
+            return Err(LxError::EADDRINUSE);
+        }
+        names.push((name.clone(), Arc::downgrade(&self.shared)));
+        inner.local = name;
+        Ok(0)
+    }
+
+    fn listen(&mut self) -> SysResult {
+        if self.type_ != UnixSocketType::Stream {
+            return Err(LxError::EOPNOTSUPP);
+        }
+        let inner = self.inner.get_mut();
+        if inner.local == UnixEndpoint::Unnamed || inner.peer.is_some() {
+            return Err(LxError::EINVAL);
+        }
+        let mut inbox = self.shared.inbox.lock();
+        inbox.backlog.get_or_insert_with(VecDeque::new);
+        Ok(0)
+    }
+
+    fn shutdown(&self) -> SysResult {
+        let mut inner = self.inner.lock();
+        inner.shut_down = true;
+        if let Some(peer) = &inner.peer {
+            let mut inbox = peer.inbox.lock();
+            inbox.eof = true;
+            peer.notify(&inbox);
+        }
+        let mut inbox = self.shared.inbox.lock();
+        inbox.eof = true;
+        inbox.closed = true;
+        self.shared.notify(&inbox);
+        Ok(0)
+    }
+
+    async fn accept(&mut self) -> LxResult<(Arc<Mutex<dyn Socket>>, Endpoint)> {
+        loop {
+            {
+                let mut inbox = self.shared.inbox.lock();
+                let backlog = inbox.backlog.as_mut().ok_or(LxError::EINVAL)?;
+                if let Some(socket) = backlog.pop_front() {
+                    self.shared.notify(&inbox);
+                    let remote = socket.inner.lock().remote.clone().unwrap_or_default();
+                    return Ok((Arc::new(Mutex::new(socket)), Endpoint::Unix(remote)));
+                }
+            }
+            if self.would_block(MsgFlags::empty()) {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.shared.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        Some(Endpoint::Unix(self.inner.lock().local.clone()))
+    }
+
+    fn remote_endpoint(&self) -> Option<Endpoint> {
+        self.inner.lock().remote.clone().map(Endpoint::Unix)
+    }
+
+    fn setsockopt(&mut self, level: usize, opt: usize, data: &[u8]) -> SysResult {
+        match (level, opt) {
+            (SOL_SOCKET, SO_PASSCRED) => {
+                let on = data.iter().any(|&b| b != 0);
+                self.inner.get_mut().pass_credentials = on;
+                Ok(0)
+            }
+            _ => {
+                warn!(
+                    "unix socket: setsockopt({}, {}) is unimplemented",
+                    level, opt
+                );
+                Ok(0)
+            }
+        }
+    }
+
+    fn fcntl(&self, cmd: usize, arg: usize) -> SysResult {
+        match cmd {
+            F_GETFL if self.nonblock.load(Ordering::Relaxed) => Ok(O_RDWR | O_NONBLOCK),
+            F_GETFL => Ok(O_RDWR),
+            F_SETFL => {
+                let nonblock = arg & O_NONBLOCK != 0;
+                self.nonblock.store(nonblock, Ordering::Relaxed);
+                Ok(0)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn send_msg(
+        &self,
+        data: &[u8],
+        endpoint: Option<Endpoint>,
+        ancillary: Ancillary,
+        _flags: MsgFlags,
+    ) -> SysResult {
+        self.send(data, endpoint, ancillary)
+    }
+
+    fn try_recv_msg(&self, data: &mut [u8], flags: MsgFlags) -> LxResult<TryRecv> {
+        match self.try_recv(data, flags)? {
+            Some(msg) => Ok(TryRecv::Received(msg)),
+            None if self.would_block(flags) => Err(LxError::EAGAIN),
+            None => Ok(TryRecv::Wait(self.shared.eventbus.clone())),
+        }
+    }
+
+    async fn recv_msg(&self, data: &mut [u8], flags: MsgFlags) -> LxResult<ReceivedMsg> {
+        loop {
+            if let Some(msg) = self.try_recv(data, flags)? {
+                return Ok(msg);
+            }
+            if self.would_block(flags) {
+                return Err(LxError::EAGAIN);
+            }
+            wait_for_event(self.shared.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+}
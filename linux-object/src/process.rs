@@ -1,28 +1,44 @@
 //! Linux Process
 
 use crate::{
+    cred::Credentials,
     error::{LxError, LxResult},
-    fs::{File, FileDesc, FileLike, OpenFlags, STDIN, STDOUT, KEYSTONE},
+    fs::{
+        File, FileDesc, FileLike, Keystone, MountTable, OpenFlags, Tty, CONSOLE_TTY, STDIN, STDOUT,
+    },
+    heap::ProgramBreak,
     ipc::*,
     net::Socket,
-    signal::{Signal as LinuxSignal, SignalAction},
+    signal::{
+        SigInfo, Signal as LinuxSignal, SignalAction, SignalActionFlags, SignalCode,
+        SignalDefaultAction, SignalQueue, Sigset, CLD_CONTINUED, CLD_EXITED, CLD_KILLED,
+        CLD_STOPPED, SIGRETURN_CODE, SIG_DFL, SIG_IGN,
+    },
+    sync::Event,
+    thread::ThreadExt,
+    time::{CpuTimer, ITimerWhich, IntervalTimer, PosixTimer, TimerValue},
 };
 use alloc::{
     boxed::Box,
+    collections::BTreeMap,
     string::String,
     sync::{Arc, Weak},
     vec::Vec,
 };
+use bitflags::bitflags;
 use core::sync::atomic::AtomicI32;
+use core::time::Duration;
 use hashbrown::HashMap;
-use kernel_hal::VirtAddr;
+use kernel_hal::{MMUFlags, VirtAddr};
+use numeric_enum_macro::numeric_enum;
 use rcore_fs::vfs::{FileSystem, INode};
 use smoltcp::socket::SocketHandle;
 use spin::{Mutex, MutexGuard};
 use zircon_object::{
     object::{KernelObject, KoID, Signal},
     signal::Futex,
-    task::{Job, Process, Status},
+    task::{Job, Process, Status, Task, Thread},
+    vm::{roundup_pages, VmAddressRegion, VmObject, PAGE_SIZE},
     ZxResult,
 };
 
@@ -35,13 +51,46 @@ pub trait ProcessExt {
     /// get linux process
     fn linux(&self) -> &LinuxProcess;
     /// fork from current linux process
-    fn fork_from(parent: &Arc<Self>, vfork: bool) -> ZxResult<Arc<Self>>;
+    ///
+    /// The new process is a child of `parent`, which is `source` itself unless `CLONE_PARENT`
+    /// makes it a sibling of `source`. It gets copies of the resources of `source`,
+    /// except those in `share` which it shares with `source`.
+    fn fork_from(source: &Arc<Self>, parent: &Arc<Self>, share: ForkShare) -> ZxResult<Arc<Self>>;
+    /// Send a signal to the process, it is taken by any thread not blocking it
+    fn send_signal(&self, info: SigInfo);
+    /// Take a pending process-directed signal not in `mask`
+    fn take_signal(&self, mask: &Sigset) -> Option<(LinuxSignal, SigInfo)>;
+    /// Terminate all threads of the process with `exit_code`
+    ///
+    /// The robust futexes of every thread are released and their child TIDs cleared first.
+    fn exit_linux(&self, exit_code: i64);
+    /// Terminate the process as the default action of `signal`
+    fn kill_by_signal(&self, signal: LinuxSignal);
+    /// Terminate all threads of the process but `current`, as by execve
+    fn kill_other_threads(&self, current: KoID);
+    /// Stop all threads of the process as the default action of `signal`
+    fn stop_by_signal(&self, signal: LinuxSignal);
+    /// Notify all threads whether they have a signal to deliver
+    fn update_signal_events(&self);
+    /// Set the interval timer `which` to `value` if given, return its previous setting
+    fn itimer(self: &Arc<Self>, which: ITimerWhich, value: Option<TimerValue>) -> TimerValue;
+    /// Account CPU time used by a thread of the process, which advances the CPU timers
+    fn account_cpu_time(&self, user: Duration, system: Duration);
 }
 
 impl ProcessExt for Process {
     fn create_linux(job: &Arc<Job>, rootfs: Arc<dyn FileSystem>) -> ZxResult<Arc<Self>> {
-        let linux_proc = LinuxProcess::new(rootfs);
-        Process::create_with_ext(job, "root", linux_proc)
+        let linux_proc = LinuxProcess::new(job, rootfs);
+        let proc = Process::create_with_ext(job, "root", linux_proc)?;
+        {
+            let mut inner = proc.linux().inner.lock();
+            inner.pid = proc.id();
+            // the leader of a new session on the console
+            inner.pgid = proc.id();
+            inner.sid = proc.id();
+        }
+        CONSOLE_TTY.set_controlling(&proc, true).unwrap();
+        Ok(proc)
     }
 
     fn linux(&self) -> &LinuxProcess {
@@ -51,87 +100,473 @@ impl ProcessExt for Process {
     /// [Fork] the process.
     ///
     /// [Fork]: http://man7.org/linux/man-pages/man2/fork.2.html
-    fn fork_from(parent: &Arc<Self>, vfork: bool) -> ZxResult<Arc<Self>> {
-        let linux_parent = parent.linux();
-        let mut linux_parent_inner = linux_parent.inner.lock();
+    fn fork_from(source: &Arc<Self>, parent: &Arc<Self>, share: ForkShare) -> ZxResult<Arc<Self>> {
+        let linux_source = source.linux();
+        // the tables are taken before `inner`, which is locked while one of them is
+        let fs = linux_source.fs.fork(share.contains(ForkShare::FS));
+        let files = linux_source.files.fork(share.contains(ForkShare::FILES));
+        let signal_actions = linux_source
+            .signal_actions
+            .fork(share.contains(ForkShare::SIGHAND));
+        let semaphores = if share.contains(ForkShare::SYSVSEM) {
+            linux_source.semaphores.share()
+        } else {
+            SharedTable::default()
+        };
+        let linux_source_inner = linux_source.inner.lock();
         let new_linux_proc = LinuxProcess {
-            root_inode: linux_parent.root_inode.clone(),
+            root_inode: linux_source.root_inode.clone(),
+            mounts: linux_source.mounts.clone(),
             parent: Arc::downgrade(parent),
+            fs,
+            files,
+            signal_actions,
+            semaphores,
             inner: Mutex::new(LinuxProcessInner {
-                execute_path: linux_parent_inner.execute_path.clone(),
-                current_working_directory: linux_parent_inner.current_working_directory.clone(),
-                files: linux_parent_inner.files.clone(),
-                signal_actions: linux_parent_inner.signal_actions.clone(),
+                execute_path: linux_source_inner.execute_path.clone(),
+                cmdline: linux_source_inner.cmdline.clone(),
+                credentials: linux_source_inner.credentials.clone(),
+                limits: linux_source_inner.limits.clone(),
+                pgid: linux_source_inner.pgid,
+                sid: linux_source_inner.sid,
+                terminal: linux_source_inner.terminal.clone(),
+                program_break: linux_source_inner.program_break.fork(),
+                // the trampoline is copied or shared with the address space
+                sigreturn_trampoline: linux_source_inner.sigreturn_trampoline,
+                termination_signal: Some(LinuxSignal::SIGCHLD),
                 ..Default::default()
             }),
+            enclave_quota: Mutex::new(EnclaveQuota {
+                enclaves: 0,
+                epm_pages: 0,
+                ..*linux_source.enclave_quota.lock()
+            }),
+        };
+        drop(linux_source_inner);
+        let new_proc = if share.contains(ForkShare::VM) {
+            Process::create_with_vmar(&source.job(), "", new_linux_proc, source.vmar())?
+        } else {
+            Process::create_with_ext(&source.job(), "", new_linux_proc)?
         };
-        let new_proc = Process::create_with_ext(&parent.job(), "", new_linux_proc)?;
-        linux_parent_inner
+        new_proc.linux().inner.lock().pid = new_proc.id();
+        // enclaves are not shared with the child, it gets its own keystone devices
+        if !share.contains(ForkShare::FILES) {
+            let files = new_proc.linux().files.get();
+            for file in files.lock().files.values_mut() {
+                if file.clone().downcast_arc::<Keystone>().is_ok() {
+                    *file = Keystone::new(&new_proc, file.flags());
+                }
+            }
+        }
+        parent
+            .linux()
+            .inner
+            .lock()
             .children
             .insert(new_proc.id(), new_proc.clone());
-        if !vfork {
-            new_proc.vmar().fork_from(&parent.vmar())?;
+        if !share.contains(ForkShare::VM) {
+            new_proc.vmar().fork_from(&source.vmar())?;
         }
 
         // notify parent on terminated
         let parent = parent.clone();
+        let child = Arc::downgrade(&new_proc);
         new_proc.add_signal_callback(Box::new(move |signal| {
             if signal.contains(Signal::PROCESS_TERMINATED) {
                 info!("Received signal: {:?}", signal);
                 parent.signal_set(Signal::SIGCHLD);
+                // the exit code is not readable while the child is terminating
+                if let Some(child) = child.upgrade() {
+                    if let Some(report) = child.linux().termination_signal() {
+                        let mut info = match child.linux().exit_signal() {
+                            Some(sig) => {
+                                SigInfo::child(child.id() as usize, CLD_KILLED, sig as i32)
+                            }
+                            None => SigInfo::child(child.id() as usize, CLD_EXITED, 0),
+                        };
+                        info.signo = report as i32;
+                        parent.send_signal(info);
+                    }
+                }
             }
             false
         }));
         Ok(new_proc)
     }
+
+    fn send_signal(&self, info: SigInfo) {
+        let signal = match info.signal() {
+            Some(signal) => signal,
+            None => return,
+        };
+        if !prepare_signal(self, signal) {
+            return;
+        }
+        self.linux().inner.lock().signal_pending.push(signal, info);
+        self.update_signal_events();
+    }
+
+    fn take_signal(&self, mask: &Sigset) -> Option<(LinuxSignal, SigInfo)> {
+        let taken = self.linux().inner.lock().signal_pending.pop(mask);
+        if taken.is_some() {
+            self.update_signal_events();
+        }
+        taken
+    }
+
+    fn exit_linux(&self, exit_code: i64) {
+        for thread in linux_threads(self) {
+            thread.release_exit_futexes();
+        }
+        self.exit(exit_code);
+    }
+
+    fn kill_by_signal(&self, signal: LinuxSignal) {
+        info!("process {} killed by {:?}", self.id(), signal);
+        self.linux().inner.lock().exit_signal = Some(signal);
+        self.exit_linux(128 + signal as i64);
+        // wake up threads blocked in syscalls
+        for thread in linux_threads(self) {
+            thread.signal_events().lock().set(Event::RECEIVE_SIGNAL);
+        }
+    }
+
+    fn kill_other_threads(&self, current: KoID) {
+        for thread in linux_threads(self) {
+            if thread.id() == current {
+                continue;
+            }
+            thread.release_exit_futexes();
+            thread.kill();
+            // wake it up if blocked in a syscall
+            thread.signal_events().lock().set(Event::RECEIVE_SIGNAL);
+        }
+    }
+
+    fn stop_by_signal(&self, signal: LinuxSignal) {
+        let threads = linux_threads(self);
+        {
+            let mut inner = self.linux().inner.lock();
+            if inner.stopped {
+                return;
+            }
+            info!("process {} stopped by {:?}", self.id(), signal);
+            inner.stopped = true;
+            inner.job_status = Some(JobStatus::Stopped(signal));
+            for thread in threads.iter() {
+                thread.suspend();
+            }
+            inner.stopped_threads = threads;
+        }
+        if let Some(parent) = self.linux().parent() {
+            parent.signal_set(Signal::SIGCHLD);
+            let info = SigInfo::child(self.id() as usize, CLD_STOPPED, signal as i32);
+            notify_parent(&parent, info);
+        }
+    }
+
+    fn update_signal_events(&self) {
+        for thread in linux_threads(self) {
+            thread.update_signal_events();
+        }
+    }
+
+    fn itimer(self: &Arc<Self>, which: ITimerWhich, value: Option<TimerValue>) -> TimerValue {
+        let mut inner = self.linux().inner.lock();
+        let timer = match which {
+            ITimerWhich::Real => {
+                let proc = Arc::downgrade(self);
+                let timer = inner.real_timer.get_or_insert_with(|| {
+                    IntervalTimer::new(Box::new(move |_| {
+                        if let Some(proc) = proc.upgrade() {
+                            proc.send_signal(SigInfo::kill(
+                                LinuxSignal::SIGALRM,
+                                SignalCode::KERNEL,
+                                0,
+                            ));
+                        }
+                    }))
+                });
+                let timer = timer.clone();
+                drop(inner);
+                return match value {
+                    Some(value) => timer.set(value),
+                    None => timer.get(),
+                };
+            }
+            ITimerWhich::Virtual => &mut inner.virtual_timer,
+            ITimerWhich::Prof => &mut inner.prof_timer,
+        };
+        match value {
+            Some(value) => timer.set(value),
+            None => timer.get(),
+        }
+    }
+
+    fn account_cpu_time(&self, user: Duration, system: Duration) {
+        let (virtual_expired, prof_expired, cpu_limit) = {
+            let mut inner = self.linux().inner.lock();
+            inner.user_time += user;
+            inner.system_time += system;
+            let virtual_expired = inner.virtual_timer.tick(user);
+            let prof_expired = inner.prof_timer.tick(user + system);
+            // SIGXCPU every second beyond the soft limit, SIGKILL at the hard one
+            let seconds = (inner.user_time + inner.system_time).as_secs();
+            let limit = inner.limits.table[Resource::Cpu as usize];
+            let cpu_limit = if seconds >= limit.max {
+                Some(LinuxSignal::SIGKILL)
+            } else if seconds >= limit.cur && inner.cpu_limit_signaled < Some(seconds) {
+                inner.cpu_limit_signaled = Some(seconds);
+                Some(LinuxSignal::SIGXCPU)
+            } else {
+                None
+            };
+            (virtual_expired > 0, prof_expired > 0, cpu_limit)
+        };
+        if virtual_expired {
+            let info = SigInfo::kill(LinuxSignal::SIGVTALRM, SignalCode::KERNEL, 0);
+            self.send_signal(info);
+        }
+        if prof_expired {
+            self.send_signal(SigInfo::kill(LinuxSignal::SIGPROF, SignalCode::KERNEL, 0));
+        }
+        if let Some(signal) = cpu_limit {
+            self.send_signal(SigInfo::kill(signal, SignalCode::KERNEL, 0));
+        }
+    }
 }
 
-/// Wait for state changes in a child of the calling process, and obtain information about
-/// the child whose state has changed.
+/// All threads of `proc`.
+fn linux_threads(proc: &Process) -> Vec<Arc<Thread>> {
+    proc.thread_ids()
+        .into_iter()
+        .filter_map(|tid| proc.get_child(tid).ok()?.downcast_arc::<Thread>().ok())
+        .collect()
+}
+
+/// Linux processes in `job`
+pub fn linux_processes(job: &Arc<Job>) -> Vec<Arc<Process>> {
+    job.process_ids()
+        .into_iter()
+        .filter_map(|id| job.get_child(id).ok())
+        .filter_map(|obj| obj.downcast_arc::<Process>().ok())
+        .filter(|proc| proc.ext().is::<LinuxProcess>())
+        .collect()
+}
+
+/// Linux processes in `job` of the process group `pgid`
+pub fn process_group(job: &Arc<Job>, pgid: KoID) -> Vec<Arc<Process>> {
+    linux_processes(job)
+        .into_iter()
+        .filter(|proc| proc.linux().pgid() == pgid)
+        .collect()
+}
+
+/// Send SIGCHLD about a stopped or continued child, unless the parent asked not to.
+fn notify_parent(parent: &Arc<Process>, info: SigInfo) {
+    let action = parent.linux().signal_action(LinuxSignal::SIGCHLD);
+    if !action.flags.contains(SignalActionFlags::NOCLDSTOP) {
+        parent.send_signal(info);
+    }
+}
+
+/// Signals whose default action is to stop the process.
+const STOP_SIGNALS: [LinuxSignal; 4] = [
+    LinuxSignal::SIGSTOP,
+    LinuxSignal::SIGTSTP,
+    LinuxSignal::SIGTTIN,
+    LinuxSignal::SIGTTOU,
+];
+
+/// Actions taken when `signal` is generated for `proc`, before it is queued.
 ///
-/// A state change is considered to be:
-/// - the child terminated.
-/// - the child was stopped by a signal. TODO
-/// - the child was resumed by a signal. TODO
-pub async fn wait_child(proc: &Arc<Process>, pid: KoID, nonblock: bool) -> LxResult<ExitCode> {
-    loop {
-        let mut inner = proc.linux().inner.lock();
-        let child = inner.children.get(&pid).ok_or(LxError::ECHILD)?;
-        if let Status::Exited(code) = child.status() {
-            inner.children.remove(&pid);
-            return Ok(code as ExitCode);
+/// SIGKILL terminates the process at once, SIGCONT resumes it. Stop signals and SIGCONT
+/// cancel each other. Returns `false` if the signal is discarded.
+pub(crate) fn prepare_signal(proc: &Process, signal: LinuxSignal) -> bool {
+    if signal == LinuxSignal::SIGKILL {
+        proc.kill_by_signal(signal);
+        return false;
+    }
+    let linux = proc.linux();
+    if signal == LinuxSignal::SIGCONT {
+        let threads = linux_threads(proc);
+        let continued = {
+            let mut inner = linux.inner.lock();
+            for &sig in STOP_SIGNALS.iter() {
+                inner.signal_pending.remove(sig);
+            }
+            let stopped = core::mem::take(&mut inner.stopped_threads);
+            for thread in stopped.iter() {
+                thread.resume();
+            }
+            let continued = core::mem::replace(&mut inner.stopped, false);
+            if continued {
+                inner.job_status = Some(JobStatus::Continued);
+            }
+            continued
+        };
+        for thread in threads.iter() {
+            let mut linux_thread = thread.lock_linux();
+            for &sig in STOP_SIGNALS.iter() {
+                linux_thread.signal_pending.remove(sig);
+            }
         }
-        if nonblock {
-            return Err(LxError::EAGAIN);
+        if continued {
+            info!("process {} continued", proc.id());
+            if let Some(parent) = linux.parent() {
+                parent.signal_set(Signal::SIGCHLD);
+                let info = SigInfo::child(proc.id() as usize, CLD_CONTINUED, signal as i32);
+                notify_parent(&parent, info);
+            }
         }
-        let child: Arc<dyn KernelObject> = child.clone();
-        drop(inner);
-        child.signal_clear(Signal::PROCESS_TERMINATED);
-        child.wait_signal(Signal::PROCESS_TERMINATED).await;
+    } else if signal.is_stop() {
+        linux.inner.lock().signal_pending.remove(LinuxSignal::SIGCONT);
+        for thread in linux_threads(proc) {
+            thread.lock_linux().signal_pending.remove(LinuxSignal::SIGCONT);
+        }
+    }
+    !is_ignored(linux, signal)
+}
+
+/// Whether `signal` has no effect once taken by the process, as it is ignored.
+pub(crate) fn is_ignored(linux: &LinuxProcess, signal: LinuxSignal) -> bool {
+    let action = linux.signal_action(signal);
+    match action.handler {
+        SIG_IGN => true,
+        SIG_DFL => matches!(
+            signal.default_action(),
+            SignalDefaultAction::Ignore | SignalDefaultAction::Continue
+        ),
+        _ => false,
     }
 }
 
-/// Wait for state changes in a child of the calling process.
-pub async fn wait_child_any(proc: &Arc<Process>, nonblock: bool) -> LxResult<(KoID, ExitCode)> {
+/// The children a wait is for
+#[derive(Debug, Clone, Copy)]
+pub enum WaitTarget {
+    /// Any child
+    AnyChild,
+    /// Any child in the process group
+    ProcessGroup(KoID),
+    /// The child with the process ID
+    Pid(KoID),
+}
+
+bitflags! {
+    /// Options of wait4
+    pub struct WaitOptions: u32 {
+        /// return at once if no child has changed state
+        const NOHANG    = 1;
+        /// also report the children stopped by a signal
+        const STOPPED   = 2;
+        /// report the terminated children, always set by wait4
+        const EXITED    = 4;
+        /// also report the children resumed by SIGCONT
+        const CONTINUED = 8;
+        /// leave the reported child in a waitable state
+        const NOWAIT    = 0x100_0000;
+    }
+}
+
+bitflags! {
+    /// Resources a forked process shares with its source, instead of getting a copy
+    pub struct ForkShare: u32 {
+        /// the address space, as with `CLONE_VM`
+        const VM      = 1 << 0;
+        /// the filesystem information, as with `CLONE_FS`
+        const FS      = 1 << 1;
+        /// the file descriptor table, as with `CLONE_FILES`
+        const FILES   = 1 << 2;
+        /// the signal actions, as with `CLONE_SIGHAND`
+        const SIGHAND = 1 << 3;
+        /// the System V semaphore adjustments, as with `CLONE_SYSVSEM`
+        const SYSVSEM = 1 << 4;
+    }
+}
+
+/// Wait for state changes in a child of the calling process, and obtain information about
+/// the child whose state has changed.
+///
+/// Returns the ID and the wait status of the child, as it is stored by `wait4`,
+/// or `None` if no child has changed state with `WNOHANG`.
+///
+/// A state change is considered to be:
+/// - the child terminated, it is reaped unless `WNOWAIT` is given.
+/// - the child was stopped by a signal, with `WUNTRACED`.
+/// - the child was resumed by SIGCONT, with `WCONTINUED`.
+pub async fn wait_child(
+    proc: &Arc<Process>,
+    target: WaitTarget,
+    options: WaitOptions,
+) -> LxResult<Option<(KoID, ExitCode)>> {
+    let proc_object: Arc<dyn KernelObject> = proc.clone();
     loop {
+        // cleared before looking at the children, so that no state change is missed
+        proc_object.signal_clear(Signal::SIGCHLD);
         let mut inner = proc.linux().inner.lock();
-        if inner.children.is_empty() {
-            return Err(LxError::ECHILD);
-        }
+        let mut found = false;
+        let mut event = None;
         for (&pid, child) in inner.children.iter() {
-            if let Status::Exited(code) = child.status() {
-                inner.children.remove(&pid);
-                return Ok((pid, code as ExitCode));
+            let matched = match target {
+                WaitTarget::AnyChild => true,
+                WaitTarget::ProcessGroup(pgid) => child.linux().pgid() == pgid,
+                WaitTarget::Pid(target) => pid == target,
+            };
+            if matched {
+                found = true;
+                event = wait_event(child, options).map(|(status, exited)| (pid, status, exited));
+                if event.is_some() {
+                    break;
+                }
             }
         }
+        if let Some((pid, status, exited)) = event {
+            if exited && !options.contains(WaitOptions::NOWAIT) {
+                if let Some(child) = inner.children.remove(&pid) {
+                    // the CPU time of a reaped child counts for its parent
+                    let child = child.linux().inner.lock();
+                    inner.children_user_time += child.user_time + child.children_user_time;
+                    inner.children_system_time += child.system_time + child.children_system_time;
+                }
+            }
+            return Ok(Some((pid, status)));
+        }
         drop(inner);
-        if nonblock {
-            return Err(LxError::EAGAIN);
+        if !found {
+            return Err(LxError::ECHILD);
+        }
+        if options.contains(WaitOptions::NOHANG) {
+            return Ok(None);
         }
-        let proc: Arc<dyn KernelObject> = proc.clone();
-        proc.signal_clear(Signal::SIGCHLD);
-        //等待进程结束信号
-        proc.wait_signal(Signal::SIGCHLD).await;
+        proc_object.wait_signal(Signal::SIGCHLD).await;
+    }
+}
+
+/// A state change of `child` reported by a wait with `options`,
+/// the wait status and whether the child terminated.
+fn wait_event(child: &Process, options: WaitOptions) -> Option<(ExitCode, bool)> {
+    if let Status::Exited(code) = child.status() {
+        return Some((wait_status(child, code), true));
+    }
+    let mut inner = child.linux().inner.lock();
+    let status = match inner.job_status? {
+        JobStatus::Stopped(signal) if options.contains(WaitOptions::STOPPED) => {
+            ((signal as ExitCode) << 8) | 0x7f
+        }
+        JobStatus::Continued if options.contains(WaitOptions::CONTINUED) => 0xffff,
+        _ => return None,
+    };
+    if !options.contains(WaitOptions::NOWAIT) {
+        inner.job_status = None;
+    }
+    Some((status, false))
+}
+
+/// Encode how an exited child terminated.
+fn wait_status(child: &Process, code: i64) -> ExitCode {
+    match child.linux().exit_signal() {
+        Some(signal) => signal as ExitCode & 0x7f,
+        None => (code as ExitCode & 0xff) << 8,
     }
 }
 
@@ -139,35 +574,160 @@ pub async fn wait_child_any(proc: &Arc<Process>, nonblock: bool) -> LxResult<(Ko
 pub struct LinuxProcess {
     /// The root INode of file system
     root_inode: Arc<dyn INode>,
+    /// Filesystems mounted in the tree of `root_inode`
+    mounts: Arc<MountTable>,
     /// Parent process
     parent: Weak<Process>,
+    /// Working directory and file mode creation mask
+    fs: SharedTable<FsContext>,
+    /// Opened files and sockets
+    files: SharedTable<FileTable>,
+    /// Signal actions
+    signal_actions: SharedTable<SignalActions>,
+    /// Semaphore
+    semaphores: SharedTable<SemProc>,
     /// Inner
     inner: Mutex<LinuxProcessInner>,
+    /// Enclave quota, kept apart from `inner` as it is released when files are dropped
+    enclave_quota: Mutex<EnclaveQuota>,
 }
 
 /// Linux process mut inner data
 #[derive(Default)]
 struct LinuxProcessInner {
+    /// ID of the process
+    pid: KoID,
     /// Execute path
     execute_path: String,
-    /// Current Working Directory
-    ///
-    /// Omit leading '/'.
-    current_working_directory: String,
-    /// file open number limit
-    file_limit: RLimit,
-    /// Opened files
-    files: HashMap<FileDesc, Arc<dyn FileLike>>,
-    /// Semaphore
-    semaphores: SemProc,
+    /// Arguments of the program
+    cmdline: Vec<String>,
+    /// Resource limits
+    limits: ResourceLimits,
     /// Share Memory
     shm_identifiers: ShmProc,
     /// Futexes
     futexes: HashMap<VirtAddr, Arc<Futex>>,
     /// Child processes
     children: HashMap<KoID, Arc<Process>>,
-    /// Signal actions
-    signal_actions: SignalActions,
+    /// Signals sent to the process
+    signal_pending: SignalQueue,
+    /// Signal that terminated the process
+    exit_signal: Option<LinuxSignal>,
+    /// Signal sent to the parent when the process terminates
+    termination_signal: Option<LinuxSignal>,
+    /// Whether the process is stopped by a signal
+    stopped: bool,
+    /// Stop or continuation not reported to the parent by wait yet
+    job_status: Option<JobStatus>,
+    /// Threads suspended when the process stopped
+    stopped_threads: Vec<Arc<Thread>>,
+    /// Code calling sys_rt_sigreturn for handlers without SA_RESTORER, 0 if not mapped yet
+    sigreturn_trampoline: VirtAddr,
+    /// User and group identity
+    credentials: Credentials,
+    /// ID of the process group
+    pgid: KoID,
+    /// ID of the session
+    sid: KoID,
+    /// Controlling terminal of the session
+    terminal: Option<Arc<Tty>>,
+    /// Heap managed by brk
+    program_break: ProgramBreak,
+    /// Interval timer `ITIMER_REAL`, created on first use
+    real_timer: Option<Arc<IntervalTimer>>,
+    /// Interval timer `ITIMER_VIRTUAL`, on the user CPU time
+    virtual_timer: CpuTimer,
+    /// Interval timer `ITIMER_PROF`, on the user and system CPU time
+    prof_timer: CpuTimer,
+    /// POSIX timers created by timer_create
+    posix_timers: BTreeMap<usize, PosixTimer>,
+    /// CPU time spent in user mode
+    user_time: Duration,
+    /// CPU time spent in the kernel
+    system_time: Duration,
+    /// CPU time spent in user mode by the reaped children and their descendants
+    children_user_time: Duration,
+    /// CPU time spent in the kernel by the reaped children and their descendants
+    children_system_time: Duration,
+    /// Seconds of CPU time when SIGXCPU was last sent for `RLIMIT_CPU`
+    cpu_limit_signaled: Option<u64>,
+}
+
+/// A job control state change of a process
+#[derive(Clone, Copy)]
+enum JobStatus {
+    /// Stopped by the signal
+    Stopped(LinuxSignal),
+    /// Resumed by SIGCONT
+    Continued,
+}
+
+/// A table of a process which `clone` may share with the child, until one of them
+/// unshares it
+#[derive(Default)]
+struct SharedTable<T>(Mutex<Arc<Mutex<T>>>);
+
+impl<T> SharedTable<T> {
+    fn new(table: T) -> Self {
+        SharedTable(Mutex::new(Arc::new(Mutex::new(table))))
+    }
+
+    /// The table currently used by the process
+    fn get(&self) -> Arc<Mutex<T>> {
+        self.0.lock().clone()
+    }
+
+    /// Use the same table in another process
+    fn share(&self) -> Self {
+        SharedTable(Mutex::new(self.get()))
+    }
+}
+
+impl<T: Clone> SharedTable<T> {
+    /// The table of a forked process, `shared` or copied
+    fn fork(&self, shared: bool) -> Self {
+        if shared {
+            self.share()
+        } else {
+            SharedTable::new(self.get().lock().clone())
+        }
+    }
+
+    /// Use a copy of the table if it is shared with other processes
+    fn unshare(&self) {
+        let mut table = self.0.lock();
+        if Arc::strong_count(&table) > 1 {
+            let copy = table.lock().clone();
+            *table = Arc::new(Mutex::new(copy));
+        }
+    }
+}
+
+/// Filesystem information, shared with `CLONE_FS`
+#[derive(Clone)]
+struct FsContext {
+    /// Current Working Directory
+    ///
+    /// Omit leading '/'.
+    cwd: String,
+    /// File mode creation mask
+    umask: u32,
+}
+
+impl Default for FsContext {
+    fn default() -> Self {
+        FsContext {
+            cwd: String::new(),
+            umask: 0o022,
+        }
+    }
+}
+
+/// File descriptor table, shared with `CLONE_FILES`
+#[derive(Default, Clone)]
+struct FileTable {
+    /// Opened files
+    files: HashMap<FileDesc, Arc<dyn FileLike>>,
     /// Sockets
     sockets: HashMap<SocketHandle, Arc<Mutex<dyn Socket>>>,
 }
@@ -185,6 +745,115 @@ impl Default for SignalActions {
     }
 }
 
+/// Limits of the resources, indexed by [`Resource`]
+#[derive(Clone)]
+struct ResourceLimits {
+    table: [RLimit; Resource::COUNT],
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        let infinity = RLimit {
+            cur: RLIM_INFINITY,
+            max: RLIM_INFINITY,
+        };
+        let mut table = [infinity; Resource::COUNT];
+        table[Resource::Stack as usize].cur = USER_STACK_SIZE as u64;
+        table[Resource::Core as usize].cur = 0;
+        table[Resource::Nofile as usize] = RLimit::default();
+        table[Resource::Memlock as usize] = RLimit {
+            cur: 64 * 1024,
+            max: 64 * 1024,
+        };
+        table[Resource::Msgqueue as usize] = RLimit {
+            cur: 819_200,
+            max: 819_200,
+        };
+        table[Resource::Nice as usize] = RLimit { cur: 0, max: 0 };
+        table[Resource::Rtprio as usize] = RLimit { cur: 0, max: 0 };
+        ResourceLimits { table }
+    }
+}
+
+/// Limits and usage of keystone enclaves
+#[derive(Clone, Copy)]
+struct EnclaveQuota {
+    /// max number of enclaves
+    enclave_limit: RLimit,
+    /// max EPM pages of all enclaves
+    epm_limit: RLimit,
+    enclaves: u64,
+    epm_pages: u64,
+}
+
+impl Default for EnclaveQuota {
+    fn default() -> Self {
+        EnclaveQuota {
+            enclave_limit: RLimit {
+                cur: 16,
+                max: 16,
+            },
+            epm_limit: RLimit {
+                cur: RLIM_INFINITY,
+                max: RLIM_INFINITY,
+            },
+            enclaves: 0,
+            epm_pages: 0,
+        }
+    }
+}
+
+/// No limit on a resource
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// Default soft limit of the stack size
+const USER_STACK_SIZE: usize = 8 * 1024 * 1024; // 8 MB, the default config of Linux
+
+numeric_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    /// Resources limited by setrlimit
+    pub enum Resource {
+        /// CPU time in seconds, SIGXCPU is sent beyond the soft limit and SIGKILL at the hard one
+        Cpu = 0,
+        /// largest size of the files written by the process
+        Fsize = 1,
+        /// largest size of the heap
+        Data = 2,
+        /// largest size of the stack
+        Stack = 3,
+        /// largest size of core dumps, which are never written
+        Core = 4,
+        /// largest resident set size
+        Rss = 5,
+        /// number of processes of the real user ID
+        Nproc = 6,
+        /// one greater than the largest file descriptor number
+        Nofile = 7,
+        /// bytes of locked memory
+        Memlock = 8,
+        /// largest size of the address space
+        As = 9,
+        /// number of file locks
+        Locks = 10,
+        /// number of pending signals
+        Sigpending = 11,
+        /// bytes of POSIX message queues
+        Msgqueue = 12,
+        /// ceiling of the nice value
+        Nice = 13,
+        /// ceiling of the real-time priority
+        Rtprio = 14,
+        /// CPU time in microseconds of a real-time thread without a blocking syscall
+        Rttime = 15,
+    }
+}
+
+impl Resource {
+    /// Number of resources
+    pub const COUNT: usize = 16;
+}
+
 /// resource limit
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -208,8 +877,8 @@ impl Default for RLimit {
 pub type ExitCode = i32;
 
 impl LinuxProcess {
-    /// Create a new process.
-    pub fn new(rootfs: Arc<dyn FileSystem>) -> Self {
+    /// Create a new process in `job`.
+    pub fn new(job: &Arc<Job>, rootfs: Arc<dyn FileSystem>) -> Self {
         let stdin = File::new(
             STDIN.clone(), // FIXME: stdin
             OpenFlags::RDONLY,
@@ -225,20 +894,25 @@ impl LinuxProcess {
             OpenFlags::WRONLY,
             String::from("/dev/stderr"),
         ) as Arc<dyn FileLike>;
-        let keystone = KEYSTONE.clone();
         let mut files = HashMap::new();
         files.insert(0.into(), stdin);
         files.insert(1.into(), stdout);
         files.insert(2.into(), stderr);
-        files.insert(666.into(), keystone);
 
+        let mounts = MountTable::new(rootfs, job);
         LinuxProcess {
-            root_inode: crate::fs::create_root_fs(rootfs), //Arc::clone(&ROOT_INODE),访问磁盘可能更快？
+            root_inode: mounts.root_inode(),
+            mounts,
             parent: Weak::default(),
-            inner: Mutex::new(LinuxProcessInner {
+            fs: SharedTable::default(),
+            files: SharedTable::new(FileTable {
                 files,
                 ..Default::default()
             }),
+            signal_actions: SharedTable::default(),
+            semaphores: SharedTable::default(),
+            inner: Mutex::new(LinuxProcessInner::default()),
+            enclave_quota: Mutex::new(EnclaveQuota::default()),
         }
     }
 
@@ -259,50 +933,131 @@ impl LinuxProcess {
 
     /// Get lowest free fd
     pub fn get_free_fd(&self) -> FileDesc {
-        self.inner.lock().get_free_fd()
+        self.files.get().lock().get_free_fd()
     }
 
     /// get the lowest available fd great than or equal to `start`.
     pub fn get_free_fd_from(&self, start: usize) -> FileDesc {
-        self.inner.lock().get_free_fd_from(start)
+        self.files.get().lock().get_free_fd_from(start)
     }
 
     /// Add a file to the file descriptor table.
     pub fn add_file(&self, file: Arc<dyn FileLike>) -> LxResult<FileDesc> {
-        let inner = self.inner.lock();
-        let fd = inner.get_free_fd();
-        self.insert_file(inner, fd, file)
+        let limit = self.rlimit(Resource::Nofile);
+        let files = self.files.get();
+        let table = files.lock();
+        let fd = table.get_free_fd();
+        Self::insert_file(table, limit, fd, file)
     }
 
     /// Add a file to the file descriptor table at given `fd`.
     pub fn add_file_at(&self, fd: FileDesc, file: Arc<dyn FileLike>) -> LxResult<FileDesc> {
-        let inner = self.inner.lock();
-        self.insert_file(inner, fd, file)
+        let limit = self.rlimit(Resource::Nofile);
+        let files = self.files.get();
+        let table = files.lock();
+        Self::insert_file(table, limit, fd, file)
     }
 
-    /// insert a file and fd into the file descriptor table
+    /// insert a file and fd into the file descriptor table, which holds at most `limit` files
     fn insert_file(
-        &self,
-        mut inner: MutexGuard<LinuxProcessInner>,
+        mut table: MutexGuard<FileTable>,
+        limit: RLimit,
         fd: FileDesc,
         file: Arc<dyn FileLike>,
     ) -> LxResult<FileDesc> {
-        if inner.files.len() < inner.file_limit.cur as usize {
-            inner.files.insert(fd, file);
+        if table.files.len() < limit.cur as usize {
+            table.files.insert(fd, file);
             Ok(fd)
         } else {
             Err(LxError::EMFILE)
         }
     }
 
-    /// get and set file limit number
-    pub fn file_limit(&self, new_limit: Option<RLimit>) -> RLimit {
+    /// get the limit of `resource`
+    pub fn rlimit(&self, resource: Resource) -> RLimit {
+        self.inner.lock().limits.table[resource as usize]
+    }
+
+    /// get and set the limit of `resource`
+    ///
+    /// The soft limit cannot exceed the hard one,
+    /// which only a privileged process can raise.
+    pub fn set_rlimit(&self, resource: Resource, new_limit: Option<RLimit>) -> LxResult<RLimit> {
         let mut inner = self.inner.lock();
-        let old = inner.file_limit;
+        let old = inner.limits.table[resource as usize];
         if let Some(limit) = new_limit {
-            inner.file_limit = limit;
+            if limit.cur > limit.max {
+                return Err(LxError::EINVAL);
+            }
+            if limit.max > old.max && !inner.credentials.is_privileged() {
+                return Err(LxError::EPERM);
+            }
+            inner.limits.table[resource as usize] = limit;
         }
-        old
+        Ok(old)
+    }
+
+    /// Check that `size` more bytes can be mapped into `vmar` within `RLIMIT_AS`
+    pub fn check_address_space(&self, vmar: &VmAddressRegion, size: usize) -> LxResult {
+        let limit = self.rlimit(Resource::As).cur;
+        if limit == RLIM_INFINITY {
+            return Ok(());
+        }
+        let mapped: usize = vmar.mappings_info().iter().map(|info| info.size).sum();
+        match mapped.checked_add(size) {
+            Some(total) if total as u64 <= limit => Ok(()),
+            _ => Err(LxError::ENOMEM),
+        }
+    }
+
+    /// Get the max number of enclaves and the max EPM pages of all enclaves.
+    pub fn enclave_limits(&self) -> (RLimit, RLimit) {
+        let quota = self.enclave_quota.lock();
+        (quota.enclave_limit, quota.epm_limit)
+    }
+
+    /// Set the max number of enclaves and the max EPM pages of all enclaves,
+    /// checked like the resource limits of `set_rlimit`.
+    pub fn set_enclave_limits(&self, enclave_limit: RLimit, epm_limit: RLimit) -> LxResult {
+        let privileged = self.credentials().is_privileged();
+        let mut quota = self.enclave_quota.lock();
+        let limits = [
+            (enclave_limit, quota.enclave_limit),
+            (epm_limit, quota.epm_limit),
+        ];
+        for (limit, old) in limits.iter() {
+            if limit.cur > limit.max {
+                return Err(LxError::EINVAL);
+            }
+            if limit.max > old.max && !privileged {
+                return Err(LxError::EPERM);
+            }
+        }
+        quota.enclave_limit = enclave_limit;
+        quota.epm_limit = epm_limit;
+        Ok(())
+    }
+
+    /// Account a new enclave with `epm_pages` pages of private memory
+    pub fn charge_enclave(&self, epm_pages: usize) -> LxResult {
+        let mut quota = self.enclave_quota.lock();
+        if quota.enclaves >= quota.enclave_limit.cur {
+            return Err(LxError::EAGAIN);
+        }
+        let epm_pages = epm_pages as u64;
+        if quota.epm_limit.cur.saturating_sub(quota.epm_pages) < epm_pages {
+            return Err(LxError::ENOMEM);
+        }
+        quota.enclaves += 1;
+        quota.epm_pages += epm_pages;
+        Ok(())
+    }
+
+    /// Give back the quota of a destroyed enclave
+    pub fn uncharge_enclave(&self, epm_pages: usize) {
+        let mut quota = self.enclave_quota.lock();
+        quota.enclaves = quota.enclaves.saturating_sub(1);
+        quota.epm_pages = quota.epm_pages.saturating_sub(epm_pages as u64);
     }
 
     /// Get the `File` with given `fd`.
@@ -316,40 +1071,43 @@ impl LinuxProcess {
 
     /// Get the `FileLike` with given `fd`.
     pub fn get_file_like(&self, fd: FileDesc) -> LxResult<Arc<dyn FileLike>> {
-        let inner = self.inner.lock();
-        trace!("get_file_like: {:#x?}", inner.files);
-        inner.files.get(&fd).cloned().ok_or(LxError::EBADF)
+        let files = self.files.get();
+        let table = files.lock();
+        trace!("get_file_like: {:#x?}", table.files);
+        table.files.get(&fd).cloned().ok_or(LxError::EBADF)
     }
 
     /// get all files
     pub fn get_files(&self) -> LxResult<HashMap<FileDesc, Arc<dyn FileLike>>> {
-        let inner = self.inner.lock();
-        Ok(inner.files.clone())
+        Ok(self.files.get().lock().files.clone())
     }
 
     /// Close file descriptor `fd`.
     pub fn close_file(&self, fd: FileDesc) -> LxResult {
-        let mut inner = self.inner.lock();
-        inner.files.remove(&fd).map(|_| ()).ok_or(LxError::EBADF)
+        let files = self.files.get();
+        let mut table = files.lock();
+        table.files.remove(&fd).map(|_| ()).ok_or(LxError::EBADF)
     }
 
     /// Add a socket to the socket set at given `SocketHandle`.
     pub fn add_socket(&self, socket: Arc<Mutex<dyn Socket>>) -> LxResult<SocketHandle> {
-        let inner = self.inner.lock();
-        let fd = inner.get_free_hd();
-        self.insert_socket(inner, fd, socket)
+        let limit = self.rlimit(Resource::Nofile);
+        let files = self.files.get();
+        let table = files.lock();
+        let fd = table.get_free_hd();
+        Self::insert_socket(table, limit, fd, socket)
         // unimplemented!()
     }
 
-    /// insert a file and fd into the file descriptor table
+    /// insert a file and fd into the file descriptor table, which holds at most `limit` sockets
     fn insert_socket(
-        &self,
-        mut inner: MutexGuard<LinuxProcessInner>,
+        mut table: MutexGuard<FileTable>,
+        limit: RLimit,
         fd: SocketHandle,
         socket: Arc<Mutex<dyn Socket>>,
     ) -> LxResult<SocketHandle> {
-        if inner.sockets.len() < inner.file_limit.cur as usize {
-            inner.sockets.insert(fd, socket);
+        if table.sockets.len() < limit.cur as usize {
+            table.sockets.insert(fd, socket);
             Ok(fd)
         } else {
             Err(LxError::EMFILE)
@@ -359,15 +1117,17 @@ impl LinuxProcess {
     /// Get the `Socket` with given `fd`.
     pub fn get_socket(&self, fd: SocketHandle) -> LxResult<Arc<Mutex<dyn Socket>>> {
         // unimplemented!()
-        let inner = self.inner.lock();
-        let socket = inner.sockets.get(&fd).cloned().ok_or(LxError::EBADF);
+        let files = self.files.get();
+        let table = files.lock();
+        let socket = table.sockets.get(&fd).cloned().ok_or(LxError::EBADF);
         socket
     }
 
     /// Close file descriptor `fd`.
     pub fn close_socket(&self, fd: SocketHandle) -> LxResult {
-        let mut inner = self.inner.lock();
-        inner.sockets.remove(&fd).map(|_| ()).ok_or(LxError::EBADF)
+        let files = self.files.get();
+        let mut table = files.lock();
+        table.sockets.remove(&fd).map(|_| ()).ok_or(LxError::EBADF)
     }
 
     /// Get root INode of the process.
@@ -375,14 +1135,24 @@ impl LinuxProcess {
         &self.root_inode
     }
 
+    /// Get the mount table of the process.
+    pub fn mounts(&self) -> &Arc<MountTable> {
+        &self.mounts
+    }
+
     /// Get parent process.
     pub fn parent(&self) -> Option<Arc<Process>> {
         self.parent.upgrade()
     }
 
+    /// Whether this is the init process, created rather than forked from a parent.
+    pub fn is_init(&self) -> bool {
+        self.parent.ptr_eq(&Weak::new())
+    }
+
     /// Get current working directory.
     pub fn current_working_directory(&self) -> String {
-        String::from("/") + &self.inner.lock().current_working_directory
+        String::from("/") + &self.fs.get().lock().cwd
     }
 
     /// Change working directory.
@@ -390,10 +1160,25 @@ impl LinuxProcess {
         if path.is_empty() {
             return;
         }
-        let mut inner = self.inner.lock();
-        let cwd = match path.as_bytes()[0] {
-            b'/' => String::new(),
-            _ => inner.current_working_directory.clone(),
+        let cwd = self.absolute_path(path);
+        self.fs.get().lock().cwd = cwd[1..].into();
+    }
+
+    /// Get the file mode creation mask.
+    pub fn umask(&self) -> u32 {
+        self.fs.get().lock().umask
+    }
+
+    /// Set the file mode creation mask, return the previous one.
+    pub fn set_umask(&self, umask: u32) -> u32 {
+        core::mem::replace(&mut self.fs.get().lock().umask, umask & 0o777)
+    }
+
+    /// Resolve `path` against the current working directory, without following links.
+    pub fn absolute_path(&self, path: &str) -> String {
+        let cwd = match path.as_bytes().first() {
+            Some(b'/') => String::new(),
+            _ => self.fs.get().lock().cwd.clone(),
         };
         let mut cwd_vec: Vec<_> = cwd.split('/').filter(|x| !x.is_empty()).collect();
         for seg in path.split('/') {
@@ -405,7 +1190,7 @@ impl LinuxProcess {
                 _ => cwd_vec.push(seg),
             }
         }
-        inner.current_working_directory = cwd_vec.join("/");
+        String::from("/") + &cwd_vec.join("/")
     }
 
     /// Get execute path.
@@ -418,57 +1203,241 @@ impl LinuxProcess {
         self.inner.lock().execute_path = String::from(path);
     }
 
+    /// Get the ID of the process.
+    pub fn pid(&self) -> KoID {
+        self.inner.lock().pid
+    }
+
+    /// Get the ID of the process group.
+    pub fn pgid(&self) -> KoID {
+        self.inner.lock().pgid
+    }
+
+    /// Move the process to the process group `pgid`.
+    pub fn set_pgid(&self, pgid: KoID) {
+        self.inner.lock().pgid = pgid;
+    }
+
+    /// Get the ID of the session.
+    pub fn sid(&self) -> KoID {
+        self.inner.lock().sid
+    }
+
+    /// Make the process the leader of a new session and of a new process group in it,
+    /// without a controlling terminal.
+    pub fn set_sid(&self) {
+        let mut inner = self.inner.lock();
+        inner.pgid = inner.pid;
+        inner.sid = inner.pid;
+        inner.terminal = None;
+    }
+
+    /// Get the controlling terminal.
+    pub fn terminal(&self) -> Option<Arc<Tty>> {
+        self.inner.lock().terminal.clone()
+    }
+
+    /// Set the controlling terminal.
+    pub fn set_terminal(&self, terminal: Option<Arc<Tty>>) {
+        self.inner.lock().terminal = terminal;
+    }
+
+    /// Get the child process `pid`.
+    pub fn child(&self, pid: KoID) -> Option<Arc<Process>> {
+        self.inner.lock().children.get(&pid).cloned()
+    }
+
+    /// Get the arguments of the program.
+    pub fn cmdline(&self) -> Vec<String> {
+        self.inner.lock().cmdline.clone()
+    }
+
+    /// Set the arguments of the program.
+    pub fn set_cmdline(&self, args: Vec<String>) {
+        self.inner.lock().cmdline = args;
+    }
+
+    /// Whether the process is stopped by a signal.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.lock().stopped
+    }
+
     /// Get signal action.
     pub fn signal_action(&self, signal: LinuxSignal) -> SignalAction {
-        self.inner.lock().signal_actions.table[signal as u8 as usize]
+        self.signal_actions.get().lock().table[signal as u8 as usize]
     }
 
     /// Set signal action.
     pub fn set_signal_action(&self, signal: LinuxSignal, action: SignalAction) {
-        self.inner.lock().signal_actions.table[signal as u8 as usize] = action;
+        self.signal_actions.get().lock().table[signal as u8 as usize] = action;
     }
 
-    /// Close file that FD_CLOEXEC is set
-    pub fn remove_cloexec_files(&self) {
+    /// Get the credentials of the process.
+    pub fn credentials(&self) -> Credentials {
+        self.inner.lock().credentials.clone()
+    }
+
+    /// Replace the credentials of the process, as set by the identity syscalls and execve.
+    pub fn set_credentials(&self, credentials: Credentials) {
+        self.inner.lock().credentials = credentials;
+    }
+
+    /// Replace the heap, as set up by the loader after the program image.
+    pub fn set_program_break(&self, program_break: ProgramBreak) {
+        self.inner.lock().program_break = program_break;
+    }
+
+    /// Move the program break to `addr` in the process `vmar` and return the new break,
+    /// which is unchanged if it cannot be moved.
+    ///
+    /// The heap cannot grow beyond `RLIMIT_DATA`, nor the address space beyond `RLIMIT_AS`.
+    pub fn brk(&self, vmar: &Arc<VmAddressRegion>, addr: VirtAddr) -> VirtAddr {
+        let (start, current, data_limit) = {
+            let inner = self.inner.lock();
+            let data_limit = inner.limits.table[Resource::Data as usize].cur;
+            let program_break = &inner.program_break;
+            (program_break.start(), program_break.current(), data_limit)
+        };
+        if addr > current && addr.checked_add(PAGE_SIZE).is_some() {
+            let grow = roundup_pages(addr) - roundup_pages(current);
+            if (addr - start) as u64 > data_limit || self.check_address_space(vmar, grow).is_err() {
+                return current;
+            }
+        }
+        self.inner.lock().program_break.set(vmar, addr)
+    }
+
+    /// Get the user and system CPU time used by the process.
+    pub fn cpu_time(&self) -> (Duration, Duration) {
+        let inner = self.inner.lock();
+        (inner.user_time, inner.system_time)
+    }
+
+    /// Get the user and system CPU time used by the reaped children and their descendants.
+    pub fn children_cpu_time(&self) -> (Duration, Duration) {
+        let inner = self.inner.lock();
+        (inner.children_user_time, inner.children_system_time)
+    }
+
+    /// Get signals sent to the process and not delivered yet.
+    pub fn signal_pending(&self) -> Sigset {
+        self.inner.lock().signal_pending.pending()
+    }
+
+    /// Discard the pending instances of `signal` sent to the process.
+    pub(crate) fn discard_signal(&self, signal: LinuxSignal) {
+        self.inner.lock().signal_pending.remove(signal);
+    }
+
+    /// Get the signal that terminated the process.
+    pub fn exit_signal(&self) -> Option<LinuxSignal> {
+        self.inner.lock().exit_signal
+    }
+
+    /// Get the signal sent to the parent when the process terminates, `SIGCHLD` by default.
+    pub fn termination_signal(&self) -> Option<LinuxSignal> {
+        self.inner.lock().termination_signal
+    }
+
+    /// Set the signal sent to the parent when the process terminates, none if `None`.
+    pub fn set_termination_signal(&self, signal: Option<LinuxSignal>) {
+        self.inner.lock().termination_signal = signal;
+    }
+
+    /// Reset signal state on execve.
+    ///
+    /// Handled signals get back their default action, ignored ones stay ignored,
+    /// in a table no longer shared with other processes.
+    /// The sigreturn trampoline is gone with the old address space.
+    pub fn reset_signals(&self) {
+        self.signal_actions.unshare();
+        let signal_actions = self.signal_actions.get();
+        for action in signal_actions.lock().table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+        self.inner.lock().sigreturn_trampoline = 0;
+    }
+
+    /// Add a POSIX timer built by `new` from its ID, return the ID
+    pub fn add_posix_timer(&self, new: impl FnOnce(usize) -> PosixTimer) -> usize {
         let mut inner = self.inner.lock();
-        let close_fds = inner
+        let id = (0..)
+            .find(|id| !inner.posix_timers.contains_key(id))
+            .unwrap();
+        inner.posix_timers.insert(id, new(id));
+        id
+    }
+
+    /// Get the POSIX timer `id`
+    pub fn posix_timer(&self, id: usize) -> LxResult<PosixTimer> {
+        let inner = self.inner.lock();
+        inner.posix_timers.get(&id).cloned().ok_or(LxError::EINVAL)
+    }
+
+    /// Delete the POSIX timer `id`, it is disarmed
+    pub fn remove_posix_timer(&self, id: usize) -> LxResult {
+        let timer = self.inner.lock().posix_timers.remove(&id);
+        let timer = timer.ok_or(LxError::EINVAL)?;
+        timer.timer.set(TimerValue::default());
+        Ok(())
+    }
+
+    /// Delete all POSIX timers, which do not survive execve
+    pub fn clear_posix_timers(&self) {
+        let timers = core::mem::take(&mut self.inner.lock().posix_timers);
+        for timer in timers.values() {
+            timer.timer.set(TimerValue::default());
+        }
+    }
+
+    /// Get the address of the sigreturn trampoline, map it on first use.
+    pub fn sigreturn_trampoline(&self, vmar: &Arc<VmAddressRegion>) -> LxResult<VirtAddr> {
+        let mut inner = self.inner.lock();
+        if inner.sigreturn_trampoline == 0 {
+            let vmo = VmObject::new_paged(1);
+            vmo.write(0, &SIGRETURN_CODE)?;
+            let flags = MMUFlags::READ | MMUFlags::EXECUTE | MMUFlags::USER;
+            inner.sigreturn_trampoline = vmar.map(None, vmo, 0, PAGE_SIZE, flags)?;
+        }
+        Ok(inner.sigreturn_trampoline)
+    }
+
+    /// Close file that FD_CLOEXEC is set, in a table no longer shared with other processes
+    pub fn remove_cloexec_files(&self) {
+        self.files.unshare();
+        let files = self.files.get();
+        let mut table = files.lock();
+        let close_fds = table
             .files
             .iter()
-            .filter_map(|(fd, file_like)| {
-                if let Ok(file) = file_like.clone().downcast_arc::<File>() {
-                    if file.flags().close_on_exec() {
-                        Some(*fd)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
+            .filter(|(_, file_like)| file_like.flags().close_on_exec())
+            .map(|(fd, _)| *fd)
             .collect::<Vec<_>>();
         for fd in close_fds {
-            inner.files.remove(&fd).map(|_| ()).unwrap();
+            table.files.remove(&fd).map(|_| ()).unwrap();
         }
     }
 
     /// Insert a `SemArray` and return its ID
     pub fn semaphores_add(&self, array: Arc<SemArray>) -> usize {
-        self.inner.lock().semaphores.add(array)
+        self.semaphores.get().lock().add(array)
     }
 
     /// Get an semaphore set by `id`
     pub fn semaphores_get(&self, id: usize) -> Option<Arc<SemArray>> {
-        self.inner.lock().semaphores.get(id)
+        self.semaphores.get().lock().get(id)
     }
 
     /// Add an undo operation
     pub fn semaphores_add_undo(&self, id: usize, num: u16, op: i16) {
-        self.inner.lock().semaphores.add_undo(id, num, op)
+        self.semaphores.get().lock().add_undo(id, num, op)
     }
 
     /// Remove an `SemArray` by ID
     pub fn semaphores_remove(&self, id: usize) {
-        self.inner.lock().semaphores.remove(id)
+        self.semaphores.get().lock().remove(id)
     }
 
     /// get ShmId from Virtual Addr
@@ -497,7 +1466,7 @@ impl LinuxProcess {
     }
 }
 
-impl LinuxProcessInner {
+impl FileTable {
     fn get_free_fd(&self) -> FileDesc {
         self.get_free_fd_from(0)
     }
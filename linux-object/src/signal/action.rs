@@ -1,5 +1,7 @@
 use crate::signal::Signal;
+use alloc::collections::VecDeque;
 use bitflags::*;
+use core::convert::TryFrom;
 
 pub const SIG_ERR: usize = usize::max_value() - 1;
 pub const SIG_DFL: usize = 0;
@@ -32,6 +34,67 @@ impl Sigset {
     pub fn remove_set(&mut self, sigset: &Sigset) {
         self.0 ^= self.0 & sigset.0;
     }
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    /// The signals in `self` but not in `mask`
+    pub fn without(&self, mask: &Sigset) -> Sigset {
+        Sigset(self.0 & !mask.0)
+    }
+    /// The lowest numbered signal in the set
+    pub fn first(&self) -> Option<Signal> {
+        if self.0 == 0 {
+            return None;
+        }
+        Signal::try_from(self.0.trailing_zeros() as u8).ok()
+    }
+}
+
+/// Signals sent but not yet delivered
+///
+/// A standard signal is pending at most once, real-time signals are queued
+/// with their info. Signals are taken lowest number first.
+#[derive(Default)]
+pub struct SignalQueue {
+    set: Sigset,
+    infos: VecDeque<SigInfo>,
+}
+
+impl SignalQueue {
+    /// Set of pending signals
+    pub fn pending(&self) -> Sigset {
+        self.set
+    }
+
+    /// Queue a signal, dropped if it is a standard signal already pending
+    pub fn push(&mut self, signal: Signal, info: SigInfo) {
+        if signal.is_standard() && self.set.contains(signal) {
+            return;
+        }
+        self.set.insert(signal);
+        self.infos.push_back(info);
+    }
+
+    /// Take the lowest pending signal not in `mask`
+    pub fn pop(&mut self, mask: &Sigset) -> Option<(Signal, SigInfo)> {
+        let signal = self.set.without(mask).first()?;
+        let idx = self
+            .infos
+            .iter()
+            .position(|info| info.signo == signal as i32)
+            .unwrap();
+        let info = self.infos.remove(idx).unwrap();
+        if !self.infos.iter().any(|info| info.signo == signal as i32) {
+            self.set.remove(signal);
+        }
+        Some((signal, info))
+    }
+
+    /// Discard all pending instances of `signal`
+    pub fn remove(&mut self, signal: Signal) {
+        self.set.remove(signal);
+        self.infos.retain(|info| info.signo != signal as i32);
+    }
 }
 
 /// Linux struct sigaction
@@ -44,15 +107,55 @@ pub struct SignalAction {
     pub mask: Sigset,
 }
 
+/// Sender of `kill`, `tkill` and `tgkill`
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SiginfoKill {
+    pub pid: i32,
+    pub uid: u32,
+}
+
+/// Sender of real-time signals and `rt_sigqueueinfo`
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SiginfoRt {
+    pub pid: i32,
+    pub uid: u32,
+    pub value: usize,
+}
+
+/// Expiration of a POSIX timer
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SiginfoTimer {
+    pub tid: i32,
+    pub overrun: i32,
+    pub value: usize,
+}
+
+/// Child state change of SIGCHLD
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SiginfoChld {
+    pub pid: i32,
+    pub uid: u32,
+    pub status: i32,
+    pub utime: usize,
+    pub stime: usize,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub union SiginfoFields {
     pad: [u8; Self::PAD_SIZE],
-    // TODO: fill this union
+    pub kill: SiginfoKill,
+    pub rt: SiginfoRt,
+    pub timer: SiginfoTimer,
+    pub chld: SiginfoChld,
 }
 
 impl SiginfoFields {
-    const PAD_SIZE: usize = 128 - 2 * core::mem::size_of::<i32>() - core::mem::size_of::<usize>();
+    const PAD_SIZE: usize = 128 - 4 * core::mem::size_of::<i32>();
 }
 
 impl Default for SiginfoFields {
@@ -63,15 +166,74 @@ impl Default for SiginfoFields {
     }
 }
 
+/// Linux struct siginfo_t
+///
+/// `code` is kept as a raw integer as it is read from user space by `rt_sigqueueinfo`.
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct SigInfo {
     pub signo: i32,
     pub errno: i32,
-    pub code: SignalCode,
+    pub code: i32,
     pub field: SiginfoFields,
 }
 
+impl SigInfo {
+    /// A signal sent by process `pid` through `kill` like syscalls
+    pub fn kill(signal: Signal, code: SignalCode, pid: usize) -> Self {
+        SigInfo {
+            signo: signal as i32,
+            errno: 0,
+            code: code as i32,
+            field: SiginfoFields {
+                kill: SiginfoKill {
+                    pid: pid as i32,
+                    uid: 0,
+                },
+            },
+        }
+    }
+
+    /// Expiration of the POSIX timer `id`, which expired `overrun` more times than delivered
+    pub fn timer(signal: Signal, id: usize, overrun: usize, value: usize) -> Self {
+        SigInfo {
+            signo: signal as i32,
+            errno: 0,
+            code: SignalCode::TIMER as i32,
+            field: SiginfoFields {
+                timer: SiginfoTimer {
+                    tid: id as i32,
+                    overrun: overrun.min(i32::MAX as usize) as i32,
+                    value,
+                },
+            },
+        }
+    }
+
+    /// SIGCHLD for the child `pid` whose state changed
+    pub fn child(pid: usize, code: i32, status: i32) -> Self {
+        SigInfo {
+            signo: Signal::SIGCHLD as i32,
+            errno: 0,
+            code,
+            field: SiginfoFields {
+                chld: SiginfoChld {
+                    pid: pid as i32,
+                    uid: 0,
+                    status,
+                    utime: 0,
+                    stime: 0,
+                },
+            },
+        }
+    }
+
+    /// The signal it carries
+    pub fn signal(&self) -> Option<Signal> {
+        Signal::try_from(self.signo as u8).ok()
+    }
+}
+
 /// A code identifying the cause of the signal.
 #[repr(i32)]
 #[derive(Debug, Copy, Clone)]
@@ -89,6 +251,17 @@ pub enum SignalCode {
     KERNEL = 128,
 }
 
+/// SIGCHLD code: child has exited
+pub const CLD_EXITED: i32 = 1;
+/// SIGCHLD code: child was killed
+pub const CLD_KILLED: i32 = 2;
+/// SIGCHLD code: child terminated abnormally
+pub const CLD_DUMPED: i32 = 3;
+/// SIGCHLD code: child has stopped
+pub const CLD_STOPPED: i32 = 5;
+/// SIGCHLD code: stopped child has continued
+pub const CLD_CONTINUED: i32 = 6;
+
 bitflags! {
     #[derive(Default)]
     pub struct SignalActionFlags : usize {
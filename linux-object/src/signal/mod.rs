@@ -1,48 +1,367 @@
 //! Linux signals
 #![allow(missing_docs)]
 use bitflags::*;
+use kernel_hal::context::UserContext;
 use numeric_enum_macro::numeric_enum;
 
 mod action;
 
 pub use self::action::*;
 
-/// struct mcontext
-#[repr(C)]
-#[derive(Clone, Debug)]
-pub struct MachineContext {
-    // gregs
-    pub r8: usize,
-    pub r9: usize,
-    pub r10: usize,
-    pub r11: usize,
-    pub r12: usize,
-    pub r13: usize,
-    pub r14: usize,
-    pub r15: usize,
-    pub rdi: usize,
-    pub rsi: usize,
-    pub rbp: usize,
-    pub rbx: usize,
-    pub rdx: usize,
-    pub rax: usize,
-    pub rcx: usize,
-    pub rsp: usize,
-    pub rip: usize,
-    pub eflags: usize,
-    pub cs: u16,
-    pub gs: u16,
-    pub fs: u16,
-    pub _pad: u16,
-    pub err: usize,
-    pub trapno: usize,
-    pub oldmask: usize,
-    pub cr2: usize,
-    // fpregs
-    // TODO
-    pub fpstate: usize,
-    // reserved
-    pub _reserved1: [usize; 8],
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        /// struct mcontext
+        #[repr(C)]
+        #[derive(Clone, Debug)]
+        pub struct MachineContext {
+            // gregs
+            pub r8: usize,
+            pub r9: usize,
+            pub r10: usize,
+            pub r11: usize,
+            pub r12: usize,
+            pub r13: usize,
+            pub r14: usize,
+            pub r15: usize,
+            pub rdi: usize,
+            pub rsi: usize,
+            pub rbp: usize,
+            pub rbx: usize,
+            pub rdx: usize,
+            pub rax: usize,
+            pub rcx: usize,
+            pub rsp: usize,
+            pub rip: usize,
+            pub eflags: usize,
+            pub cs: u16,
+            pub gs: u16,
+            pub fs: u16,
+            pub _pad: u16,
+            pub err: usize,
+            pub trapno: usize,
+            pub oldmask: usize,
+            pub cr2: usize,
+            // fpregs
+            // TODO
+            pub fpstate: usize,
+            // reserved
+            pub _reserved1: [usize; 8],
+        }
+
+        impl MachineContext {
+            /// Flags in rflags that user space may change: CF, PF, AF, ZF, SF, TF, DF, OF
+            const USER_RFLAGS: usize = 0xdd5;
+
+            /// Save the user registers
+            pub fn save(ctx: &mut UserContext) -> Self {
+                let regs = ctx.general();
+                MachineContext {
+                    r8: regs.r8,
+                    r9: regs.r9,
+                    r10: regs.r10,
+                    r11: regs.r11,
+                    r12: regs.r12,
+                    r13: regs.r13,
+                    r14: regs.r14,
+                    r15: regs.r15,
+                    rdi: regs.rdi,
+                    rsi: regs.rsi,
+                    rbp: regs.rbp,
+                    rbx: regs.rbx,
+                    rdx: regs.rdx,
+                    rax: regs.rax,
+                    rcx: regs.rcx,
+                    rsp: regs.rsp,
+                    rip: regs.rip,
+                    eflags: regs.rflags,
+                    cs: 0,
+                    gs: 0,
+                    fs: 0,
+                    _pad: 0,
+                    err: 0,
+                    trapno: 0,
+                    oldmask: 0,
+                    cr2: 0,
+                    fpstate: 0,
+                    _reserved1: [0; 8],
+                }
+            }
+
+            /// Restore the user registers, privileged flags are left untouched
+            pub fn restore(&self, ctx: &mut UserContext) {
+                let regs = ctx.general_mut();
+                regs.r8 = self.r8;
+                regs.r9 = self.r9;
+                regs.r10 = self.r10;
+                regs.r11 = self.r11;
+                regs.r12 = self.r12;
+                regs.r13 = self.r13;
+                regs.r14 = self.r14;
+                regs.r15 = self.r15;
+                regs.rdi = self.rdi;
+                regs.rsi = self.rsi;
+                regs.rbp = self.rbp;
+                regs.rbx = self.rbx;
+                regs.rdx = self.rdx;
+                regs.rax = self.rax;
+                regs.rcx = self.rcx;
+                regs.rsp = self.rsp;
+                regs.rip = self.rip;
+                regs.rflags =
+                    (regs.rflags & !Self::USER_RFLAGS) | (self.eflags & Self::USER_RFLAGS);
+            }
+
+            /// Value of the return register
+            pub fn return_value(&self) -> usize {
+                self.rax
+            }
+        }
+
+        /// See musl struct __ucontext
+        ///
+        /// Not exactly the same for now
+        #[repr(C)]
+        #[derive(Clone)]
+        pub struct SignalUserContext {
+            pub flags: usize,
+            pub link: usize,
+            pub stack: SignalStack,
+            pub context: MachineContext,
+            pub sig_mask: Sigset,
+        }
+
+        impl SignalUserContext {
+            pub fn new(stack: SignalStack, context: MachineContext, sig_mask: Sigset) -> Self {
+                SignalUserContext {
+                    flags: 0,
+                    link: 0,
+                    stack,
+                    context,
+                    sig_mask,
+                }
+            }
+        }
+
+        /// Linux struct rt_sigframe
+        ///
+        /// The handler returns to `ret_code_addr`, leaving the stack pointer just above it.
+        #[repr(C)]
+        #[derive(Clone)]
+        pub struct SignalFrame {
+            /// point to the code calling sys_sigreturn
+            pub ret_code_addr: usize,
+            /// Signal Frame info
+            pub info: SigInfo,
+            /// adapt interface, a little bit waste
+            pub ucontext: SignalUserContext,
+        }
+
+        impl SignalFrame {
+            pub fn new(info: SigInfo, ucontext: SignalUserContext, ret_code_addr: usize) -> Self {
+                SignalFrame {
+                    ret_code_addr,
+                    info,
+                    ucontext,
+                }
+            }
+
+            /// Address of a frame pushed below `stack_top`
+            pub fn locate(stack_top: usize) -> usize {
+                // as if the return address was pushed by a call
+                ((stack_top - core::mem::size_of::<Self>()) & !0xf) - 8
+            }
+
+            /// Address of the frame when its handler returned with stack pointer `sp`
+            pub fn locate_on_return(sp: usize) -> usize {
+                sp - 8
+            }
+
+            /// Set up `ctx` to call `handler(args)` on the frame at `frame_addr`
+            pub fn enter_handler(
+                ctx: &mut UserContext,
+                handler: usize,
+                frame_addr: usize,
+                args: [usize; 3],
+                _ret_code_addr: usize,
+            ) {
+                let regs = ctx.general_mut();
+                regs.rip = handler;
+                regs.rsp = frame_addr;
+                regs.rdi = args[0];
+                regs.rsi = args[1];
+                regs.rdx = args[2];
+                regs.rax = 0;
+                // clear TF and DF
+                regs.rflags &= !0x500;
+            }
+        }
+
+        /// Bytes under the stack pointer the user code may use
+        pub const RED_ZONE_SIZE: usize = 128;
+
+        /// `mov eax, 15; syscall`
+        pub const SIGRETURN_CODE: [u8; 7] = [0xb8, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05];
+    } else if #[cfg(target_arch = "riscv64")] {
+        use kernel_hal::context::UserContextField;
+
+        /// Linux struct __riscv_q_ext_state
+        #[repr(C, align(16))]
+        #[derive(Clone, Debug)]
+        pub struct FpState {
+            pub f: [u64; 64],
+            pub fcsr: u32,
+            pub _reserved: [u32; 3],
+        }
+
+        /// struct mcontext
+        #[repr(C)]
+        #[derive(Clone, Debug)]
+        pub struct MachineContext {
+            /// pc, then x1 to x31
+            pub gregs: [usize; 32],
+            // TODO: save floating point registers
+            pub fpregs: FpState,
+        }
+
+        impl MachineContext {
+            /// Save the user registers
+            pub fn save(ctx: &mut UserContext) -> Self {
+                let pc = ctx.get_field(UserContextField::InstrPointer);
+                let r = ctx.general();
+                MachineContext {
+                    gregs: [
+                        pc, r.ra, r.sp, r.gp, r.tp, r.t0, r.t1, r.t2, r.s0, r.s1, r.a0, r.a1, r.a2,
+                        r.a3, r.a4, r.a5, r.a6, r.a7, r.s2, r.s3, r.s4, r.s5, r.s6, r.s7, r.s8,
+                        r.s9, r.s10, r.s11, r.t3, r.t4, r.t5, r.t6,
+                    ],
+                    fpregs: FpState {
+                        f: [0; 64],
+                        fcsr: 0,
+                        _reserved: [0; 3],
+                    },
+                }
+            }
+
+            /// Restore the user registers, the status register is left untouched
+            pub fn restore(&self, ctx: &mut UserContext) {
+                ctx.set_field(UserContextField::InstrPointer, self.gregs[0]);
+                let r = ctx.general_mut();
+                let g = &self.gregs;
+                r.ra = g[1];
+                r.sp = g[2];
+                r.gp = g[3];
+                r.tp = g[4];
+                r.t0 = g[5];
+                r.t1 = g[6];
+                r.t2 = g[7];
+                r.s0 = g[8];
+                r.s1 = g[9];
+                r.a0 = g[10];
+                r.a1 = g[11];
+                r.a2 = g[12];
+                r.a3 = g[13];
+                r.a4 = g[14];
+                r.a5 = g[15];
+                r.a6 = g[16];
+                r.a7 = g[17];
+                r.s2 = g[18];
+                r.s3 = g[19];
+                r.s4 = g[20];
+                r.s5 = g[21];
+                r.s6 = g[22];
+                r.s7 = g[23];
+                r.s8 = g[24];
+                r.s9 = g[25];
+                r.s10 = g[26];
+                r.s11 = g[27];
+                r.t3 = g[28];
+                r.t4 = g[29];
+                r.t5 = g[30];
+                r.t6 = g[31];
+            }
+
+            /// Value of the return register
+            pub fn return_value(&self) -> usize {
+                self.gregs[10]
+            }
+        }
+
+        /// See musl struct __ucontext
+        #[repr(C)]
+        #[derive(Clone)]
+        pub struct SignalUserContext {
+            pub flags: usize,
+            pub link: usize,
+            pub stack: SignalStack,
+            pub sig_mask: Sigset,
+            /// room for a larger sigset_t
+            pub _unused: [u8; 120],
+            pub context: MachineContext,
+        }
+
+        impl SignalUserContext {
+            pub fn new(stack: SignalStack, context: MachineContext, sig_mask: Sigset) -> Self {
+                SignalUserContext {
+                    flags: 0,
+                    link: 0,
+                    stack,
+                    sig_mask,
+                    _unused: [0; 120],
+                    context,
+                }
+            }
+        }
+
+        /// Linux struct rt_sigframe
+        ///
+        /// The stack pointer points to the frame when the handler is entered.
+        #[repr(C)]
+        #[derive(Clone)]
+        pub struct SignalFrame {
+            /// Signal Frame info
+            pub info: SigInfo,
+            pub ucontext: SignalUserContext,
+        }
+
+        impl SignalFrame {
+            pub fn new(info: SigInfo, ucontext: SignalUserContext, _ret_code_addr: usize) -> Self {
+                SignalFrame { info, ucontext }
+            }
+
+            /// Address of a frame pushed below `stack_top`
+            pub fn locate(stack_top: usize) -> usize {
+                (stack_top - core::mem::size_of::<Self>()) & !0xf
+            }
+
+            /// Address of the frame when its handler returned with stack pointer `sp`
+            pub fn locate_on_return(sp: usize) -> usize {
+                sp
+            }
+
+            /// Set up `ctx` to call `handler(args)` on the frame at `frame_addr`
+            pub fn enter_handler(
+                ctx: &mut UserContext,
+                handler: usize,
+                frame_addr: usize,
+                args: [usize; 3],
+                ret_code_addr: usize,
+            ) {
+                ctx.set_field(UserContextField::InstrPointer, handler);
+                let regs = ctx.general_mut();
+                regs.sp = frame_addr;
+                regs.a0 = args[0];
+                regs.a1 = args[1];
+                regs.a2 = args[2];
+                regs.ra = ret_code_addr;
+            }
+        }
+
+        /// Bytes under the stack pointer the user code may use
+        pub const RED_ZONE_SIZE: usize = 0;
+
+        /// `li a7, 139; ecall`
+        pub const SIGRETURN_CODE: [u8; 8] = [0x93, 0x08, 0xb0, 0x08, 0x73, 0x00, 0x00, 0x00];
+    }
 }
 
 numeric_enum! {
@@ -124,32 +443,39 @@ impl Signal {
     pub fn is_standard(self) -> bool {
         (self as usize) < Self::RTMIN
     }
-}
 
-/// See musl struct __ucontext
-///
-/// Not exactly the same for now
-#[repr(C)]
-#[derive(Clone)]
-pub struct SignalUserContext {
-    pub flags: usize,
-    pub link: usize,
-    pub stack: SignalStack,
-    pub context: MachineContext,
-    pub sig_mask: Sigset,
+    /// Whether it stops the process by default
+    pub fn is_stop(self) -> bool {
+        self.default_action() == SignalDefaultAction::Stop
+    }
+
+    /// Action taken when the handler is `SIG_DFL`
+    pub fn default_action(self) -> SignalDefaultAction {
+        use self::Signal::*;
+        match self {
+            SIGCHLD | SIGURG | SIGWINCH => SignalDefaultAction::Ignore,
+            SIGCONT => SignalDefaultAction::Continue,
+            SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => SignalDefaultAction::Stop,
+            SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU
+            | SIGXFSZ | SIGSYS => SignalDefaultAction::CoreDump,
+            _ => SignalDefaultAction::Terminate,
+        }
+    }
 }
 
-#[repr(C)]
-#[derive(Clone)]
-pub struct SignalFrame {
-    /// point to ret_code
-    pub ret_code_addr: usize,
-    /// Signal Frame info
-    pub info: SigInfo,
-    /// adapt interface, a little bit waste
-    pub ucontext: SignalUserContext,
-    /// call sys_sigreturn
-    pub ret_code: [u8; 7],
+/// Default action of a signal
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum SignalDefaultAction {
+    /// Terminate the process
+    Terminate,
+    /// Terminate the process and dump core
+    CoreDump,
+    /// Stop the process
+    Stop,
+    /// Continue the process if it is stopped
+    Continue,
+    /// Ignore the signal
+    Ignore,
 }
 
 bitflags! {
@@ -169,6 +495,15 @@ pub struct SignalStack {
     pub size: usize,
 }
 
+impl SignalStack {
+    /// Whether `sp` is on this stack
+    pub fn contains(&self, sp: usize) -> bool {
+        !self.flags.contains(SignalStackFlags::DISABLE)
+            && sp > self.sp
+            && sp - self.sp <= self.size
+    }
+}
+
 impl Default for SignalStack {
     fn default() -> Self {
         // default to disabled
@@ -0,0 +1,434 @@
+//! Linux futexes on top of the zircon [`Futex`].
+//!
+//! A private futex is found by the address of its word in the process.
+//! A futex shared by processes is found by the VMO page backing its word,
+//! so processes mapping the memory at different addresses wait on the same futex.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicI32, Ordering};
+use core::task::{Context, Poll};
+use core::time::Duration;
+use kernel_hal::user::UserInPtr;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use zircon_object::object::{KernelObject, KoID};
+use zircon_object::signal::Futex;
+use zircon_object::task::Process;
+use zircon_object::vm::VirtAddr;
+use zircon_object::ZxError;
+
+use crate::error::{LxError, LxResult};
+use crate::process::ProcessExt;
+
+/// The futex word has waiters in the kernel, the owner must enter the kernel to unlock it
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// The owner of the futex word exited without unlocking it
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// The thread ID of the owner in a futex word
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+/// The bitset of `FUTEX_WAIT` and `FUTEX_WAKE`, matching any other bitset
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Entries of a robust list walked at most, in case the list is a loop
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+lazy_static! {
+    /// Futexes shared by processes, by the VMO and the offset of their word
+    static ref SHARED_FUTEXES: Mutex<BTreeMap<(KoID, usize), Weak<Futex>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// A futex word in the address space of the current process
+pub struct FutexWord {
+    /// The word seen by the current process
+    value: &'static AtomicI32,
+    /// The futex the waiters on the word are queued on
+    futex: Arc<Futex>,
+}
+
+impl FutexWord {
+    /// Find the futex of the word at `uaddr` in `proc`, `shared` if other processes may map it
+    #[allow(unsafe_code)]
+    pub fn new(proc: &Arc<Process>, uaddr: VirtAddr, shared: bool) -> LxResult<Self> {
+        if uaddr % 4 != 0 {
+            return Err(LxError::EINVAL);
+        }
+        let value = unsafe { &*(uaddr as *const AtomicI32) };
+        let futex = if shared {
+            let info = proc
+                .vmar()
+                .mappings_info()
+                .into_iter()
+                .find(|info| (info.addr..info.addr + info.size).contains(&uaddr))
+                .ok_or(LxError::EFAULT)?;
+            let key = (info.vmo.id(), info.vmo_offset + uaddr - info.addr);
+            let mut futexes = SHARED_FUTEXES.lock();
+            match futexes.get(&key).and_then(Weak::upgrade) {
+                Some(futex) => futex,
+                None => {
+                    // futexes nobody waits on are dropped
+                    futexes.retain(|_, futex| futex.strong_count() > 0);
+                    let futex = Futex::new(value);
+                    futexes.insert(key, Arc::downgrade(&futex));
+                    futex
+                }
+            }
+        } else {
+            proc.vmar().find_mapping(uaddr).ok_or(LxError::EFAULT)?;
+            proc.linux().get_futex(uaddr)
+        };
+        Ok(FutexWord { value, futex })
+    }
+
+    /// Get the value of the word
+    pub fn load(&self) -> u32 {
+        self.value.load(Ordering::SeqCst) as u32
+    }
+
+    /// Wait while the word holds `current_value` until a wake with a bit in common with `bitset`,
+    /// or `ETIMEDOUT` at `deadline`.
+    pub async fn wait(
+        &self,
+        current_value: u32,
+        bitset: u32,
+        deadline: Option<Duration>,
+    ) -> LxResult {
+        let wait = self
+            .futex
+            .wait_bitset(self.value, current_value as i32, None, bitset);
+        match with_deadline(Box::pin(wait), deadline).await {
+            Some(Ok(())) => Ok(()),
+            Some(Err(ZxError::BAD_STATE)) => Err(LxError::EAGAIN),
+            Some(Err(e)) => Err(e.into()),
+            None => Err(LxError::ETIMEDOUT),
+        }
+    }
+
+    /// Wake at most `count` waiters with a bit in common with `bitset`, return the number woken
+    pub fn wake(&self, count: usize, bitset: u32) -> usize {
+        self.futex.wake_bitset(count, bitset)
+    }
+
+    /// Wake at most `wake_count` waiters and move at most `requeue_count` others to `target`.
+    ///
+    /// If `expected` is given, fail with `EAGAIN` unless the word holds it.
+    /// Return the number of waiters woken or moved.
+    pub fn requeue(
+        &self,
+        expected: Option<u32>,
+        wake_count: usize,
+        requeue_count: usize,
+        target: &FutexWord,
+    ) -> LxResult<usize> {
+        let expected = expected.map(|value| (self.value, value as i32));
+        self.futex
+            .wake_requeue(expected, wake_count, requeue_count, &target.futex)
+            .map_err(|_| LxError::EAGAIN)
+    }
+
+    /// Apply the operation encoded in `op` to the word of `other`, wake at most `wake_count`
+    /// waiters of this word, and at most `other_wake_count` waiters of `other` if the old value
+    /// of `other` passes the comparison encoded in `op`.
+    ///
+    /// Return the number of waiters woken.
+    pub fn wake_op(
+        &self,
+        wake_count: usize,
+        other: &FutexWord,
+        other_wake_count: usize,
+        op: u32,
+    ) -> LxResult<usize> {
+        let op = WakeOp::decode(op)?;
+        let old = other
+            .value
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+                Some(op.apply(old))
+            })
+            .unwrap();
+        let mut woken = self.wake(wake_count, FUTEX_BITSET_MATCH_ANY);
+        if op.compare(old) {
+            woken += other.wake(other_wake_count, FUTEX_BITSET_MATCH_ANY);
+        }
+        Ok(woken)
+    }
+
+    /// Lock the word as a PI futex for the thread `tid`, or fail with `EAGAIN` instead of
+    /// waiting if `try_only`.
+    ///
+    /// An unlocked word is 0 or `FUTEX_OWNER_DIED`, a locked one holds the TID of its owner.
+    /// The priority of the owner is not raised to the one of the waiters.
+    pub async fn lock_pi(&self, tid: u32, deadline: Option<Duration>, try_only: bool) -> LxResult {
+        loop {
+            let value = self.load();
+            let owner = value & FUTEX_TID_MASK;
+            if owner == 0 {
+                // user space learns from `FUTEX_OWNER_DIED` that the last owner died
+                let locked = tid | (value & FUTEX_OWNER_DIED);
+                if self.compare_exchange(value, locked) {
+                    return Ok(());
+                }
+                continue;
+            }
+            if owner == tid {
+                return Err(LxError::EDEADLK);
+            }
+            if try_only {
+                return Err(LxError::EAGAIN);
+            }
+            // make the owner enter the kernel to unlock
+            let waiting = value | FUTEX_WAITERS;
+            if waiting != value && !self.compare_exchange(value, waiting) {
+                continue;
+            }
+            match self.wait(waiting, FUTEX_BITSET_MATCH_ANY, deadline).await {
+                Ok(()) | Err(LxError::EAGAIN) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Unlock the word locked as a PI futex by the thread `tid`.
+    ///
+    /// All waiters are woken to compete for the lock again,
+    /// the ones not getting it mark the word with `FUTEX_WAITERS` again.
+    pub fn unlock_pi(&self, tid: u32) -> LxResult {
+        loop {
+            let value = self.load();
+            if value & FUTEX_TID_MASK != tid {
+                return Err(LxError::EPERM);
+            }
+            if self.compare_exchange(value, 0) {
+                break;
+            }
+        }
+        self.wake(usize::MAX, FUTEX_BITSET_MATCH_ANY);
+        Ok(())
+    }
+
+    fn compare_exchange(&self, current: u32, new: u32) -> bool {
+        self.value
+            .compare_exchange(
+                current as i32,
+                new as i32,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+    }
+}
+
+/// Wake at most `count` waiters of the word at `uaddr` in `proc`, for wakes done by the kernel.
+///
+/// Both the private and the shared futexes of the word are woken,
+/// as user space may wait on the word either way.
+pub fn wake_futex_word(proc: &Arc<Process>, uaddr: VirtAddr, count: usize) {
+    for shared in [false, true] {
+        if let Ok(word) = FutexWord::new(proc, uaddr, shared) {
+            word.wake(count, FUTEX_BITSET_MATCH_ANY);
+        }
+    }
+}
+
+/// Operation of `FUTEX_WAKE_OP` (see [linux man futex(2)](https://www.man7.org/linux/man-pages/man2/futex.2.html))
+struct WakeOp {
+    op: u32,
+    oparg: i32,
+    cmp: u32,
+    cmparg: i32,
+}
+
+impl WakeOp {
+    /// `oparg` is a shift count: `1 << oparg`
+    const OPARG_SHIFT: u32 = 8;
+
+    fn decode(encoded: u32) -> LxResult<Self> {
+        // 12-bit signed arguments
+        let sign_extend = |arg: u32| ((arg << 20) as i32) >> 20;
+        let mut op = WakeOp {
+            op: encoded >> 28,
+            oparg: sign_extend(encoded >> 12),
+            cmp: (encoded >> 24) & 0xf,
+            cmparg: sign_extend(encoded),
+        };
+        if op.op & Self::OPARG_SHIFT != 0 {
+            op.op &= !Self::OPARG_SHIFT;
+            op.oparg = 1 << (op.oparg & 31);
+        }
+        if op.op > 4 || op.cmp > 5 {
+            return Err(LxError::ENOSYS);
+        }
+        Ok(op)
+    }
+
+    fn apply(&self, old: i32) -> i32 {
+        match self.op {
+            0 => self.oparg,
+            1 => old.wrapping_add(self.oparg),
+            2 => old | self.oparg,
+            3 => old & !self.oparg,
+            _ => old ^ self.oparg,
+        }
+    }
+
+    fn compare(&self, old: i32) -> bool {
+        match self.cmp {
+            0 => old == self.cmparg,
+            1 => old != self.cmparg,
+            2 => old < self.cmparg,
+            3 => old <= self.cmparg,
+            4 => old > self.cmparg,
+            _ => old >= self.cmparg,
+        }
+    }
+}
+
+/// Linux struct robust_list_head, the list of robust futexes held by a thread
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct RobustListHead {
+    /// the first entry, the list ends with the head itself
+    list: VirtAddr,
+    /// offset of the futex word from an entry
+    futex_offset: isize,
+    /// the entry being locked or unlocked
+    list_op_pending: VirtAddr,
+}
+
+/// Release the robust futexes still held by the exiting thread `tid`
+/// (see [linux man set_robust_list(2)](https://www.man7.org/linux/man-pages/man2/set_robust_list.2.html)).
+///
+/// Their words are marked with `FUTEX_OWNER_DIED` and one waiter of each is woken.
+/// The list is walked in the address space of `proc`, which need not be the current one.
+pub fn exit_robust_list(proc: &Arc<Process>, head: UserInPtr<RobustListHead>, tid: u32) {
+    let head_addr = head.as_addr();
+    let robust_list = match read_user::<RobustListHead>(proc, head_addr) {
+        Some(robust_list) => robust_list,
+        None => return,
+    };
+    let futex_addr = |entry: VirtAddr| (entry & !1).wrapping_add(robust_list.futex_offset as usize);
+    let mut entry = robust_list.list;
+    for _ in 0..ROBUST_LIST_LIMIT {
+        if entry == head_addr || entry == 0 {
+            break;
+        }
+        // the entry may be freed once the futex is released
+        let next = match read_user::<VirtAddr>(proc, entry & !1) {
+            Some(next) => next,
+            None => break,
+        };
+        if entry != robust_list.list_op_pending {
+            handle_futex_death(proc, futex_addr(entry), tid);
+        }
+        entry = next;
+    }
+    if robust_list.list_op_pending != 0 {
+        handle_futex_death(proc, futex_addr(robust_list.list_op_pending), tid);
+    }
+}
+
+fn handle_futex_death(proc: &Arc<Process>, uaddr: VirtAddr, tid: u32) {
+    let word = match user_word(proc, uaddr) {
+        Some(word) => word,
+        None => return,
+    };
+    loop {
+        let value = word.load(Ordering::SeqCst) as u32;
+        if value & FUTEX_TID_MASK != tid {
+            return;
+        }
+        let died = (value & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        let exchange = word.compare_exchange(
+            value as i32,
+            died as i32,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+        if exchange.is_ok() {
+            if value & FUTEX_WAITERS != 0 {
+                wake_futex_word(proc, uaddr, 1);
+            }
+            return;
+        }
+    }
+}
+
+/// Clear the word at `uaddr` in `proc` and wake one of its waiters, for `CLONE_CHILD_CLEARTID`
+/// when a thread exits. `proc` need not be the current process.
+pub fn exit_clear_tid(proc: &Arc<Process>, uaddr: VirtAddr) {
+    if let Some(word) = user_word(proc, uaddr) {
+        word.store(0, Ordering::SeqCst);
+        wake_futex_word(proc, uaddr, 1);
+    }
+}
+
+/// Get the word at `uaddr` in the address space of `proc` for writing,
+/// through the kernel mapping of its page as `proc` may not be the current process.
+#[allow(unsafe_code)]
+fn user_word(proc: &Arc<Process>, uaddr: VirtAddr) -> Option<&'static AtomicI32> {
+    if uaddr % 4 != 0 {
+        return None;
+    }
+    // all processes share the address space of the host in libos
+    #[cfg(not(target_os = "none"))]
+    let word = {
+        proc.vmar().find_mapping(uaddr)?;
+        uaddr as *const AtomicI32
+    };
+    #[cfg(target_os = "none")]
+    let word = {
+        use kernel_hal::{mem::PhysFrame, MMUFlags};
+        use zircon_object::vm::PAGE_SIZE;
+        let vmar = proc.vmar();
+        // populate the page, or copy it if it is copy-on-write
+        vmar.handle_page_fault(uaddr, MMUFlags::WRITE).ok()?;
+        let (paddr, _, _) = vmar.query_vaddr(uaddr).ok()?;
+        let frame = unsafe { PhysFrame::from_paddr(paddr & !(PAGE_SIZE - 1)) };
+        unsafe { frame.as_ptr().add(paddr % PAGE_SIZE) as *const AtomicI32 }
+    };
+    Some(unsafe { &*word })
+}
+
+/// Read a `T` at `uaddr` in the address space of `proc`, which need not be the current one
+#[allow(unsafe_code)]
+fn read_user<T: Copy>(proc: &Arc<Process>, uaddr: VirtAddr) -> Option<T> {
+    let mut buf = vec![0u8; core::mem::size_of::<T>()];
+    match proc.vmar().read_memory(uaddr, &mut buf) {
+        Ok(len) if len == buf.len() => {
+            Some(unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const T) })
+        }
+        _ => None,
+    }
+}
+
+/// Run `future` until it completes, or return `None` once `deadline` passes
+async fn with_deadline<F: Future + Unpin>(
+    future: F,
+    deadline: Option<Duration>,
+) -> Option<F::Output> {
+    #[must_use = "future does nothing unless polled/`await`-ed"]
+    struct Deadline<F, S> {
+        future: F,
+        timer: Option<S>,
+    }
+
+    impl<F: Future + Unpin, S: Future + Unpin> Future for Deadline<F, S> {
+        type Output = Option<F::Output>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            if let Poll::Ready(output) = Pin::new(&mut self.future).poll(cx) {
+                return Poll::Ready(Some(output));
+            }
+            match self.timer.as_mut() {
+                Some(timer) if Pin::new(timer).poll(cx).is_ready() => Poll::Ready(None),
+                _ => Poll::Pending,
+            }
+        }
+    }
+
+    let timer = deadline.map(|deadline| Box::pin(kernel_hal::thread::sleep_until(deadline)));
+    Deadline { future, timer }.await
+}
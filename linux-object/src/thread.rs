@@ -1,9 +1,17 @@
 //! Linux Thread
 
-use crate::process::ProcessExt;
-use crate::signal::{SignalStack, Sigset};
+use crate::error::{LxError, LxResult};
+use crate::process::{is_ignored, prepare_signal, ProcessExt};
+use crate::signal::{
+    MachineContext, SigInfo, Signal, SignalAction, SignalActionFlags, SignalDefaultAction,
+    SignalFrame, SignalQueue, SignalStack, SignalStackFlags, SignalUserContext, Sigset,
+    RED_ZONE_SIZE, SIG_DFL, SIG_IGN,
+};
+use crate::sync::{exit_clear_tid, exit_robust_list, Event, EventBus, RobustListHead};
 use alloc::sync::Arc;
-use kernel_hal::user::{Out, UserOutPtr, UserPtr};
+use core::time::Duration;
+use kernel_hal::context::{UserContext, UserContextField};
+use kernel_hal::user::{Out, UserInPtr, UserOutPtr, UserPtr};
 use spin::{Mutex, MutexGuard};
 use zircon_object::task::{CurrentThread, Process, Thread};
 use zircon_object::ZxResult;
@@ -16,20 +24,75 @@ pub trait ThreadExt {
     fn lock_linux(&self) -> MutexGuard<'_, LinuxThread>;
     /// Set pointer to thread ID.
     fn set_tid_address(&self, tidptr: UserOutPtr<i32>);
+    /// Send a signal to this thread only
+    fn send_signal(&self, info: SigInfo);
+    /// Get the event bus where `RECEIVE_SIGNAL` is set while a signal can be delivered
+    fn signal_events(&self) -> Arc<Mutex<EventBus>>;
+    /// Recompute whether the thread has a signal to deliver
+    fn update_signal_events(&self);
+    /// Discard the pending signals the thread would ignore once taken, then return whether
+    /// a signal is left to run a handler, or to stop or kill the process.
+    ///
+    /// Only such a signal interrupts a blocked syscall.
+    fn flush_ignored_signals(&self) -> bool;
+    /// Account CPU time spent by the thread in user and kernel mode
+    fn account_cpu_time(&self, user: Duration, system: Duration);
+    /// Get the user and system CPU time used by the thread
+    fn cpu_time(&self) -> (Duration, Duration);
+    /// Release the robust futexes of the exiting thread and clear its child TID,
+    /// waking the threads joining it.
+    ///
+    /// The thread may belong to a process other than the current one.
+    fn release_exit_futexes(&self);
+    /// Block the signals in `mask` instead while a syscall like `epoll_pwait` waits
+    ///
+    /// The replaced mask is restored by [`ThreadExt::restore_signal_mask`] when the syscall
+    /// returns, or when a signal interrupting the syscall is taken. The frame of a handler
+    /// run for the signal saves the replaced mask, so it comes back once the handler returns.
+    fn set_temporary_signal_mask(&self, mask: Sigset);
+    /// Restore the signal mask replaced by [`ThreadExt::set_temporary_signal_mask`], if any
+    fn restore_signal_mask(&self);
 }
 
 /// CurrentThread extension for linux
 pub trait CurrentThreadExt {
     /// exit linux thread
     fn exit_linux(&self, exit_code: i32);
+    /// Take a pending signal before returning to user mode.
+    ///
+    /// Signals without a handler get their default action here.
+    fn take_signal(&self) -> Option<SignalDelivery>;
+    /// Push a signal frame on the user stack and make `ctx` enter the handler.
+    ///
+    /// The process is killed by SIGSEGV if the frame cannot be written.
+    fn setup_signal_frame(
+        &self,
+        ctx: &mut UserContext,
+        info: &SigInfo,
+        action: &SignalAction,
+    ) -> LxResult;
+}
+
+/// A signal taken by [`CurrentThreadExt::take_signal`]
+pub enum SignalDelivery {
+    /// The signal is to be handled by a user handler
+    Handler(SigInfo, SignalAction),
+    /// The default action was taken or the signal was ignored
+    Default,
 }
 
 impl ThreadExt for Thread {
     fn create_linux(proc: &Arc<Process>) -> ZxResult<Arc<Self>> {
         let linux_thread = Mutex::new(LinuxThread {
             clear_child_tid: 0.into(),
+            robust_list: 0.into(),
             signal_mask: Sigset::default(),
+            saved_signal_mask: None,
             signal_alternate_stack: SignalStack::default(),
+            signal_pending: SignalQueue::default(),
+            signal_events: EventBus::new(),
+            user_time: Duration::default(),
+            system_time: Duration::default(),
         });
         Thread::create_with_ext(proc, "", linux_thread)
     }
@@ -45,24 +108,274 @@ impl ThreadExt for Thread {
     fn set_tid_address(&self, tidptr: UserPtr<i32, Out>) {
         self.lock_linux().clear_child_tid = tidptr;
     }
-}
 
-impl CurrentThreadExt for CurrentThread {
-    /// Exit current thread for Linux.
-    fn exit_linux(&self, _exit_code: i32) {
-        let mut linux_thread = self.lock_linux();
-        let clear_child_tid = &mut linux_thread.clear_child_tid;
+    fn send_signal(&self, info: SigInfo) {
+        let signal = match info.signal() {
+            Some(signal) => signal,
+            None => return,
+        };
+        if !prepare_signal(self.proc(), signal) {
+            return;
+        }
+        self.lock_linux().signal_pending.push(signal, info);
+        self.update_signal_events();
+    }
+
+    fn signal_events(&self) -> Arc<Mutex<EventBus>> {
+        self.lock_linux().signal_events.clone()
+    }
+
+    fn account_cpu_time(&self, user: Duration, system: Duration) {
+        {
+            let mut inner = self.lock_linux();
+            inner.user_time += user;
+            inner.system_time += system;
+        }
+        self.time_add((user + system).as_nanos());
+        self.proc().account_cpu_time(user, system);
+    }
+
+    fn cpu_time(&self) -> (Duration, Duration) {
+        let inner = self.lock_linux();
+        (inner.user_time, inner.system_time)
+    }
+
+    fn update_signal_events(&self) {
+        // never hold the process lock with the thread lock
+        let mut pending = self.proc().linux().signal_pending();
+        let linux_thread = self.lock_linux();
+        pending.insert_set(&linux_thread.signal_pending.pending());
+        let mut events = linux_thread.signal_events.lock();
+        if pending.without(&linux_thread.signal_mask).is_empty() {
+            events.clear(Event::RECEIVE_SIGNAL);
+        } else {
+            events.set(Event::RECEIVE_SIGNAL);
+        }
+    }
+
+    fn flush_ignored_signals(&self) -> bool {
+        let proc = self.proc();
+        let linux = proc.linux();
+        let mut pending = linux.signal_pending();
+        let mask = {
+            let linux_thread = self.lock_linux();
+            pending.insert_set(&linux_thread.signal_pending.pending());
+            linux_thread.signal_mask
+        };
+        let mut pending = pending.without(&mask);
+        let mut deliverable = false;
+        let mut discarded = false;
+        while let Some(signal) = pending.first() {
+            pending.remove(signal);
+            if is_ignored(linux, signal) {
+                // never hold the process lock with the thread lock
+                self.lock_linux().signal_pending.remove(signal);
+                linux.discard_signal(signal);
+                discarded = true;
+            } else {
+                deliverable = true;
+            }
+        }
+        if discarded {
+            self.update_signal_events();
+        }
+        deliverable
+    }
+
+    fn release_exit_futexes(&self) {
+        let (robust_list, clear_child_tid) = {
+            let mut linux_thread = self.lock_linux();
+            let robust_list = core::mem::replace(&mut linux_thread.robust_list, 0.into());
+            let clear_child_tid = core::mem::replace(&mut linux_thread.clear_child_tid, 0.into());
+            (robust_list, clear_child_tid)
+        };
+        // release the robust futexes before waking the threads joining this one
+        if !robust_list.is_null() {
+            exit_robust_list(self.proc(), robust_list, self.id() as u32);
+        }
         // perform futex wake 1
         // ref: http://man7.org/linux/man-pages/man2/set_tid_address.2.html
         if !clear_child_tid.is_null() {
             info!("exit: do futex {:?} wake 1", clear_child_tid);
-            clear_child_tid.write(0).unwrap();
-            let uaddr = clear_child_tid.as_addr();
-            let futex = self.proc().linux().get_futex(uaddr);
-            futex.wake(1);
+            exit_clear_tid(self.proc(), clear_child_tid.as_addr());
+        }
+    }
+
+    fn set_temporary_signal_mask(&self, mut mask: Sigset) {
+        // SIGKILL and SIGSTOP cannot be blocked
+        mask.remove(Signal::SIGKILL);
+        mask.remove(Signal::SIGSTOP);
+        {
+            let mut linux_thread = self.lock_linux();
+            let old_mask = core::mem::replace(&mut linux_thread.signal_mask, mask);
+            linux_thread.saved_signal_mask.get_or_insert(old_mask);
         }
+        self.update_signal_events();
+    }
+
+    fn restore_signal_mask(&self) {
+        {
+            let mut linux_thread = self.lock_linux();
+            match linux_thread.saved_signal_mask.take() {
+                Some(mask) => linux_thread.signal_mask = mask,
+                None => return,
+            }
+        }
+        self.update_signal_events();
+    }
+}
+
+impl CurrentThreadExt for CurrentThread {
+    /// Exit current thread for Linux.
+    fn exit_linux(&self, _exit_code: i32) {
+        self.release_exit_futexes();
         self.exit();
     }
+
+    fn take_signal(&self) -> Option<SignalDelivery> {
+        let proc = self.proc();
+        let (mask, taken) = {
+            let mut linux_thread = self.lock_linux();
+            let mask = linux_thread.signal_mask;
+            (mask, linux_thread.signal_pending.pop(&mask))
+        };
+        let (signal, info) = match taken {
+            Some(taken) => {
+                self.update_signal_events();
+                taken
+            }
+            None => match proc.take_signal(&mask) {
+                Some(taken) => taken,
+                None => {
+                    self.restore_signal_mask();
+                    return None;
+                }
+            },
+        };
+        let action = proc.linux().signal_action(signal);
+        info!(
+            "thread {} takes {:?}, handler={:#x}",
+            self.id(),
+            signal,
+            action.handler
+        );
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match signal.default_action() {
+                SignalDefaultAction::Terminate | SignalDefaultAction::CoreDump => {
+                    // TODO: dump core
+                    proc.kill_by_signal(signal);
+                }
+                SignalDefaultAction::Stop => proc.stop_by_signal(signal),
+                SignalDefaultAction::Continue | SignalDefaultAction::Ignore => {}
+            },
+            _ => return Some(SignalDelivery::Handler(info, action)),
+        }
+        self.restore_signal_mask();
+        Some(SignalDelivery::Default)
+    }
+
+    fn setup_signal_frame(
+        &self,
+        ctx: &mut UserContext,
+        info: &SigInfo,
+        action: &SignalAction,
+    ) -> LxResult {
+        let proc = self.proc();
+        let signal = info.signal().ok_or(LxError::EINVAL)?;
+        let ret_code_addr = if action.flags.contains(SignalActionFlags::RESTORER) {
+            action.restorer
+        } else {
+            proc.linux().sigreturn_trampoline(&proc.vmar())?
+        };
+        let sp = ctx.get_field(UserContextField::StackPointer);
+        let mut linux_thread = self.lock_linux();
+        let mut stack = linux_thread.signal_alternate_stack;
+        let on_stack = stack.contains(sp);
+        let switch_stack = action.flags.contains(SignalActionFlags::ONSTACK)
+            && !stack.flags.contains(SignalStackFlags::DISABLE)
+            && !on_stack;
+        let stack_top = if switch_stack {
+            stack.sp + stack.size
+        } else {
+            sp - RED_ZONE_SIZE
+        };
+        let frame_addr = SignalFrame::locate(stack_top);
+        if on_stack || switch_stack {
+            stack.flags.insert(SignalStackFlags::ONSTACK);
+        }
+        // the handler returns to the mask replaced by the interrupted syscall
+        let old_mask = linux_thread
+            .saved_signal_mask
+            .take()
+            .unwrap_or(linux_thread.signal_mask);
+        let ucontext = SignalUserContext::new(stack, MachineContext::save(ctx), old_mask);
+        let frame = SignalFrame::new(*info, ucontext, ret_code_addr);
+        let frame_base = &frame as *const SignalFrame as usize;
+        let info_addr = frame_addr + (&frame.info as *const SigInfo as usize - frame_base);
+        let ucontext_addr =
+            frame_addr + (&frame.ucontext as *const SignalUserContext as usize - frame_base);
+        if let Err(err) = write_signal_frame(proc, frame_addr, frame) {
+            drop(linux_thread);
+            error!(
+                "failed to push signal frame @ {:#x} for {:?}, thread={}",
+                frame_addr,
+                signal,
+                self.id()
+            );
+            proc.kill_by_signal(Signal::SIGSEGV);
+            return Err(err);
+        }
+        SignalFrame::enter_handler(
+            ctx,
+            action.handler,
+            frame_addr,
+            [signal as usize, info_addr, ucontext_addr],
+            ret_code_addr,
+        );
+        if !action.flags.contains(SignalActionFlags::NODEFER) {
+            linux_thread.signal_mask.insert(signal);
+        }
+        linux_thread.signal_mask.insert_set(&action.mask);
+        linux_thread.signal_mask.remove(Signal::SIGKILL);
+        linux_thread.signal_mask.remove(Signal::SIGSTOP);
+        if switch_stack
+            && linux_thread
+                .signal_alternate_stack
+                .flags
+                .contains(SignalStackFlags::AUTODISARM)
+        {
+            linux_thread.signal_alternate_stack = SignalStack::default();
+        }
+        drop(linux_thread);
+        if action.flags.contains(SignalActionFlags::RESETHAND) {
+            proc.linux()
+                .set_signal_action(signal, SignalAction::default());
+        }
+        self.update_signal_events();
+        Ok(())
+    }
+}
+
+/// Write a signal frame to the user stack at `addr`.
+fn write_signal_frame(proc: &Arc<Process>, addr: usize, frame: SignalFrame) -> LxResult {
+    // user pages are not populated for the kernel on bare metal
+    #[cfg(target_os = "none")]
+    {
+        use kernel_hal::MMUFlags;
+        use zircon_object::vm::{pages, PAGE_SIZE};
+        let vmar = proc.vmar();
+        let start = addr / PAGE_SIZE * PAGE_SIZE;
+        for page in 0..pages(addr + core::mem::size_of::<SignalFrame>() - start) {
+            vmar.handle_page_fault(start + page * PAGE_SIZE, MMUFlags::WRITE)
+                .map_err(|_| LxError::EFAULT)?;
+        }
+    }
+    #[cfg(not(target_os = "none"))]
+    let _ = proc;
+    let mut ptr: UserOutPtr<SignalFrame> = addr.into();
+    ptr.write(frame)?;
+    Ok(())
 }
 
 /// Linux specific thread information.
@@ -70,8 +383,20 @@ pub struct LinuxThread {
     /// Kernel performs futex wake when thread exits.
     /// Ref: <http://man7.org/linux/man-pages/man2/set_tid_address.2.html>
     clear_child_tid: UserOutPtr<i32>,
+    /// Robust futexes released when the thread exits, set by `set_robust_list`
+    pub robust_list: UserInPtr<RobustListHead>,
     /// Signal mask
     pub signal_mask: Sigset,
+    /// The mask replaced by a syscall waiting with a temporary signal mask
+    saved_signal_mask: Option<Sigset>,
     /// signal alternate stack
     pub signal_alternate_stack: SignalStack,
+    /// Signals sent to this thread
+    pub(crate) signal_pending: SignalQueue,
+    /// `RECEIVE_SIGNAL` is set while an unblocked signal is pending
+    signal_events: Arc<Mutex<EventBus>>,
+    /// CPU time spent in user mode
+    user_time: Duration,
+    /// CPU time spent in kernel mode
+    system_time: Duration,
 }
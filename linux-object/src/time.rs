@@ -1,8 +1,17 @@
 //! Linux time objects
 
-use alloc::sync::Arc;
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use core::convert::TryFrom;
+use core::sync::atomic::AtomicUsize;
 use core::time::Duration;
+use kernel_hal::timer::timer_now;
 use rcore_fs::vfs::*;
+use spin::Mutex;
+use zircon_object::object::{KernelObject, Signal};
+use zircon_object::signal::Timer;
+
+use crate::error::LxError;
 
 /// TimeSpec struct for clock_gettime, similar to Timespec
 #[repr(C)]
@@ -88,6 +97,30 @@ impl From<TimeSpec> for Duration {
     }
 }
 
+impl From<Duration> for TimeSpec {
+    fn from(d: Duration) -> Self {
+        Self {
+            sec: d.as_secs() as usize,
+            nsec: d.subsec_nanos() as usize,
+        }
+    }
+}
+
+impl From<TimeVal> for Duration {
+    fn from(t: TimeVal) -> Self {
+        Self::new(t.sec as _, (t.usec * 1_000) as _)
+    }
+}
+
+impl From<Duration> for TimeVal {
+    fn from(d: Duration) -> Self {
+        Self {
+            sec: d.as_secs() as usize,
+            usec: d.subsec_micros() as usize,
+        }
+    }
+}
+
 impl From<TimeSpec> for TimeVal {
     fn from(t: TimeSpec) -> Self {
         Self {
@@ -98,13 +131,41 @@ impl From<TimeSpec> for TimeVal {
 }
 
 /// RUsage for sys_getrusage()
-/// ignore other fields for now
 #[repr(C)]
+#[derive(Debug, Default)]
 pub struct RUsage {
     /// user CPU time used
     pub utime: TimeVal,
     /// system CPU time used
     pub stime: TimeVal,
+    /// maximum resident set size in kilobytes
+    pub maxrss: usize,
+    /// integral shared memory size
+    pub ixrss: usize,
+    /// integral unshared data size
+    pub idrss: usize,
+    /// integral unshared stack size
+    pub isrss: usize,
+    /// page reclaims (soft page faults)
+    pub minflt: usize,
+    /// page faults (hard page faults)
+    pub majflt: usize,
+    /// swaps
+    pub nswap: usize,
+    /// block input operations
+    pub inblock: usize,
+    /// block output operations
+    pub oublock: usize,
+    /// IPC messages sent
+    pub msgsnd: usize,
+    /// IPC messages received
+    pub msgrcv: usize,
+    /// signals received
+    pub nsignals: usize,
+    /// voluntary context switches
+    pub nvcsw: usize,
+    /// involuntary context switches
+    pub nivcsw: usize,
 }
 
 /// Tms for times()
@@ -190,3 +251,287 @@ pub async fn nanosleep(dur: Duration) {
     use kernel_hal::thread;
     thread::sleep_until(dur).await;
 }
+
+/// Linux struct itimerval for getitimer and setitimer
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ITimerVal {
+    /// period of the timer, zero for a one-shot timer
+    pub interval: TimeVal,
+    /// time until the next expiration, zero if the timer is disarmed
+    pub value: TimeVal,
+}
+
+/// Linux struct itimerspec for POSIX timers and timerfd
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ITimerSpec {
+    /// period of the timer, zero for a one-shot timer
+    pub interval: TimeSpec,
+    /// time until the next expiration, zero if the timer is disarmed
+    pub value: TimeSpec,
+}
+
+/// Interval timers of a process, see [linux man setitimer(2)](https://www.man7.org/linux/man-pages/man2/setitimer.2.html)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ITimerWhich {
+    /// on the system clock, sends SIGALRM
+    Real = 0,
+    /// on the user CPU time of the process, sends SIGVTALRM
+    Virtual = 1,
+    /// on the user and system CPU time of the process, sends SIGPROF
+    Prof = 2,
+}
+
+impl TryFrom<usize> for ITimerWhich {
+    type Error = LxError;
+    fn try_from(which: usize) -> Result<Self, LxError> {
+        match which {
+            0 => Ok(ITimerWhich::Real),
+            1 => Ok(ITimerWhich::Virtual),
+            2 => Ok(ITimerWhich::Prof),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+}
+
+/// Setting of a timer
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TimerValue {
+    /// time until the next expiration, zero if the timer is disarmed
+    pub value: Duration,
+    /// period of the timer, zero for a one-shot timer
+    pub interval: Duration,
+}
+
+impl TryFrom<ITimerVal> for TimerValue {
+    type Error = LxError;
+    fn try_from(t: ITimerVal) -> Result<Self, LxError> {
+        Ok(Self {
+            value: timer_duration(t.value.sec, t.value.usec, 1_000)?,
+            interval: timer_duration(t.interval.sec, t.interval.usec, 1_000)?,
+        })
+    }
+}
+
+impl From<TimerValue> for ITimerVal {
+    fn from(t: TimerValue) -> Self {
+        Self {
+            value: t.value.into(),
+            interval: t.interval.into(),
+        }
+    }
+}
+
+impl TryFrom<ITimerSpec> for TimerValue {
+    type Error = LxError;
+    fn try_from(t: ITimerSpec) -> Result<Self, LxError> {
+        Ok(Self {
+            value: timer_duration(t.value.sec, t.value.nsec, 1)?,
+            interval: timer_duration(t.interval.sec, t.interval.nsec, 1)?,
+        })
+    }
+}
+
+/// The duration of `sec` seconds and `frac` units of `unit` nanoseconds given to a timer,
+/// `EINVAL` if it is negative or `frac` is not less than a second
+fn timer_duration(sec: usize, frac: usize, unit: usize) -> Result<Duration, LxError> {
+    if (sec as isize) < 0 || frac >= 1_000_000_000 / unit {
+        return Err(LxError::EINVAL);
+    }
+    Ok(Duration::new(sec as u64, (frac * unit) as u32))
+}
+
+/// `nanos` as a duration, the longest one if it does not fit
+fn duration_from_nanos(nanos: u128) -> Duration {
+    let secs = u64::try_from(nanos / 1_000_000_000).unwrap_or(u64::MAX);
+    Duration::new(secs, (nanos % 1_000_000_000) as u32)
+}
+
+impl From<TimerValue> for ITimerSpec {
+    fn from(t: TimerValue) -> Self {
+        Self {
+            value: t.value.into(),
+            interval: t.interval.into(),
+        }
+    }
+}
+
+/// Called with the number of expirations of a timer since it was last notified
+pub type ExpireHandler = Box<dyn Fn(usize) + Send + Sync>;
+
+/// A timer on the system clock, which may be periodic
+///
+/// Every period waits on a one-shot [`Timer`] in a task of its own, so the expirations are
+/// handled outside the timer interrupt.
+pub struct IntervalTimer {
+    state: Mutex<IntervalState>,
+    on_expire: ExpireHandler,
+}
+
+#[derive(Default)]
+struct IntervalState {
+    deadline: Option<Duration>,
+    interval: Duration,
+    /// changed by every `set`, the task waiting for an older setting gives up
+    generation: usize,
+    /// the one-shot timer the task is waiting on, fired early to stop the task
+    waiting: Option<Arc<Timer>>,
+}
+
+impl IntervalState {
+    /// Stop the task waiting for the current setting
+    fn cancel(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        if let Some(timer) = self.waiting.take() {
+            timer.cancel();
+            timer.signal_set(Signal::SIGNALED);
+        }
+    }
+}
+
+impl IntervalTimer {
+    /// Create a disarmed timer calling `on_expire` when it expires
+    pub fn new(on_expire: ExpireHandler) -> Arc<Self> {
+        Arc::new(IntervalTimer {
+            state: Mutex::new(IntervalState::default()),
+            on_expire,
+        })
+    }
+
+    /// Get the current setting of the timer
+    pub fn get(&self) -> TimerValue {
+        let state = self.state.lock();
+        let remaining = |deadline: Duration| deadline.saturating_sub(timer_now());
+        TimerValue {
+            value: state.deadline.map_or(Duration::ZERO, remaining),
+            interval: state.interval,
+        }
+    }
+
+    /// Arm the timer to expire after `value.value`, or disarm it if it is zero.
+    ///
+    /// Return the previous setting.
+    pub fn set(self: &Arc<Self>, value: TimerValue) -> TimerValue {
+        let old = self.get();
+        let mut state = self.state.lock();
+        state.cancel();
+        state.interval = value.interval;
+        state.deadline = None;
+        if value.value != Duration::ZERO {
+            let deadline = timer_now()
+                .checked_add(value.value)
+                .unwrap_or(Duration::MAX);
+            state.deadline = Some(deadline);
+            let generation = state.generation;
+            kernel_hal::thread::spawn(Self::run(Arc::downgrade(self), generation, deadline));
+        }
+        old
+    }
+
+    /// Wait for the expirations of the timer while its setting is `generation`
+    async fn run(me: Weak<Self>, generation: usize, mut deadline: Duration) {
+        loop {
+            if deadline > timer_now() {
+                let timer = Timer::one_shot(deadline);
+                match me.upgrade() {
+                    Some(this) => {
+                        let mut state = this.state.lock();
+                        if state.generation != generation {
+                            return;
+                        }
+                        state.waiting = Some(timer.clone());
+                    }
+                    None => return,
+                }
+                let timer: Arc<dyn KernelObject> = timer;
+                timer.wait_signal(Signal::SIGNALED).await;
+            }
+            let this = match me.upgrade() {
+                Some(this) => this,
+                None => return,
+            };
+            let (expirations, periodic) = {
+                let mut state = this.state.lock();
+                if state.generation != generation {
+                    return;
+                }
+                state.waiting = None;
+                let interval = state.interval.as_nanos();
+                if interval == 0 {
+                    state.deadline = None;
+                    (1, false)
+                } else {
+                    // periods missed while the task was not running count as expirations
+                    let late = timer_now().saturating_sub(deadline).as_nanos() / interval;
+                    deadline = deadline
+                        .checked_add(duration_from_nanos((late + 1) * interval))
+                        .unwrap_or(Duration::MAX);
+                    state.deadline = Some(deadline);
+                    (late as usize + 1, true)
+                }
+            };
+            (this.on_expire)(expirations);
+            if !periodic {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for IntervalTimer {
+    fn drop(&mut self) {
+        self.state.lock().cancel();
+    }
+}
+
+/// A timer on the CPU time consumed by a process, see `ITIMER_VIRTUAL` and `ITIMER_PROF`
+///
+/// It is advanced by the time accounted to the process instead of the system clock.
+#[derive(Debug, Default)]
+pub struct CpuTimer {
+    value: TimerValue,
+}
+
+impl CpuTimer {
+    /// Get the current setting of the timer
+    pub fn get(&self) -> TimerValue {
+        self.value
+    }
+
+    /// Arm or disarm the timer like [`IntervalTimer::set`], return the previous setting
+    pub fn set(&mut self, value: TimerValue) -> TimerValue {
+        core::mem::replace(&mut self.value, value)
+    }
+
+    /// Account `elapsed` CPU time, return the number of expirations
+    pub fn tick(&mut self, elapsed: Duration) -> usize {
+        let value = &mut self.value;
+        if value.value == Duration::ZERO {
+            return 0;
+        }
+        if elapsed < value.value {
+            value.value -= elapsed;
+            return 0;
+        }
+        let late = elapsed - value.value;
+        if value.interval == Duration::ZERO {
+            value.value = Duration::ZERO;
+            return 1;
+        }
+        let interval = value.interval.as_nanos();
+        let periods = late.as_nanos() / interval;
+        let left = interval - late.as_nanos() % interval;
+        value.value = duration_from_nanos(left);
+        periods as usize + 1
+    }
+}
+
+/// A POSIX timer created by timer_create
+#[derive(Clone)]
+pub struct PosixTimer {
+    /// the timer on the system clock
+    pub timer: Arc<IntervalTimer>,
+    /// expirations beyond the one notified last, see timer_getoverrun
+    pub overrun: Arc<AtomicUsize>,
+}
@@ -0,0 +1,211 @@
+use super::*;
+use alloc::vec::Vec;
+use linux_object::cred::{Credentials, NGROUPS_MAX};
+use linux_object::error::LxResult;
+
+/// Syscalls for user and group identity.
+///
+/// # Menu
+///
+/// - [`getuid`](Self::sys_getuid)
+/// - [`geteuid`](Self::sys_geteuid)
+/// - [`getgid`](Self::sys_getgid)
+/// - [`getegid`](Self::sys_getegid)
+/// - [`setuid`](Self::sys_setuid)
+/// - [`setgid`](Self::sys_setgid)
+/// - [`setreuid`](Self::sys_setreuid)
+/// - [`setregid`](Self::sys_setregid)
+/// - [`setresuid`](Self::sys_setresuid)
+/// - [`setresgid`](Self::sys_setresgid)
+/// - [`getresuid`](Self::sys_getresuid)
+/// - [`getresgid`](Self::sys_getresgid)
+/// - [`setfsuid`](Self::sys_setfsuid)
+/// - [`setfsgid`](Self::sys_setfsgid)
+/// - [`getgroups`](Self::sys_getgroups)
+/// - [`setgroups`](Self::sys_setgroups)
+impl Syscall<'_> {
+    /// Returns the real user ID of the calling process
+    /// (see [linux man getuid(2)](https://www.man7.org/linux/man-pages/man2/getuid.2.html)).
+    pub fn sys_getuid(&self) -> SysResult {
+        info!("getuid:");
+        Ok(self.linux_process().credentials().uid as usize)
+    }
+
+    /// Returns the effective user ID of the calling process
+    pub fn sys_geteuid(&self) -> SysResult {
+        info!("geteuid:");
+        Ok(self.linux_process().credentials().euid as usize)
+    }
+
+    /// Returns the real group ID of the calling process
+    /// (see [linux man getgid(2)](https://www.man7.org/linux/man-pages/man2/getgid.2.html)).
+    pub fn sys_getgid(&self) -> SysResult {
+        info!("getgid:");
+        Ok(self.linux_process().credentials().gid as usize)
+    }
+
+    /// Returns the effective group ID of the calling process
+    pub fn sys_getegid(&self) -> SysResult {
+        info!("getegid:");
+        Ok(self.linux_process().credentials().egid as usize)
+    }
+
+    /// Sets the effective user ID of the calling process, and also the real and saved ones
+    /// if it is privileged
+    /// (see [linux man setuid(2)](https://www.man7.org/linux/man-pages/man2/setuid.2.html)).
+    pub fn sys_setuid(&self, uid: usize) -> SysResult {
+        info!("setuid: uid={}", uid as i32);
+        let uid = id_arg(uid).ok_or(LxError::EINVAL)?;
+        self.update_credentials(|cred| cred.setuid(uid))
+    }
+
+    /// Sets the effective group ID of the calling process, and also the real and saved ones
+    /// if it is privileged
+    /// (see [linux man setgid(2)](https://www.man7.org/linux/man-pages/man2/setgid.2.html)).
+    pub fn sys_setgid(&self, gid: usize) -> SysResult {
+        info!("setgid: gid={}", gid as i32);
+        let gid = id_arg(gid).ok_or(LxError::EINVAL)?;
+        self.update_credentials(|cred| cred.setgid(gid))
+    }
+
+    /// Sets the real and effective user IDs of the calling process, -1 keeps an ID
+    /// (see [linux man setreuid(2)](https://www.man7.org/linux/man-pages/man2/setreuid.2.html)).
+    pub fn sys_setreuid(&self, ruid: usize, euid: usize) -> SysResult {
+        info!("setreuid: ruid={}, euid={}", ruid as i32, euid as i32);
+        self.update_credentials(|cred| cred.setreuid(id_arg(ruid), id_arg(euid)))
+    }
+
+    /// Sets the real and effective group IDs of the calling process, -1 keeps an ID
+    /// (see [linux man setregid(2)](https://www.man7.org/linux/man-pages/man2/setregid.2.html)).
+    pub fn sys_setregid(&self, rgid: usize, egid: usize) -> SysResult {
+        info!("setregid: rgid={}, egid={}", rgid as i32, egid as i32);
+        self.update_credentials(|cred| cred.setregid(id_arg(rgid), id_arg(egid)))
+    }
+
+    /// Sets the real, effective and saved user IDs of the calling process, -1 keeps an ID
+    /// (see [linux man setresuid(2)](https://www.man7.org/linux/man-pages/man2/setresuid.2.html)).
+    pub fn sys_setresuid(&self, ruid: usize, euid: usize, suid: usize) -> SysResult {
+        info!(
+            "setresuid: ruid={}, euid={}, suid={}",
+            ruid as i32, euid as i32, suid as i32
+        );
+        self.update_credentials(|cred| cred.setresuid(id_arg(ruid), id_arg(euid), id_arg(suid)))
+    }
+
+    /// Sets the real, effective and saved group IDs of the calling process, -1 keeps an ID
+    /// (see [linux man setresgid(2)](https://www.man7.org/linux/man-pages/man2/setresgid.2.html)).
+    pub fn sys_setresgid(&self, rgid: usize, egid: usize, sgid: usize) -> SysResult {
+        info!(
+            "setresgid: rgid={}, egid={}, sgid={}",
+            rgid as i32, egid as i32, sgid as i32
+        );
+        self.update_credentials(|cred| cred.setresgid(id_arg(rgid), id_arg(egid), id_arg(sgid)))
+    }
+
+    /// Gets the real, effective and saved user IDs of the calling process
+    /// (see [linux man getresuid(2)](https://www.man7.org/linux/man-pages/man2/getresuid.2.html)).
+    pub fn sys_getresuid(
+        &self,
+        mut ruid: UserOutPtr<u32>,
+        mut euid: UserOutPtr<u32>,
+        mut suid: UserOutPtr<u32>,
+    ) -> SysResult {
+        info!("getresuid:");
+        let cred = self.linux_process().credentials();
+        ruid.write(cred.uid)?;
+        euid.write(cred.euid)?;
+        suid.write(cred.suid)?;
+        Ok(0)
+    }
+
+    /// Gets the real, effective and saved group IDs of the calling process
+    pub fn sys_getresgid(
+        &self,
+        mut rgid: UserOutPtr<u32>,
+        mut egid: UserOutPtr<u32>,
+        mut sgid: UserOutPtr<u32>,
+    ) -> SysResult {
+        info!("getresgid:");
+        let cred = self.linux_process().credentials();
+        rgid.write(cred.gid)?;
+        egid.write(cred.egid)?;
+        sgid.write(cred.sgid)?;
+        Ok(0)
+    }
+
+    /// Sets the user ID used for file access, returns the previous one whether or not it changed
+    /// (see [linux man setfsuid(2)](https://www.man7.org/linux/man-pages/man2/setfsuid.2.html)).
+    pub fn sys_setfsuid(&self, fsuid: usize) -> SysResult {
+        info!("setfsuid: fsuid={}", fsuid as i32);
+        let proc = self.linux_process();
+        let mut cred = proc.credentials();
+        let old = match id_arg(fsuid) {
+            Some(fsuid) => cred.setfsuid(fsuid),
+            None => cred.fsuid,
+        };
+        proc.set_credentials(cred);
+        Ok(old as usize)
+    }
+
+    /// Sets the group ID used for file access, returns the previous one whether or not it changed
+    /// (see [linux man setfsgid(2)](https://www.man7.org/linux/man-pages/man2/setfsgid.2.html)).
+    pub fn sys_setfsgid(&self, fsgid: usize) -> SysResult {
+        info!("setfsgid: fsgid={}", fsgid as i32);
+        let proc = self.linux_process();
+        let mut cred = proc.credentials();
+        let old = match id_arg(fsgid) {
+            Some(fsgid) => cred.setfsgid(fsgid),
+            None => cred.fsgid,
+        };
+        proc.set_credentials(cred);
+        Ok(old as usize)
+    }
+
+    /// Gets the supplementary group IDs of the calling process
+    /// (see [linux man getgroups(2)](https://www.man7.org/linux/man-pages/man2/getgroups.2.html)).
+    ///
+    /// With `size` 0 only the number of groups is returned.
+    pub fn sys_getgroups(&self, size: usize, mut list: UserOutPtr<u32>) -> SysResult {
+        info!("getgroups: size={}, list={:?}", size as i32, list);
+        let groups = self.linux_process().credentials().groups;
+        if size == 0 {
+            return Ok(groups.len());
+        }
+        if (size as i32) < 0 || size < groups.len() {
+            return Err(LxError::EINVAL);
+        }
+        list.write_array(&groups)?;
+        Ok(groups.len())
+    }
+
+    /// Sets the supplementary group IDs of the calling process, which must be privileged
+    /// (see [linux man setgroups(2)](https://www.man7.org/linux/man-pages/man2/setgroups.2.html)).
+    pub fn sys_setgroups(&self, size: usize, list: UserInPtr<u32>) -> SysResult {
+        info!("setgroups: size={}, list={:?}", size, list);
+        if size > NGROUPS_MAX {
+            return Err(LxError::EINVAL);
+        }
+        let groups = if size == 0 {
+            Vec::new()
+        } else {
+            list.read_array(size)?
+        };
+        self.update_credentials(|cred| cred.set_groups(groups))
+    }
+
+    fn update_credentials(&self, f: impl FnOnce(&mut Credentials) -> LxResult<()>) -> SysResult {
+        let proc = self.linux_process();
+        let mut cred = proc.credentials();
+        f(&mut cred)?;
+        proc.set_credentials(cred);
+        Ok(0)
+    }
+}
+
+/// A user or group ID argument, -1 for none
+pub(crate) fn id_arg(id: usize) -> Option<u32> {
+    match id as u32 {
+        u32::MAX => None,
+        id => Some(id),
+    }
+}
@@ -8,6 +8,7 @@
 //! - link(at)
 //! - unlink(at)
 //! - rename(at)
+//! - symlink(at)
 //! - readlink(at)
 
 use super::*;
@@ -43,6 +44,7 @@ impl Syscall<'_> {
         if info.type_ != FileType::Dir {
             return Err(LxError::ENOTDIR);
         }
+        proc.credentials().check_access(&info, Access::EXECUTE)?;
         proc.change_directory(path);
         Ok(0)
     }
@@ -69,7 +71,13 @@ impl Syscall<'_> {
         if inode.find(file_name).is_ok() {
             return Err(LxError::EEXIST);
         }
-        inode.create(file_name, FileType::Dir, mode as u32)?;
+        let cred = proc.credentials();
+        let dir_info = inode.metadata()?;
+        cred.check_access(&dir_info, Access::WRITE | Access::EXECUTE)?;
+        proc.mounts().check_writable(&inode)?;
+        let mode = mode as u32 & 0o1777 & !proc.umask();
+        let new_dir = inode.create(file_name, FileType::Dir, mode)?;
+        set_new_owner(&cred, &dir_info, &new_dir)?;
         Ok(0)
     }
     /// Remove a directory.
@@ -82,9 +90,13 @@ impl Syscall<'_> {
         let proc = self.linux_process();
         let dir_inode = proc.lookup_inode(dir_path)?;
         let file_inode = dir_inode.find(file_name)?;
-        if file_inode.metadata()?.type_ != FileType::Dir {
+        let file_info = file_inode.metadata()?;
+        if file_info.type_ != FileType::Dir {
             return Err(LxError::ENOTDIR);
         }
+        proc.credentials()
+            .check_delete(&dir_inode.metadata()?, &file_info)?;
+        proc.mounts().check_writable(&dir_inode)?;
         dir_inode.unlink(file_name)?;
         Ok(0)
     }
@@ -151,8 +163,11 @@ impl Syscall<'_> {
 
         let proc = self.linux_process();
         let (new_dir_path, new_file_name) = split_path(newpath);
-        let inode = proc.lookup_inode_at(olddirfd, oldpath, true)?;
+        // unlike most calls, linkat does not follow a final symbolic link by default
+        let follow = flags.contains(AtFlags::SYMLINK_FOLLOW);
+        let inode = proc.lookup_inode_at(olddirfd, oldpath, follow)?;
         let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, true)?;
+        proc.mounts().check_writable(&new_dir_inode)?;
         new_dir_inode.link(new_file_name, &inode)?;
         Ok(0)
     }
@@ -179,9 +194,13 @@ impl Syscall<'_> {
         let (dir_path, file_name) = split_path(path);
         let dir_inode = proc.lookup_inode_at(dirfd, dir_path, true)?;
         let file_inode = dir_inode.find(file_name)?;
-        if file_inode.metadata()?.type_ == FileType::Dir {
+        let file_info = file_inode.metadata()?;
+        if file_info.type_ == FileType::Dir {
             return Err(LxError::EISDIR);
         }
+        proc.credentials()
+            .check_delete(&dir_inode.metadata()?, &file_info)?;
+        proc.mounts().check_writable(&dir_inode)?;
         dir_inode.unlink(file_name)?;
         Ok(0)
     }
@@ -209,20 +228,63 @@ impl Syscall<'_> {
         let proc = self.linux_process();
         let (old_dir_path, old_file_name) = split_path(oldpath);
         let (new_dir_path, new_file_name) = split_path(newpath);
-        let old_dir_inode = proc.lookup_inode_at(olddirfd, old_dir_path, false)?;
-        let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, false)?;
+        let old_dir_inode = proc.lookup_inode_at(olddirfd, old_dir_path, true)?;
+        let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, true)?;
+        proc.mounts().check_writable(&old_dir_inode)?;
+        proc.mounts().check_writable(&new_dir_inode)?;
         old_dir_inode.move_(old_file_name, &new_dir_inode, new_file_name)?;
         Ok(0)
     }
 
+    /// make a new name for a file
+    /// (see [linux man symlink(2)](https://www.man7.org/linux/man-pages/man2/symlink.2.html)).
+    pub fn sys_symlink(&self, target: UserInPtr<u8>, linkpath: UserInPtr<u8>) -> SysResult {
+        self.sys_symlinkat(target, FileDesc::CWD, linkpath)
+    }
+
+    /// create a symbolic link relative to directory file descriptor
+    /// symlinkat() creates a symbolic link named linkpath which contains the string target,
+    /// which is neither checked nor resolved until the link is followed.
+    pub fn sys_symlinkat(
+        &self,
+        target: UserInPtr<u8>,
+        newdirfd: FileDesc,
+        linkpath: UserInPtr<u8>,
+    ) -> SysResult {
+        let target = target.as_c_str()?;
+        let linkpath = linkpath.as_c_str()?;
+        info!(
+            "symlinkat: target={:?}, newdirfd={:?}, linkpath={:?}",
+            target, newdirfd, linkpath
+        );
+        if target.is_empty() || linkpath.is_empty() {
+            return Err(LxError::ENOENT);
+        }
+
+        let proc = self.linux_process();
+        let (dir_path, file_name) = split_path(linkpath);
+        let dir_inode = proc.lookup_inode_at(newdirfd, dir_path, true)?;
+        if dir_inode.find(file_name).is_ok() {
+            return Err(LxError::EEXIST);
+        }
+        let cred = proc.credentials();
+        let dir_info = dir_inode.metadata()?;
+        cred.check_access(&dir_info, Access::WRITE | Access::EXECUTE)?;
+        proc.mounts().check_writable(&dir_inode)?;
+        let link = dir_inode.create(file_name, FileType::SymLink, 0o777)?;
+        link.write_at(0, target.as_bytes())?;
+        set_new_owner(&cred, &dir_info, &link)?;
+        Ok(0)
+    }
+
     /// read value of symbolic link
     pub fn sys_readlink(&self, path: UserInPtr<u8>, base: UserOutPtr<u8>, len: usize) -> SysResult {
         self.sys_readlinkat(FileDesc::CWD, path, base, len)
     }
 
     /// read value of symbolic link relative to directory file descriptor
-    /// readlink() places the contents of the symbolic link path in the buffer base, which has size len
-    /// TODO: recursive link resolution and loop detection
+    /// readlink() places the contents of the symbolic link path in the buffer base, which has size len.
+    /// The contents are truncated to len bytes and not null-terminated.
     pub fn sys_readlinkat(
         &self,
         dirfd: FileDesc,
@@ -236,15 +298,18 @@ impl Syscall<'_> {
             dirfd, path, base, len
         );
 
+        if (len as isize) <= 0 {
+            return Err(LxError::EINVAL);
+        }
+
         let proc = self.linux_process();
         let inode = proc.lookup_inode_at(dirfd, path, false)?;
         if inode.metadata()?.type_ != FileType::SymLink {
             return Err(LxError::EINVAL);
         }
-        // TODO: recursive link resolution and loop detection
-        let mut buf = vec![0; len];
-        let len = inode.read_at(0, &mut buf)?;
-        base.write_array(&buf[..len])?;
+        let target = read_link(&inode)?;
+        let len = target.len().min(len);
+        base.write_array(&target.as_bytes()[..len])?;
         Ok(len)
     }
 }
@@ -354,5 +419,9 @@ bitflags! {
     pub struct AtFlags: usize {
         const EMPTY_PATH = 0x1000;
         const SYMLINK_NOFOLLOW = 0x100;
+        /// linkat: dereference oldpath if it is a symbolic link
+        const SYMLINK_FOLLOW = 0x400;
+        /// faccessat: check with the effective IDs instead of the real ones
+        const EACCESS = 0x200;
     }
 }
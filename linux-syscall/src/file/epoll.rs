@@ -0,0 +1,135 @@
+//! Event notification descriptors
+//!
+//! - epoll_create, epoll_create1
+//! - epoll_ctl
+//! - epoll_wait, epoll_pwait
+//! - eventfd, eventfd2
+
+use super::file::SOCKET_FD;
+use super::*;
+use linux_object::signal::Sigset;
+use linux_object::thread::ThreadExt;
+
+impl Syscall<'_> {
+    /// Open an epoll file descriptor, `size` is ignored but must be greater than zero
+    pub fn sys_epoll_create(&self, size: isize) -> SysResult {
+        info!("epoll_create: size={}", size);
+        if size <= 0 {
+            return Err(LxError::EINVAL);
+        }
+        self.sys_epoll_create1(0)
+    }
+
+    /// Open an epoll file descriptor
+    /// (see [linux man epoll_create(2)](https://www.man7.org/linux/man-pages/man2/epoll_create.2.html)).
+    pub fn sys_epoll_create1(&self, flags: usize) -> SysResult {
+        info!("epoll_create1: flags={:#x}", flags);
+        let flags = EpollCreateFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        let epoll = EpollInstance::new(flags);
+        let fd = self.linux_process().add_file(epoll)?;
+        Ok(fd.into())
+    }
+
+    /// Add, modify, or remove entries in the interest list of the epoll instance `epfd`
+    /// (see [linux man epoll_ctl(2)](https://www.man7.org/linux/man-pages/man2/epoll_ctl.2.html)).
+    pub fn sys_epoll_ctl(
+        &self,
+        epfd: FileDesc,
+        op: usize,
+        fd: FileDesc,
+        event: UserInPtr<EpollEvent>,
+    ) -> SysResult {
+        info!(
+            "epoll_ctl: epfd={:?}, op={}, fd={:?}, event={:?}",
+            epfd, op, fd, event
+        );
+        let op = EpollCtlOp::try_from(op).map_err(|_| LxError::EINVAL)?;
+        if fd == epfd {
+            return Err(LxError::EINVAL);
+        }
+        let proc = self.linux_process();
+        let epoll = proc
+            .get_file_like(epfd)?
+            .downcast_arc::<EpollInstance>()
+            .map_err(|_| LxError::EINVAL)?;
+        let target = if usize::from(fd) >= SOCKET_FD {
+            EpollTarget::Socket(proc.get_socket(usize::from(fd).into())?)
+        } else {
+            EpollTarget::File(proc.get_file_like(fd)?)
+        };
+        let event = match op {
+            EpollCtlOp::DEL => None,
+            _ => Some(event.read()?),
+        };
+        epoll.control(op, fd, target, event)?;
+        Ok(0)
+    }
+
+    /// Wait for an I/O event on the epoll instance `epfd`
+    pub async fn sys_epoll_wait(
+        &self,
+        epfd: FileDesc,
+        events: UserOutPtr<EpollEvent>,
+        maxevents: isize,
+        timeout: isize,
+    ) -> SysResult {
+        self.sys_epoll_pwait(epfd, events, maxevents, timeout, 0.into(), 0)
+            .await
+    }
+
+    /// Wait for an I/O event on the epoll instance `epfd`, with `sigmask` replacing the signal mask during the wait
+    /// (see [linux man epoll_wait(2)](https://www.man7.org/linux/man-pages/man2/epoll_wait.2.html)).
+    ///
+    /// `timeout` is in milliseconds, a negative value waits forever.
+    /// The original mask is restored as soon as the wait ends, so a signal that is only unblocked
+    /// by `sigmask` interrupts the wait but stays pending.
+    pub async fn sys_epoll_pwait(
+        &self,
+        epfd: FileDesc,
+        mut events: UserOutPtr<EpollEvent>,
+        maxevents: isize,
+        timeout: isize,
+        sigmask: UserInPtr<Sigset>,
+        sigsetsize: usize,
+    ) -> SysResult {
+        info!(
+            "epoll_pwait: epfd={:?}, events={:?}, maxevents={}, timeout={}, sigmask={:?}",
+            epfd, events, maxevents, timeout, sigmask
+        );
+        if maxevents <= 0 {
+            return Err(LxError::EINVAL);
+        }
+        let epoll = self
+            .linux_process()
+            .get_file_like(epfd)?
+            .downcast_arc::<EpollInstance>()
+            .map_err(|_| LxError::EINVAL)?;
+        if !sigmask.is_null() {
+            if sigsetsize != core::mem::size_of::<Sigset>() {
+                return Err(LxError::EINVAL);
+            }
+            // a signal interrupting the wait is taken with this mask
+            self.thread.set_temporary_signal_mask(sigmask.read()?);
+        }
+        let result = epoll.wait(maxevents as usize, timeout).await;
+        self.thread.restore_signal_mask();
+        let ready = result?;
+        events.write_array(&ready)?;
+        Ok(ready.len())
+    }
+
+    /// Create a file descriptor for event notification
+    pub fn sys_eventfd(&self, initval: u32) -> SysResult {
+        self.sys_eventfd2(initval, 0)
+    }
+
+    /// Create a file descriptor for event notification
+    /// (see [linux man eventfd(2)](https://www.man7.org/linux/man-pages/man2/eventfd.2.html)).
+    pub fn sys_eventfd2(&self, initval: u32, flags: usize) -> SysResult {
+        info!("eventfd2: initval={}, flags={:#x}", initval, flags);
+        let flags = EventFdFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        let eventfd = EventFd::new(initval as u64, flags);
+        let fd = self.linux_process().add_file(eventfd)?;
+        Ok(fd.into())
+    }
+}
@@ -30,7 +30,12 @@ impl Syscall<'_> {
             dir_fd, path, flags, mode
         );
 
+        let cred = proc.credentials();
+        let follow = !flags.contains(OpenFlags::NOFOLLOW);
         let inode = if flags.contains(OpenFlags::CREATE) {
+            if path.ends_with('/') {
+                return Err(LxError::EISDIR);
+            }
             let (dir_path, file_name) = split_path(path);
             // relative to cwd
             let dir_inode = proc.lookup_inode_at(dir_fd, dir_path, true)?;
@@ -39,18 +44,53 @@ impl Syscall<'_> {
                     if flags.contains(OpenFlags::EXCLUSIVE) {
                         return Err(LxError::EEXIST);
                     }
+                    let is_link = file_inode.metadata()?.type_ == FileType::SymLink;
+                    let file_inode = if follow && is_link {
+                        proc.lookup_inode_at(dir_fd, path, true)?
+                    } else {
+                        file_inode
+                    };
+                    cred.check_access(&file_inode.metadata()?, open_access(flags))?;
                     file_inode
                 }
                 Err(FsError::EntryNotFound) => {
-                    dir_inode.create(file_name, FileType::File, mode as u32)?
+                    let dir_info = dir_inode.metadata()?;
+                    cred.check_access(&dir_info, Access::WRITE | Access::EXECUTE)?;
+                    proc.mounts().check_writable(&dir_inode)?;
+                    // the creator may use the new file whatever its mode
+                    let mode = mode as u32 & 0o7777 & !proc.umask();
+                    let file_inode = dir_inode.create(file_name, FileType::File, mode)?;
+                    set_new_owner(&cred, &dir_info, &file_inode)?;
+                    file_inode
                 }
                 Err(e) => return Err(LxError::from(e)),
             }
         } else {
-            proc.lookup_inode_at(dir_fd, path, true)?
+            let inode = proc.lookup_inode_at(dir_fd, path, follow)?;
+            cred.check_access(&inode.metadata()?, open_access(flags))?;
+            inode
         };
+        let type_ = inode.metadata()?.type_;
+        if type_ == FileType::SymLink {
+            // only reachable with O_NOFOLLOW
+            return Err(LxError::ELOOP);
+        }
+        if flags.contains(OpenFlags::DIRECTORY) && type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        if open_access(flags).contains(Access::WRITE) {
+            proc.mounts().check_writable(&inode)?;
+        }
+        let is_device = matches!(type_, FileType::CharDevice | FileType::BlockDevice);
+        if is_device && proc.mounts().flags_of(&inode).contains(MountFlags::NODEV) {
+            return Err(LxError::EACCES);
+        }
 
-        let file = File::new(inode, flags, path.into());
+        let file: Arc<dyn FileLike> = match inode.downcast_ref::<KeystoneDev>() {
+            // every open of the keystone device gets its own enclaves
+            Some(keystone) => keystone.open(self.zircon_process(), flags),
+            None => File::new(inode, flags, path.into()),
+        };
         let fd = proc.add_file(file)?;
         Ok(fd.into())
     }
@@ -59,6 +99,11 @@ impl Syscall<'_> {
     pub fn sys_close(&self, fd: FileDesc) -> SysResult {
         info!("close: fd={:?}", fd);
         let proc = self.linux_process();
+        // TODO wait a new struct to refactor
+        if usize::from(fd) >= super::file::SOCKET_FD {
+            proc.close_socket(usize::from(fd).into())?;
+            return Ok(0);
+        }
         proc.close_file(fd)?;
         Ok(0)
     }
@@ -138,3 +183,15 @@ impl Syscall<'_> {
         Ok(0)
     }
 }
+
+/// Access to a file needed to open it with `flags`
+fn open_access(flags: OpenFlags) -> Access {
+    let mut access = Access::empty();
+    if flags.readable() {
+        access |= Access::READ;
+    }
+    if flags.writable() || flags.contains(OpenFlags::TRUNCATE) {
+        access |= Access::WRITE;
+    }
+    access
+}
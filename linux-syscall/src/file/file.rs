@@ -8,9 +8,19 @@
 //! - sync, fsync, fdatasync
 //! - ioctl, fcntl
 //! - access, faccessat
+//! - chmod, fchmod, fchmodat, umask
+//! - chown, lchown, fchown, fchownat
+//! - statfs, fstatfs
+//! - mount, umount2
 
 use super::*;
-use linux_object::{process::FsInfo, time::TimeSpec};
+use crate::cred::id_arg;
+use crate::net::recv_socket;
+use linux_object::net::MsgFlags;
+use linux_object::process::{FsInfo, Resource};
+use linux_object::signal::{SigInfo, Signal as LinuxSignal, SignalCode};
+use linux_object::thread::ThreadExt;
+use linux_object::time::TimeSpec;
 
 impl Syscall<'_> {
     /// Reads from a specified file using a file descriptor. Before using this call,
@@ -27,8 +37,8 @@ impl Syscall<'_> {
             let x = usize::from(fd);
             let socket = proc.get_socket(x.into())?;
             let mut buf = vec![0u8; len];
-            let (len, _) = socket.lock().read(&mut buf).await;
-            let len = len.unwrap_or(0);
+            let received = recv_socket(&socket, &mut buf, MsgFlags::empty()).await;
+            let len = received.map_or(0, |msg| msg.len);
             base.write_array(&buf[..len])?;
             Ok(len)
         } else {
@@ -45,11 +55,19 @@ impl Syscall<'_> {
     /// - fd – file descriptor
     /// - base – pointer to the buffer write
     /// - len – number of bytes to write
-    pub fn sys_write(&self, fd: FileDesc, base: UserInPtr<u8>, len: usize) -> SysResult {
+    pub async fn sys_write(&self, fd: FileDesc, base: UserInPtr<u8>, len: usize) -> SysResult {
         info!("write: fd={:?}, base={:?}, len={:#x}", fd, base, len);
-        self.linux_process()
-            .get_file_like(fd)?
-            .write(base.as_slice(len)?)
+        let proc = self.linux_process();
+
+        // TODO wait a new struct to refactor
+        if usize::from(fd) >= SOCKET_FD {
+            let socket = proc.get_socket(usize::from(fd).into())?;
+            let len = socket.lock().write(base.as_slice(len)?, None)?;
+            return Ok(len);
+        }
+        let len = self.limit_write(fd, None, len)?;
+        let file_like = proc.get_file_like(fd)?;
+        file_like.async_write(base.as_slice(len)?).await
     }
 
     /// read from or write to a file descriptor at a given offset
@@ -87,6 +105,7 @@ impl Syscall<'_> {
             "pwrite: fd={:?}, base={:?}, len={}, offset={}",
             fd, base, len, offset
         );
+        let len = self.limit_write(fd, Some(offset), len)?;
         self.linux_process()
             .get_file_like(fd)?
             .write_at(offset, base.as_slice(len)?)
@@ -110,8 +129,7 @@ impl Syscall<'_> {
             let x = usize::from(fd);
             let socket = proc.get_socket(x.into())?;
             let mut buf = vec![0u8; iovs.total_len()];
-            let (len, _) = socket.lock().read(&mut buf).await;
-            let len = len.unwrap();
+            let len = recv_socket(&socket, &mut buf, MsgFlags::empty()).await?.len;
             iovs.write_from_buf(&buf)?;
             Ok(len)
         } else {
@@ -148,7 +166,8 @@ impl Syscall<'_> {
             Ok(len)
         } else {
             let file_like = proc.get_file_like(fd)?;
-            let len = file_like.write(&buf)?;
+            let len = self.limit_write(fd, None, buf.len())?;
+            let len = file_like.write(&buf[..len])?;
             Ok(len)
         }
     }
@@ -178,15 +197,20 @@ impl Syscall<'_> {
     pub fn sys_truncate(&self, path: UserInPtr<u8>, len: usize) -> SysResult {
         let path = path.as_c_str()?;
         info!("truncate: path={:?}, len={}", path, len);
-        self.linux_process().lookup_inode(path)?.resize(len)?;
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode(path)?;
+        proc.mounts().check_writable(&inode)?;
+        self.check_file_size(len as u64)?;
+        inode.resize(len)?;
         Ok(0)
     }
 
     /// cause the regular file referenced by fd to be truncated to a size of precisely length bytes.
     pub fn sys_ftruncate(&self, fd: FileDesc, len: usize) -> SysResult {
         info!("ftruncate: fd={:?}, len={}", fd, len);
-        let proc = self.linux_process();
-        proc.get_file(fd)?.set_len(len as u64)?;
+        let file = self.linux_process().get_file(fd)?;
+        self.check_file_size(len as u64)?;
+        file.set_len(len as u64)?;
         Ok(0)
     }
 
@@ -284,7 +308,7 @@ impl Syscall<'_> {
     pub fn sys_sync(&self) -> SysResult {
         info!("sync:");
         let proc = self.linux_process();
-        proc.root_inode().fs().sync()?;
+        proc.mounts().sync()?;
         Ok(0)
     }
 
@@ -306,7 +330,7 @@ impl Syscall<'_> {
     }
 
     /// Set parameters of device files.
-    pub fn sys_ioctl(
+    pub async fn sys_ioctl(
         &self,
         fd: FileDesc,
         request: usize,
@@ -327,7 +351,15 @@ impl Syscall<'_> {
             x.ioctl(request, arg1, arg2, arg3)
         } else {
             let file_like = proc.get_file_like(fd)?;
-            file_like.ioctl(request, arg1, arg2, arg3)
+            // job control requests on a terminal need the calling process
+            if let Ok(file) = file_like.clone().downcast_arc::<File>() {
+                if let Some(tty) = Tty::of(&*file.inode()) {
+                    if let Some(result) = tty.ioctl(self.zircon_process(), request, arg1) {
+                        return result;
+                    }
+                }
+            }
+            file_like.async_ioctl(request, arg1, arg2, arg3).await
         }
     }
 
@@ -392,7 +424,9 @@ impl Syscall<'_> {
     }
 
     /// Check user's permissions of a file relative to a directory file descriptor
-    /// TODO: check permissions based on uid/gid
+    /// (see [linux man access(2)](https://www.man7.org/linux/man-pages/man2/access.2.html)).
+    ///
+    /// The check uses the real user and group IDs, or the effective ones with `AT_EACCESS`.
     pub fn sys_faccessat(
         &self,
         dirfd: FileDesc,
@@ -400,16 +434,127 @@ impl Syscall<'_> {
         mode: usize,
         flags: usize,
     ) -> SysResult {
-        // TODO: check permissions based on uid/gid
         let path = path.as_c_str()?;
         let flags = AtFlags::from_bits_truncate(flags);
         info!(
             "faccessat: dirfd={:?}, path={:?}, mode={:#o}, flags={:?}",
             dirfd, path, mode, flags
         );
+        // F_OK is 0, checking only that the file exists
+        let access = Access::from_bits(mode as u32).ok_or(LxError::EINVAL)?;
         let proc = self.linux_process();
         let follow = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
-        let _inode = proc.lookup_inode_at(dirfd, path, follow)?;
+        let inode = proc.lookup_inode_at(dirfd, path, follow)?;
+        let cred = if flags.contains(AtFlags::EACCESS) {
+            proc.credentials()
+        } else {
+            proc.credentials().real_access()
+        };
+        cred.check_access(&inode.metadata()?, access)?;
+        Ok(0)
+    }
+
+    /// Change the mode of a file
+    pub fn sys_chmod(&self, path: UserInPtr<u8>, mode: usize) -> SysResult {
+        self.sys_fchmodat(FileDesc::CWD, path, mode)
+    }
+
+    /// Change the mode of the file referred to by `fd`
+    pub fn sys_fchmod(&self, fd: FileDesc, mode: usize) -> SysResult {
+        info!("fchmod: fd={:?}, mode={:#o}", fd, mode);
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.inode();
+        proc.mounts().check_writable(&inode)?;
+        let mut metadata = inode.metadata()?;
+        proc.credentials().chmod(&mut metadata, mode as u32)?;
+        inode.set_metadata(&metadata)?;
+        Ok(0)
+    }
+
+    /// Change the mode of a file relative to a directory file descriptor
+    /// (see [linux man chmod(2)](https://www.man7.org/linux/man-pages/man2/chmod.2.html)).
+    ///
+    /// Only the owner of a file, or a privileged process, may change its mode.
+    pub fn sys_fchmodat(&self, dirfd: FileDesc, path: UserInPtr<u8>, mode: usize) -> SysResult {
+        let path = path.as_c_str()?;
+        info!(
+            "fchmodat: dirfd={:?}, path={:?}, mode={:#o}",
+            dirfd, path, mode
+        );
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode_at(dirfd, path, true)?;
+        proc.mounts().check_writable(&inode)?;
+        let mut metadata = inode.metadata()?;
+        proc.credentials().chmod(&mut metadata, mode as u32)?;
+        inode.set_metadata(&metadata)?;
+        Ok(0)
+    }
+
+    /// Set the file mode creation mask of the calling process to `mask & 0o777`
+    /// and return the previous one
+    /// (see [linux man umask(2)](https://www.man7.org/linux/man-pages/man2/umask.2.html)).
+    pub fn sys_umask(&self, mask: usize) -> SysResult {
+        info!("umask: mask={:#o}", mask);
+        Ok(self.linux_process().set_umask(mask as u32) as usize)
+    }
+
+    /// Change the owner and group of a file
+    pub fn sys_chown(&self, path: UserInPtr<u8>, uid: usize, gid: usize) -> SysResult {
+        self.sys_fchownat(FileDesc::CWD, path, uid, gid, 0)
+    }
+
+    /// Change the owner and group of a file, without following a final symbolic link
+    pub fn sys_lchown(&self, path: UserInPtr<u8>, uid: usize, gid: usize) -> SysResult {
+        let flags = AtFlags::SYMLINK_NOFOLLOW.bits();
+        self.sys_fchownat(FileDesc::CWD, path, uid, gid, flags)
+    }
+
+    /// Change the owner and group of the file referred to by `fd`
+    pub fn sys_fchown(&self, fd: FileDesc, uid: usize, gid: usize) -> SysResult {
+        info!(
+            "fchown: fd={:?}, uid={}, gid={}",
+            fd, uid as i32, gid as i32
+        );
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.inode();
+        proc.mounts().check_writable(&inode)?;
+        let mut metadata = inode.metadata()?;
+        proc.credentials()
+            .chown(&mut metadata, id_arg(uid), id_arg(gid))?;
+        inode.set_metadata(&metadata)?;
+        Ok(0)
+    }
+
+    /// Change the owner and group of a file relative to a directory file descriptor
+    /// (see [linux man chown(2)](https://www.man7.org/linux/man-pages/man2/chown.2.html)).
+    ///
+    /// An ID of -1 is left unchanged.
+    pub fn sys_fchownat(
+        &self,
+        dirfd: FileDesc,
+        path: UserInPtr<u8>,
+        uid: usize,
+        gid: usize,
+        flags: usize,
+    ) -> SysResult {
+        let path = path.as_c_str()?;
+        let flags = AtFlags::from_bits_truncate(flags);
+        info!(
+            "fchownat: dirfd={:?}, path={:?}, uid={}, gid={}, flags={:?}",
+            dirfd, path, uid as i32, gid as i32, flags
+        );
+        let proc = self.linux_process();
+        let inode = if flags.contains(AtFlags::EMPTY_PATH) && path.is_empty() {
+            proc.get_file(dirfd)?.inode()
+        } else {
+            let follow = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
+            proc.lookup_inode_at(dirfd, path, follow)?
+        };
+        proc.mounts().check_writable(&inode)?;
+        let mut metadata = inode.metadata()?;
+        proc.credentials()
+            .chown(&mut metadata, id_arg(uid), id_arg(gid))?;
+        inode.set_metadata(&metadata)?;
         Ok(0)
     }
 
@@ -486,14 +631,8 @@ impl Syscall<'_> {
     pub fn sys_statfs(&self, path: UserInPtr<u8>, mut buf: UserOutPtr<StatFs>) -> SysResult {
         let path = path.as_c_str()?;
         info!("statfs: path={:?}, buf={:?}", path, buf);
-
-        // TODO
-        // 现在 `path` 没用到，因为没实现真正的挂载，不可能搞一个非主要文件系统的路径。
-        // 实现挂载之后，要用 `path` 分辨路径在哪个文件系统里，根据对应文件系统的特性返回统计信息。
-        // （以及根据挂载选项填写 `StatFs::f_flags`！）
-
-        let info = self.linux_process().root_inode().fs().info();
-        buf.write(info.into())?;
+        let inode = self.linux_process().lookup_inode(path)?;
+        buf.write(self.statfs(&inode))?;
         Ok(0)
     }
 
@@ -506,10 +645,127 @@ impl Syscall<'_> {
     pub fn sys_fstatfs(&self, fd: FileDesc, mut buf: UserOutPtr<StatFs>) -> SysResult {
         info!("statfs: fd={:?}, buf={:?}", fd, buf);
 
-        let info = self.linux_process().get_file(fd)?.inode().fs().info();
-        buf.write(info.into())?;
+        let inode = self.linux_process().get_file(fd)?.inode();
+        buf.write(self.statfs(&inode))?;
+        Ok(0)
+    }
+
+    /// Statistics of the filesystem `inode` is in, with the type and flags of its mount
+    fn statfs(&self, inode: &Arc<dyn INode>) -> StatFs {
+        let fs = inode.fs();
+        let mut stat = StatFs::from(fs.info());
+        if let Some(mount) = self.linux_process().mounts().find(&fs) {
+            stat.f_type = mount.magic;
+            stat.f_fsid = (mount.id as i32, 0);
+            stat.f_flags = mount.flags.bits() as isize;
+        }
+        stat
+    }
+
+    /// Mount a filesystem, the calling process must be privileged
+    /// (see [linux man mount(2)](https://man7.org/linux/man-pages/man2/mount.2.html)).
+    ///
+    /// `source` is a block device such as `/dev/blk0` for disk filesystems and is only shown
+    /// in /proc/mounts for the others. `data` may be null.
+    pub fn sys_mount(
+        &self,
+        source: UserInPtr<u8>,
+        target: UserInPtr<u8>,
+        fstype: UserInPtr<u8>,
+        flags: usize,
+        data: UserInPtr<u8>,
+    ) -> SysResult {
+        let flags = mount_flags(flags);
+        let source = if source.is_null() {
+            ""
+        } else {
+            source.as_c_str()?
+        };
+        let target = target.as_c_str()?;
+        let fstype = if fstype.is_null() {
+            ""
+        } else {
+            fstype.as_c_str()?
+        };
+        let data = if data.is_null() { "" } else { data.as_c_str()? };
+        info!(
+            "mount: source={:?}, target={:?}, fstype={:?}, flags={:?}, data={:?}",
+            source, target, fstype, flags, data
+        );
+        let proc = self.linux_process();
+        if !proc.credentials().is_privileged() {
+            return Err(LxError::EPERM);
+        }
+        let target = proc.absolute_path(target);
+        proc.mounts().mount(source, &target, fstype, flags, data)?;
+        Ok(0)
+    }
+
+    /// Unmount the filesystem mounted at `target`, the calling process must be privileged
+    /// (see [linux man umount2(2)](https://man7.org/linux/man-pages/man2/umount2.2.html)).
+    ///
+    /// Unmounting is always lazy: files opened in the filesystem keep working.
+    pub fn sys_umount2(&self, target: UserInPtr<u8>, flags: usize) -> SysResult {
+        let target = target.as_c_str()?;
+        info!("umount2: target={:?}, flags={:#x}", target, flags);
+        let flags = UmountFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        if flags.contains(UmountFlags::EXPIRE)
+            && flags.intersects(UmountFlags::FORCE | UmountFlags::DETACH)
+        {
+            return Err(LxError::EINVAL);
+        }
+        let proc = self.linux_process();
+        if !proc.credentials().is_privileged() {
+            return Err(LxError::EPERM);
+        }
+        let target = proc.absolute_path(target);
+        proc.mounts().umount(&target)?;
         Ok(0)
     }
+
+    /// Shorten a write of `len` bytes on `fd`, at `offset` or else at the file offset,
+    /// so that a regular file does not grow beyond `RLIMIT_FSIZE`.
+    fn limit_write(&self, fd: FileDesc, offset: Option<u64>, len: usize) -> LxResult<usize> {
+        let file = match self.linux_process().get_file(fd) {
+            Ok(file) if file.metadata()?.type_ == FileType::File => file,
+            _ => return Ok(len),
+        };
+        let offset = match offset {
+            Some(offset) => offset,
+            None => file.write_offset()?,
+        };
+        if len == 0 {
+            return Ok(0);
+        }
+        self.check_file_size(offset + 1)?;
+        let limit = self.linux_process().rlimit(Resource::Fsize).cur;
+        Ok((len as u64).min(limit - offset) as usize)
+    }
+
+    /// Fail with `EFBIG` and send `SIGXFSZ` to the calling thread
+    /// if a file of `size` bytes is beyond `RLIMIT_FSIZE`.
+    fn check_file_size(&self, size: u64) -> LxResult {
+        if size > self.linux_process().rlimit(Resource::Fsize).cur {
+            let info = SigInfo::kill(LinuxSignal::SIGXFSZ, SignalCode::KERNEL, 0);
+            self.thread.send_signal(info);
+            return Err(LxError::EFBIG);
+        }
+        Ok(())
+    }
+}
+
+bitflags! {
+    /// Flags of umount2(2)
+    struct UmountFlags: usize {
+        /// Abort pending requests before unmounting
+        const FORCE = 1;
+        /// Detach the filesystem from the tree, freeing it once it is no longer busy
+        const DETACH = 2;
+        /// Mark the mount as expired, only unmount it if already marked
+        const EXPIRE = 4;
+        /// Do not dereference `target` if it is a symbolic link
+        const NOFOLLOW = 8;
+    }
 }
 
 const F_LINUX_SPECIFIC_BASE: usize = 1024;
@@ -586,4 +842,4 @@ numeric_enum_macro::numeric_enum! {
 }
 
 // Temp , TODO warp a struct impl into/from with FileDesc and SocketHandle
-const SOCKET_FD: usize = 10000;
+pub(super) const SOCKET_FD: usize = 10000;
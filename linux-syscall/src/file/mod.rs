@@ -6,6 +6,7 @@ use linux_object::fs::vfs::{FileType, FsError};
 use linux_object::fs::*;
 
 mod dir;
+mod epoll;
 mod fd;
 #[allow(clippy::module_inception)]
 mod file;
@@ -13,3 +14,21 @@ mod poll;
 mod stat;
 
 use self::dir::AtFlags;
+use alloc::sync::Arc;
+use linux_object::cred::{Access, Credentials};
+use linux_object::error::LxResult;
+use linux_object::fs::vfs::{INode, Metadata};
+
+/// Give an inode just created in directory `dir` the owner and group of the process
+pub(crate) fn set_new_owner(
+    cred: &Credentials,
+    dir: &Metadata,
+    inode: &Arc<dyn INode>,
+) -> LxResult<()> {
+    let (uid, gid) = cred.new_file_owner(dir);
+    let mut metadata = inode.metadata()?;
+    metadata.uid = uid as _;
+    metadata.gid = gid as _;
+    inode.set_metadata(&metadata)?;
+    Ok(())
+}
@@ -54,7 +54,11 @@ impl Syscall<'_> {
                         let status = match fut.as_mut().poll(cx) {
                             Poll::Ready(Ok(ret)) => ret,
                             Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
-                            Poll::Pending => continue,
+                            // the waker is registered, but other events may be ready now
+                            Poll::Pending => match file_like.poll() {
+                                Ok(ret) => ret,
+                                Err(_) => continue,
+                            },
                         };
                         if status.error {
                             poll.revents |= PE::HUP;
@@ -203,7 +207,11 @@ impl Syscall<'_> {
                     let status = match fut.as_mut().poll(cx) {
                         Poll::Ready(Ok(ret)) => ret,
                         Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
-                        Poll::Pending => continue,
+                        // the waker is registered, but other events may be ready now
+                        Poll::Pending => match file_like.poll() {
+                            Ok(ret) => ret,
+                            Err(_) => continue,
+                        },
                     };
                     if status.error && self.err_fds.contains(fd) {
                         self.err_fds.set(fd);
@@ -34,7 +34,7 @@ use kernel_hal::vm::PagingResult;
 use kernel_hal::MMUFlags;
 use linux_object::error::{LxError, SysResult};
 use linux_object::fs::FileDesc;
-use linux_object::process::{wait_child, wait_child_any, LinuxProcess, ProcessExt, RLimit};
+use linux_object::process::{wait_child, LinuxProcess, ProcessExt, RLimit};
 use zircon_object::object::{KernelObject, KoID, Signal};
 use zircon_object::task::{CurrentThread, Process, Thread, ThreadFn};
 use zircon_object::{vm::VirtAddr, ZxError};
@@ -45,6 +45,7 @@ mod consts {
     // generated from syscall.h.in
     include!(concat!(env!("OUT_DIR"), "/consts.rs"));
 }
+mod cred;
 mod file;
 mod ipc;
 mod misc;
@@ -164,7 +165,10 @@ impl Syscall<'_> {
                 self.sys_read(a0.into(), self.into_out_userptr(a1).unwrap(), a2)
                     .await
             }
-            Sys::WRITE => self.sys_write(a0.into(), self.into_in_userptr(a1).unwrap(), a2),
+            Sys::WRITE => {
+                self.sys_write(a0.into(), self.into_in_userptr(a1).unwrap(), a2)
+                    .await
+            }
             Sys::OPENAT => self.sys_openat(a0.into(), self.into_in_userptr(a1).unwrap(),
                                            a2, a3),
             Sys::CLOSE => self.sys_close(a0.into()),
@@ -176,7 +180,7 @@ impl Syscall<'_> {
                 a3,
             ),
             Sys::LSEEK => self.sys_lseek(a0.into(), a1 as i64, a2 as u8),
-            Sys::IOCTL => self.sys_ioctl(a0.into(), a1, a2, a3, a4),
+            Sys::IOCTL => self.sys_ioctl(a0.into(), a1, a2, a3, a4).await,
             Sys::PREAD64 => {
                 self.sys_pread(a0.into(), self.into_out_userptr(a1).unwrap(), a2, a3 as _)
                     .await
@@ -224,17 +228,23 @@ impl Syscall<'_> {
                 a4,
             ),
             Sys::UNLINKAT => self.sys_unlinkat(a0.into(), self.into_in_userptr(a1).unwrap(), a2),
-            Sys::SYMLINKAT => self.unimplemented("symlinkat", Err(LxError::EACCES)),
+            Sys::SYMLINKAT => self.sys_symlinkat(
+                self.into_in_userptr(a0).unwrap(),
+                a1.into(),
+                self.into_in_userptr(a2).unwrap(),
+            ),
             Sys::READLINKAT => self.sys_readlinkat(
                 a0.into(),
                 self.into_in_userptr(a1).unwrap(),
                 self.into_out_userptr(a2).unwrap(),
                 a3,
             ),
-            Sys::FCHMOD => self.unimplemented("fchmod", Ok(0)),
-            Sys::FCHMODAT => self.unimplemented("fchmodat", Ok(0)),
-            Sys::FCHOWN => self.unimplemented("fchown", Ok(0)),
-            Sys::FCHOWNAT => self.unimplemented("fchownat", Ok(0)),
+            Sys::FCHMOD => self.sys_fchmod(a0.into(), a1),
+            Sys::FCHMODAT => self.sys_fchmodat(a0.into(), self.into_in_userptr(a1).unwrap(), a2),
+            Sys::FCHOWN => self.sys_fchown(a0.into(), a1, a2),
+            Sys::FCHOWNAT => {
+                self.sys_fchownat(a0.into(), self.into_in_userptr(a1).unwrap(), a2, a3, a4)
+            }
             Sys::FACCESSAT => {
                 self.sys_faccessat(a0.into(), self.into_in_userptr(a1).unwrap(), a2, a3)
             }
@@ -276,12 +286,23 @@ impl Syscall<'_> {
                 )
                 .await
             } // ignore sigmask
-            //            Sys::EPOLL_CREATE1 => self.sys_epoll_create1(a0),
-            //            Sys::EPOLL_CTL => self.sys_epoll_ctl(a0, a1, a2, a3.into()),
-            //            Sys::EPOLL_PWAIT => self.sys_epoll_pwait(a0, a1.into(), a2, a3, a4),
-            //            Sys::EVENTFD2 => self.unimplemented("eventfd2", Err(LxError::EACCES)),
+            Sys::EPOLL_CREATE1 => self.sys_epoll_create1(a0),
+            Sys::EPOLL_CTL => {
+                self.sys_epoll_ctl(a0.into(), a1, a2.into(), self.into_in_userptr(a3).unwrap())
+            }
+            Sys::EPOLL_PWAIT => {
+                self.sys_epoll_pwait(
+                    a0.into(),
+                    self.into_out_userptr(a1).unwrap(),
+                    a2 as i32 as _,
+                    a3 as i32 as _,
+                    self.into_in_userptr(a4).unwrap(),
+                    a5,
+                )
+                .await
+            }
+            Sys::EVENTFD2 => self.sys_eventfd2(a0 as _, a1),
 
-            //            Sys::SOCKETPAIR => self.unimplemented("socketpair", Err(LxError::EACCES)),
             // file system
             Sys::STATFS => self.sys_statfs(
                 self.into_in_userptr(a0).unwrap(),
@@ -289,15 +310,21 @@ impl Syscall<'_> {
             ),
             Sys::FSTATFS => self.sys_fstatfs(a0.into(), self.into_out_userptr(a1).unwrap()),
             Sys::SYNC => self.sys_sync(),
-            Sys::MOUNT => self.unimplemented("mount", Err(LxError::EACCES)),
-            Sys::UMOUNT2 => self.unimplemented("umount2", Err(LxError::EACCES)),
+            Sys::MOUNT => self.sys_mount(
+                self.into_in_userptr(a0).unwrap(),
+                self.into_in_userptr(a1).unwrap(),
+                self.into_in_userptr(a2).unwrap(),
+                a3,
+                self.into_in_userptr(a4).unwrap(),
+            ),
+            Sys::UMOUNT2 => self.sys_umount2(self.into_in_userptr(a0).unwrap(), a1),
 
             // memory
-            Sys::BRK => self.unimplemented("brk", Err(LxError::ENOMEM)),
+            Sys::BRK => self.sys_brk(a0),
             Sys::MMAP => self.sys_mmap(a0, a1, a2, a3, a4.into(), a5 as _).await,
             Sys::MPROTECT => self.sys_mprotect(a0, a1, a2),
             Sys::MUNMAP => self.sys_munmap(a0, a1),
-            Sys::MADVISE => self.unimplemented("madvise", Ok(0)),
+            Sys::MADVISE => self.sys_madvise(a0, a1, a2),
 
             // signal
             Sys::RT_SIGACTION => self.sys_rt_sigaction(
@@ -312,12 +339,17 @@ impl Syscall<'_> {
                 self.into_out_userptr(a2).unwrap(),
                 a3,
             ),
-            // Sys::RT_SIGRETURN => self.sys_rt_sigreturn(),
+            Sys::RT_SIGRETURN => self.sys_rt_sigreturn(),
             Sys::SIGALTSTACK => self.sys_sigaltstack(
                 self.into_in_userptr(a0).unwrap(),
                 self.into_out_userptr(a1).unwrap(),
             ),
-            //            Sys::KILL => self.sys_kill(a0, a1),
+            Sys::KILL => self.sys_kill(a0 as _, a1),
+            Sys::TKILL => self.sys_tkill(a0, a1),
+            Sys::TGKILL => self.sys_tgkill(a0, a1, a2),
+            Sys::RT_SIGQUEUEINFO => {
+                self.sys_rt_sigqueueinfo(a0, a1, self.into_in_userptr(a2).unwrap())
+            }
 
             // schedule
             Sys::SCHED_YIELD => self.unimplemented("yield", Ok(0)),
@@ -326,6 +358,7 @@ impl Syscall<'_> {
 
             // socket
             Sys::SOCKET => self.sys_socket(a0, a1, a2),
+            Sys::SOCKETPAIR => self.sys_socketpair(a0, a1, a2, self.into_out_userptr(a3).unwrap()),
             Sys::CONNECT => self.sys_connect(a0, a1.into(), a2).await,
             Sys::ACCEPT => {
                 self.sys_accept(
@@ -348,8 +381,11 @@ impl Syscall<'_> {
                 self.sys_recvfrom(a0, a1.into(), a2, a3, a4.into(), a5.into())
                     .await
             }
-            Sys::SENDMSG => self.unimplemented("sys_sendmsg(),", Ok(0)),
-            Sys::RECVMSG => self.unimplemented("sys_recvmsg(a0, a1.into(), a2),", Ok(0)),
+            Sys::SENDMSG => self.sys_sendmsg(a0, self.into_in_userptr(a1).unwrap(), a2),
+            Sys::RECVMSG => {
+                self.sys_recvmsg(a0, self.into_inout_userptr(a1).unwrap(), a2)
+                    .await
+            }
             Sys::SHUTDOWN => self.sys_shutdown(a0, a1),
             Sys::BIND => self.sys_bind(a0, self.into_in_userptr(a1).unwrap(), a2),
             Sys::LISTEN => self.sys_listen(a0, a1),
@@ -372,17 +408,12 @@ impl Syscall<'_> {
             }
 
             // process
-            Sys::CLONE => {
-                // warn!("a2={} a3={}", a2, a3);
-                // self.sys_clone(
-                //     a0,
-                //     a1,
-                //     self.into_out_userptr(a2).unwrap(),
-                //     self.into_out_userptr(a3).unwrap(),
-                //     a4,
-                // )
-                self.sys_clone(a0, a1, a2.into(), a3.into(), a4)
-            }
+            // the thread pointer comes before the child TID on riscv
+            #[cfg(not(target_arch = "riscv64"))]
+            Sys::CLONE => self.sys_clone(a0, a1, a2.into(), a3.into(), a4).await,
+            #[cfg(target_arch = "riscv64")]
+            Sys::CLONE => self.sys_clone(a0, a1, a2.into(), a4.into(), a3).await,
+            Sys::CLONE3 => self.sys_clone3(self.into_in_userptr(a0).unwrap(), a1).await,
             Sys::EXECVE => self.sys_execve(
                 self.into_in_userptr(a0).unwrap(),
                 self.into_in_userptr(a1).unwrap(),
@@ -395,11 +426,13 @@ impl Syscall<'_> {
                     .await
             }
             Sys::SET_TID_ADDRESS => self.sys_set_tid_address(self.into_out_userptr(a0).unwrap()),
-            Sys::FUTEX => {
-                // ignore timeout argument when op is wake
-                self.sys_futex(a0, a1 as _, a2 as _, a3).await
-            }
-            Sys::TKILL => self.unimplemented("tkill", Ok(0)),
+            Sys::FUTEX => self.sys_futex(a0, a1 as _, a2 as _, a3, a4, a5 as _).await,
+            Sys::SET_ROBUST_LIST => self.sys_set_robust_list(self.into_in_userptr(a0).unwrap(), a1),
+            Sys::GET_ROBUST_LIST => self.sys_get_robust_list(
+                a0,
+                self.into_out_userptr(a1).unwrap(),
+                self.into_out_userptr(a2).unwrap(),
+            ),
 
             // time
             Sys::NANOSLEEP => self.sys_nanosleep(self.into_in_userptr(a0).unwrap()).await,
@@ -412,7 +445,36 @@ impl Syscall<'_> {
                 )
                 .await
             }
-            Sys::SETITIMER => self.unimplemented("setitimer", Ok(0)),
+            Sys::GETITIMER => self.sys_getitimer(a0, self.into_out_userptr(a1).unwrap()),
+            Sys::SETITIMER => self.sys_setitimer(
+                a0,
+                self.into_in_userptr(a1).unwrap(),
+                self.into_out_userptr(a2).unwrap(),
+            ),
+            Sys::TIMER_CREATE => self.sys_timer_create(
+                a0,
+                self.into_in_userptr(a1).unwrap(),
+                self.into_out_userptr(a2).unwrap(),
+            ),
+            Sys::TIMER_SETTIME => self.sys_timer_settime(
+                a0,
+                a1,
+                self.into_in_userptr(a2).unwrap(),
+                self.into_out_userptr(a3).unwrap(),
+            ),
+            Sys::TIMER_GETTIME => self.sys_timer_gettime(a0, self.into_out_userptr(a1).unwrap()),
+            Sys::TIMER_GETOVERRUN => self.sys_timer_getoverrun(a0),
+            Sys::TIMER_DELETE => self.sys_timer_delete(a0),
+            Sys::TIMERFD_CREATE => self.sys_timerfd_create(a0, a1),
+            Sys::TIMERFD_SETTIME => self.sys_timerfd_settime(
+                a0.into(),
+                a1,
+                self.into_in_userptr(a2).unwrap(),
+                self.into_out_userptr(a3).unwrap(),
+            ),
+            Sys::TIMERFD_GETTIME => {
+                self.sys_timerfd_gettime(a0.into(), self.into_out_userptr(a1).unwrap())
+            }
             Sys::GETTIMEOFDAY => self.sys_gettimeofday(
                 self.into_out_userptr(a0).unwrap(),
                 self.into_in_userptr(a1).unwrap(),
@@ -445,23 +507,41 @@ impl Syscall<'_> {
             Sys::GETPID => self.sys_getpid(),
             Sys::GETTID => self.sys_gettid(),
             Sys::UNAME => self.sys_uname(self.into_out_userptr(a0).unwrap()),
-            Sys::UMASK => self.unimplemented("umask", Ok(0o777)),
+            Sys::UMASK => self.sys_umask(a0),
             //            Sys::GETRLIMIT => self.sys_getrlimit(),
             //            Sys::SETRLIMIT => self.sys_setrlimit(),
             Sys::GETRUSAGE => self.sys_getrusage(a0, self.into_out_userptr(a1).unwrap()),
             Sys::SYSINFO => self.sys_sysinfo(self.into_out_userptr(a0).unwrap()),
             Sys::TIMES => self.sys_times(self.into_out_userptr(a0).unwrap()),
-            Sys::GETUID => self.unimplemented("getuid", Ok(0)),
-            Sys::GETGID => self.unimplemented("getgid", Ok(0)),
-            Sys::SETUID => self.unimplemented("setuid", Ok(0)),
-            Sys::GETEUID => self.unimplemented("geteuid", Ok(0)),
-            Sys::GETEGID => self.unimplemented("getegid", Ok(0)),
-            Sys::SETPGID => self.unimplemented("setpgid", Ok(0)),
+            Sys::GETUID => self.sys_getuid(),
+            Sys::GETGID => self.sys_getgid(),
+            Sys::SETUID => self.sys_setuid(a0),
+            Sys::SETGID => self.sys_setgid(a0),
+            Sys::GETEUID => self.sys_geteuid(),
+            Sys::GETEGID => self.sys_getegid(),
+            Sys::SETREUID => self.sys_setreuid(a0, a1),
+            Sys::SETREGID => self.sys_setregid(a0, a1),
+            Sys::SETRESUID => self.sys_setresuid(a0, a1, a2),
+            Sys::SETRESGID => self.sys_setresgid(a0, a1, a2),
+            Sys::GETRESUID => self.sys_getresuid(
+                self.into_out_userptr(a0).unwrap(),
+                self.into_out_userptr(a1).unwrap(),
+                self.into_out_userptr(a2).unwrap(),
+            ),
+            Sys::GETRESGID => self.sys_getresgid(
+                self.into_out_userptr(a0).unwrap(),
+                self.into_out_userptr(a1).unwrap(),
+                self.into_out_userptr(a2).unwrap(),
+            ),
+            Sys::SETFSUID => self.sys_setfsuid(a0),
+            Sys::SETFSGID => self.sys_setfsgid(a0),
+            Sys::SETPGID => self.sys_setpgid(a0, a1),
             Sys::GETPPID => self.sys_getppid(),
-            Sys::SETSID => self.unimplemented("setsid", Ok(0)),
-            Sys::GETPGID => self.unimplemented("getpgid", Ok(0)),
-            Sys::GETGROUPS => self.unimplemented("getgroups", Ok(0)),
-            Sys::SETGROUPS => self.unimplemented("setgroups", Ok(0)),
+            Sys::SETSID => self.sys_setsid(),
+            Sys::GETPGID => self.sys_getpgid(a0),
+            Sys::GETSID => self.sys_getsid(a0),
+            Sys::GETGROUPS => self.sys_getgroups(a0, self.into_out_userptr(a1).unwrap()),
+            Sys::SETGROUPS => self.sys_setgroups(a0, self.into_in_userptr(a1).unwrap()),
             //            Sys::SETPRIORITY => self.sys_set_priority(a0),
             Sys::PRCTL => self.unimplemented("prctl", Ok(0)),
             Sys::MEMBARRIER => self.unimplemented("membarrier", Ok(0)),
@@ -475,7 +555,6 @@ impl Syscall<'_> {
             Sys::GETRANDOM => {
                 self.sys_getrandom(self.into_out_userptr(a0).unwrap(), a1 as usize, a2 as u32)
             }
-            Sys::RT_SIGQUEUEINFO => self.unimplemented("rt_sigqueueinfo", Ok(0)),
 
             // kernel module
             //            Sys::INIT_MODULE => self.sys_init_module(a0.into(), a1 as usize, a2.into()),
@@ -524,9 +603,10 @@ impl Syscall<'_> {
                 .await
             }
             Sys::DUP2 => self.sys_dup2(a0.into(), a1.into()),
-            //            Sys::ALARM => self.unimplemented("alarm", Ok(0)),
+            Sys::ALARM => self.sys_alarm(a0),
             Sys::FORK => self.sys_fork(),
             Sys::VFORK => self.sys_vfork().await,
+            Sys::GETPGRP => self.sys_getpgid(0),
             Sys::RENAME => self.sys_rename(
                 self.into_in_userptr(a0).unwrap(),
                 self.into_in_userptr(a1).unwrap(),
@@ -538,17 +618,31 @@ impl Syscall<'_> {
                 self.into_in_userptr(a1).unwrap(),
             ),
             Sys::UNLINK => self.sys_unlink(self.into_in_userptr(a0).unwrap()),
+            Sys::SYMLINK => self.sys_symlink(
+                self.into_in_userptr(a0).unwrap(),
+                self.into_in_userptr(a1).unwrap(),
+            ),
             Sys::READLINK => self.sys_readlink(
                 self.into_in_userptr(a0).unwrap(),
                 self.into_out_userptr(a1).unwrap(),
                 a2,
             ),
-            Sys::CHMOD => self.unimplemented("chmod", Ok(0)),
-            Sys::CHOWN => self.unimplemented("chown", Ok(0)),
+            Sys::CHMOD => self.sys_chmod(self.into_in_userptr(a0).unwrap(), a1),
+            Sys::CHOWN => self.sys_chown(self.into_in_userptr(a0).unwrap(), a1, a2),
+            Sys::LCHOWN => self.sys_lchown(self.into_in_userptr(a0).unwrap(), a1, a2),
             Sys::ARCH_PRCTL => self.sys_arch_prctl(a0 as _, a1),
             Sys::TIME => self.sys_time(self.into_out_userptr(a0).unwrap()),
-            //            Sys::EPOLL_CREATE => self.sys_epoll_create(a0),
-            //            Sys::EPOLL_WAIT => self.sys_epoll_wait(a0, a1.into(), a2, a3),
+            Sys::EPOLL_CREATE => self.sys_epoll_create(a0 as i32 as _),
+            Sys::EPOLL_WAIT => {
+                self.sys_epoll_wait(
+                    a0.into(),
+                    self.into_out_userptr(a1).unwrap(),
+                    a2 as i32 as _,
+                    a3 as i32 as _,
+                )
+                .await
+            }
+            Sys::EVENTFD => self.sys_eventfd(a0 as _),
             _ => self.unknown_syscall(sys_type),
         }
     }
@@ -567,7 +661,7 @@ impl Syscall<'_> {
     fn unknown_syscall(&mut self, sys_type: Sys) -> SysResult {
         error!("unknown syscall: {:?}. exit...", sys_type);
         let proc = self.zircon_process();
-        proc.exit(-1);
+        proc.exit_linux(-1);
         Err(LxError::ENOSYS)
     }
 
@@ -1,6 +1,14 @@
 use super::*;
 use bitflags::bitflags;
+use core::time::Duration;
+use kernel_hal::timer::timer_now;
+use linux_object::error::LxResult;
+use linux_object::process::{linux_processes, Resource};
+use linux_object::sync::{FutexWord, RobustListHead, FUTEX_BITSET_MATCH_ANY};
+use linux_object::thread::ThreadExt;
 use linux_object::time::*;
+use numeric_enum_macro::numeric_enum;
+use zircon_object::vm::PAGE_SIZE;
 
 impl Syscall<'_> {
     #[cfg(target_arch = "x86_64")]
@@ -58,65 +66,178 @@ impl Syscall<'_> {
 
     /// provides a simple way of getting overall system statistics
     pub fn sys_sysinfo(&mut self, mut sys_info: UserOutPtr<SysInfo>) -> SysResult {
-        let sysinfo = SysInfo::default();
+        let (total, free) = kernel_hal::mem::frame_stats();
+        let job = self.zircon_process().job();
+        let sysinfo = SysInfo {
+            uptime: timer_now().as_secs(),
+            totalram: (total * PAGE_SIZE) as u64,
+            freeram: (free * PAGE_SIZE) as u64,
+            procs: linux_processes(&job).len() as u16,
+            mem_unit: 1,
+            ..Default::default()
+        };
         sys_info.write(sysinfo)?;
         Ok(0)
     }
 
-    /// provides a method for waiting until a certain condition becomes true.
+    /// provides a method for waiting until a certain condition becomes true
+    /// (see [linux man futex(2)](https://www.man7.org/linux/man-pages/man2/futex.2.html)).
     /// - `uaddr` - points to the futex word.
-    /// - `op` -  the operation to perform on the futex
+    /// - `op` -  the operation to perform on the futex, with `FUTEX_PRIVATE_FLAG` and `FUTEX_CLOCK_REALTIME`
     /// - `val` -  a value whose meaning and purpose depends on op
-    /// - `timeout` - not support now
-    /// TODO: support timeout
+    /// - `timeout` - the timeout of the waiting operations, or the number of waiters to requeue
+    /// - `uaddr2` - points to the second futex word of the requeue operations and `FUTEX_WAKE_OP`
+    /// - `val3` - the expected value, the bitset or the encoded operation, depending on op
+    ///
+    /// Futexes without `FUTEX_PRIVATE_FLAG` may be shared by processes,
+    /// they are found by the VMO page backing the word.
+    /// `FUTEX_WAIT_REQUEUE_PI` and `FUTEX_CMP_REQUEUE_PI` are not supported.
     pub async fn sys_futex(
         &self,
         uaddr: usize,
         op: u32,
-        val: i32,
-        timeout_addr: usize,
+        val: u32,
+        timeout: usize,
+        uaddr2: usize,
+        val3: u32,
     ) -> SysResult {
-        let op = FutexFlags::from_bits_truncate(op);
-        let timeout = if op.contains(FutexFlags::WAKE) {
-            self.into_inout_userptr::<TimeSpec>(0).unwrap()
-        } else {
-            let timeout_result = self.into_inout_userptr::<TimeSpec>(timeout_addr);
-            match timeout_result {
-                Ok(t) => t,
-                Err(_e) => return Err(LxError::EACCES),
-            }
-        };
+        let flags = FutexFlags::from_bits_truncate(op);
+        let op = FutexOp::try_from(op & !FutexFlags::all().bits()).map_err(|_| LxError::ENOSYS)?;
         info!(
-            "futex: uaddr: {:#x}, op: {:?}, val: {}, timeout_ptr: {:?}",
-            uaddr, op, val, timeout
+            "futex: uaddr: {:#x}, op: {:?}, flags: {:?}, val: {}, timeout: {:#x}, uaddr2: {:#x}, val3: {:#x}",
+            uaddr, op, flags, val, timeout, uaddr2, val3
         );
-
-        if op.contains(FutexFlags::PRIVATE) {
-            warn!("process-shared futex is unimplemented");
+        let clock_ops = [FutexOp::Wait, FutexOp::WaitBitset, FutexOp::LockPi2];
+        if flags.contains(FutexFlags::CLOCK_REALTIME) && !clock_ops.contains(&op) {
+            return Err(LxError::ENOSYS);
         }
-        let futex = self.linux_process().get_futex(uaddr);
-        match op.bits & 0xf {
-            0 => {
-                // FIXME: support timeout
-                let _timeout = timeout.read_if_not_null()?;
-                match futex.wait(val).await {
-                    Ok(_) => Ok(0),
-                    Err(ZxError::BAD_STATE) => Err(LxError::EAGAIN),
-                    Err(e) => Err(e.into()),
+        let shared = !flags.contains(FutexFlags::PRIVATE);
+        let word = |uaddr: usize, access: MMUFlags| {
+            self.check_pagefault(uaddr, access)
+                .map_err(|_| LxError::EFAULT)?;
+            FutexWord::new(self.zircon_process(), uaddr, shared)
+        };
+        // the timeout is relative for FUTEX_WAIT and absolute for the others,
+        // all clocks are the system clock
+        let deadline = |relative: bool| -> LxResult<Option<Duration>> {
+            let timeout = self
+                .into_in_userptr::<TimeSpec>(timeout)
+                .map_err(|_| LxError::EFAULT)?;
+            match timeout.read_if_not_null()? {
+                Some(ts) if ts.nsec >= 1_000_000_000 => Err(LxError::EINVAL),
+                Some(ts) if relative => Ok(Some(timer_now() + Duration::from(ts))),
+                Some(ts) => Ok(Some(ts.into())),
+                None => Ok(None),
+            }
+        };
+        let tid = self.thread.id() as u32;
+        match op {
+            FutexOp::Wait | FutexOp::WaitBitset => {
+                let bitset = match op {
+                    FutexOp::Wait => FUTEX_BITSET_MATCH_ANY,
+                    _ => val3,
+                };
+                if bitset == 0 {
+                    return Err(LxError::EINVAL);
+                }
+                let deadline = deadline(op == FutexOp::Wait)?;
+                let word = word(uaddr, MMUFlags::READ)?;
+                word.wait(val, bitset, deadline).await?;
+                Ok(0)
+            }
+            FutexOp::Wake | FutexOp::WakeBitset => {
+                let bitset = match op {
+                    FutexOp::Wake => FUTEX_BITSET_MATCH_ANY,
+                    _ => val3,
+                };
+                if bitset == 0 {
+                    return Err(LxError::EINVAL);
                 }
+                let word = word(uaddr, MMUFlags::READ)?;
+                Ok(word.wake(val as usize, bitset))
             }
-            1 => {
-                let woken_up_count = futex.wake(val as usize);
-                Ok(woken_up_count)
+            FutexOp::Requeue | FutexOp::CmpRequeue => {
+                let expected = match op {
+                    FutexOp::CmpRequeue => Some(val3),
+                    _ => None,
+                };
+                let requeue_count = timeout as u32 as usize;
+                let target = word(uaddr2, MMUFlags::READ)?;
+                let word = word(uaddr, MMUFlags::READ)?;
+                word.requeue(expected, val as usize, requeue_count, &target)
             }
-            _ => {
+            FutexOp::WakeOp => {
+                let other_wake_count = timeout as u32 as usize;
+                let other = word(uaddr2, MMUFlags::READ | MMUFlags::WRITE)?;
+                let word = word(uaddr, MMUFlags::READ)?;
+                word.wake_op(val as usize, &other, other_wake_count, val3)
+            }
+            FutexOp::LockPi | FutexOp::LockPi2 | FutexOp::TrylockPi => {
+                let deadline = match op {
+                    FutexOp::TrylockPi => None,
+                    _ => deadline(false)?,
+                };
+                let word = word(uaddr, MMUFlags::READ | MMUFlags::WRITE)?;
+                let try_only = op == FutexOp::TrylockPi;
+                word.lock_pi(tid, deadline, try_only).await?;
+                Ok(0)
+            }
+            FutexOp::UnlockPi => {
+                let word = word(uaddr, MMUFlags::READ | MMUFlags::WRITE)?;
+                word.unlock_pi(tid)?;
+                Ok(0)
+            }
+            FutexOp::Fd | FutexOp::WaitRequeuePi | FutexOp::CmpRequeuePi => {
                 warn!("unsupported futex operation: {:?}", op);
                 Err(LxError::ENOSYS)
             }
         }
     }
 
+    /// set the list of robust futexes released when the calling thread exits
+    /// (see [linux man set_robust_list(2)](https://www.man7.org/linux/man-pages/man2/set_robust_list.2.html)).
+    pub fn sys_set_robust_list(&self, head: UserInPtr<RobustListHead>, len: usize) -> SysResult {
+        info!("set_robust_list: head={:?}, len={}", head, len);
+        if len != core::mem::size_of::<RobustListHead>() {
+            return Err(LxError::EINVAL);
+        }
+        self.thread.lock_linux().robust_list = head;
+        Ok(0)
+    }
+
+    /// get the list of robust futexes of the thread `tid`, 0 for the calling thread
+    /// (see [linux man get_robust_list(2)](https://www.man7.org/linux/man-pages/man2/get_robust_list.2.html)).
+    pub fn sys_get_robust_list(
+        &self,
+        tid: usize,
+        mut head_ptr: UserOutPtr<usize>,
+        mut len_ptr: UserOutPtr<usize>,
+    ) -> SysResult {
+        info!(
+            "get_robust_list: tid={}, head_ptr={:?}, len_ptr={:?}",
+            tid, head_ptr, len_ptr
+        );
+        let tid = match tid {
+            0 => self.thread.id(),
+            tid => tid as KoID,
+        };
+        let thread = self
+            .zircon_process()
+            .get_child(tid)
+            .ok()
+            .and_then(|thread| thread.downcast_arc::<Thread>().ok())
+            .ok_or(LxError::ESRCH)?;
+        let head = thread.lock_linux().robust_list.as_addr();
+        head_ptr.write(head)?;
+        len_ptr.write(core::mem::size_of::<RobustListHead>())?;
+        Ok(0)
+    }
+
     /// Combines and extends the functionality of setrlimit() and getrlimit()
+    /// - `pid` - the process whose limit is read and set, 0 for the calling process
+    ///
+    /// The limits of `RLIMIT_AS`, `RLIMIT_DATA`, `RLIMIT_NPROC`, `RLIMIT_CPU`, `RLIMIT_FSIZE`
+    /// and `RLIMIT_NOFILE` are enforced, the others are only recorded.
     pub fn sys_prlimit64(
         &mut self,
         pid: usize,
@@ -128,29 +249,25 @@ impl Syscall<'_> {
             "prlimit64: pid: {}, resource: {}, new_limit: {:x?}, old_limit: {:x?}",
             pid, resource, new_limit, old_limit
         );
-        let proc = self.linux_process();
-        match resource {
-            RLIMIT_STACK => {
-                old_limit.write_if_not_null(RLimit {
-                    cur: USER_STACK_SIZE as u64,
-                    max: USER_STACK_SIZE as u64,
-                })?;
-                Ok(0)
-            }
-            RLIMIT_NOFILE => {
-                let new_limit = new_limit.read_if_not_null()?;
-                old_limit.write_if_not_null(proc.file_limit(new_limit))?;
-                Ok(0)
-            }
-            RLIMIT_RSS | RLIMIT_AS => {
-                old_limit.write_if_not_null(RLimit {
-                    cur: 1024 * 1024 * 1024,
-                    max: 1024 * 1024 * 1024,
-                })?;
-                Ok(0)
+        let proc = match pid {
+            0 => self.zircon_process().clone(),
+            pid => {
+                let proc = self.linux_process_by_id(pid as KoID)?;
+                let cred = self.linux_process().credentials();
+                let target = proc.linux().credentials();
+                let same_user = [target.uid, target.euid, target.suid].contains(&cred.uid);
+                if !cred.is_privileged() && !same_user {
+                    return Err(LxError::EPERM);
+                }
+                proc
             }
-            _ => Err(LxError::ENOSYS),
-        }
+        };
+        let linux = proc.linux();
+        let new_limit = new_limit.read_if_not_null()?;
+        let resource = Resource::try_from(resource).map_err(|_| LxError::EINVAL)?;
+        let old = linux.set_rlimit(resource, new_limit)?;
+        old_limit.write_if_not_null(old)?;
+        Ok(0)
     }
 
     #[allow(unsafe_code)]
@@ -171,25 +288,50 @@ impl Syscall<'_> {
 }
 
 bitflags! {
-    /// for op argument in futex()
+    /// Flags of the op argument in futex()
     struct FutexFlags: u32 {
-        /// tests that the value at the futex word pointed
-        /// to by the address uaddr still contains the expected value val,
-        /// and if so, then sleeps waiting for a FUTEX_WAKE operation on the futex word.
-        const WAIT      = 0;
-        /// wakes at most val of the waiters that are waiting on the futex word at the address uaddr.
-        const WAKE      = 1;
         /// can be employed with all futex operations, tells the kernel that the futex is process-private and not shared with another process
         const PRIVATE   = 0x80;
+        /// the timeout is measured against CLOCK_REALTIME instead of CLOCK_MONOTONIC
+        const CLOCK_REALTIME = 0x100;
     }
 }
 
-const USER_STACK_SIZE: usize = 8 * 1024 * 1024; // 8 MB, the default config of Linux
-
-const RLIMIT_STACK: usize = 3;
-const RLIMIT_RSS: usize = 5;
-const RLIMIT_NOFILE: usize = 7;
-const RLIMIT_AS: usize = 9;
+numeric_enum! {
+    #[repr(u32)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    /// Operations of futex()
+    enum FutexOp {
+        /// sleeps if the futex word still contains `val`, until a wake on the word
+        Wait = 0,
+        /// wakes at most `val` of the waiters on the futex word
+        Wake = 1,
+        /// creates a file descriptor for the futex, removed in Linux 2.6.26
+        Fd = 2,
+        /// wakes `val` waiters and moves at most `val2` others to `uaddr2`
+        Requeue = 3,
+        /// `Requeue` if the futex word still contains `val3`
+        CmpRequeue = 4,
+        /// changes the word at `uaddr2`, then wakes waiters on both words
+        WakeOp = 5,
+        /// locks a priority-inheritance futex
+        LockPi = 6,
+        /// unlocks a priority-inheritance futex
+        UnlockPi = 7,
+        /// `LockPi` without waiting
+        TrylockPi = 8,
+        /// `Wait` woken by the wakes with a bit in common with `val3`
+        WaitBitset = 9,
+        /// `Wake` for the waiters with a bit in common with `val3`
+        WakeBitset = 10,
+        /// waits on a non-PI futex to be requeued to the PI futex `uaddr2`
+        WaitRequeuePi = 11,
+        /// requeues waiters from a non-PI futex to the PI futex `uaddr2`
+        CmpRequeuePi = 12,
+        /// `LockPi` with the timeout against CLOCK_MONOTONIC
+        LockPi2 = 13,
+    }
+}
 
 /// sysinfo() return information sturct
 #[repr(C)]
@@ -1,13 +1,81 @@
 use super::*;
 
+use alloc::{string::String, vec::Vec};
+use core::convert::TryInto;
+use core::mem::size_of;
+use linux_object::cred::Access;
+use linux_object::error::LxResult;
+use linux_object::fs::split_path;
+use linux_object::fs::vfs::{FileType, INode};
 use linux_object::net::sockaddr_to_endpoint;
 use linux_object::net::SockAddr;
 use linux_object::net::Socket;
 use linux_object::net::TcpSocketState;
 use linux_object::net::UdpSocketState;
+use linux_object::net::{
+    Ancillary, Endpoint, MsgFlags, PassedFd, ReceivedMsg, TryRecv, UCred, UnixEndpoint,
+    UnixSocketState, UnixSocketType,
+};
+use linux_object::sync::{wait_for_event, Event};
 
 use spin::Mutex;
 
+/// Mask of the socket type, the other bits of the argument are flags
+const SOCK_TYPE_MASK: usize = 0xf;
+/// Create the socket in non-blocking mode
+const SOCK_NONBLOCK: usize = 0o4000;
+
+/// `SOL_SOCKET` level of control messages
+const SOL_SOCKET: i32 = 1;
+/// Control message passing file descriptors
+const SCM_RIGHTS: i32 = 1;
+/// Control message passing the credentials of the sender
+const SCM_CREDENTIALS: i32 = 2;
+/// Max number of file descriptors passed in one `SCM_RIGHTS` message
+const SCM_MAX_FD: usize = 253;
+/// Size of `struct cmsghdr`: `cmsg_len: usize`, `cmsg_level: i32`, `cmsg_type: i32`
+const CMSG_HDR_LEN: usize = 16;
+
+/// `struct msghdr` of `sendmsg` and `recvmsg`
+#[repr(C)]
+#[derive(Debug)]
+pub struct MsgHdr {
+    /// address of the peer
+    name: usize,
+    /// size of the address
+    namelen: u32,
+    /// scatter/gather array
+    iov: usize,
+    /// number of elements in `iov`
+    iovlen: usize,
+    /// control messages
+    control: usize,
+    /// size of the control messages buffer
+    controllen: usize,
+    /// flags of the message received
+    flags: i32,
+}
+
+/// Round the length of a control message up to the alignment of the next one
+fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+/// Append a control message to `control` if it fits in `max_len` bytes
+fn push_cmsg(control: &mut Vec<u8>, max_len: usize, type_: i32, data: &[u8]) -> bool {
+    let len = CMSG_HDR_LEN + data.len();
+    if control.len() + len > max_len {
+        return false;
+    }
+    control.extend_from_slice(&len.to_ne_bytes());
+    control.extend_from_slice(&SOL_SOCKET.to_ne_bytes());
+    control.extend_from_slice(&type_.to_ne_bytes());
+    control.extend_from_slice(data);
+    let end = max_len.min(control.len() - len + cmsg_align(len));
+    control.resize(end, 0);
+    true
+}
+
 impl Syscall<'_> {
     /// net socket
     pub fn sys_socket(&mut self, domain: usize, socket_type: usize, protocol: usize) -> SysResult {
@@ -16,12 +84,21 @@ impl Syscall<'_> {
             domain, socket_type, protocol
         );
         let proc = self.linux_process();
+        let nonblock = socket_type & SOCK_NONBLOCK != 0;
         let socket: Arc<Mutex<dyn Socket>> = match domain {
             //     musl
             //     domain local 1
             //     domain inet  2
             //     domain inet6 10
-            2 | 1 => match socket_type {
+            1 => {
+                let type_ = unix_socket_type(socket_type, protocol)?;
+                Arc::new(Mutex::new(UnixSocketState::new(
+                    type_,
+                    self.ucred(),
+                    nonblock,
+                )))
+            }
+            2 => match socket_type & SOCK_TYPE_MASK {
                 //         musl socket type
                 //              1 STREAM
                 //              2 DGRAM
@@ -61,7 +138,7 @@ impl Syscall<'_> {
         let mut _proc = self.linux_process();
         let sa: SockAddr = addr.read()?;
 
-        let endpoint = sockaddr_to_endpoint(sa, addr_len)?;
+        let endpoint = self.resolve_endpoint(sockaddr_to_endpoint(sa, addr_len)?)?;
         let socket = _proc.get_socket(fd.into())?;
         let x = socket.lock();
         x.connect(endpoint).await?;
@@ -122,7 +199,7 @@ impl Syscall<'_> {
         } else {
             let _sa: SockAddr = dest_addr.read()?;
             let endpoint = sockaddr_to_endpoint(dest_addr.read()?, addrlen)?;
-            Some(endpoint)
+            Some(self.resolve_endpoint(endpoint)?)
         };
         let proc = self.linux_process();
         let socket = proc.get_socket(sockfd.into())?;
@@ -147,14 +224,14 @@ impl Syscall<'_> {
         let proc = self.linux_process();
         let mut data = vec![0u8; length];
         let socket = proc.get_socket(sockfd.into())?;
-        let x = socket.lock();
-        let (result, endpoint) = x.read(&mut data).await;
-        if result.is_ok() && !addr.is_null() {
-            let sockaddr_in = SockAddr::from(endpoint);
+        let flags = MsgFlags::from_bits_truncate(flags);
+        let received = recv_socket(&socket, &mut data, flags).await?;
+        if !addr.is_null() {
+            let sockaddr_in = SockAddr::from(received.endpoint);
             sockaddr_in.write_to(addr, addr_len)?;
         }
         buffer.write_array(&data[..length])?;
-        result
+        Ok(received.len)
     }
 
     /// net bind
@@ -167,6 +244,17 @@ impl Syscall<'_> {
 
         let socket = proc.get_socket(fd.into())?;
         let mut x = socket.lock();
+        if let Endpoint::Unix(UnixEndpoint::Path(path)) = endpoint {
+            // the socket file is created first and removed if the socket cannot be bound
+            let (dir_inode, real_path) = self.create_socket_file(&path)?;
+            let (_, file_name) = split_path(&path);
+            return x
+                .bind(Endpoint::Unix(UnixEndpoint::Path(real_path)))
+                .map_err(|err| {
+                    let _ = dir_inode.unlink(file_name);
+                    err
+                });
+        }
         x.bind(endpoint)
     }
 
@@ -269,4 +357,280 @@ impl Syscall<'_> {
         sockaddr_in.write_to(addr, addr_len)?;
         Ok(0)
     }
+
+    /// create a pair of connected Unix domain sockets
+    /// (see [linux man socketpair(2)](https://www.man7.org/linux/man-pages/man2/socketpair.2.html)).
+    pub fn sys_socketpair(
+        &mut self,
+        domain: usize,
+        socket_type: usize,
+        protocol: usize,
+        mut sv: UserOutPtr<[i32; 2]>,
+    ) -> SysResult {
+        info!(
+            "sys_socketpair: domain={}, socket_type={:#x}, protocol={}, sv={:?}",
+            domain, socket_type, protocol, sv
+        );
+        match domain {
+            1 => {}
+            2 => return Err(LxError::EOPNOTSUPP),
+            _ => return Err(LxError::EAFNOSUPPORT),
+        }
+        let type_ = unix_socket_type(socket_type, protocol)?;
+        let nonblock = socket_type & SOCK_NONBLOCK != 0;
+        let (a, b) = UnixSocketState::new_pair(type_, self.ucred(), nonblock);
+        let proc = self.linux_process();
+        let fd0 = proc.add_socket(Arc::new(Mutex::new(a)))?;
+        let fd1 = match proc.add_socket(Arc::new(Mutex::new(b))) {
+            Ok(fd1) => fd1,
+            Err(err) => {
+                proc.close_socket(fd0)?;
+                return Err(err);
+            }
+        };
+        let (fd0, fd1): (usize, usize) = (fd0.into(), fd1.into());
+        sv.write([fd0 as i32, fd1 as i32])?;
+        Ok(0)
+    }
+
+    /// send a message with control messages on a socket
+    /// (see [linux man sendmsg(2)](https://www.man7.org/linux/man-pages/man2/sendmsg.2.html)).
+    pub fn sys_sendmsg(&mut self, fd: usize, msg: UserInPtr<MsgHdr>, flags: usize) -> SysResult {
+        info!("sys_sendmsg: fd={}, msg={:?}, flags={:#x}", fd, msg, flags);
+        let hdr = msg.read()?;
+        let proc = self.linux_process();
+        let socket = proc.get_socket(fd.into())?;
+        let endpoint = if hdr.name == 0 {
+            None
+        } else {
+            let sa: SockAddr = UserInPtr::<SockAddr>::from(hdr.name).read()?;
+            let endpoint = sockaddr_to_endpoint(sa, hdr.namelen as usize)?;
+            Some(self.resolve_endpoint(endpoint)?)
+        };
+        let iovs = UserInPtr::<IoVecIn>::from(hdr.iov).read_iovecs(hdr.iovlen)?;
+        let data = iovs.read_to_vec()?;
+        let ancillary = if hdr.controllen == 0 {
+            Ancillary::default()
+        } else {
+            let control = UserInPtr::<u8>::from(hdr.control).read_array(hdr.controllen)?;
+            self.parse_control(&control)?
+        };
+        let flags = MsgFlags::from_bits_truncate(flags);
+        let x = socket.lock();
+        x.send_msg(&data, endpoint, ancillary, flags)
+    }
+
+    /// receive a message with control messages from a socket
+    /// (see [linux man recvmsg(2)](https://www.man7.org/linux/man-pages/man2/recvmsg.2.html)).
+    pub async fn sys_recvmsg(
+        &mut self,
+        fd: usize,
+        mut msg: UserInOutPtr<MsgHdr>,
+        flags: usize,
+    ) -> SysResult {
+        info!("sys_recvmsg: fd={}, msg={:?}, flags={:#x}", fd, msg, flags);
+        let mut hdr = msg.read()?;
+        let proc = self.linux_process();
+        let socket = proc.get_socket(fd.into())?;
+        let mut iovs = UserInPtr::<IoVecOut>::from(hdr.iov).read_iovecs(hdr.iovlen)?;
+        let mut buf = vec![0u8; iovs.total_len()];
+        let flags = MsgFlags::from_bits_truncate(flags);
+        let received = recv_socket(&socket, &mut buf, flags).await?;
+        iovs.write_from_buf(&buf[..received.len.min(buf.len())])?;
+
+        if hdr.name != 0 {
+            let sockaddr = SockAddr::from(received.endpoint);
+            let len = sockaddr.write_truncated(hdr.name.into(), hdr.namelen as usize)?;
+            hdr.namelen = len as u32;
+        }
+        let mut out_flags = received.flags;
+        let mut control = Vec::new();
+        if let Some(cred) = received.ancillary.credentials {
+            let data = [cred.pid, cred.uid, cred.gid];
+            let data: Vec<u8> = data.iter().flat_map(|id| id.to_ne_bytes()).collect();
+            if !push_cmsg(&mut control, hdr.controllen, SCM_CREDENTIALS, &data) {
+                out_flags |= MsgFlags::CTRUNC;
+            }
+        }
+        let rights = received.ancillary.rights;
+        if !rights.is_empty() {
+            // only the descriptors which fit are installed, the others are closed
+            let room = hdr.controllen.saturating_sub(control.len() + CMSG_HDR_LEN);
+            let count = rights.len().min(room / size_of::<i32>());
+            if count < rights.len() {
+                out_flags |= MsgFlags::CTRUNC;
+            }
+            let mut data = Vec::new();
+            let (mut files, mut sockets) = (Vec::new(), Vec::new());
+            for passed in rights.into_iter().take(count) {
+                let installed = match passed {
+                    PassedFd::File(file) => proc.add_file(file).map(|fd| {
+                        files.push(fd);
+                        usize::from(fd)
+                    }),
+                    PassedFd::Socket(socket) => proc.add_socket(socket).map(|fd| {
+                        sockets.push(fd);
+                        usize::from(fd)
+                    }),
+                };
+                match installed {
+                    Ok(fd) => data.extend_from_slice(&(fd as i32).to_ne_bytes()),
+                    Err(err) => {
+                        // the descriptors are installed all together or not at all
+                        for fd in files {
+                            proc.close_file(fd)?;
+                        }
+                        for fd in sockets {
+                            proc.close_socket(fd)?;
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+            if count > 0 {
+                push_cmsg(&mut control, hdr.controllen, SCM_RIGHTS, &data);
+            }
+        }
+        if !control.is_empty() {
+            UserOutPtr::<u8>::from(hdr.control).write_array(&control)?;
+        }
+        hdr.controllen = control.len();
+        hdr.flags = out_flags.bits() as i32;
+        msg.write(hdr)?;
+        Ok(received.len)
+    }
+
+    /// The credentials sent by the sockets of the calling process
+    fn ucred(&self) -> UCred {
+        let cred = self.linux_process().credentials();
+        UCred {
+            pid: self.zircon_process().id() as u32,
+            uid: cred.euid,
+            gid: cred.egid,
+        }
+    }
+
+    /// Resolve the socket file a Unix domain socket address refers to
+    fn resolve_endpoint(&self, endpoint: Endpoint) -> LxResult<Endpoint> {
+        let path = match endpoint {
+            Endpoint::Unix(UnixEndpoint::Path(path)) => path,
+            endpoint => return Ok(endpoint),
+        };
+        let proc = self.linux_process();
+        let (inode, real_path) = proc.lookup_inode_path_at(FileDesc::CWD, &path, true)?;
+        let metadata = inode.metadata()?;
+        if metadata.type_ != FileType::Socket {
+            return Err(LxError::ECONNREFUSED);
+        }
+        proc.credentials().check_access(&metadata, Access::WRITE)?;
+        Ok(Endpoint::Unix(UnixEndpoint::Path(real_path)))
+    }
+
+    /// Create the socket file of a Unix domain socket bound to `path`
+    fn create_socket_file(&self, path: &str) -> LxResult<(Arc<dyn INode>, String)> {
+        let proc = self.linux_process();
+        let (dir_path, file_name) = split_path(path);
+        let (dir_inode, real_dir) = proc.lookup_inode_path_at(FileDesc::CWD, dir_path, true)?;
+        if dir_inode.find(file_name).is_ok() {
+            return Err(LxError::EADDRINUSE);
+        }
+        let cred = proc.credentials();
+        let dir_info = dir_inode.metadata()?;
+        cred.check_access(&dir_info, Access::WRITE | Access::EXECUTE)?;
+        let inode = dir_inode.create(file_name, FileType::Socket, 0o777)?;
+        crate::file::set_new_owner(&cred, &dir_info, &inode)?;
+        let real_path = match real_dir.as_str() {
+            "/" => format!("/{}", file_name),
+            dir => format!("{}/{}", dir, file_name),
+        };
+        Ok((dir_inode, real_path))
+    }
+
+    /// Parse the control messages given to `sendmsg`
+    fn parse_control(&self, control: &[u8]) -> LxResult<Ancillary> {
+        let proc = self.linux_process();
+        let mut ancillary = Ancillary::default();
+        let mut offset = 0;
+        while offset + CMSG_HDR_LEN <= control.len() {
+            let header = &control[offset..offset + CMSG_HDR_LEN];
+            let len = usize::from_ne_bytes(header[..8].try_into().unwrap());
+            let level = i32::from_ne_bytes(header[8..12].try_into().unwrap());
+            let type_ = i32::from_ne_bytes(header[12..].try_into().unwrap());
+            if len < CMSG_HDR_LEN || offset + len > control.len() {
+                return Err(LxError::EINVAL);
+            }
+            let data = &control[offset + CMSG_HDR_LEN..offset + len];
+            match (level, type_) {
+                (SOL_SOCKET, SCM_RIGHTS) => {
+                    let fds = data.chunks_exact(size_of::<i32>());
+                    if ancillary.rights.len() + fds.len() > SCM_MAX_FD {
+                        return Err(LxError::EINVAL);
+                    }
+                    for fd in fds {
+                        let fd = i32::from_ne_bytes(fd.try_into().unwrap());
+                        let passed = match proc.get_socket((fd as usize).into()) {
+                            Ok(socket) => PassedFd::Socket(socket),
+                            Err(_) => PassedFd::File(proc.get_file_like(fd.into())?),
+                        };
+                        ancillary.rights.push(passed);
+                    }
+                }
+                (SOL_SOCKET, SCM_CREDENTIALS) => {
+                    if data.len() < size_of::<UCred>() {
+                        return Err(LxError::EINVAL);
+                    }
+                    let id = |i: usize| u32::from_ne_bytes(data[i..i + 4].try_into().unwrap());
+                    let ucred = UCred {
+                        pid: id(0),
+                        uid: id(4),
+                        gid: id(8),
+                    };
+                    // only privileged processes may send other credentials than their own
+                    let cred = proc.credentials();
+                    let own = ucred.pid == self.zircon_process().id() as u32
+                        && [cred.uid, cred.euid, cred.suid].contains(&ucred.uid)
+                        && [cred.gid, cred.egid, cred.sgid].contains(&ucred.gid);
+                    if !own && !cred.is_privileged() {
+                        return Err(LxError::EPERM);
+                    }
+                    ancillary.credentials = Some(ucred);
+                }
+                _ => return Err(LxError::EINVAL),
+            }
+            offset += cmsg_align(len);
+        }
+        Ok(ancillary)
+    }
+}
+
+/// The type of a Unix domain socket created by `socket` or `socketpair`
+fn unix_socket_type(socket_type: usize, protocol: usize) -> LxResult<UnixSocketType> {
+    if protocol != 0 {
+        return Err(LxError::EPROTONOSUPPORT);
+    }
+    match socket_type & SOCK_TYPE_MASK {
+        1 => Ok(UnixSocketType::Stream),
+        2 => Ok(UnixSocketType::Datagram),
+        _ => Err(LxError::EINVAL),
+    }
+}
+
+/// Receive from `socket`, waiting for data without holding the socket lock
+/// so that other threads can send on the socket meanwhile.
+pub(crate) async fn recv_socket(
+    socket: &Arc<Mutex<dyn Socket>>,
+    data: &mut [u8],
+    flags: MsgFlags,
+) -> LxResult<ReceivedMsg> {
+    loop {
+        let eventbus = {
+            let x = socket.lock();
+            match x.try_recv_msg(data, flags)? {
+                TryRecv::Received(msg) => return Ok(msg),
+                TryRecv::Wait(eventbus) => eventbus,
+                TryRecv::Unsupported => return x.recv_msg(data, flags).await,
+            }
+        };
+        wait_for_event(eventbus, Event::READABLE).await;
+    }
 }
@@ -5,10 +5,18 @@
 //! - rt_sigprocmask
 //! - kill
 //! - tkill
+//! - tgkill
+//! - rt_sigqueueinfo
 //! - sigaltstack
 
 use super::*;
-use linux_object::signal::{Signal, SignalAction, SignalStack, SignalStackFlags, Sigset};
+use alloc::vec::Vec;
+use kernel_hal::context::UserContextField;
+use linux_object::error::LxResult;
+use linux_object::process::process_group;
+use linux_object::signal::{
+    SigInfo, Signal, SignalAction, SignalCode, SignalFrame, SignalStack, SignalStackFlags, Sigset,
+};
 use linux_object::thread::ThreadExt;
 use numeric_enum_macro::numeric_enum;
 
@@ -77,6 +85,163 @@ impl Syscall<'_> {
             How::Unblock => thread.signal_mask.remove_set(&set),
             How::SetMask => thread.signal_mask = set,
         }
+        // SIGKILL and SIGSTOP cannot be blocked
+        thread.signal_mask.remove(Signal::SIGKILL);
+        thread.signal_mask.remove(Signal::SIGSTOP);
+        drop(thread);
+        // newly unblocked signals are delivered on return to user mode
+        self.thread.update_signal_events();
+        Ok(0)
+    }
+
+    /// Return from a signal handler and restore the context saved in the signal frame
+    /// (see [linux man rt_sigreturn(2)](https://www.man7.org/linux/man-pages/man2/rt_sigreturn.2.html)).
+    ///
+    /// The signal mask and the alternate stack are restored as well.
+    pub fn sys_rt_sigreturn(&self) -> SysResult {
+        let sp = self
+            .thread
+            .with_context(|ctx| ctx.get_field(UserContextField::StackPointer))?;
+        let frame_addr = SignalFrame::locate_on_return(sp);
+        info!("rt_sigreturn: frame={:#x}", frame_addr);
+        let frame = match self
+            .into_in_userptr::<SignalFrame>(frame_addr)
+            .map_err(|_| LxError::EFAULT)
+            .and_then(|frame| Ok(frame.read()?))
+        {
+            Ok(frame) => frame,
+            Err(err) => {
+                error!("rt_sigreturn: bad signal frame @ {:#x}", frame_addr);
+                self.zircon_process().kill_by_signal(Signal::SIGSEGV);
+                return Err(err);
+            }
+        };
+        let ucontext = &frame.ucontext;
+        self.thread
+            .with_context(|ctx| ucontext.context.restore(ctx))?;
+        let mut thread = self.thread.lock_linux();
+        thread.signal_mask = ucontext.sig_mask;
+        thread.signal_mask.remove(Signal::SIGKILL);
+        thread.signal_mask.remove(Signal::SIGSTOP);
+        let mut stack = ucontext.stack;
+        stack.flags.remove(SignalStackFlags::ONSTACK);
+        if !thread.signal_alternate_stack.contains(sp)
+            && (SignalStackFlags::AUTODISARM | SignalStackFlags::DISABLE).contains(stack.flags)
+        {
+            thread.signal_alternate_stack = stack;
+        }
+        drop(thread);
+        self.thread.update_signal_events();
+        // the return value register holds its saved value
+        Ok(ucontext.context.return_value())
+    }
+
+    /// Send a signal to a process or a group of processes
+    /// (see [linux man kill(2)](https://www.man7.org/linux/man-pages/man2/kill.2.html)).
+    ///
+    /// - **pid > 0**: the process `pid`.
+    /// - **pid == 0**: every process in the process group of the caller.
+    /// - **pid == -1**: every process the caller may signal, except itself and init.
+    /// - **pid < -1**: every process in the process group `-pid`.
+    ///
+    /// If `signum` is 0, no signal is sent but the existence of the target
+    /// and the permission to signal it are checked.
+    pub fn sys_kill(&self, pid: i32, signum: usize) -> SysResult {
+        info!("kill: pid={}, signal={}", pid, signum);
+        let signal = parse_signal(signum)?;
+        let current = self.zircon_process();
+        let targets = match pid {
+            0 => process_group(&current.job(), current.linux().pgid()),
+            -1 => {
+                let job = current.job();
+                job.process_ids()
+                    .into_iter()
+                    .filter(|&id| id != current.id())
+                    .filter_map(|id| self.linux_process_by_id(id).ok())
+                    .filter(|proc| !proc.linux().is_init())
+                    .collect::<Vec<_>>()
+            }
+            p if p > 0 => vec![self.linux_process_by_id(p as KoID)?],
+            p => {
+                let pgid = p.checked_neg().ok_or(LxError::ESRCH)?;
+                process_group(&current.job(), pgid as KoID)
+            }
+        };
+        if targets.is_empty() {
+            return Err(LxError::ESRCH);
+        }
+        let targets = targets
+            .into_iter()
+            .filter(|proc| self.may_signal(proc, signal))
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return Err(LxError::EPERM);
+        }
+        if let Some(signal) = signal {
+            for proc in targets {
+                proc.send_signal(SigInfo::kill(signal, SignalCode::USER, current.id() as usize));
+            }
+        }
+        Ok(0)
+    }
+
+    /// Send a signal to the thread `tid`
+    /// (see [linux man tkill(2)](https://www.man7.org/linux/man-pages/man2/tkill.2.html)).
+    ///
+    /// Obsolete predecessor of [`Self::sys_tgkill`].
+    pub fn sys_tkill(&self, tid: usize, signum: usize) -> SysResult {
+        info!("tkill: tid={}, signal={}", tid, signum);
+        let signal = parse_signal(signum)?;
+        let job = self.zircon_process().job();
+        let thread = job
+            .process_ids()
+            .into_iter()
+            .filter_map(|id| self.linux_process_by_id(id).ok())
+            .find_map(|proc| proc.get_child(tid as KoID).ok())
+            .and_then(|thread| thread.downcast_arc::<Thread>().ok())
+            .ok_or(LxError::ESRCH)?;
+        self.send_thread_signal(&thread, signal);
+        Ok(0)
+    }
+
+    /// Send a signal to the thread `tid` in the thread group `tgid`
+    /// (see [linux man tgkill(2)](https://www.man7.org/linux/man-pages/man2/tgkill.2.html)).
+    pub fn sys_tgkill(&self, tgid: usize, tid: usize, signum: usize) -> SysResult {
+        info!("tgkill: tgid={}, tid={}, signal={}", tgid, tid, signum);
+        let signal = parse_signal(signum)?;
+        let thread = self
+            .linux_process_by_id(tgid as KoID)?
+            .get_child(tid as KoID)
+            .ok()
+            .and_then(|thread| thread.downcast_arc::<Thread>().ok())
+            .ok_or(LxError::ESRCH)?;
+        self.send_thread_signal(&thread, signal);
+        Ok(0)
+    }
+
+    /// Send a signal with the data in `info` to the process `tgid`
+    /// (see [linux man rt_sigqueueinfo(2)](https://www.man7.org/linux/man-pages/man2/rt_sigqueueinfo.2.html)).
+    ///
+    /// A process cannot pretend to be the kernel or `kill` when signaling another process.
+    pub fn sys_rt_sigqueueinfo(
+        &self,
+        tgid: usize,
+        signum: usize,
+        info: UserInPtr<SigInfo>,
+    ) -> SysResult {
+        info!("rt_sigqueueinfo: tgid={}, signal={}", tgid, signum);
+        let signal = parse_signal(signum)?;
+        let mut info = info.read()?;
+        let proc = self.linux_process_by_id(tgid as KoID)?;
+        if (info.code >= 0 || info.code == SignalCode::TKILL as i32)
+            && proc.id() != self.zircon_process().id()
+        {
+            return Err(LxError::EPERM);
+        }
+        if let Some(signal) = signal {
+            info.signo = signal as i32;
+            proc.send_signal(info);
+        }
         Ok(0)
     }
 
@@ -88,28 +253,74 @@ impl Syscall<'_> {
         mut old_ss: UserOutPtr<SignalStack>,
     ) -> SysResult {
         info!("sigaltstack: ss={:?}, old_ss={:?}", ss, old_ss);
+        let sp = self
+            .thread
+            .with_context(|ctx| ctx.get_field(UserContextField::StackPointer))?;
         let mut thread = self.thread.lock_linux();
-        old_ss.write_if_not_null(thread.signal_alternate_stack)?;
+        let on_stack = thread.signal_alternate_stack.contains(sp);
+        let mut current = thread.signal_alternate_stack;
+        if on_stack {
+            current.flags.insert(SignalStackFlags::ONSTACK);
+        }
+        old_ss.write_if_not_null(current)?;
         if ss.is_null() {
             return Ok(0);
         }
         let ss = ss.read()?;
-        // check stack size when not disable
-        const MIN_SIGSTACK_SIZE: usize = 2048;
-        if ss.flags.contains(SignalStackFlags::DISABLE) && ss.size < MIN_SIGSTACK_SIZE {
-            return Err(LxError::ENOMEM);
+        if on_stack {
+            // cannot change signal alternate stack when we are on it
+            // see man sigaltstack(2)
+            return Err(LxError::EPERM);
         }
         // only allow SS_AUTODISARM and SS_DISABLE
         if !(SignalStackFlags::AUTODISARM | SignalStackFlags::DISABLE).contains(ss.flags) {
             return Err(LxError::EINVAL);
         }
-        let old_ss = &mut thread.signal_alternate_stack;
-        if old_ss.flags.contains(SignalStackFlags::ONSTACK) {
-            // cannot change signal alternate stack when we are on it
-            // see man sigaltstack(2)
-            return Err(LxError::EPERM);
+        // check stack size when not disable
+        const MIN_SIGSTACK_SIZE: usize = 2048;
+        if !ss.flags.contains(SignalStackFlags::DISABLE) && ss.size < MIN_SIGSTACK_SIZE {
+            return Err(LxError::ENOMEM);
         }
-        *old_ss = ss;
+        thread.signal_alternate_stack = ss;
         Ok(0)
     }
+
+    /// Get the Linux process `pid`.
+    pub(crate) fn linux_process_by_id(&self, pid: KoID) -> LxResult<Arc<Process>> {
+        self.zircon_process()
+            .job()
+            .get_child(pid)
+            .ok()
+            .and_then(|proc| proc.downcast_arc::<Process>().ok())
+            .filter(|proc| proc.ext().is::<LinuxProcess>())
+            .ok_or(LxError::ESRCH)
+    }
+
+    /// Whether the calling process may send `signal` to `proc`,
+    /// `SIGCONT` may also be sent to any process in the same session.
+    fn may_signal(&self, proc: &Arc<Process>, signal: Option<Signal>) -> bool {
+        let current = self.linux_process();
+        let target = proc.linux();
+        (signal == Some(Signal::SIGCONT) && target.sid() == current.sid())
+            || current.credentials().may_signal(&target.credentials())
+    }
+
+    /// Send `signal` to `thread` from the calling process.
+    fn send_thread_signal(&self, thread: &Arc<Thread>, signal: Option<Signal>) {
+        if let Some(signal) = signal {
+            let pid = self.zircon_process().id() as usize;
+            thread.send_signal(SigInfo::kill(signal, SignalCode::TKILL, pid));
+        }
+    }
+}
+
+/// Parse a signal number, 0 is no signal.
+fn parse_signal(signum: usize) -> LxResult<Option<Signal>> {
+    match signum {
+        0 => Ok(None),
+        1..=Signal::RTMAX => Ok(Some(
+            Signal::try_from(signum as u8).map_err(|_| LxError::EINVAL)?,
+        )),
+        _ => Err(LxError::EINVAL),
+    }
 }
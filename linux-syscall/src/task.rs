@@ -5,9 +5,20 @@ use alloc::string::ToString;
 use bitflags::bitflags;
 
 use kernel_hal::context::UserContextField;
+use linux_object::cred::Access;
+use linux_object::error::LxResult;
+use linux_object::fs::vfs::FileType;
+use linux_object::process::{
+    linux_processes, process_group, ForkShare, Resource, WaitOptions, WaitTarget,
+};
+use linux_object::signal::Signal as LinuxSignal;
 use linux_object::thread::{CurrentThreadExt, ThreadExt};
 // use linux_object::time::TimeSpec;
-use linux_object::{fs::INodeExt, loader::LinuxElfLoader};
+use linux_object::{
+    fs::{INodeExt, MountFlags},
+    loader::{is_interpreted, LinuxElfLoader},
+};
+use zircon_object::vm::PAGE_SIZE;
 
 /// Syscalls for process.
 ///
@@ -16,6 +27,7 @@ use linux_object::{fs::INodeExt, loader::LinuxElfLoader};
 /// - [`fork`](Self::sys_fork)
 /// - [`vfork`](Self::sys_vfork)
 /// - [`clone`](Self::sys_clone)
+/// - [`clone3`](Self::sys_clone3)
 /// - [`wait4`](Self::sys_wait4)
 /// - [`execve`](Self::sys_execve)
 /// - [`gettid`](Self::sys_gettid)
@@ -59,8 +71,11 @@ impl Syscall<'_> {
     ///   This means that the two file descriptors share open file status flags and file offset.
     pub fn sys_fork(&self) -> SysResult {
         info!("fork:");
-        let new_proc = Process::fork_from(self.zircon_process(), false)?; // old pt NULL here
+        let proc = self.zircon_process();
+        self.check_process_limit()?;
+        let new_proc = Process::fork_from(proc, proc, ForkShare::empty())?; // old pt NULL here
         let new_thread = Thread::create_linux(&new_proc)?;
+        new_thread.lock_linux().signal_mask = self.thread.lock_linux().signal_mask;
         let mut new_ctx = self.thread.context_cloned()?;
         new_ctx.set_field(UserContextField::ReturnValue, 0);
         new_thread.with_context(|ctx| *ctx = new_ctx)?;
@@ -79,8 +94,11 @@ impl Syscall<'_> {
     /// or it makes a call to [`Self::sys_execve`].
     pub async fn sys_vfork(&self) -> SysResult {
         info!("vfork:");
-        let new_proc = Process::fork_from(self.zircon_process(), true)?;
+        let proc = self.zircon_process();
+        self.check_process_limit()?;
+        let new_proc = Process::fork_from(proc, proc, ForkShare::VM)?;
         let new_thread = Thread::create_linux(&new_proc)?;
+        new_thread.lock_linux().signal_mask = self.thread.lock_linux().signal_mask;
         let mut new_ctx = self.thread.context_cloned()?;
         new_ctx.set_field(UserContextField::ReturnValue, 0);
         new_thread.with_context(|ctx| *ctx = new_ctx)?;
@@ -92,56 +110,218 @@ impl Syscall<'_> {
             self.zircon_process().id(),
             new_proc.id()
         );
-        new_proc.wait_signal(Signal::SIGNALED).await; // wait for execve
+        // wait for the child to exit or call execve
+        new_proc
+            .wait_signal(Signal::SIGNALED | Signal::VFORK_DONE)
+            .await;
         Ok(new_proc.id() as usize)
     }
 
-    /// `sys_clone` create a new thread in the current process.
-    /// The new thread's stack pointer will be set to `newsp`,
-    /// and thread pointer will be set to `newtls`.
-    /// The child TID will be stored at both `parent_tid` and `child_tid`.
+    /// `sys_clone` creates a new process or thread, like [`Self::sys_fork`] but with
+    /// `flags` choosing what the child shares with the caller
+    /// (see [linux man clone(2)](https://www.man7.org/linux/man-pages/man2/clone.2.html)).
+    ///
+    /// - With `CLONE_THREAD` the child is a thread of the calling process,
+    ///   it must share the memory, files, filesystem information and signal handlers.
+    /// - Otherwise the child is a new process. It shares the memory with `CLONE_VM`,
+    ///   the file descriptor table with `CLONE_FILES`, the working directory and umask
+    ///   with `CLONE_FS`, the signal handlers with `CLONE_SIGHAND` and the System V
+    ///   semaphores with `CLONE_SYSVSEM`, and has copies of the others.
+    ///   With `CLONE_VFORK` the caller waits until the child calls [`Self::sys_execve`] or exits.
     ///
-    /// > **NOTE!** This system call is not exactly the same as `clone` in Linux.
+    /// The stack pointer of the child is set to `newsp` unless it is 0,
+    /// and its thread pointer is set to `newtls` with `CLONE_SETTLS`.
+    /// The child ID is stored at `parent_tid` with `CLONE_PARENT_SETTID`,
+    /// and at `child_tid` in the memory of the child with `CLONE_CHILD_SETTID`.
+    /// `child_tid` is cleared when the child exits with `CLONE_CHILD_CLEARTID`.
     ///
-    /// > **NOTE!** This is partially implemented for `musl` only.
-    pub fn sys_clone(
+    /// A new process sends the signal in the lowest byte of `flags` to its parent
+    /// when it terminates, or no signal if it is 0.
+    ///
+    /// Flags not listed here, such as the namespace flags, fail with `EINVAL`,
+    /// as do threads without `CLONE_SIGHAND` and shared signal handlers without `CLONE_VM`.
+    /// Threads that do not share the files and filesystem information fail with `EOPNOTSUPP`.
+    pub async fn sys_clone(
         &self,
         flags: usize,
         newsp: usize,
-        mut parent_tid: UserOutPtr<i32>,
-        mut child_tid: UserOutPtr<i32>,
+        parent_tid: UserOutPtr<i32>,
+        child_tid: UserOutPtr<i32>,
         newtls: usize,
     ) -> SysResult {
-        let _flags = CloneFlags::from_bits_truncate(flags);
         info!(
             "clone: flags={:#x}, newsp={:#x}, parent_tid={:?}, child_tid={:?}, newtls={:#x}",
             flags, newsp, parent_tid, child_tid, newtls
         );
-        if flags == 0x4111 || flags == 0x11 {
-            warn!("sys_clone is calling sys_fork instead, ignoring other args");
-            //unimplemented!()
-            return self.sys_fork();
+        let exit_signal = flags & CloneFlags::CSIGNAL.bits();
+        let flags = CloneFlags::from_bits(flags & !CloneFlags::CSIGNAL.bits());
+        let args = CloneArgs {
+            flags: flags.ok_or(LxError::EINVAL)?,
+            exit_signal,
+            stack: newsp,
+            tls: newtls,
+            parent_tid,
+            child_tid,
+        };
+        self.clone_task(args).await
+    }
+
+    /// `sys_clone3` is [`Self::sys_clone`] with its arguments in `struct clone_args`
+    /// of `size` bytes at `args`
+    /// (see [linux man clone3(2)](https://www.man7.org/linux/man-pages/man2/clone3.2.html)).
+    ///
+    /// The stack of the child is given by its lowest address and its size.
+    /// `CLONE_PIDFD`, `set_tid` and `cgroup` are not supported.
+    pub async fn sys_clone3(&self, args: UserInPtr<u64>, size: usize) -> SysResult {
+        info!("clone3: args={:?}, size={}", args, size);
+        // flags, pidfd, child_tid, parent_tid, exit_signal, stack, stack_size, tls
+        const SIZE_VER0: usize = 64;
+        // set_tid, set_tid_size, cgroup
+        const SIZE_VER2: usize = 88;
+        if size < SIZE_VER0 || size % 8 != 0 {
+            return Err(LxError::EINVAL);
+        }
+        if size > PAGE_SIZE {
+            return Err(LxError::E2BIG);
+        }
+        let words = args.read_array(size / 8)?;
+        // fields from a newer version of the struct must be left zero
+        if words.iter().skip(SIZE_VER2 / 8).any(|&word| word != 0) {
+            return Err(LxError::E2BIG);
+        }
+        let field = |i: usize| words.get(i).copied().unwrap_or(0) as usize;
+        let (flags, pidfd, child_tid, parent_tid) = (field(0), field(1), field(2), field(3));
+        let (exit_signal, stack, stack_size, tls) = (field(4), field(5), field(6), field(7));
+        let (set_tid, set_tid_size, cgroup) = (field(8), field(9), field(10));
+        if pidfd != 0 || set_tid != 0 || set_tid_size != 0 || cgroup != 0 {
+            warn!("clone3: pidfd, set_tid and cgroup are not supported");
+            return Err(LxError::EINVAL);
+        }
+        if exit_signal > LinuxSignal::RTMAX || (stack == 0) != (stack_size == 0) {
+            return Err(LxError::EINVAL);
+        }
+        // the signal has a field of its own
+        if flags & CloneFlags::CSIGNAL.bits() != 0 {
+            return Err(LxError::EINVAL);
+        }
+        // the stack grows down from its end
+        let stack = match stack {
+            0 => 0,
+            _ => stack.checked_add(stack_size).ok_or(LxError::EINVAL)?,
+        };
+        let args = CloneArgs {
+            flags: CloneFlags::from_bits(flags).ok_or(LxError::EINVAL)?,
+            exit_signal,
+            stack,
+            tls,
+            parent_tid: parent_tid.into(),
+            child_tid: child_tid.into(),
+        };
+        self.clone_task(args).await
+    }
+
+    /// Create the child of [`Self::sys_clone`] and [`Self::sys_clone3`]
+    async fn clone_task(&self, args: CloneArgs) -> SysResult {
+        let CloneArgs {
+            flags,
+            exit_signal,
+            stack,
+            tls,
+            mut parent_tid,
+            mut child_tid,
+        } = args;
+        if !CloneFlags::SUPPORTED.contains(flags) {
+            warn!(
+                "clone: unsupported flags {:?}",
+                flags - CloneFlags::SUPPORTED
+            );
+            return Err(LxError::EINVAL);
+        }
+        if flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::SIGHAND)
+            || flags.contains(CloneFlags::SIGHAND) && !flags.contains(CloneFlags::VM)
+        {
+            return Err(LxError::EINVAL);
         }
-        if flags != 0x7d_0f00 && flags != 0x5d_0f00 {
-            // 0x5d0f00: gcc of alpine linux
-            // 0x7d0f00: pthread_create of alpine linux
-            // warn!("sys_clone only support musl pthread_create");
-            panic!("unsupported sys_clone flags: {:#x}", flags);
+        // the threads of a process share a single file table and filesystem information
+        if flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::FS | CloneFlags::FILES)
+        {
+            warn!("clone: threads must share the files and filesystem information");
+            return Err(LxError::EOPNOTSUPP);
         }
-        let new_thread = Thread::create_linux(self.zircon_process())?;
+        let termination_signal = match exit_signal {
+            0 => None,
+            signal => Some(LinuxSignal::try_from(signal as u8).map_err(|_| LxError::EINVAL)?),
+        };
+
+        let proc = self.zircon_process();
+        let (new_proc, new_thread) = if flags.contains(CloneFlags::THREAD) {
+            (proc.clone(), Thread::create_linux(proc)?)
+        } else {
+            let parent = if flags.contains(CloneFlags::PARENT) {
+                self.linux_process().parent().ok_or(LxError::EINVAL)?
+            } else {
+                proc.clone()
+            };
+            self.check_process_limit()?;
+            let mut share = ForkShare::empty();
+            share.set(ForkShare::VM, flags.contains(CloneFlags::VM));
+            share.set(ForkShare::FS, flags.contains(CloneFlags::FS));
+            share.set(ForkShare::FILES, flags.contains(CloneFlags::FILES));
+            share.set(ForkShare::SIGHAND, flags.contains(CloneFlags::SIGHAND));
+            share.set(ForkShare::SYSVSEM, flags.contains(CloneFlags::SYSVSEM));
+            let new_proc = Process::fork_from(proc, &parent, share)?;
+            new_proc.linux().set_termination_signal(termination_signal);
+            let new_thread = Thread::create_linux(&new_proc)?;
+            (new_proc, new_thread)
+        };
+        new_thread.lock_linux().signal_mask = self.thread.lock_linux().signal_mask;
         let mut new_ctx = self.thread.context_cloned()?;
-        new_ctx.set_field(UserContextField::StackPointer, newsp);
-        new_ctx.set_field(UserContextField::ThreadPointer, newtls);
+        if stack != 0 {
+            new_ctx.set_field(UserContextField::StackPointer, stack);
+        }
+        if flags.contains(CloneFlags::SETTLS) {
+            new_ctx.set_field(UserContextField::ThreadPointer, tls);
+        }
         new_ctx.set_field(UserContextField::ReturnValue, 0);
         new_thread.with_context(|ctx| *ctx = new_ctx)?;
+
+        // the ID returned to the caller
+        let id = if flags.contains(CloneFlags::THREAD) {
+            new_thread.id()
+        } else {
+            new_proc.id()
+        };
+        if flags.contains(CloneFlags::PARENT_SETTID) {
+            parent_tid.write(id as i32)?;
+        }
+        if flags.contains(CloneFlags::CHILD_SETTID) {
+            if flags.contains(CloneFlags::VM) {
+                child_tid.write(id as i32)?;
+            } else {
+                let addr = child_tid.as_addr();
+                new_proc
+                    .vmar()
+                    .write_memory(addr, &(id as i32).to_ne_bytes())?;
+            }
+        }
+        if flags.contains(CloneFlags::CHILD_CLEARTID) {
+            new_thread.set_tid_address(child_tid);
+        }
         new_thread.start(self.thread_fn)?;
+        info!("clone: {} -> {}", self.thread.id(), id);
 
-        let tid = new_thread.id();
-        info!("clone: {} -> {}", self.thread.id(), tid);
-        parent_tid.write(tid as i32)?;
-        child_tid.write(tid as i32)?;
-        new_thread.set_tid_address(child_tid);
-        Ok(tid as usize)
+        if flags.contains(CloneFlags::VFORK) {
+            // wait for the child to exit or call execve
+            let child: Arc<dyn KernelObject> = if flags.contains(CloneFlags::THREAD) {
+                new_thread
+            } else {
+                new_proc
+            };
+            child
+                .wait_signal(Signal::SIGNALED | Signal::VFORK_DONE)
+                .await;
+        }
+        Ok(id as usize)
     }
 
     /// `sys_wait4` suspends execution of the calling thread
@@ -152,6 +332,8 @@ impl Syscall<'_> {
     ///
     /// The value of `pid` can be:
     ///
+    /// - **< -1**: meaning wait for any child process whose process group ID is equal to
+    ///          the absolute value of `pid`.
     /// - **-1**: meaning wait for any child process.
     /// - **0**: meaning wait for any child process whose process group ID is equal to
     ///          that of the calling process at the time of the call to `sys_wait4`.
@@ -161,23 +343,23 @@ impl Syscall<'_> {
     ///
     /// - **NOHANG**    = 0x000_0001;
     ///
-    ///   TODO
+    ///   return immediately if no child has exited.
     ///
     /// - **STOPPED**   = 0x000_0002;
     ///
-    ///   TODO
+    ///   also return if a child has stopped, as `WUNTRACED`.
     ///
     /// - **EXITED**    = 0x000_0004;
     ///
-    ///   TODO
+    ///   return if a child has terminated, which is always the case.
     ///
     /// - **CONTINUED** = 0x000_0008;
     ///
-    ///   TODO
+    ///   also return if a stopped child has been resumed by delivery of SIGCONT.
     ///
     /// - **NOWAIT**    = 0x100_0000;
     ///
-    ///   TODO
+    ///   leave the child in a waitable state, a later wait call can retrieve it again.
     ///
     /// On success, returns the process ID of the child whose state has changed;
     /// if `NOHANG` flag was specified and one or more child(ren) specified by pid exist,
@@ -189,41 +371,25 @@ impl Syscall<'_> {
         mut wstatus: UserOutPtr<i32>,
         options: u32,
     ) -> SysResult {
-        #[derive(Debug)]
-        enum WaitTarget {
-            AnyChild,
-            AnyChildInGroup,
-            Pid(KoID),
-        }
-        bitflags! {
-            struct WaitFlags: u32 {
-                const NOHANG    = 1;
-                const STOPPED   = 2;
-                const EXITED    = 4;
-                const CONTINUED = 8;
-                const NOWAIT    = 0x100_0000;
-            }
-        }
+        let proc = self.zircon_process();
         let target = match pid {
             -1 => WaitTarget::AnyChild,
-            0 => WaitTarget::AnyChildInGroup,
+            0 => WaitTarget::ProcessGroup(proc.linux().pgid()),
             p if p > 0 => WaitTarget::Pid(p as KoID),
-            _ => unimplemented!(),
+            p => WaitTarget::ProcessGroup(-(p as i64) as KoID),
         };
-        let flags = WaitFlags::from_bits_truncate(options);
-        let nohang = flags.contains(WaitFlags::NOHANG);
+        let options = WaitOptions::from_bits_truncate(options) | WaitOptions::EXITED;
         info!(
             "wait4: target={:?}, wstatus={:?}, options={:?}",
-            target, wstatus, flags,
+            target, wstatus, options,
         );
-        let (pid, code) = match target {
-            WaitTarget::AnyChild | WaitTarget::AnyChildInGroup => {
-                wait_child_any(self.zircon_process(), nohang).await?
+        match wait_child(proc, target, options).await? {
+            Some((pid, code)) => {
+                wstatus.write_if_not_null(code)?;
+                Ok(pid as usize)
             }
-            WaitTarget::Pid(pid) => (pid, wait_child(self.zircon_process(), pid, nohang).await?),
-        };
-        wstatus.write_if_not_null(code)?;
-        Ok(pid as usize)
+            None => Ok(0),
+        }
     }
 
     /// `sys_execve` executes the program referred to by `path`
@@ -232,7 +398,10 @@ impl Syscall<'_> {
     /// by the calling process to be replaced with a new program,
     /// with newly initialized stack, heap, and (initialized and uninitialized) data segments.
     ///
-    /// `path` argument must be a binary executable file.
+    /// `path` argument must be a binary executable file, a script starting with
+    /// `#!interpreter [optional-arg]`, or a file of a format registered in
+    /// `/proc/sys/fs/binfmt_misc`, the last two being run by their interpreter.
+    /// As in Linux, the set-user-ID and set-group-ID bits of the last two are ignored.
     ///
     /// `argv` is an array of argument strings passed to the new program.
     /// By convention, the first of these strings (i.e., `argv[0]`)
@@ -265,34 +434,62 @@ impl Syscall<'_> {
             return Err(LxError::EINVAL);
         }
 
-        // TODO: check and kill other threads
-
         // Read program file
         let proc = self.linux_process();
         let inode = proc.lookup_inode(path)?;
+        let mut metadata = inode.metadata()?;
+        if metadata.type_ != FileType::File {
+            return Err(LxError::EACCES);
+        }
+        let mount_flags = proc.mounts().flags_of(&inode);
+        if mount_flags.contains(MountFlags::NOEXEC) {
+            return Err(LxError::EACCES);
+        }
+        let mut cred = proc.credentials();
+        cred.check_access(&metadata, Access::EXECUTE)?;
         let data = inode.read_as_vec()?;
 
+        // the other threads are gone with the old program
+        self.zircon_process().kill_other_threads(self.thread.id());
+
+        // a set-user-ID or set-group-ID program runs with the identity of its file,
+        // unless it is a script, which its interpreter opens again after the check,
+        // or it is in a mount ignoring these bits
+        if is_interpreted(path, &data) || mount_flags.contains(MountFlags::NOSUID) {
+            metadata.mode &= !0o6000;
+        }
+        cred.exec(&metadata);
+        proc.set_credentials(cred);
         proc.remove_cloexec_files();
+        proc.reset_signals();
+        proc.clear_posix_timers();
+        {
+            let mut linux_thread = self.thread.lock_linux();
+            linux_thread.signal_alternate_stack = Default::default();
+            // the robust list lives in the old address space
+            linux_thread.robust_list = 0.into();
+        }
 
         // 注意！即将销毁旧应用程序的用户空间，现在将必要的信息拷贝到内核！
         // Notice! About to destroy the user space of the old application, now copy the necessary information into kernel!
         let path = path.to_string();
-        let vmar = self.zircon_process().vmar();
-        vmar.clear()?;
+        // the old memory may be shared with the parent, after vfork or clone
+        let vmar = self.zircon_process().replace_vmar()?;
+        kernel_hal::vm::activate_paging(vmar.table_phys());
+        // release the parent waiting in vfork, which gets its memory back
+        self.zircon_process().signal_set(Signal::VFORK_DONE);
 
         // Modify exec path
-        proc.set_execute_path(&path);
+        proc.set_execute_path(&proc.absolute_path(&path));
+        proc.set_cmdline(args.clone());
 
-        let (entry, sp) = LinuxElfLoader {
+        let (entry, sp, program_break) = LinuxElfLoader {
             syscall_entry: self.syscall_entry,
             stack_pages: 8,
             root_inode: proc.root_inode().clone(),
         }
         .load(&vmar, &data, args, envs, path)?;
-
-        // TODO: use right signal
-        // self.zircon_process().signal_set(Signal::SIGNALED);
-        // Workaround, the child process could NOT exit correctly
+        proc.set_program_break(program_break);
 
         self.thread
             .with_context(|ctx| ctx.setup_uspace(entry, sp, 0, 0))?;
@@ -303,29 +500,6 @@ impl Syscall<'_> {
     //        thread::yield_now();
     //        Ok(0)
     //    }
-    //
-    //    /// Kill the process
-    //    pub fn sys_kill(&self, pid: usize, sig: usize) -> SysResult {
-    //        info!(
-    //            "kill: thread {} kill process {} with signal {}",
-    //            thread::current().id(),
-    //            pid,
-    //            sig
-    //        );
-    //        let current_pid = self.process().pid.get().clone();
-    //        if current_pid == pid {
-    //            // killing myself
-    //            self.sys_exit_group(sig);
-    //        } else {
-    //            if let Some(proc_arc) = PROCESSES.read().get(&pid).and_then(|weak| weak.upgrade()) {
-    //                let mut proc = proc_arc.lock();
-    //                proc.exit(sig);
-    //                Ok(0)
-    //            } else {
-    //                Err(LxError::EINVAL)
-    //            }
-    //        }
-    //    }
 
     /// `sys_gettid` returns the caller's thread ID (TID)
     /// (see [linux man gettid(2)](https://www.man7.org/linux/man-pages/man2/gettid.2.html)).
@@ -357,6 +531,79 @@ impl Syscall<'_> {
         Ok(ppid as usize)
     }
 
+    /// `sys_setpgid` moves the process `pid` to the process group `pgid`
+    /// (see [linux man setpgid(2)](https://www.man7.org/linux/man-pages/man2/setpgid.2.html)).
+    ///
+    /// `pid` is the calling process or one of its children if 0, and `pgid` is `pid` if 0,
+    /// which makes the process the leader of a new group.
+    /// Otherwise the group must exist in the session of the calling process.
+    pub fn sys_setpgid(&self, pid: usize, pgid: usize) -> SysResult {
+        info!("setpgid: pid={}, pgid={}", pid, pgid);
+        let current = self.zircon_process();
+        let proc = match pid as KoID {
+            0 => current.clone(),
+            pid if pid == current.id() => current.clone(),
+            pid => current.linux().child(pid).ok_or(LxError::ESRCH)?,
+        };
+        if (pgid as i32) < 0 {
+            return Err(LxError::EINVAL);
+        }
+        let pgid = match pgid as KoID {
+            0 => proc.id(),
+            pgid => pgid,
+        };
+        let linux = proc.linux();
+        let sid = linux.sid();
+        if sid == proc.id() || sid != current.linux().sid() {
+            return Err(LxError::EPERM);
+        }
+        let group_exists = process_group(&current.job(), pgid)
+            .iter()
+            .any(|member| member.linux().sid() == sid);
+        if pgid != proc.id() && !group_exists {
+            return Err(LxError::EPERM);
+        }
+        linux.set_pgid(pgid);
+        Ok(0)
+    }
+
+    /// `sys_getpgid` returns the process group ID of the process `pid`, or the calling process if 0
+    /// (see [linux man getpgid(2)](https://www.man7.org/linux/man-pages/man2/getpgid.2.html)).
+    pub fn sys_getpgid(&self, pid: usize) -> SysResult {
+        info!("getpgid: pid={}", pid);
+        let pgid = match pid {
+            0 => self.linux_process().pgid(),
+            pid => self.linux_process_by_id(pid as KoID)?.linux().pgid(),
+        };
+        Ok(pgid as usize)
+    }
+
+    /// `sys_setsid` creates a new session led by the calling process, in a new process group
+    /// (see [linux man setsid(2)](https://www.man7.org/linux/man-pages/man2/setsid.2.html)).
+    ///
+    /// The new session has no controlling terminal.
+    /// It fails if the calling process is already a process group leader.
+    pub fn sys_setsid(&self) -> SysResult {
+        info!("setsid:");
+        let proc = self.zircon_process();
+        if !process_group(&proc.job(), proc.id()).is_empty() {
+            return Err(LxError::EPERM);
+        }
+        proc.linux().set_sid();
+        Ok(proc.id() as usize)
+    }
+
+    /// `sys_getsid` returns the session ID of the process `pid`, or the calling process if 0
+    /// (see [linux man getsid(2)](https://www.man7.org/linux/man-pages/man2/getsid.2.html)).
+    pub fn sys_getsid(&self, pid: usize) -> SysResult {
+        info!("getsid: pid={}", pid);
+        let sid = match pid {
+            0 => self.linux_process().sid(),
+            pid => self.linux_process_by_id(pid as KoID)?.linux().sid(),
+        };
+        Ok(sid as usize)
+    }
+
     /// `sys_exit` system call terminates only the calling thread
     /// (see [linux man _exit(2)](https://www.man7.org/linux/man-pages/man2/exit.2.html),
     /// this syscall is same as a raw `_exit` in glibc),
@@ -377,7 +624,7 @@ impl Syscall<'_> {
     pub fn sys_exit_group(&mut self, exit_code: i32) -> SysResult {
         info!("exit_group: code={}", exit_code);
         let proc = self.zircon_process();
-        proc.exit(exit_code as i64);
+        proc.exit_linux(exit_code as i64);
         Err(LxError::ENOSYS)
     }
 
@@ -416,6 +663,38 @@ impl Syscall<'_> {
         let tid = self.thread.id();
         Ok(tid as usize)
     }
+
+    /// Fail with `EAGAIN` if the real user of the caller already runs
+    /// as many processes as its `RLIMIT_NPROC` allows.
+    fn check_process_limit(&self) -> LxResult {
+        let linux = self.linux_process();
+        let cred = linux.credentials();
+        let limit = linux.rlimit(Resource::Nproc).cur;
+        if cred.is_privileged() {
+            return Ok(());
+        }
+        let job = self.zircon_process().job();
+        let count = linux_processes(&job)
+            .iter()
+            .filter(|proc| proc.linux().credentials().uid == cred.uid)
+            .count();
+        if count as u64 >= limit {
+            return Err(LxError::EAGAIN);
+        }
+        Ok(())
+    }
+}
+
+/// Arguments of [`Syscall::sys_clone`] and [`Syscall::sys_clone3`]
+struct CloneArgs {
+    flags: CloneFlags,
+    /// signal sent to the parent when the child terminates
+    exit_signal: usize,
+    /// stack pointer of the child, 0 to keep the one of the caller
+    stack: usize,
+    tls: usize,
+    parent_tid: UserOutPtr<i32>,
+    child_tid: UserOutPtr<i32>,
 }
 
 bitflags! {
@@ -468,5 +747,11 @@ bitflags! {
         const NEWNET =          1 << 30;
         /// the new process shares an I/O context with the calling process.
         const IO =              1 << 31;
+
+        /// flags handled by clone, `DETACHED` is ignored as in Linux
+        const SUPPORTED = Self::VM.bits | Self::FS.bits | Self::FILES.bits | Self::SIGHAND.bits
+            | Self::VFORK.bits | Self::PARENT.bits | Self::THREAD.bits | Self::SYSVSEM.bits
+            | Self::SETTLS.bits | Self::PARENT_SETTID.bits | Self::CHILD_CLEARTID.bits
+            | Self::DETACHED.bits | Self::CHILD_SETTID.bits;
     }
 }
@@ -1,14 +1,60 @@
 //! Syscalls for time
 //! - clock_gettime
+//! - getitimer, setitimer, alarm
+//! - timer_create, timer_settime, timer_gettime, timer_getoverrun, timer_delete
+//! - timerfd_create, timerfd_settime, timerfd_gettime
 //!
 use crate::Syscall;
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
 use kernel_hal::{user::UserInPtr, user::UserOutPtr};
-use linux_object::error::LxError;
-use linux_object::error::SysResult;
+use linux_object::error::{LxError, LxResult, SysResult};
+use linux_object::fs::{FileDesc, TimerFd, TimerFdFlags, TimerFdSetFlags};
+use linux_object::process::ProcessExt;
+use linux_object::signal::{SigInfo, Signal};
+use linux_object::thread::ThreadExt;
 use linux_object::time::*;
+use zircon_object::object::{KernelObject, KoID};
+use zircon_object::task::{Process, Thread};
 
 const USEC_PER_TICK: usize = 10000;
 
+/// Deliver the expirations of a POSIX timer as a signal
+const SIGEV_SIGNAL: i32 = 0;
+/// Do not notify the expirations of a POSIX timer
+const SIGEV_NONE: i32 = 1;
+/// Deliver the expirations of a POSIX timer as a signal, the C library runs a thread for it
+const SIGEV_THREAD: i32 = 2;
+/// Deliver the expirations of a POSIX timer as a signal to the thread `tid`
+const SIGEV_THREAD_ID: i32 = 4;
+
+/// The expiration time of `timer_settime` is absolute instead of relative to now
+const TIMER_ABSTIME: usize = 1;
+
+/// Linux struct sigevent of timer_create
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SigEvent {
+    /// passed with the signal
+    value: usize,
+    /// signal to send
+    signo: i32,
+    /// how to notify
+    notify: i32,
+    /// thread receiving the signal for `SIGEV_THREAD_ID`
+    tid: i32,
+    _pad: [i32; 11],
+}
+
+/// Whom the expirations of a POSIX timer are sent to
+enum SignalTarget {
+    Process(Weak<Process>),
+    Thread(Weak<Thread>),
+}
+
 impl Syscall<'_> {
     /// finds the resolution (precision) of the specified clock clockid, and,
     /// if buffer is non-NULL, stores it in the struct timespec pointed to by buffer
@@ -43,6 +89,238 @@ impl Syscall<'_> {
         Ok(0)
     }
 
+    /// get the value of an interval timer
+    /// (see [linux man getitimer(2)](https://www.man7.org/linux/man-pages/man2/getitimer.2.html)).
+    pub fn sys_getitimer(&self, which: usize, mut curr_value: UserOutPtr<ITimerVal>) -> SysResult {
+        info!("getitimer: which={}, curr_value={:?}", which, curr_value);
+        let which = ITimerWhich::try_from(which)?;
+        let value = self.zircon_process().itimer(which, None);
+        curr_value.write(value.into())?;
+        Ok(0)
+    }
+
+    /// set the value of an interval timer, a NULL `new_value` disarms it
+    /// (see [linux man setitimer(2)](https://www.man7.org/linux/man-pages/man2/setitimer.2.html)).
+    pub fn sys_setitimer(
+        &self,
+        which: usize,
+        new_value: UserInPtr<ITimerVal>,
+        mut old_value: UserOutPtr<ITimerVal>,
+    ) -> SysResult {
+        info!(
+            "setitimer: which={}, new_value={:?}, old_value={:?}",
+            which, new_value, old_value
+        );
+        let which = ITimerWhich::try_from(which)?;
+        let new_value = new_value.read_if_not_null()?.unwrap_or_default();
+        let new_value = TimerValue::try_from(new_value)?;
+        let old = self.zircon_process().itimer(which, Some(new_value));
+        old_value.write_if_not_null(old.into())?;
+        Ok(0)
+    }
+
+    /// deliver SIGALRM after `seconds`, 0 cancels the alarm.
+    /// Return the seconds left before the previous alarm
+    /// (see [linux man alarm(2)](https://www.man7.org/linux/man-pages/man2/alarm.2.html)).
+    pub fn sys_alarm(&self, seconds: usize) -> SysResult {
+        info!("alarm: seconds={}", seconds);
+        let value = TimerValue {
+            value: Duration::from_secs(seconds as u32 as u64),
+            interval: Duration::ZERO,
+        };
+        let old = self.zircon_process().itimer(ITimerWhich::Real, Some(value));
+        // a pending alarm is never reported as 0 seconds left
+        let left = old.value.saturating_add(Duration::from_millis(500));
+        match (left.as_secs(), old.value) {
+            (0, Duration::ZERO) => Ok(0),
+            (0, _) => Ok(1),
+            (secs, _) => Ok(secs as usize),
+        }
+    }
+
+    /// create a POSIX per-process timer
+    /// (see [linux man timer_create(2)](https://www.man7.org/linux/man-pages/man2/timer_create.2.html)).
+    pub fn sys_timer_create(
+        &self,
+        clockid: usize,
+        sevp: UserInPtr<SigEvent>,
+        mut timerid: UserOutPtr<i32>,
+    ) -> SysResult {
+        info!(
+            "timer_create: clockid={}, sevp={:?}, timerid={:?}",
+            clockid, sevp, timerid
+        );
+        check_timer_clock(clockid)?;
+        let event = sevp.read_if_not_null()?;
+        let (target, signal, value) = match event {
+            // SIGALRM to the process with the ID of the timer
+            None => {
+                let target = SignalTarget::Process(Arc::downgrade(self.zircon_process()));
+                (target, Some(Signal::SIGALRM), None)
+            }
+            Some(event) => {
+                let target = match event.notify {
+                    SIGEV_SIGNAL | SIGEV_THREAD | SIGEV_NONE => {
+                        SignalTarget::Process(Arc::downgrade(self.zircon_process()))
+                    }
+                    SIGEV_THREAD_ID => {
+                        let thread = self
+                            .zircon_process()
+                            .get_child(event.tid as KoID)
+                            .ok()
+                            .and_then(|thread| thread.downcast_arc::<Thread>().ok())
+                            .ok_or(LxError::EINVAL)?;
+                        SignalTarget::Thread(Arc::downgrade(&thread))
+                    }
+                    _ => return Err(LxError::EINVAL),
+                };
+                let signal = match event.notify {
+                    SIGEV_NONE => None,
+                    _ => Some(timer_signal(event.signo)?),
+                };
+                (target, signal, Some(event.value))
+            }
+        };
+        let id = self.linux_process().add_posix_timer(|id| {
+            let overrun = Arc::new(AtomicUsize::new(0));
+            let handler = {
+                let overrun = overrun.clone();
+                let value = value.unwrap_or(id);
+                Box::new(move |expirations: usize| {
+                    overrun.store(expirations - 1, Ordering::Relaxed);
+                    let signal = match signal {
+                        Some(signal) => signal,
+                        None => return,
+                    };
+                    let info = SigInfo::timer(signal, id, expirations - 1, value);
+                    match &target {
+                        SignalTarget::Process(proc) => {
+                            if let Some(proc) = proc.upgrade() {
+                                proc.send_signal(info);
+                            }
+                        }
+                        SignalTarget::Thread(thread) => {
+                            if let Some(thread) = thread.upgrade() {
+                                thread.send_signal(info);
+                            }
+                        }
+                    }
+                })
+            };
+            PosixTimer {
+                timer: IntervalTimer::new(handler),
+                overrun,
+            }
+        });
+        timerid.write(id as i32)?;
+        Ok(0)
+    }
+
+    /// arm or disarm a POSIX per-process timer
+    /// (see [linux man timer_settime(2)](https://www.man7.org/linux/man-pages/man2/timer_settime.2.html)).
+    pub fn sys_timer_settime(
+        &self,
+        timerid: usize,
+        flags: usize,
+        new_value: UserInPtr<ITimerSpec>,
+        mut old_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        info!(
+            "timer_settime: timerid={}, flags={:#x}, new_value={:?}, old_value={:?}",
+            timerid, flags, new_value, old_value
+        );
+        let timer = self.linux_process().posix_timer(timerid)?;
+        let value = timer_value(new_value.read()?, flags & TIMER_ABSTIME != 0)?;
+        let old = timer.timer.set(value);
+        old_value.write_if_not_null(old.into())?;
+        Ok(0)
+    }
+
+    /// get the time left before the next expiration of a POSIX per-process timer
+    /// (see [linux man timer_gettime(2)](https://www.man7.org/linux/man-pages/man2/timer_gettime.2.html)).
+    pub fn sys_timer_gettime(
+        &self,
+        timerid: usize,
+        mut curr_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        info!(
+            "timer_gettime: timerid={}, curr_value={:?}",
+            timerid, curr_value
+        );
+        let timer = self.linux_process().posix_timer(timerid)?;
+        curr_value.write(timer.timer.get().into())?;
+        Ok(0)
+    }
+
+    /// get the number of expirations of a POSIX per-process timer missed by its last signal
+    /// (see [linux man timer_getoverrun(2)](https://www.man7.org/linux/man-pages/man2/timer_getoverrun.2.html)).
+    pub fn sys_timer_getoverrun(&self, timerid: usize) -> SysResult {
+        info!("timer_getoverrun: timerid={}", timerid);
+        let timer = self.linux_process().posix_timer(timerid)?;
+        let overrun = timer.overrun.load(Ordering::Relaxed);
+        Ok(overrun.min(i32::MAX as usize))
+    }
+
+    /// delete a POSIX per-process timer
+    /// (see [linux man timer_delete(2)](https://www.man7.org/linux/man-pages/man2/timer_delete.2.html)).
+    pub fn sys_timer_delete(&self, timerid: usize) -> SysResult {
+        info!("timer_delete: timerid={}", timerid);
+        self.linux_process().remove_posix_timer(timerid)?;
+        Ok(0)
+    }
+
+    /// create a timer that notifies via a file descriptor
+    /// (see [linux man timerfd_create(2)](https://www.man7.org/linux/man-pages/man2/timerfd_create.2.html)).
+    pub fn sys_timerfd_create(&self, clockid: usize, flags: usize) -> SysResult {
+        info!("timerfd_create: clockid={}, flags={:#x}", clockid, flags);
+        check_timer_clock(clockid).map_err(|_| LxError::EINVAL)?;
+        let flags = TimerFdFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        let fd = self.linux_process().add_file(TimerFd::new(flags))?;
+        Ok(fd.into())
+    }
+
+    /// arm or disarm the timer of a timerfd
+    /// (see [linux man timerfd_settime(2)](https://www.man7.org/linux/man-pages/man2/timerfd_settime.2.html)).
+    pub fn sys_timerfd_settime(
+        &self,
+        fd: FileDesc,
+        flags: usize,
+        new_value: UserInPtr<ITimerSpec>,
+        mut old_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        info!(
+            "timerfd_settime: fd={:?}, flags={:#x}, new_value={:?}, old_value={:?}",
+            fd, flags, new_value, old_value
+        );
+        let flags = TimerFdSetFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        let timerfd = self.timerfd(fd)?;
+        let absolute = flags.contains(TimerFdSetFlags::ABSTIME);
+        let value = timer_value(new_value.read()?, absolute)?;
+        let old = timerfd.set(value);
+        old_value.write_if_not_null(old.into())?;
+        Ok(0)
+    }
+
+    /// get the time left before the next expiration of a timerfd
+    /// (see [linux man timerfd_gettime(2)](https://www.man7.org/linux/man-pages/man2/timerfd_gettime.2.html)).
+    pub fn sys_timerfd_gettime(
+        &self,
+        fd: FileDesc,
+        mut curr_value: UserOutPtr<ITimerSpec>,
+    ) -> SysResult {
+        info!("timerfd_gettime: fd={:?}, curr_value={:?}", fd, curr_value);
+        let timerfd = self.timerfd(fd)?;
+        curr_value.write(timerfd.get().into())?;
+        Ok(0)
+    }
+
+    fn timerfd(&self, fd: FileDesc) -> LxResult<Arc<TimerFd>> {
+        self.linux_process()
+            .get_file_like(fd)?
+            .downcast_arc::<TimerFd>()
+            .map_err(|_| LxError::EINVAL)
+    }
+
     /// get time in seconds
     #[cfg(target_arch = "x86_64")]
     pub fn sys_time(&mut self, mut time: UserOutPtr<u64>) -> SysResult {
@@ -52,16 +330,36 @@ impl Syscall<'_> {
         Ok(sec)
     }
 
-    /// get resource usage
-    /// currently only support ru_utime and ru_stime:
-    /// - `ru_utime`: user CPU time used
-    /// - `ru_stime`: system CPU time used
+    /// get resource usage of the process, its reaped children or the calling thread
+    /// - `who` - `RUSAGE_SELF` (0), `RUSAGE_CHILDREN` (-1) or `RUSAGE_THREAD` (1)
+    ///
+    /// Only `ru_utime`, `ru_stime` and `ru_maxrss` are filled, the committed memory of
+    /// the address space is reported as the maximum resident set size.
     pub fn sys_getrusage(&mut self, who: usize, mut rusage: UserOutPtr<RUsage>) -> SysResult {
         info!("getrusage: who: {}, rusage: {:?}", who, rusage);
+        const RUSAGE_SELF: usize = 0;
+        const RUSAGE_CHILDREN: usize = usize::MAX;
+        const RUSAGE_THREAD: usize = 1;
 
+        let linux = self.linux_process();
+        let (utime, stime) = match who {
+            RUSAGE_SELF => linux.cpu_time(),
+            RUSAGE_CHILDREN => linux.children_cpu_time(),
+            RUSAGE_THREAD => self.thread.cpu_time(),
+            _ => return Err(LxError::EINVAL),
+        };
+        let maxrss = match who {
+            RUSAGE_CHILDREN => 0,
+            _ => {
+                let stats = self.zircon_process().vmar().get_task_stats();
+                stats.committed_bytes() as usize / 1024
+            }
+        };
         let new_rusage = RUsage {
-            utime: TimeVal::now(),
-            stime: TimeVal::now(),
+            utime: utime.into(),
+            stime: stime.into(),
+            maxrss,
+            ..Default::default()
         };
         rusage.write(new_rusage)?;
         Ok(0)
@@ -75,11 +373,15 @@ impl Syscall<'_> {
 
         let tick = (tv.sec * 1_000_000 + tv.usec) / USEC_PER_TICK;
 
+        let ticks = |time: Duration| (time.as_micros() / USEC_PER_TICK as u128) as u64;
+        let linux = self.linux_process();
+        let (utime, stime) = linux.cpu_time();
+        let (cutime, cstime) = linux.children_cpu_time();
         let new_buf = Tms {
-            tms_utime: 0,
-            tms_stime: 0,
-            tms_cutime: 0,
-            tms_cstime: 0,
+            tms_utime: ticks(utime),
+            tms_stime: ticks(stime),
+            tms_cutime: ticks(cutime),
+            tms_cstime: ticks(cstime),
         };
 
         buf.write(new_buf)?;
@@ -152,3 +454,34 @@ impl Syscall<'_> {
         Ok(0)
     }
 }
+
+/// Check a clock POSIX timers and timerfd can be based on.
+///
+/// They only run on the system clock, not on CPU time clocks.
+fn check_timer_clock(clockid: usize) -> LxResult<()> {
+    match clockid {
+        // realtime, monotonic, boottime and their alarm variants
+        0 | 1 | 7 | 8 | 9 => Ok(()),
+        2 | 3 => Err(LxError::EOPNOTSUPP),
+        _ => Err(LxError::EINVAL),
+    }
+}
+
+/// The signal sent by a POSIX timer
+fn timer_signal(signo: i32) -> LxResult<Signal> {
+    match signo {
+        1..=64 => Signal::try_from(signo as u8).map_err(|_| LxError::EINVAL),
+        _ => Err(LxError::EINVAL),
+    }
+}
+
+/// Convert the setting given to `timer_settime` or `timerfd_settime`
+fn timer_value(spec: ITimerSpec, absolute: bool) -> LxResult<TimerValue> {
+    let mut value = TimerValue::try_from(spec)?;
+    if absolute && value.value != Duration::ZERO {
+        // an expiration time already passed fires at once
+        let now: Duration = TimeSpec::now().into();
+        value.value = value.value.saturating_sub(now).max(Duration::from_nanos(1));
+    }
+    Ok(value)
+}
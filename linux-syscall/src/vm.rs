@@ -1,7 +1,9 @@
 use super::*;
+use alloc::vec::Vec;
 use bitflags::bitflags;
-use linux_object::fs::KEYSTONE;
-use zircon_object::vm::{pages, MMUFlags, VmObject};
+use linux_object::error::LxResult;
+use linux_object::fs::Keystone;
+use zircon_object::vm::{pages, MMUFlags, VmAddressRegion, VmObject, VmoInfoFlags, PAGE_SIZE};
 
 /// Syscalls for virtual memory.
 ///
@@ -10,6 +12,8 @@ use zircon_object::vm::{pages, MMUFlags, VmObject};
 /// - [`mmap`](Self::sys_mmap)
 /// - [`mprotect`](Self::sys_mprotect)
 /// - [`munmap`](Self::sys_munmap)
+/// - [`madvise`](Self::sys_madvise)
+/// - [`brk`](Self::sys_brk)
 impl Syscall<'_> {
     /// Map files or devices into memory
     /// (see [linux man mmap(2)](https://www.man7.org/linux/man-pages/man2/mmap.2.html)).
@@ -91,10 +95,26 @@ impl Syscall<'_> {
         let proc = self.zircon_process();
         let vmar = proc.vmar();
 
+        // the keystone SDK passes an enclave id in the upper bits of `len`
+        // to map the UTM into the enclave as well, plain mmaps go through `get_vmo`
+        let keystone = if flags.contains(MmapFlags::ANONYMOUS) || len >> 48 == 0 {
+            None
+        } else {
+            self.linux_process()
+                .get_file_like(fd)?
+                .downcast_arc::<Keystone>()
+                .ok()
+        };
+        let map_len = match keystone {
+            Some(_) => len & ((1 << 48) - 1),
+            None => len,
+        };
         if flags.contains(MmapFlags::FIXED) {
             // unmap first
-            vmar.unmap(addr, if usize::from(fd) == 666 { len & ((1 << 48) - 1) } else { len })?;
+            vmar.unmap(addr, map_len)?;
         }
+        self.linux_process()
+            .check_address_space(vmar, pages(map_len) * PAGE_SIZE)?;
         let vmar_offset = flags.contains(MmapFlags::FIXED).then(|| addr - vmar.addr());
         if flags.contains(MmapFlags::ANONYMOUS) {
             if flags.contains(MmapFlags::SHARED) {
@@ -104,8 +124,8 @@ impl Syscall<'_> {
             let addr = vmar.map(vmar_offset, vmo.clone(), 0, vmo.len(), prot.to_flags())?;
             Ok(addr)
         } else {
-            let vmo = if usize::from(fd) == 666 {
-                KEYSTONE.mmap(vmar_offset, len, offset as usize, prot.contains(MmapProt::USER))?
+            let vmo = if let Some(keystone) = keystone {
+                keystone.mmap(vmar_offset, len, offset as usize, prot.contains(MmapProt::USER))?
             } else {
                 let file_like = self.linux_process().get_file_like(fd)?;
                 file_like.get_vmo(offset as usize, len)?
@@ -118,8 +138,6 @@ impl Syscall<'_> {
     /// Set protection on a region of memory
     /// (see [linux man mprotect(2)](https://www.man7.org/linux/man-pages/man2/mprotect.2.html)).
     ///
-    /// **NOTE!** This syscall is now unimplemented. Calling it always return `Ok(0)`.
-    ///
     /// `sys_mprotect` changes the access protections for the calling process's memory pages
     /// containing any part of the address range in the interval `[addr, addr+len-1]`.
     /// `addr` must be aligned to a page boundary.
@@ -143,13 +161,23 @@ impl Syscall<'_> {
     ///   The memory can be executed.
     ///
     /// If `prot` is 0, the memory cannot be accessed at all.
+    ///
+    /// If some pages of the range are not mapped, an [`ENOMEM`](LxError::ENOMEM) is returned.
     pub fn sys_mprotect(&self, addr: usize, len: usize, prot: usize) -> SysResult {
         let prot = MmapProt::from_bits_truncate(prot);
         info!(
             "mprotect: addr={:#x}, size={:#x}, prot={:?}",
             addr, len, prot
         );
-        warn!("mprotect: unimplemented");
+        if addr % PAGE_SIZE != 0 {
+            return Err(LxError::EINVAL);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+        let vmar = self.zircon_process().vmar();
+        vmar.protect(addr, pages(len) * PAGE_SIZE, prot.to_flags())
+            .map_err(unmapped_range)?;
         Ok(0)
     }
 
@@ -173,6 +201,52 @@ impl Syscall<'_> {
         vmar.unmap(addr, len)?;
         Ok(0)
     }
+
+    /// Give advice about use of memory
+    /// (see [linux man madvise(2)](https://www.man7.org/linux/man-pages/man2/madvise.2.html)).
+    ///
+    /// - **`MADV_DONTNEED`** releases the pages of the range. Private anonymous pages read as
+    ///   zero on the next access, the pages of shared and file mappings keep their content.
+    /// - **`MADV_FREE`** releases private anonymous pages the same way,
+    ///   an [`EINVAL`](LxError::EINVAL) is returned for other mappings.
+    /// - **`MADV_WILLNEED`** commits the pages of the range ahead of their first access.
+    ///
+    /// The other advice is accepted and ignored.
+    /// If some pages of the range are not mapped, an [`ENOMEM`](LxError::ENOMEM) is returned.
+    pub fn sys_madvise(&self, addr: usize, len: usize, advice: usize) -> SysResult {
+        info!(
+            "madvise: addr={:#x}, size={:#x}, advice={}",
+            addr, len, advice
+        );
+        if addr % PAGE_SIZE != 0 {
+            return Err(LxError::EINVAL);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+        let vmar = self.zircon_process().vmar();
+        let len = pages(len) * PAGE_SIZE;
+        match advice {
+            MADV_DONTNEED | MADV_FREE => discard_pages(&vmar, addr, len, advice == MADV_FREE)?,
+            MADV_WILLNEED => vmar.commit(addr, len).map_err(unmapped_range)?,
+            _ => warn!("madvise: advice {} ignored", advice),
+        }
+        Ok(0)
+    }
+
+    /// Change the location of the program break
+    /// (see [linux man brk(2)](https://www.man7.org/linux/man-pages/man2/brk.2.html)).
+    ///
+    /// The program break is the end of the heap, which starts right after the program image.
+    /// Moving it up allocates zeroed memory, moving it down releases the pages above it.
+    ///
+    /// Returns the new program break on success, or the current one on failure,
+    /// so `addr` 0 queries the current break.
+    pub fn sys_brk(&self, addr: usize) -> SysResult {
+        info!("brk: addr={:#x}", addr);
+        let vmar = self.zircon_process().vmar();
+        Ok(self.linux_process().brk(&vmar, addr))
+    }
 }
 
 bitflags! {
@@ -224,10 +298,60 @@ impl MmapProt {
         if self.contains(MmapProt::EXEC) {
             flags |= MMUFlags::EXECUTE;
         }
-        // FIXME: hack for unimplemented mprotect
-        if self.is_empty() {
-            flags |= MMUFlags::READ | MMUFlags::WRITE;
-        }
         flags
     }
 }
+
+/// Expect access in the near future
+const MADV_WILLNEED: usize = 3;
+/// Do not expect access in the near future, the pages are released
+const MADV_DONTNEED: usize = 4;
+/// The pages can be freed, they are released right away
+const MADV_FREE: usize = 8;
+
+/// Drop the pages of `[addr, addr + len)` for `MADV_DONTNEED`, or for `MADV_FREE` if `lazy`.
+///
+/// Private anonymous memory is zero-filled on the next access. Shared and file mappings keep
+/// their content and only lose their page table entries. Device memory cannot be dropped,
+/// and only private anonymous memory can be freed lazily.
+fn discard_pages(vmar: &VmAddressRegion, addr: usize, len: usize, lazy: bool) -> LxResult {
+    let end = addr + len;
+    let mut anonymous = Vec::new();
+    for info in vmar.mappings_info() {
+        let (start, stop) = (info.addr.max(addr), (info.addr + info.size).min(end));
+        if start >= stop {
+            continue;
+        }
+        if !info.vmo.get_info().flags.contains(VmoInfoFlags::TYPE_PAGED) {
+            return Err(LxError::EINVAL);
+        }
+        if is_private_anonymous(&info.vmo) {
+            anonymous.push((start, stop - start));
+        } else if lazy {
+            return Err(LxError::EINVAL);
+        }
+    }
+    vmar.unmap_pages(addr, len).map_err(unmapped_range)?;
+    for (start, len) in anonymous {
+        vmar.decommit(start, len).map_err(unmapped_range)?;
+    }
+    Ok(())
+}
+
+/// Whether `vmo` backs private anonymous memory, whose pages `MADV_DONTNEED` zero-fills.
+///
+/// The VMOs of files and shared memory segments are named after them.
+fn is_private_anonymous(vmo: &VmObject) -> bool {
+    let flags = vmo.get_info().flags;
+    flags.contains(VmoInfoFlags::TYPE_PAGED)
+        && !flags.contains(VmoInfoFlags::CONTIGUOUS)
+        && ["", "[heap]", "[stack]"].contains(&vmo.name().as_str())
+}
+
+/// Unmapped pages in a range are reported as `ENOMEM`
+fn unmapped_range(err: ZxError) -> LxError {
+    match err {
+        ZxError::NOT_FOUND => LxError::ENOMEM,
+        err => err.into(),
+    }
+}
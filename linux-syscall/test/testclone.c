@@ -0,0 +1,180 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <poll.h>
+#include <sched.h>
+#include <stdio.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <pthread.h>
+#include <sys/wait.h>
+#include <sys/syscall.h>
+
+#define STACK_SIZE (64 * 1024)
+
+struct clone_args
+{
+    uint64_t flags;
+    uint64_t pidfd;
+    uint64_t child_tid;
+    uint64_t parent_tid;
+    uint64_t exit_signal;
+    uint64_t stack;
+    uint64_t stack_size;
+    uint64_t tls;
+};
+
+volatile int shared;
+int fd_to_close;
+
+int set_shared(void *arg)
+{
+    shared = 1;
+    return 0;
+}
+
+int close_fd(void *arg)
+{
+    close(fd_to_close);
+    return 0;
+}
+
+int change_dir(void *arg)
+{
+    return chdir("/tmp") == 0 ? 0 : 1;
+}
+
+int fails(void *arg)
+{
+    return 0;
+}
+
+pid_t run_clone(int (*fn)(void *), int flags)
+{
+    char *stack = malloc(STACK_SIZE);
+    assert(stack != NULL);
+    return clone(fn, stack + STACK_SIZE, flags, NULL);
+}
+
+void wait_child(pid_t pid, int code)
+{
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == code);
+}
+
+void *write_later(void *arg)
+{
+    int fd = *(int *)arg;
+    usleep(50000);
+    write(fd, "alive", 5);
+    return NULL;
+}
+
+// after execve from a program with another thread, that thread is gone
+int exec_killed_thread(char *self)
+{
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    pthread_t thread;
+    assert(pthread_create(&thread, NULL, write_later, &pipefd[1]) == 0);
+    char fd_arg[16];
+    snprintf(fd_arg, sizeof(fd_arg), "%d", pipefd[0]);
+    char *args[] = {self, "nothread", fd_arg, NULL};
+    execv(self, args);
+    return 1;
+}
+
+int main(int argc, char **argv)
+{
+    if (argc == 3 && strcmp(argv[1], "nothread") == 0)
+    {
+        struct pollfd pfd = {.fd = atoi(argv[2]), .events = POLLIN};
+        return poll(&pfd, 1, 200) == 0 ? 0 : 1;
+    }
+    if (argc == 2 && strcmp(argv[1], "sleep") == 0)
+    {
+        usleep(100000);
+        return 7;
+    }
+
+    // CLONE_VM | CLONE_VFORK: the child runs in the same memory before the parent goes on
+    shared = 0;
+    pid_t pid = run_clone(set_shared, CLONE_VM | CLONE_VFORK | SIGCHLD);
+    assert(pid > 0);
+    assert(shared == 1);
+    wait_child(pid, 0);
+
+    // vfork: execve releases the parent before the child exits
+    pid = vfork();
+    if (pid == 0)
+    {
+        char *args[] = {argv[0], "sleep", NULL};
+        execv(argv[0], args);
+        _exit(1);
+    }
+    assert(pid > 0);
+    int status;
+    assert(waitpid(pid, &status, WNOHANG) == 0);
+    wait_child(pid, 7);
+
+    // CLONE_FILES: closing in the child closes in the parent
+    fd_to_close = dup(1);
+    pid = run_clone(close_fd, CLONE_FILES | SIGCHLD);
+    wait_child(pid, 0);
+    errno = 0;
+    assert(fcntl(fd_to_close, F_GETFD) == -1 && errno == EBADF);
+    fd_to_close = dup(1);
+    pid = run_clone(close_fd, SIGCHLD);
+    wait_child(pid, 0);
+    assert(fcntl(fd_to_close, F_GETFD) >= 0);
+    close(fd_to_close);
+
+    // CLONE_FS: the working directory is shared
+    char cwd[256];
+    assert(chdir("/") == 0);
+    pid = run_clone(change_dir, SIGCHLD);
+    wait_child(pid, 0);
+    assert(getcwd(cwd, sizeof(cwd)) && strcmp(cwd, "/") == 0);
+    pid = run_clone(change_dir, CLONE_FS | SIGCHLD);
+    wait_child(pid, 0);
+    assert(getcwd(cwd, sizeof(cwd)) && strcmp(cwd, "/tmp") == 0);
+    assert(chdir("/") == 0);
+
+    // invalid combinations
+    errno = 0;
+    assert(run_clone(fails, CLONE_THREAD | CLONE_VM) == -1 && errno == EINVAL);
+    errno = 0;
+    assert(run_clone(fails, CLONE_SIGHAND | SIGCHLD) == -1 && errno == EINVAL);
+
+    // clone3: a fork with the parent told the child ID
+    pid_t parent_tid = 0;
+    struct clone_args args = {0};
+    args.flags = CLONE_PARENT_SETTID;
+    args.parent_tid = (uintptr_t)&parent_tid;
+    args.exit_signal = SIGCHLD;
+    pid = syscall(SYS_clone3, &args, sizeof(args));
+    if (pid == 0)
+        _exit(3);
+    assert(pid > 0 && parent_tid == pid);
+    wait_child(pid, 3);
+    // bad sizes and the signal in the flags
+    errno = 0;
+    assert(syscall(SYS_clone3, &args, 8) == -1 && errno == EINVAL);
+    args.flags = SIGCHLD;
+    errno = 0;
+    assert(syscall(SYS_clone3, &args, sizeof(args)) == -1 && errno == EINVAL);
+
+    // execve ends the other threads
+    pid = fork();
+    if (pid == 0)
+        _exit(exec_killed_thread(argv[0]));
+    wait_child(pid, 0);
+
+    printf("clone tests passed\n");
+    return 0;
+}
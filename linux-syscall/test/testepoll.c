@@ -0,0 +1,63 @@
+#include <errno.h>
+#include <stdio.h>
+#include <signal.h>
+#include <unistd.h>
+#include <assert.h>
+#include <stdint.h>
+#include <sys/time.h>
+#include <sys/epoll.h>
+#include <sys/eventfd.h>
+
+volatile sig_atomic_t alarms = 0;
+
+void on_alarm(int sig)
+{
+    alarms++;
+}
+
+int main(int argc, char **argv)
+{
+    int epfd = epoll_create1(0);
+    assert(epfd >= 0);
+    int efd = eventfd(0, EFD_NONBLOCK);
+    assert(efd >= 0);
+    struct epoll_event ev = {.events = EPOLLIN, .data.fd = efd};
+    assert(epoll_ctl(epfd, EPOLL_CTL_ADD, efd, &ev) == 0);
+
+    // an eventfd with a count is readable
+    struct epoll_event out;
+    assert(epoll_wait(epfd, &out, 1, 0) == 0);
+    uint64_t one = 1;
+    assert(write(efd, &one, sizeof(one)) == sizeof(one));
+    assert(epoll_wait(epfd, &out, 1, 0) == 1 && out.data.fd == efd);
+    uint64_t count;
+    assert(read(efd, &count, sizeof(count)) == sizeof(count) && count == 1);
+
+    // SIGALRM is blocked except while waiting in epoll_pwait
+    signal(SIGALRM, on_alarm);
+    sigset_t blocked, unblocked, current;
+    sigemptyset(&blocked);
+    sigaddset(&blocked, SIGALRM);
+    sigemptyset(&unblocked);
+    assert(sigprocmask(SIG_BLOCK, &blocked, NULL) == 0);
+    struct itimerval it = {{0, 0}, {0, 10000}};
+    assert(setitimer(ITIMER_REAL, &it, NULL) == 0);
+    errno = 0;
+    assert(epoll_pwait(epfd, &out, 1, -1, &unblocked) == -1 && errno == EINTR);
+    assert(alarms == 1);
+    // the handler returned to the mask replaced by epoll_pwait
+    assert(sigprocmask(SIG_BLOCK, NULL, &current) == 0);
+    assert(sigismember(&current, SIGALRM));
+
+    // a signal arriving after the wait stays pending
+    assert(setitimer(ITIMER_REAL, &it, NULL) == 0);
+    assert(epoll_pwait(epfd, &out, 1, 50, &blocked) == 0);
+    assert(alarms == 1);
+    assert(sigprocmask(SIG_UNBLOCK, &blocked, NULL) == 0);
+    assert(alarms == 2);
+
+    close(efd);
+    close(epfd);
+    printf("epoll tests passed\n");
+    return 0;
+}
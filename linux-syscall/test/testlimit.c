@@ -0,0 +1,217 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <signal.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/sysinfo.h>
+#include <sys/wait.h>
+
+#define FSIZE_FILE "/tmp/testlimit_fsize"
+
+void busy(long ms)
+{
+    struct timespec start, now;
+    clock_gettime(CLOCK_MONOTONIC, &start);
+    do
+    {
+        clock_gettime(CLOCK_MONOTONIC, &now);
+    } while ((now.tv_sec - start.tv_sec) * 1000 + (now.tv_nsec - start.tv_nsec) / 1000000 < ms);
+}
+
+long cpu_ms(struct rusage *usage)
+{
+    return (usage->ru_utime.tv_sec + usage->ru_stime.tv_sec) * 1000 +
+           (usage->ru_utime.tv_usec + usage->ru_stime.tv_usec) / 1000;
+}
+
+int wait_child(pid_t pid)
+{
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    return status;
+}
+
+int run(int (*fn)(void))
+{
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(fn());
+    int status = wait_child(pid);
+    assert(WIFEXITED(status));
+    return WEXITSTATUS(status);
+}
+
+int nofile_limit(void)
+{
+    struct rlimit limit = {.rlim_cur = 8, .rlim_max = 8};
+    if (setrlimit(RLIMIT_NOFILE, &limit) != 0)
+        return 1;
+    int fd;
+    while ((fd = dup(0)) >= 0)
+        if (fd >= 8)
+            return 2;
+    return errno == EMFILE ? 0 : 3;
+}
+
+int fsize_limit(void)
+{
+    signal(SIGXFSZ, SIG_IGN);
+    struct rlimit limit = {.rlim_cur = 100, .rlim_max = 100};
+    if (setrlimit(RLIMIT_FSIZE, &limit) != 0)
+        return 1;
+    int fd = open(FSIZE_FILE, O_CREAT | O_TRUNC | O_WRONLY, 0644);
+    if (fd < 0)
+        return 2;
+    char buf[150];
+    memset(buf, 'x', sizeof(buf));
+    // the write is cut at the limit, then fails
+    if (write(fd, buf, sizeof(buf)) != 100)
+        return 3;
+    errno = 0;
+    if (write(fd, buf, 1) != -1 || errno != EFBIG)
+        return 4;
+    errno = 0;
+    if (ftruncate(fd, 200) != -1 || errno != EFBIG)
+        return 5;
+    close(fd);
+    return 0;
+}
+
+int fsize_signal(void)
+{
+    struct rlimit limit = {.rlim_cur = 0, .rlim_max = 0};
+    setrlimit(RLIMIT_FSIZE, &limit);
+    int fd = open(FSIZE_FILE, O_CREAT | O_TRUNC | O_WRONLY, 0644);
+    write(fd, "x", 1);
+    return 0;
+}
+
+int as_limit(void)
+{
+    struct rlimit limit = {.rlim_cur = 1 << 30, .rlim_max = 1 << 30};
+    if (setrlimit(RLIMIT_AS, &limit) != 0)
+        return 1;
+    errno = 0;
+    void *mem = mmap(NULL, 2UL << 30, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (mem != MAP_FAILED || errno != ENOMEM)
+        return 2;
+    mem = mmap(NULL, 1 << 20, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    return mem == MAP_FAILED ? 3 : 0;
+}
+
+int nproc_limit(void)
+{
+    struct rlimit limit = {.rlim_cur = 1, .rlim_max = 1};
+    if (setrlimit(RLIMIT_NPROC, &limit) != 0 || setuid(1000) != 0)
+        return 1;
+    errno = 0;
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(0);
+    return pid == -1 && errno == EAGAIN ? 0 : 2;
+}
+
+// an unprivileged process may lower its hard limits but not raise them
+int unprivileged_limits(void)
+{
+    if (setuid(1000) != 0)
+        return 1;
+    struct rlimit limit = {.rlim_cur = 32, .rlim_max = 64};
+    if (setrlimit(RLIMIT_NOFILE, &limit) != 0)
+        return 2;
+    limit.rlim_max = 128;
+    errno = 0;
+    if (setrlimit(RLIMIT_NOFILE, &limit) != -1 || errno != EPERM)
+        return 3;
+    // nor touch the limits of another user
+    errno = 0;
+    if (prlimit(getppid(), RLIMIT_NOFILE, NULL, &limit) != -1 || errno != EPERM)
+        return 4;
+    return 0;
+}
+
+int cpu_limit(void)
+{
+    struct rlimit limit = {.rlim_cur = 1, .rlim_max = 10};
+    setrlimit(RLIMIT_CPU, &limit);
+    busy(3000);
+    return 0;
+}
+
+int main(void)
+{
+    // getrlimit and setrlimit checks
+    struct rlimit limit;
+    assert(getrlimit(RLIMIT_NOFILE, &limit) == 0 && limit.rlim_cur <= limit.rlim_max);
+    struct rlimit bad = {.rlim_cur = 2, .rlim_max = 1};
+    errno = 0;
+    assert(setrlimit(RLIMIT_NOFILE, &bad) == -1 && errno == EINVAL);
+    errno = 0;
+    assert(getrlimit(100, &limit) == -1 && errno == EINVAL);
+
+    // prlimit on a child reads and sets its limits
+    int ready[2];
+    assert(pipe(ready) == 0);
+    pid_t pid = fork();
+    if (pid == 0)
+    {
+        char c;
+        close(ready[1]);
+        read(ready[0], &c, 1);
+        getrlimit(RLIMIT_NOFILE, &limit);
+        _exit(limit.rlim_cur == 20 ? 0 : 1);
+    }
+    struct rlimit child_limit = {.rlim_cur = 20, .rlim_max = 20};
+    struct rlimit old;
+    assert(prlimit(pid, RLIMIT_NOFILE, &child_limit, &old) == 0);
+    assert(prlimit(pid, RLIMIT_NOFILE, NULL, &limit) == 0 && limit.rlim_cur == 20);
+    assert(write(ready[1], "x", 1) == 1);
+    int status = wait_child(pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    errno = 0;
+    assert(prlimit(pid, RLIMIT_NOFILE, NULL, &limit) == -1 && errno == ESRCH);
+
+    assert(run(nofile_limit) == 0);
+    assert(run(fsize_limit) == 0);
+    unlink(FSIZE_FILE);
+    pid = fork();
+    if (pid == 0)
+        _exit(fsize_signal());
+    status = wait_child(pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGXFSZ);
+    unlink(FSIZE_FILE);
+    assert(run(as_limit) == 0);
+    assert(run(nproc_limit) == 0);
+    assert(run(unprivileged_limits) == 0);
+    pid = fork();
+    if (pid == 0)
+        _exit(cpu_limit());
+    status = wait_child(pid);
+    assert(WIFSIGNALED(status) && WTERMSIG(status) == SIGXCPU);
+
+    // getrusage counts the CPU time of the process and of its waited children
+    struct rusage usage;
+    busy(200);
+    assert(getrusage(RUSAGE_SELF, &usage) == 0);
+    assert(cpu_ms(&usage) >= 150);
+    assert(usage.ru_maxrss > 0);
+    assert(getrusage(RUSAGE_CHILDREN, &usage) == 0);
+    assert(cpu_ms(&usage) >= 1000);
+    errno = 0;
+    assert(getrusage(5, &usage) == -1 && errno == EINVAL);
+
+    // sysinfo reports the memory and the processes
+    struct sysinfo info;
+    assert(sysinfo(&info) == 0);
+    assert(info.totalram > 0 && info.freeram <= info.totalram);
+    assert(info.mem_unit >= 1 && info.procs >= 1);
+
+    printf("limit tests passed\n");
+    return 0;
+}
@@ -0,0 +1,63 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/ipc.h>
+#include <sys/shm.h>
+#include <sys/mman.h>
+
+int main(int argc, char **argv)
+{
+    long page = sysconf(_SC_PAGESIZE);
+
+    // private anonymous memory reads as zero once dropped
+    char *anon = mmap(NULL, 2 * page, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(anon != MAP_FAILED);
+    anon[0] = 1;
+    anon[page] = 2;
+    assert(madvise(anon, page, MADV_DONTNEED) == 0);
+    assert(anon[0] == 0 && anon[page] == 2);
+    assert(madvise(anon + page, page, MADV_FREE) == 0);
+    anon[page] = 3;
+    assert(anon[page] == 3);
+
+    // unmapped pages in the range
+    assert(munmap(anon + page, page) == 0);
+    errno = 0;
+    assert(madvise(anon, 2 * page, MADV_DONTNEED) == -1 && errno == ENOMEM);
+    assert(munmap(anon, page) == 0);
+
+    // shared memory keeps its content, it cannot be freed lazily
+    int shmid = shmget(IPC_PRIVATE, page, IPC_CREAT | 0600);
+    assert(shmid >= 0);
+    char *shm = shmat(shmid, NULL, 0);
+    assert(shm != (void *)-1);
+    strcpy(shm, "shared");
+    assert(madvise(shm, page, MADV_DONTNEED) == 0);
+    assert(strcmp(shm, "shared") == 0);
+    errno = 0;
+    assert(madvise(shm, page, MADV_FREE) == -1 && errno == EINVAL);
+    assert(strcmp(shm, "shared") == 0);
+    assert(shmdt(shm) == 0);
+    assert(shmctl(shmid, IPC_RMID, NULL) == 0);
+
+    // a file mapping keeps the content of the file
+    int fd = open("/tmp/testmadvise", O_RDWR | O_CREAT | O_TRUNC, 0600);
+    assert(fd >= 0);
+    assert(write(fd, "file", 4) == 4);
+    char *file = mmap(NULL, page, PROT_READ, MAP_PRIVATE, fd, 0);
+    assert(file != MAP_FAILED);
+    assert(memcmp(file, "file", 4) == 0);
+    assert(madvise(file, page, MADV_DONTNEED) == 0);
+    assert(memcmp(file, "file", 4) == 0);
+    errno = 0;
+    assert(madvise(file, page, MADV_FREE) == -1 && errno == EINVAL);
+    assert(munmap(file, page) == 0);
+    close(fd);
+    unlink("/tmp/testmadvise");
+
+    printf("madvise tests passed\n");
+    return 0;
+}
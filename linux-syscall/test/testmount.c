@@ -0,0 +1,45 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/stat.h>
+#include <sys/mount.h>
+
+int main(int argc, char **argv)
+{
+    // nothing can be changed in a read-only mount
+    assert(mkdir("/tmp/testmount_ro", 0755) == 0 || errno == EEXIST);
+    assert(mount("tmpfs", "/tmp/testmount_ro", "tmpfs", MS_RDONLY, NULL) == 0);
+    errno = 0;
+    assert(open("/tmp/testmount_ro/file", O_WRONLY | O_CREAT, 0644) == -1 && errno == EROFS);
+    errno = 0;
+    assert(mkdir("/tmp/testmount_ro/dir", 0755) == -1 && errno == EROFS);
+    errno = 0;
+    assert(symlink("/", "/tmp/testmount_ro/link") == -1 && errno == EROFS);
+    errno = 0;
+    assert(chmod("/tmp/testmount_ro", 0700) == -1 && errno == EROFS);
+    int fd = open("/tmp/testmount_ro", O_RDONLY | O_DIRECTORY);
+    assert(fd >= 0);
+    close(fd);
+    assert(umount("/tmp/testmount_ro") == 0);
+    assert(rmdir("/tmp/testmount_ro") == 0);
+
+    // programs cannot run from a noexec mount
+    assert(mkdir("/tmp/testmount_noexec", 0755) == 0 || errno == EEXIST);
+    assert(mount("tmpfs", "/tmp/testmount_noexec", "tmpfs", MS_NOEXEC, NULL) == 0);
+    fd = open("/tmp/testmount_noexec/script", O_WRONLY | O_CREAT, 0755);
+    assert(fd >= 0);
+    assert(write(fd, "#!/bin/sh\nexit 0\n", 17) == 17);
+    close(fd);
+    char *args[] = {"/tmp/testmount_noexec/script", NULL};
+    char *envp[] = {NULL};
+    errno = 0;
+    assert(execve(args[0], args, envp) == -1 && errno == EACCES);
+    assert(unlink("/tmp/testmount_noexec/script") == 0);
+    assert(umount("/tmp/testmount_noexec") == 0);
+    assert(rmdir("/tmp/testmount_noexec") == 0);
+
+    printf("mount tests passed\n");
+    return 0;
+}
@@ -0,0 +1,110 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+#include <assert.h>
+#include <dirent.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+
+#define SECRET "/tmp/testpath_secret"
+#define FILE_LINK "/tmp/testpath_link"
+#define DIR_LINK "/tmp/testpath_dirlink"
+#define LOOP_A "/tmp/testpath_loop_a"
+#define LOOP_B "/tmp/testpath_loop_b"
+
+int denied(const char *path)
+{
+    errno = 0;
+    int fd = open(path, O_RDONLY);
+    if (fd >= 0)
+        close(fd);
+    return fd == -1 && errno == EACCES;
+}
+
+// an unprivileged user may not reach below a directory it cannot search
+int unprivileged_walk(void)
+{
+    if (setuid(1000) != 0)
+        return 1;
+    struct stat st;
+    if (!denied(SECRET "/file") || !denied(SECRET "/../testpath_secret/file"))
+        return 2;
+    // neither through symbolic links to the file or to the directory
+    if (!denied(FILE_LINK) || !denied(DIR_LINK "/file"))
+        return 3;
+    // the directory itself only needs search permission on its parent
+    if (stat(SECRET, &st) != 0 || stat(DIR_LINK, &st) != 0)
+        return 4;
+    // nor may it open the directory up
+    if (chmod(SECRET, 0711) != -1 || errno != EPERM)
+        return 5;
+    return 0;
+}
+
+// with search but no read permission, known names are reachable but not listed
+int search_only_walk(void)
+{
+    if (setuid(1000) != 0)
+        return 1;
+    int fd = open(FILE_LINK, O_RDONLY);
+    if (fd < 0)
+        return 2;
+    close(fd);
+    errno = 0;
+    if (opendir(SECRET) != NULL || errno != EACCES)
+        return 3;
+    return 0;
+}
+
+int run_as_user(int (*fn)(void))
+{
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(fn());
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status));
+    return WEXITSTATUS(status);
+}
+
+int main(void)
+{
+    unlink(SECRET "/file");
+    rmdir(SECRET);
+    unlink(FILE_LINK);
+    unlink(DIR_LINK);
+    unlink(LOOP_A);
+    unlink(LOOP_B);
+
+    assert(mkdir(SECRET, 0700) == 0);
+    int fd = open(SECRET "/file", O_CREAT | O_WRONLY, 0644);
+    assert(fd >= 0);
+    close(fd);
+    assert(symlink(SECRET "/file", FILE_LINK) == 0);
+    assert(symlink(SECRET, DIR_LINK) == 0);
+
+    assert(run_as_user(unprivileged_walk) == 0);
+    assert(chmod(SECRET, 0711) == 0);
+    assert(run_as_user(search_only_walk) == 0);
+
+    // links pointing at each other
+    assert(symlink(LOOP_B, LOOP_A) == 0);
+    assert(symlink(LOOP_A, LOOP_B) == 0);
+    errno = 0;
+    assert(open(LOOP_A, O_RDONLY) == -1 && errno == ELOOP);
+    errno = 0;
+    assert(open(FILE_LINK, O_RDONLY | O_NOFOLLOW) == -1 && errno == ELOOP);
+    errno = 0;
+    assert(open(FILE_LINK "/", O_RDONLY) == -1 && errno == ENOTDIR);
+
+    unlink(LOOP_A);
+    unlink(LOOP_B);
+    unlink(FILE_LINK);
+    unlink(DIR_LINK);
+    unlink(SECRET "/file");
+    rmdir(SECRET);
+    printf("path tests passed\n");
+    return 0;
+}
@@ -0,0 +1,107 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <dirent.h>
+#include <time.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+
+#define RSS_GROWTH (8 << 20)
+
+char buf[4096];
+
+const char *read_file(const char *path)
+{
+    int fd = open(path, O_RDONLY);
+    assert(fd >= 0);
+    ssize_t len = read(fd, buf, sizeof(buf) - 1);
+    assert(len > 0);
+    buf[len] = 0;
+    close(fd);
+    return buf;
+}
+
+// VmRSS of /proc/self/status in kB
+long vm_rss(void)
+{
+    const char *line = strstr(read_file("/proc/self/status"), "VmRSS:");
+    assert(line != NULL);
+    return atol(line + strlen("VmRSS:"));
+}
+
+// utime plus stime of /proc/self/stat in clock ticks
+long cpu_ticks(void)
+{
+    // the fields after the command name, which may hold spaces
+    const char *fields = strrchr(read_file("/proc/self/stat"), ')');
+    assert(fields != NULL);
+    long utime, stime;
+    assert(sscanf(fields, ") %*c %*d %*d %*d %*d %*d %*u %*u %*u %*u %*u %ld %ld",
+                  &utime, &stime) == 2);
+    return utime + stime;
+}
+
+// another user sees the status of the parent, but neither its files nor its memory
+int other_user(pid_t parent)
+{
+    char path[64];
+    if (setuid(1000) != 0)
+        return 1;
+    snprintf(path, sizeof(path), "/proc/%d/status", parent);
+    int fd = open(path, O_RDONLY);
+    if (fd < 0)
+        return 2;
+    close(fd);
+    snprintf(path, sizeof(path), "/proc/%d/maps", parent);
+    errno = 0;
+    if (open(path, O_RDONLY) != -1 || errno != EACCES)
+        return 3;
+    snprintf(path, sizeof(path), "/proc/%d/fd", parent);
+    errno = 0;
+    if (opendir(path) != NULL || errno != EACCES)
+        return 4;
+    snprintf(path, sizeof(path), "/proc/%d/fd/0", parent);
+    errno = 0;
+    if (open(path, O_RDONLY) != -1 || errno != EACCES)
+        return 5;
+    return 0;
+}
+
+int main(void)
+{
+    // resident memory counts the pages committed, not those mapped
+    long rss = vm_rss();
+    char *mem = mmap(NULL, RSS_GROWTH, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    assert(mem != MAP_FAILED);
+    assert(vm_rss() < rss + RSS_GROWTH / 1024 / 2);
+    memset(mem, 1, RSS_GROWTH);
+    assert(vm_rss() >= rss + RSS_GROWTH / 1024);
+    munmap(mem, RSS_GROWTH);
+
+    // CPU time shows up in ticks of 10ms
+    struct timespec start, now;
+    clock_gettime(CLOCK_MONOTONIC, &start);
+    do
+    {
+        clock_gettime(CLOCK_MONOTONIC, &now);
+    } while ((now.tv_sec - start.tv_sec) * 1000000000L + now.tv_nsec - start.tv_nsec < 200000000L);
+    assert(cpu_ticks() >= 10);
+
+    // the parent owns its entries
+    assert(opendir("/proc/self/fd") != NULL);
+    read_file("/proc/self/maps");
+    pid_t parent = getpid();
+    pid_t pid = fork();
+    if (pid == 0)
+        _exit(other_user(parent));
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    printf("procfs tests passed\n");
+    return 0;
+}
@@ -0,0 +1,86 @@
+#include <errno.h>
+#include <stdio.h>
+#include <signal.h>
+#include <unistd.h>
+#include <assert.h>
+#include <pthread.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+
+struct shared
+{
+    pthread_mutex_t main_lock;
+    pthread_mutex_t thread_lock;
+    volatile int locked;
+};
+
+struct shared *shm;
+
+void init_lock(pthread_mutex_t *lock)
+{
+    pthread_mutexattr_t attr;
+    pthread_mutexattr_init(&attr);
+    pthread_mutexattr_setpshared(&attr, PTHREAD_PROCESS_SHARED);
+    pthread_mutexattr_setrobust(&attr, PTHREAD_MUTEX_ROBUST);
+    assert(pthread_mutex_init(lock, &attr) == 0);
+    pthread_mutexattr_destroy(&attr);
+}
+
+void *hold_lock(void *arg)
+{
+    assert(pthread_mutex_lock(&shm->thread_lock) == 0);
+    __sync_fetch_and_add(&shm->locked, 1);
+    for (;;)
+        pause();
+    return NULL;
+}
+
+// the child holds a lock in each of its two threads, then dies by `how`
+void run_child(int how)
+{
+    shm->locked = 0;
+    init_lock(&shm->main_lock);
+    init_lock(&shm->thread_lock);
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        pthread_t thread;
+        assert(pthread_mutex_lock(&shm->main_lock) == 0);
+        assert(pthread_create(&thread, NULL, hold_lock, NULL) == 0);
+        while (shm->locked == 0)
+            usleep(1000);
+        if (how == 0)
+            _exit(0);
+        for (;;)
+            pause();
+    }
+    while (shm->locked == 0)
+        usleep(1000);
+    if (how != 0)
+        assert(kill(pid, SIGKILL) == 0);
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+
+    // both locks are released with their owner marked dead
+    assert(pthread_mutex_lock(&shm->main_lock) == EOWNERDEAD);
+    assert(pthread_mutex_consistent(&shm->main_lock) == 0);
+    assert(pthread_mutex_unlock(&shm->main_lock) == 0);
+    assert(pthread_mutex_lock(&shm->thread_lock) == EOWNERDEAD);
+    assert(pthread_mutex_consistent(&shm->thread_lock) == 0);
+    assert(pthread_mutex_unlock(&shm->thread_lock) == 0);
+}
+
+int main(int argc, char **argv)
+{
+    shm = mmap(NULL, sizeof(struct shared), PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    assert(shm != MAP_FAILED);
+
+    // exit_group
+    run_child(0);
+    // killed by a signal sent from another process
+    run_child(1);
+
+    printf("robust futex tests passed\n");
+    return 0;
+}
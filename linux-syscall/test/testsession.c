@@ -0,0 +1,124 @@
+#include <errno.h>
+#include <stdio.h>
+#include <signal.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/ioctl.h>
+#include <sys/wait.h>
+
+// the standard input is the controlling terminal of the session led by the test
+
+int wait_child(pid_t pid)
+{
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status));
+    return WEXITSTATUS(status);
+}
+
+// a new session has no controlling terminal and may not take one in use
+int new_session(void)
+{
+    pid_t sid = setsid();
+    if (sid != getpid() || getsid(0) != sid || getpgrp() != sid)
+        return 1;
+    errno = 0;
+    if (tcgetpgrp(0) != -1 || errno != ENOTTY)
+        return 2;
+    errno = 0;
+    if (ioctl(0, TIOCSCTTY, 0) != -1 || errno != EPERM)
+        return 3;
+    // stealing the terminal needs privilege
+    if (setuid(1000) != 0)
+        return 4;
+    errno = 0;
+    if (ioctl(0, TIOCSCTTY, 1) != -1 || errno != EPERM)
+        return 5;
+    return 0;
+}
+
+// a process that is not the leader may only leave the terminal itself
+int leave_terminal(void)
+{
+    if (tcgetpgrp(0) != getsid(0))
+        return 1;
+    if (ioctl(0, TIOCNOTTY) != 0)
+        return 2;
+    errno = 0;
+    if (tcgetpgrp(0) != -1 || errno != ENOTTY)
+        return 3;
+    return 0;
+}
+
+int main(void)
+{
+    pid_t pid = getpid();
+    assert(getsid(0) == pid);
+    pid_t sid = 0;
+    assert(ioctl(0, TIOCGSID, &sid) == 0 && sid == pid);
+    assert(tcgetpgrp(0) == getpgrp());
+    errno = 0;
+    assert(setsid() == -1 && errno == EPERM);
+
+    // move a new process group to the foreground and back
+    signal(SIGTTOU, SIG_IGN);
+    int ready[2], done[2];
+    assert(pipe(ready) == 0 && pipe(done) == 0);
+    pid_t child = fork();
+    if (child == 0)
+    {
+        char c = 0;
+        close(done[1]);
+        setpgid(0, 0);
+        write(ready[1], &c, 1);
+        read(done[0], &c, 1);
+        _exit(0);
+    }
+    char c;
+    assert(read(ready[0], &c, 1) == 1);
+    assert(getpgid(child) == child);
+    assert(tcsetpgrp(0, child) == 0);
+    assert(tcgetpgrp(0) == child);
+    assert(tcsetpgrp(0, pid) == 0);
+    assert(tcgetpgrp(0) == pid);
+    errno = 0;
+    assert(tcsetpgrp(0, 0x7fff0000) == -1 && errno == ESRCH);
+    assert(write(done[1], &c, 1) == 1);
+    assert(wait_child(child) == 0);
+
+    child = fork();
+    if (child == 0)
+        _exit(new_session());
+    assert(wait_child(child) == 0);
+    // the terminal still belongs to the session of the test
+    assert(ioctl(0, TIOCGSID, &sid) == 0 && sid == pid);
+
+    // a process in another session cannot join a group of this one,
+    // nor can its group take the terminal
+    child = fork();
+    if (child == 0)
+    {
+        close(done[1]);
+        setsid();
+        write(ready[1], &c, 1);
+        read(done[0], &c, 1);
+        _exit(0);
+    }
+    assert(read(ready[0], &c, 1) == 1);
+    assert(getsid(child) == child);
+    errno = 0;
+    assert(setpgid(child, pid) == -1 && errno == EPERM);
+    errno = 0;
+    assert(tcsetpgrp(0, child) == -1 && errno == EPERM);
+    assert(write(done[1], &c, 1) == 1);
+    assert(wait_child(child) == 0);
+
+    child = fork();
+    if (child == 0)
+        _exit(leave_terminal());
+    assert(wait_child(child) == 0);
+    assert(tcgetpgrp(0) == pid);
+
+    printf("session tests passed\n");
+    return 0;
+}
@@ -0,0 +1,128 @@
+#include <errno.h>
+#include <limits.h>
+#include <stdio.h>
+#include <signal.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/wait.h>
+#include <sys/epoll.h>
+
+volatile sig_atomic_t handled = 0;
+volatile int info_signo, info_code, info_pid;
+volatile int usr2_blocked;
+volatile int on_alt_stack;
+char *alt_stack;
+
+void on_usr1(int sig, siginfo_t *info, void *ucontext)
+{
+    handled++;
+    info_signo = info->si_signo;
+    info_code = info->si_code;
+    info_pid = info->si_pid;
+    // the signals in sa_mask are blocked while the handler runs
+    sigset_t current;
+    sigprocmask(SIG_BLOCK, NULL, &current);
+    usr2_blocked = sigismember(&current, SIGUSR2);
+}
+
+void on_usr2(int sig)
+{
+    handled++;
+    char local;
+    on_alt_stack = &local >= alt_stack && &local < alt_stack + SIGSTKSZ;
+    stack_t ss;
+    sigaltstack(NULL, &ss);
+    on_alt_stack = on_alt_stack && (ss.ss_flags & SS_ONSTACK);
+}
+
+void on_alarm(int sig)
+{
+    handled++;
+}
+
+int main(int argc, char **argv)
+{
+    // delivery: the handler gets the sender and blocks sa_mask
+    struct sigaction sa = {0};
+    sa.sa_sigaction = on_usr1;
+    sa.sa_flags = SA_SIGINFO;
+    sigemptyset(&sa.sa_mask);
+    sigaddset(&sa.sa_mask, SIGUSR2);
+    assert(sigaction(SIGUSR1, &sa, NULL) == 0);
+    assert(kill(getpid(), SIGUSR1) == 0);
+    assert(handled == 1);
+    assert(info_signo == SIGUSR1 && info_code == SI_USER && info_pid == getpid());
+    assert(usr2_blocked);
+
+    // sigreturn restores the mask the handler was entered with
+    sigset_t set, current;
+    assert(sigprocmask(SIG_BLOCK, NULL, &current) == 0);
+    assert(!sigismember(&current, SIGUSR2));
+
+    // a blocked signal stays pending until it is unblocked
+    sigemptyset(&set);
+    sigaddset(&set, SIGUSR1);
+    assert(sigprocmask(SIG_BLOCK, &set, NULL) == 0);
+    assert(kill(getpid(), SIGUSR1) == 0);
+    assert(handled == 1);
+    assert(sigprocmask(SIG_UNBLOCK, &set, NULL) == 0);
+    assert(handled == 2);
+
+    // sigaltstack: the handler runs on the alternate stack
+    alt_stack = malloc(SIGSTKSZ);
+    stack_t ss = {.ss_sp = alt_stack, .ss_size = SIGSTKSZ, .ss_flags = 0};
+    assert(sigaltstack(&ss, NULL) == 0);
+    memset(&sa, 0, sizeof(sa));
+    sa.sa_handler = on_usr2;
+    sa.sa_flags = SA_ONSTACK;
+    assert(sigaction(SIGUSR2, &sa, NULL) == 0);
+    assert(raise(SIGUSR2) == 0);
+    assert(handled == 3 && on_alt_stack);
+    // the alternate stack is left once the handler returns
+    assert(sigaltstack(NULL, &ss) == 0);
+    assert(!(ss.ss_flags & SS_ONSTACK));
+
+    // a pending signal that becomes ignored does not interrupt a wait
+    signal(SIGALRM, on_alarm);
+    sigemptyset(&set);
+    sigaddset(&set, SIGALRM);
+    assert(sigprocmask(SIG_BLOCK, &set, NULL) == 0);
+    assert(kill(getpid(), SIGALRM) == 0);
+    signal(SIGALRM, SIG_IGN);
+    int epfd = epoll_create1(0);
+    assert(epfd >= 0);
+    struct epoll_event ev;
+    sigemptyset(&set);
+    assert(epoll_pwait(epfd, &ev, 1, 20, &set) == 0);
+    assert(handled == 3);
+    close(epfd);
+
+    // kill: bad process groups and permissions
+    errno = 0;
+    assert(kill(INT_MIN, 0) == -1 && errno == ESRCH);
+    assert(kill(getpid(), 0) == 0);
+    pid_t parent = getpid();
+    pid_t pid = fork();
+    assert(pid >= 0);
+    if (pid == 0)
+    {
+        if (setuid(1000) != 0)
+            _exit(1);
+        errno = 0;
+        if (kill(parent, SIGUSR1) != -1 || errno != EPERM)
+            _exit(2);
+        // a process may signal itself
+        if (kill(getpid(), 0) != 0)
+            _exit(3);
+        _exit(0);
+    }
+    int status;
+    assert(waitpid(pid, &status, 0) == pid);
+    assert(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    assert(handled == 3);
+
+    printf("signal tests passed\n");
+    return 0;
+}
@@ -0,0 +1,94 @@
+#include <time.h>
+#include <stdio.h>
+#include <errno.h>
+#include <limits.h>
+#include <signal.h>
+#include <stdint.h>
+#include <unistd.h>
+#include <assert.h>
+#include <sys/time.h>
+#include <sys/timerfd.h>
+
+volatile sig_atomic_t alarms = 0;
+
+void on_alarm(int sig)
+{
+    alarms++;
+}
+
+int main(int argc, char **argv)
+{
+    signal(SIGALRM, on_alarm);
+
+    // setitimer: a periodic timer fires more than once
+    struct itimerval it = {{0, 20000}, {0, 20000}};
+    struct itimerval old;
+    assert(setitimer(ITIMER_REAL, &it, NULL) == 0);
+    while (alarms < 3)
+        pause();
+    struct itimerval off = {{0, 0}, {0, 0}};
+    assert(setitimer(ITIMER_REAL, &off, &old) == 0);
+    assert(old.it_interval.tv_usec == 20000);
+
+    // setitimer: invalid and out of range values
+    struct itimerval bad = {{0, 0}, {0, 1000000}};
+    errno = 0;
+    assert(setitimer(ITIMER_REAL, &bad, NULL) == -1 && errno == EINVAL);
+    bad.it_value.tv_sec = -1;
+    bad.it_value.tv_usec = 0;
+    errno = 0;
+    assert(setitimer(ITIMER_REAL, &bad, NULL) == -1 && errno == EINVAL);
+    struct itimerval far = {{0, 0}, {LONG_MAX, 0}};
+    assert(setitimer(ITIMER_REAL, &far, NULL) == 0);
+    assert(getitimer(ITIMER_REAL, &old) == 0);
+    assert(old.it_value.tv_sec > 1000000);
+    // re-arming replaces the far deadline
+    int before = alarms;
+    struct itimerval soon = {{0, 0}, {0, 10000}};
+    assert(setitimer(ITIMER_REAL, &soon, NULL) == 0);
+    while (alarms == before)
+        pause();
+    assert(getitimer(ITIMER_REAL, &old) == 0);
+    assert(old.it_value.tv_sec == 0 && old.it_value.tv_usec == 0);
+
+    // timer_create/timer_settime
+    timer_t timer;
+    struct sigevent sev = {0};
+    sev.sigev_notify = SIGEV_SIGNAL;
+    sev.sigev_signo = SIGALRM;
+    assert(timer_create(CLOCK_MONOTONIC, &sev, &timer) == 0);
+    struct itimerspec ts = {{0, 0}, {0, 10000000}};
+    before = alarms;
+    assert(timer_settime(timer, 0, &ts, NULL) == 0);
+    while (alarms == before)
+        pause();
+    struct itimerspec bad_ts = {{0, 0}, {0, 1000000000}};
+    errno = 0;
+    assert(timer_settime(timer, 0, &bad_ts, NULL) == -1 && errno == EINVAL);
+    bad_ts.it_value.tv_sec = -1;
+    bad_ts.it_value.tv_nsec = 0;
+    errno = 0;
+    assert(timer_settime(timer, 0, &bad_ts, NULL) == -1 && errno == EINVAL);
+    struct itimerspec far_ts = {{0, 0}, {LONG_MAX, 0}};
+    assert(timer_settime(timer, 0, &far_ts, NULL) == 0);
+    assert(timer_delete(timer) == 0);
+
+    // timerfd: expirations are counted and read
+    int fd = timerfd_create(CLOCK_MONOTONIC, 0);
+    assert(fd >= 0);
+    struct itimerspec tfd = {{0, 10000000}, {0, 10000000}};
+    assert(timerfd_settime(fd, 0, &tfd, NULL) == 0);
+    uint64_t expirations = 0;
+    assert(read(fd, &expirations, sizeof(expirations)) == sizeof(expirations));
+    assert(expirations >= 1);
+    errno = 0;
+    assert(timerfd_settime(fd, 0, &bad_ts, NULL) == -1 && errno == EINVAL);
+    assert(timerfd_settime(fd, 0, &far_ts, NULL) == 0);
+    struct itimerspec cur;
+    assert(timerfd_gettime(fd, &cur) == 0);
+    assert(cur.it_value.tv_sec > 1000000);
+    close(fd);
+
+    printf("timer tests passed\n");
+    return 0;
+}
@@ -0,0 +1,126 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+#include <assert.h>
+#include <stddef.h>
+#include <pthread.h>
+#include <sys/un.h>
+#include <sys/socket.h>
+
+// send `fd` with SCM_RIGHTS along with one byte of data
+void send_fd(int sock, int fd)
+{
+    char byte = 'f';
+    struct iovec iov = {&byte, 1};
+    char control[CMSG_SPACE(sizeof(int))];
+    struct msghdr msg = {0};
+    msg.msg_iov = &iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control;
+    msg.msg_controllen = sizeof(control);
+    struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+    cmsg->cmsg_level = SOL_SOCKET;
+    cmsg->cmsg_type = SCM_RIGHTS;
+    cmsg->cmsg_len = CMSG_LEN(sizeof(int));
+    memcpy(CMSG_DATA(cmsg), &fd, sizeof(int));
+    assert(sendmsg(sock, &msg, 0) == 1);
+}
+
+// receive a file descriptor sent by `send_fd`
+int recv_fd(int sock)
+{
+    char byte;
+    struct iovec iov = {&byte, 1};
+    char control[CMSG_SPACE(sizeof(int))];
+    struct msghdr msg = {0};
+    msg.msg_iov = &iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control;
+    msg.msg_controllen = sizeof(control);
+    assert(recvmsg(sock, &msg, 0) == 1 && byte == 'f');
+    struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+    assert(cmsg != NULL);
+    assert(cmsg->cmsg_level == SOL_SOCKET && cmsg->cmsg_type == SCM_RIGHTS);
+    int fd;
+    memcpy(&fd, CMSG_DATA(cmsg), sizeof(int));
+    return fd;
+}
+
+void *send_later(void *arg)
+{
+    int sock = *(int *)arg;
+    usleep(20000);
+    assert(write(sock, "ping", 4) == 4);
+    return NULL;
+}
+
+int main(int argc, char **argv)
+{
+    // socketpair: data flows both ways and EOF follows the close of the peer
+    int sv[2];
+    char buf[16];
+    assert(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+    assert(write(sv[0], "hello", 5) == 5);
+    assert(read(sv[1], buf, sizeof(buf)) == 5 && memcmp(buf, "hello", 5) == 0);
+    assert(write(sv[1], "world", 5) == 5);
+    assert(read(sv[0], buf, sizeof(buf)) == 5 && memcmp(buf, "world", 5) == 0);
+
+    // a thread blocked receiving does not keep others from using the socket
+    pthread_t thread;
+    assert(pthread_create(&thread, NULL, send_later, &sv[0]) == 0);
+    struct iovec iov = {buf, sizeof(buf)};
+    struct msghdr msg = {0};
+    msg.msg_iov = &iov;
+    msg.msg_iovlen = 1;
+    assert(recvmsg(sv[1], &msg, 0) == 4 && memcmp(buf, "ping", 4) == 0);
+    assert(pthread_join(thread, NULL) == 0);
+    errno = 0;
+    assert(recv(sv[1], buf, sizeof(buf), MSG_DONTWAIT) == -1 && errno == EAGAIN);
+
+    // SCM_RIGHTS: the received descriptor refers to the same pipe
+    int pipefd[2];
+    assert(pipe(pipefd) == 0);
+    send_fd(sv[0], pipefd[1]);
+    int passed = recv_fd(sv[1]);
+    assert(passed >= 0 && passed != pipefd[1]);
+    assert(write(passed, "pipe", 4) == 4);
+    assert(read(pipefd[0], buf, sizeof(buf)) == 4 && memcmp(buf, "pipe", 4) == 0);
+    close(passed);
+    close(pipefd[0]);
+    close(pipefd[1]);
+    close(sv[0]);
+    assert(read(sv[1], buf, sizeof(buf)) == 0);
+    close(sv[1]);
+
+    // abstract names: bound without a file and taken only once
+    struct sockaddr_un addr = {0};
+    addr.sun_family = AF_UNIX;
+    memcpy(addr.sun_path, "\0zcore-test", 11);
+    socklen_t len = offsetof(struct sockaddr_un, sun_path) + 11;
+    int server = socket(AF_UNIX, SOCK_STREAM, 0);
+    assert(server >= 0);
+    assert(bind(server, (struct sockaddr *)&addr, len) == 0);
+    assert(listen(server, 1) == 0);
+    int other = socket(AF_UNIX, SOCK_STREAM, 0);
+    errno = 0;
+    assert(bind(other, (struct sockaddr *)&addr, len) == -1 && errno == EADDRINUSE);
+    close(other);
+    int client = socket(AF_UNIX, SOCK_STREAM, 0);
+    assert(connect(client, (struct sockaddr *)&addr, len) == 0);
+    int conn = accept(server, NULL, NULL);
+    assert(conn >= 0);
+    assert(write(client, "abs", 3) == 3);
+    assert(read(conn, buf, sizeof(buf)) == 3 && memcmp(buf, "abs", 3) == 0);
+    struct sockaddr_un peer;
+    socklen_t peer_len = sizeof(peer);
+    assert(getpeername(client, (struct sockaddr *)&peer, &peer_len) == 0);
+    assert(peer_len == len && memcmp(peer.sun_path, addr.sun_path, 11) == 0);
+    close(conn);
+    close(client);
+    close(server);
+
+    printf("unix socket tests passed\n");
+    return 0;
+}
@@ -0,0 +1,367 @@
+//! Host-side helpers for the zCore keystone driver (`/dev/keystone`).
+//!
+//! Enclave ids are only valid on the fd they were created through.
+
+use core::mem::size_of;
+
+use super::{open, sys_ioctl, sys_mmap_file, sys_ppoll, OpenFlags, PollFd, POLLIN};
+
+const KEYSTONE_PATH: &str = "/dev/keystone\0";
+
+const IOC_MAGIC: usize = 0xa4 << 8;
+const IOC_WRITE: usize = 1 << 30;
+const RUN_ENCLAVE: usize = IOC_MAGIC | 0x04;
+const RESUME_ENCLAVE: usize = IOC_MAGIC | 0x05;
+const UTM_INIT: usize = IOC_MAGIC | 0x07;
+const ATTEST_ENCLAVE: usize = IOC_MAGIC | 0x08;
+const GET_MEASUREMENT: usize = IOC_MAGIC | 0x09;
+const SET_THREADS: usize = IOC_MAGIC | 0x0a;
+const GET_LIMITS: usize = IOC_MAGIC | 0x0b;
+const SET_LIMITS: usize = IOC_MAGIC | 0x0c;
+
+const PROT_READ: usize = 1 << 0;
+const PROT_WRITE: usize = 1 << 1;
+const MAP_SHARED: usize = 1 << 0;
+
+pub const MDSIZE: usize = 64;
+pub const SIGNATURE_SIZE: usize = 64;
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const ATTEST_DATA_MAXLEN: usize = 1024;
+/// Edge call of the eapp getting the nonce to attest, served by the kernel
+pub const CALL_ID_ATTEST_NONCE: u64 = 0xa408_0001;
+/// Edge call of the eapp passing its `Report` for the nonce, served by the kernel
+pub const CALL_ID_ATTEST_REPORT: u64 = 0xa408_0002;
+/// Size of the SHA3-512 enclave measurement, as in the security monitor's reports
+pub const MEASUREMENT_SIZE: usize = MDSIZE;
+/// Most threads an enclave can be given with `set_threads`
+pub const ENCLAVE_THREADS_MAX: usize = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct EnclaveReport {
+    pub hash: [u8; MDSIZE],
+    pub data_len: u64,
+    pub data: [u8; ATTEST_DATA_MAXLEN],
+    pub signature: [u8; SIGNATURE_SIZE],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SmReport {
+    pub hash: [u8; MDSIZE],
+    pub public_key: [u8; PUBLIC_KEY_SIZE],
+    pub signature: [u8; SIGNATURE_SIZE],
+}
+
+/// Signed attestation report, to be checked by a remote verifier
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Report {
+    pub enclave: EnclaveReport,
+    pub sm: SmReport,
+    pub dev_public_key: [u8; PUBLIC_KEY_SIZE],
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Report {
+            enclave: EnclaveReport {
+                hash: [0; MDSIZE],
+                data_len: 0,
+                data: [0; ATTEST_DATA_MAXLEN],
+                signature: [0; SIGNATURE_SIZE],
+            },
+            sm: SmReport {
+                hash: [0; MDSIZE],
+                public_key: [0; PUBLIC_KEY_SIZE],
+                signature: [0; SIGNATURE_SIZE],
+            },
+            dev_public_key: [0; PUBLIC_KEY_SIZE],
+        }
+    }
+
+    /// The nonce echoed back by the security monitor
+    pub fn nonce(&self) -> &[u8] {
+        let len = (self.enclave.data_len as usize).min(ATTEST_DATA_MAXLEN);
+        &self.enclave.data[..len]
+    }
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why the kernel handed control back to the host
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ExitReason {
+    /// The enclave exited with `RunParams::value`
+    Exited,
+    /// The enclave waits for the edge call in the shared buffer to be served
+    EdgeCall,
+    /// The security monitor failed with `RunParams::error`
+    SmError,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct RunParams {
+    pub eid: usize,
+    pub error: usize,
+    pub value: usize,
+    exit_reason: usize,
+    pub tid: usize,
+}
+
+impl RunParams {
+    pub fn new(eid: usize) -> Self {
+        RunParams {
+            eid,
+            ..Default::default()
+        }
+    }
+
+    /// Run or resume the thread `tid` of the enclave `eid`
+    pub fn with_thread(eid: usize, tid: usize) -> Self {
+        RunParams {
+            eid,
+            tid,
+            ..Default::default()
+        }
+    }
+
+    pub fn exit_reason(&self) -> ExitReason {
+        match self.exit_reason {
+            0 => ExitReason::Exited,
+            1 => ExitReason::EdgeCall,
+            _ => ExitReason::SmError,
+        }
+    }
+}
+
+#[repr(C)]
+struct AttestParams {
+    eid: usize,
+    nonce_ptr: usize,
+    nonce_size: usize,
+    report_ptr: usize,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct RuntimeParams {
+    runtime_entry: usize,
+    user_entry: usize,
+    untrusted_ptr: usize,
+    untrusted_size: usize,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CreateParams {
+    eid: usize,
+    min_pages: usize,
+    runtime_vaddr: usize,
+    runtime_size: usize,
+    user_vaddr: usize,
+    user_size: usize,
+    utm_free_ptr: usize,
+    params: RuntimeParams,
+}
+
+#[repr(C)]
+struct ThreadsParams {
+    eid: usize,
+    threads: usize,
+}
+
+/// A soft and a hard limit, as `struct rlimit`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Limit {
+    pub cur: u64,
+    pub max: u64,
+}
+
+/// Limits on the enclaves of the process owning a keystone fd
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct EnclaveLimits {
+    /// Number of enclaves
+    pub enclaves: Limit,
+    /// EPM pages of all enclaves
+    pub epm_pages: Limit,
+}
+
+#[repr(C)]
+struct MeasureParams {
+    eid: usize,
+    hash: [u8; MEASUREMENT_SIZE],
+}
+
+const fn ioc_write(nr: usize, size: usize) -> usize {
+    IOC_WRITE | (size << 16) | nr
+}
+
+/// Open the keystone driver, with enclaves of its own.
+///
+/// Returns the fd, or a negative errno.
+pub fn open_keystone() -> isize {
+    open(KEYSTONE_PATH, OpenFlags::RDWR)
+}
+
+/// Open the keystone driver with runs going on in the background.
+///
+/// `run_enclave` and `resume_enclave` then fail with `-EAGAIN` until the enclave thread stops,
+/// which `wait_stopped` waits for. Repeat the same call to get the result.
+pub fn open_keystone_nonblock() -> isize {
+    open(KEYSTONE_PATH, OpenFlags::RDWR | OpenFlags::NONBLOCK)
+}
+
+/// Wait until a thread run in the background on `fd` has stopped.
+///
+/// Returns 1, or a negative errno.
+pub fn wait_stopped(fd: usize) -> isize {
+    let mut fds = [PollFd {
+        fd: fd as i32,
+        events: POLLIN,
+        revents: 0,
+    }];
+    sys_ppoll(&mut fds)
+}
+
+fn run_ioctl(fd: usize, nr: usize, params: &mut RunParams) -> isize {
+    sys_ioctl(
+        fd,
+        ioc_write(nr, size_of::<RunParams>()),
+        params as *mut RunParams as usize,
+    )
+}
+
+/// Start the thread `params.tid` of the enclave `params.eid`, returning once it exits or makes an edge call.
+///
+/// Other threads of the enclave may run from other host threads meanwhile.
+/// Returns 0 on success, or a negative errno (`-EPROTO` for a malformed edge call,
+/// `-EBUSY` if the thread is already running).
+pub fn run_enclave(fd: usize, params: &mut RunParams) -> isize {
+    run_ioctl(fd, RUN_ENCLAVE, params)
+}
+
+/// Resume the thread `params.tid` of the enclave `params.eid` after serving its edge call.
+pub fn resume_enclave(fd: usize, params: &mut RunParams) -> isize {
+    run_ioctl(fd, RESUME_ENCLAVE, params)
+}
+
+/// Ask the security monitor to attest the enclave `eid` with a non-empty `nonce`.
+///
+/// The emulated monitor attests from the host at once. A real monitor attests the
+/// enclave calling it, so the report is relayed by the eapp: the call fails with
+/// `-EAGAIN` and records the request, the eapp gets the nonce with the edge call
+/// `CALL_ID_ATTEST_NONCE`, has its runtime attest it and passes the report with
+/// `CALL_ID_ATTEST_REPORT`. The kernel serves both calls during a run of the enclave,
+/// repeat the same call afterwards to get the report.
+///
+/// Returns 0 on success, or a negative errno.
+pub fn attest_enclave(fd: usize, eid: usize, nonce: &[u8], report: &mut Report) -> isize {
+    let params = AttestParams {
+        eid,
+        nonce_ptr: nonce.as_ptr() as usize,
+        nonce_size: nonce.len(),
+        report_ptr: report as *mut Report as usize,
+    };
+    sys_ioctl(
+        fd,
+        ioc_write(ATTEST_ENCLAVE, size_of::<AttestParams>()),
+        &params as *const AttestParams as usize,
+    )
+}
+
+/// Fetch the measurement the kernel computed when finalizing the enclave `eid`.
+///
+/// Returns 0 on success, or a negative errno.
+pub fn get_measurement(fd: usize, eid: usize, hash: &mut [u8; MEASUREMENT_SIZE]) -> isize {
+    let mut params = MeasureParams {
+        eid,
+        hash: [0; MEASUREMENT_SIZE],
+    };
+    let ret = sys_ioctl(
+        fd,
+        ioc_write(GET_MEASUREMENT, size_of::<MeasureParams>()),
+        &mut params as *mut MeasureParams as usize,
+    );
+    if ret == 0 {
+        *hash = params.hash;
+    }
+    ret
+}
+
+/// Get the enclave limits of the process which opened `fd`.
+///
+/// Returns 0 on success, or a negative errno.
+pub fn get_enclave_limits(fd: usize, limits: &mut EnclaveLimits) -> isize {
+    sys_ioctl(
+        fd,
+        ioc_write(GET_LIMITS, size_of::<EnclaveLimits>()),
+        limits as *mut EnclaveLimits as usize,
+    )
+}
+
+/// Set the enclave limits of the process which opened `fd`.
+///
+/// As with `setrlimit`, only a privileged process may raise a hard limit.
+/// Returns 0 on success, or a negative errno.
+pub fn set_enclave_limits(fd: usize, limits: &EnclaveLimits) -> isize {
+    sys_ioctl(
+        fd,
+        ioc_write(SET_LIMITS, size_of::<EnclaveLimits>()),
+        limits as *const EnclaveLimits as usize,
+    )
+}
+
+/// Give the enclave `eid` a shared buffer of at least `size` bytes, which `mmap` of `fd` maps from then on.
+///
+/// Returns 0 on success, or a negative errno.
+pub fn utm_init(fd: usize, eid: usize, size: usize) -> isize {
+    let mut params = CreateParams {
+        eid,
+        params: RuntimeParams {
+            untrusted_size: size,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    sys_ioctl(
+        fd,
+        ioc_write(UTM_INIT, size_of::<CreateParams>()),
+        &mut params as *mut CreateParams as usize,
+    )
+}
+
+/// Map `size` bytes of the shared buffer of the enclave last given one on `fd`.
+///
+/// Host and enclave see the same pages, edge call data needs no copy.
+/// Returns the address of the buffer, or a negative errno.
+pub fn map_shared_buffer(fd: usize, size: usize) -> isize {
+    sys_mmap_file(0, size, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0)
+}
+
+/// Give the enclave `eid` `threads` entry threads, before it is finalized.
+///
+/// Every thread enters the eapp with its id in the first argument.
+/// Returns 0 on success, or a negative errno (`-EOPNOTSUPP` if the security monitor
+/// cannot run that many threads, the firmware monitor of Keystone runs a single one).
+pub fn set_threads(fd: usize, eid: usize, threads: usize) -> isize {
+    let params = ThreadsParams { eid, threads };
+    sys_ioctl(
+        fd,
+        ioc_write(SET_THREADS, size_of::<ThreadsParams>()),
+        &params as *const ThreadsParams as usize,
+    )
+}
+
+/// Offset and size of the part of a shared buffer of `size` bytes holding the edge calls of thread `tid`.
+pub fn thread_buffer(size: usize, threads: usize, tid: usize) -> (usize, usize) {
+    let slot = (size / threads) & !(size_of::<u64>() - 1);
+    (tid * slot, slot)
+}
@@ -5,6 +5,7 @@
 
 #[macro_use]
 pub mod console;
+pub mod keystone;
 mod lang_items;
 mod syscall;
 
@@ -59,6 +60,7 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NONBLOCK = 1 << 11;
     }
 }
 
@@ -108,6 +110,17 @@ impl Default for Stat {
     }
 }
 
+/// Poll input `POLLIN`
+pub const POLLIN: u16 = 0x0001;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
 bitflags! {
     pub struct StatMode: u32 {
         const NULL  = 0;
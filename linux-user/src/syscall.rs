@@ -1,6 +1,6 @@
 use core::arch::asm;
 
-use super::{Stat, TimeVal};
+use super::{PollFd, Stat, TimeVal};
 
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
@@ -23,7 +23,9 @@ pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
 pub const SYSCALL_DUP: usize = 24;
+pub const SYSCALL_IOCTL: usize = 29;
 pub const SYSCALL_PIPE: usize = 59;
+pub const SYSCALL_PPOLL: usize = 73;
 
 pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -164,6 +166,10 @@ pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
     syscall(SYSCALL_MMAP, [start, len, prot])
 }
 
+pub fn sys_mmap_file(start: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    syscall6(SYSCALL_MMAP, [start, len, prot, flags, fd, offset])
+}
+
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
@@ -179,3 +185,15 @@ pub fn sys_dup(fd: usize) -> isize {
 pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
 }
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+/// Wait for events on `fds` without a timeout
+pub fn sys_ppoll(fds: &mut [PollFd]) -> isize {
+    syscall6(
+        SYSCALL_PPOLL,
+        [fds.as_mut_ptr() as usize, fds.len(), 0, 0, 0, 0],
+    )
+}
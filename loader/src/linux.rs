@@ -1,11 +1,20 @@
 //! Run Linux process and manage trap/interrupt/syscall.
 
 use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
-use core::{future::Future, pin::Pin};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
 
 use kernel_hal::context::{TrapReason, UserContext, UserContextField};
+use kernel_hal::timer::timer_now;
+use linux_object::error::LxError;
 use linux_object::fs::{vfs::FileSystem, INodeExt};
-use linux_object::thread::{CurrentThreadExt, ThreadExt};
+use linux_object::signal::{Signal, SignalActionFlags};
+use linux_object::sync::{wait_for_event, Event};
+use linux_object::thread::{CurrentThreadExt, SignalDelivery, ThreadExt};
 use linux_object::{loader::LinuxElfLoader, process::ProcessExt};
 use zircon_object::task::{CurrentThread, Job, Process, Thread, ThreadState};
 use zircon_object::{object::KernelObject, ZxError, ZxResult};
@@ -26,11 +35,14 @@ pub fn run(args: Vec<String>, envs: Vec<String>, rootfs: Arc<dyn FileSystem>) ->
     let inode = rootfs.root_inode().lookup(&args[0]).unwrap();
     let data = inode.read_as_vec().unwrap();
     let path = args[0].clone();
+    proc.linux().set_execute_path(&path);
+    proc.linux().set_cmdline(args.clone());
 
     let pg_token = kernel_hal::vm::current_vmtoken();
     debug!("current pgt = {:#x}", pg_token);
     //调用zircon-object/src/task/thread.start设置好要执行的thread
-    let (entry, sp) = loader.load(&proc.vmar(), &data, args, envs, path).unwrap();
+    let (entry, sp, program_break) = loader.load(&proc.vmar(), &data, args, envs, path).unwrap();
+    proc.linux().set_program_break(program_break);
 
     thread
         .start_with_entry(entry, sp, 0, 0, thread_fn)
@@ -47,11 +59,14 @@ fn thread_fn(thread: CurrentThread) -> Pin<Box<dyn Future<Output = ()> + Send +
 /// loop:
 /// - wait for the thread to be ready
 /// - get user thread context
+/// - deliver a pending signal
 /// - enter user mode
 /// - handle trap/interrupt/syscall according to the return value
+/// - account the CPU time used to the process
 /// - return the context to the user thread
 async fn run_user(thread: CurrentThread) {
     kernel_hal::thread::set_tid(thread.id(), thread.proc().id());
+    let mut interrupted = None;
     loop {
         // wait
         let mut ctx = thread.wait_for_run().await;
@@ -59,19 +74,127 @@ async fn run_user(thread: CurrentThread) {
             break;
         }
 
+        // signal
+        match thread.take_signal() {
+            Some(SignalDelivery::Handler(info, action)) => {
+                if let Some(syscall) = interrupted.take() {
+                    if action.flags.contains(SignalActionFlags::RESTART) {
+                        restart_syscall(&mut ctx, syscall);
+                    }
+                }
+                if thread.setup_signal_frame(&mut ctx, &info, &action).is_err() {
+                    thread.put_context(ctx);
+                    continue;
+                }
+            }
+            Some(SignalDelivery::Default) => {
+                // the process may be stopped or killed, check again
+                thread.put_context(ctx);
+                continue;
+            }
+            None => {
+                // no handler ran, the interrupted syscall goes on transparently
+                if let Some(syscall) = interrupted.take() {
+                    restart_syscall(&mut ctx, syscall);
+                }
+            }
+        }
+
         // run
         trace!("go to user: {:#x?}", ctx);
+        let enter_time = timer_now();
         ctx.enter_uspace();
+        let user_time = timer_now() - enter_time;
         trace!("back from user: {:#x?}", ctx);
 
         // handle trap/interrupt/syscall
-        if let Err(err) = handle_user_trap(&thread, ctx).await {
-            thread.exit_linux(err as i32);
+        let (result, system_time) = CpuTimed::new(Box::pin(handle_user_trap(&thread, ctx))).await;
+        thread.account_cpu_time(user_time, system_time);
+        match result {
+            Ok(syscall) => interrupted = syscall,
+            // a fault the thread cannot recover from kills the whole process
+            Err(_) if thread.state() != ThreadState::Dying => {
+                thread.proc().kill_by_signal(Signal::SIGSEGV)
+            }
+            Err(_) => break,
+        }
+    }
+}
+
+/// Runs a future and measures the time spent polling it, which is the CPU time it used
+/// without the time it was waiting.
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct CpuTimed<F> {
+    inner: F,
+    elapsed: Duration,
+}
+
+impl<F> CpuTimed<F> {
+    fn new(inner: F) -> Self {
+        CpuTimed {
+            inner,
+            elapsed: Duration::ZERO,
         }
     }
 }
 
-async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) -> ZxResult {
+impl<F: Future + Unpin> Future for CpuTimed<F> {
+    type Output = (F::Output, Duration);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let start = timer_now();
+        let poll = Pin::new(&mut self.inner).poll(cx);
+        self.elapsed += timer_now() - start;
+        poll.map(|output| (output, self.elapsed))
+    }
+}
+
+/// A syscall returned `EINTR` as a signal arrived while it was blocked.
+struct InterruptedSyscall {
+    num: usize,
+    arg0: usize,
+}
+
+/// Runs a syscall until it completes or a signal can be delivered to the thread.
+#[must_use = "future does nothing unless polled/`await`-ed"]
+struct Interruptible<F, S> {
+    syscall: F,
+    signal: S,
+}
+
+impl<F, S> Future for Interruptible<F, S>
+where
+    F: Future<Output = isize> + Unpin,
+    S: Future + Unpin,
+{
+    type Output = Option<isize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if let Poll::Ready(ret) = Pin::new(&mut self.syscall).poll(cx) {
+            return Poll::Ready(Some(ret));
+        }
+        if Pin::new(&mut self.signal).poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    }
+}
+
+/// Wait for a signal that interrupts a blocked syscall, ignored signals are discarded.
+async fn wait_for_interrupt(thread: &CurrentThread) {
+    loop {
+        wait_for_event(thread.signal_events(), Event::RECEIVE_SIGNAL).await;
+        // a process being killed wakes its threads without a pending signal
+        if thread.flush_ignored_signals() || thread.state() == ThreadState::Dying {
+            return;
+        }
+    }
+}
+
+async fn handle_user_trap(
+    thread: &CurrentThread,
+    mut ctx: Box<UserContext>,
+) -> ZxResult<Option<InterruptedSyscall>> {
     let reason = ctx.trap_reason();
 
     if let TrapReason::Syscall = reason {
@@ -84,9 +207,23 @@ async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) ->
             thread_fn,
             syscall_entry: kernel_hal::context::syscall_entry as usize,
         };
-        let ret = syscall.syscall(num as u32, args).await as usize;
+        let run = Interruptible {
+            syscall: Box::pin(syscall.syscall(num as u32, args)),
+            signal: Box::pin(wait_for_interrupt(thread)),
+        };
+        let (ret, interrupted) = match run.await {
+            Some(ret) => (ret as usize, None),
+            None => {
+                info!("syscall {} interrupted by signal, tid={}", num, thread.id());
+                let syscall = InterruptedSyscall {
+                    num,
+                    arg0: args[0],
+                };
+                (-(LxError::EINTR as isize) as usize, Some(syscall))
+            }
+        };
         thread.with_context(|ctx| ctx.set_field(UserContextField::ReturnValue, ret))?;
-        return Ok(());
+        return Ok(interrupted);
     }
 
     thread.put_context(ctx);
@@ -96,7 +233,7 @@ async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) ->
         TrapReason::Interrupt(vector) => {
             kernel_hal::interrupt::handle_irq(vector);
             kernel_hal::thread::yield_now().await;
-            Ok(())
+            Ok(None)
         }
         TrapReason::PageFault(vaddr, flags) => {
             warn!(
@@ -113,7 +250,8 @@ async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) ->
                     thread.context_cloned(),
                 );
                 err
-            })
+            })?;
+            Ok(None)
         }
         _ => {
             error!(
@@ -127,6 +265,32 @@ async fn handle_user_trap(thread: &CurrentThread, mut ctx: Box<UserContext>) ->
     }
 }
 
+/// Make the thread issue the interrupted syscall again.
+fn restart_syscall(ctx: &mut UserContext, syscall: InterruptedSyscall) {
+    cfg_if! {
+        if #[cfg(feature = "libos")] {
+            // syscalls are function calls in libos, they cannot be issued again
+            warn!("syscall {} cannot be restarted, return EINTR", syscall.num);
+            let _ = (ctx, syscall.arg0);
+        } else if #[cfg(target_arch = "x86_64")] {
+            // `syscall` is 2 bytes long
+            let regs = ctx.general_mut();
+            regs.rip -= 2;
+            regs.rax = syscall.num;
+            regs.rdi = syscall.arg0;
+        } else if #[cfg(target_arch = "riscv64")] {
+            // `ecall` is 4 bytes long
+            let pc = ctx.get_field(UserContextField::InstrPointer);
+            ctx.set_field(UserContextField::InstrPointer, pc - 4);
+            let regs = ctx.general_mut();
+            regs.a7 = syscall.num;
+            regs.a0 = syscall.arg0;
+        } else {
+            unimplemented!()
+        }
+    }
+}
+
 fn syscall_num(ctx: &UserContext) -> usize {
     let regs = ctx.general();
     cfg_if! {
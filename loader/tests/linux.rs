@@ -129,6 +129,11 @@ async fn test_time() {
     assert_eq!(test("/bin/testtime").await, 0);
 }
 
+#[async_std::test]
+async fn test_timer() {
+    assert_eq!(test("/bin/testtimer").await, 0);
+}
+
 #[async_std::test]
 async fn test_random() {
     assert_eq!(test("/bin/testrandom").await, 0);
@@ -144,6 +149,51 @@ async fn test_shm() {
     assert_eq!(test("/bin/testshm1").await, 0);
 }
 
+#[async_std::test]
+async fn test_robust_futex() {
+    assert_eq!(test("/bin/testrobust").await, 0);
+}
+
+#[async_std::test]
+async fn test_madvise() {
+    assert_eq!(test("/bin/testmadvise").await, 0);
+}
+
+#[async_std::test]
+async fn test_mount_flags() {
+    assert_eq!(test("/bin/testmount").await, 0);
+}
+
+#[async_std::test]
+async fn test_signal() {
+    assert_eq!(test("/bin/testsignal").await, 0);
+}
+
+#[async_std::test]
+async fn test_clone() {
+    assert_eq!(test("/bin/testclone").await, 0);
+}
+
+#[async_std::test]
+async fn test_path_walk() {
+    assert_eq!(test("/bin/testpath").await, 0);
+}
+
+#[async_std::test]
+async fn test_procfs() {
+    assert_eq!(test("/bin/testproc").await, 0);
+}
+
+#[async_std::test]
+async fn test_session() {
+    assert_eq!(test("/bin/testsession").await, 0);
+}
+
+#[async_std::test]
+async fn test_resource_limits() {
+    assert_eq!(test("/bin/testlimit").await, 0);
+}
+
 #[async_std::test]
 async fn test_select() {
     assert_eq!(test("/bin/testselect").await, 0);
@@ -153,3 +203,13 @@ async fn test_select() {
 async fn test_poll() {
     assert_eq!(test("/bin/testpoll").await, 0);
 }
+
+#[async_std::test]
+async fn test_epoll() {
+    assert_eq!(test("/bin/testepoll").await, 0);
+}
+
+#[async_std::test]
+async fn test_unix_socket() {
+    assert_eq!(test("/bin/testunix").await, 0);
+}
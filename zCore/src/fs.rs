@@ -29,6 +29,13 @@ cfg_if! {
             } else {
                 std::env::current_dir().unwrap()
             };
+            // host directories can be mounted with `mount -t hostfs <host path> <target>`
+            linux_object::fs::register_filesystem(linux_object::fs::FileSystemType {
+                name: "hostfs",
+                magic: 0x00c0_ffee,
+                nodev: true,
+                mount: |_, source, _| Ok(rcore_fs_hostfs::HostFS::new(source)),
+            });
             rcore_fs_hostfs::HostFS::new(base.join("rootfs"))
         }
 
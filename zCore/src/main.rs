@@ -34,7 +34,21 @@ fn primary_main(config: kernel_hal::KernelConfig) {
     let options = utils::boot_options();
     logging::set_max_level(&options.log_level);
     info!("Boot options: {:#?}", options);
-    memory::init_frame_allocator(&kernel_hal::mem::free_pmem_regions());
+    #[allow(unused_mut)]
+    let mut regions = kernel_hal::mem::free_pmem_regions();
+    #[cfg(feature = "linux")]
+    let epm_reserve = memory::carve_region(&mut regions, options.epm_reserve);
+    memory::init_frame_allocator(&regions);
+    #[cfg(feature = "linux")]
+    {
+        match epm_reserve {
+            Some(region) => linux_object::fs::init_epm_reserve(region),
+            None if options.epm_reserve != 0 => {
+                warn!("no room for an EPM reserve of {:#x} bytes", options.epm_reserve)
+            }
+            None => {}
+        }
+    }
     kernel_hal::primary_init();
     STARTED.store(true, Ordering::SeqCst);
 
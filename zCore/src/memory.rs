@@ -1,6 +1,8 @@
 //! Define physical frame allocation and dynamic memory allocation.
 
+use alloc::vec::Vec;
 use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use bitmap_allocator::BitAlloc;
 use kernel_hal::PhysAddr;
@@ -22,6 +24,12 @@ const PAGE_SIZE: usize = 4096;
 /// Global physical frame allocator
 static FRAME_ALLOCATOR: Mutex<FrameAlloc> = Mutex::new(FrameAlloc::DEFAULT);
 
+/// Number of frames added to the allocator
+static TOTAL_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of frames allocated
+static USED_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
 fn phys_addr_to_frame_idx(addr: PhysAddr) -> usize {
     (addr - PHYS_MEMORY_BASE) / PAGE_SIZE
 }
@@ -38,6 +46,7 @@ pub fn init_frame_allocator(regions: &[Range<PhysAddr>]) {
         let frame_end = phys_addr_to_frame_idx(region.end - 1) + 1;
         if frame_start < frame_end {
             ba.insert(frame_start..frame_end);
+            TOTAL_FRAMES.fetch_add(frame_end - frame_start, Ordering::Relaxed);
             info!(
                 "Frame allocator: add range {:#x?}",
                 frame_idx_to_phys_addr(frame_start)..frame_idx_to_phys_addr(frame_end),
@@ -47,9 +56,37 @@ pub fn init_frame_allocator(regions: &[Range<PhysAddr>]) {
     info!("Frame allocator init end.");
 }
 
+/// Take `size` bytes out of `regions`, aligned to the largest power of two not above `size`
+pub fn carve_region(regions: &mut Vec<Range<PhysAddr>>, size: usize) -> Option<Range<PhysAddr>> {
+    let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    if size == 0 {
+        return None;
+    }
+    let align = 1 << (usize::BITS - 1 - size.leading_zeros());
+    for i in 0..regions.len() {
+        let region = regions[i].clone();
+        let start = (region.start + align - 1) & !(align - 1);
+        if start < region.start || start + size > region.end {
+            continue;
+        }
+        regions.remove(i);
+        if start + size < region.end {
+            regions.insert(i, start + size..region.end);
+        }
+        if region.start < start {
+            regions.insert(i, region.start..start);
+        }
+        return Some(start..start + size);
+    }
+    None
+}
+
 pub fn frame_alloc() -> Option<PhysAddr> {
     let ret = FRAME_ALLOCATOR.lock().alloc().map(frame_idx_to_phys_addr);
     trace!("frame_alloc(): {:x?}", ret);
+    if ret.is_some() {
+        USED_FRAMES.fetch_add(1, Ordering::Relaxed);
+    }
     ret
 }
 
@@ -64,6 +101,9 @@ pub fn frame_alloc_contiguous(frame_count: usize, align_log2: usize) -> Option<P
         ret.map(|x| x + frame_count),
         align_log2,
     );
+    if ret.is_some() {
+        USED_FRAMES.fetch_add(frame_count, Ordering::Relaxed);
+    }
     ret
 }
 
@@ -71,7 +111,14 @@ pub fn frame_dealloc(target: PhysAddr) {
     trace!("frame_dealloc(): {:x}", target);
     FRAME_ALLOCATOR
         .lock()
-        .dealloc(phys_addr_to_frame_idx(target))
+        .dealloc(phys_addr_to_frame_idx(target));
+    USED_FRAMES.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Number of frames in the allocator, and of those not allocated
+pub fn frame_stats() -> (usize, usize) {
+    let total = TOTAL_FRAMES.load(Ordering::Relaxed);
+    (total, total - USED_FRAMES.load(Ordering::Relaxed))
 }
 
 cfg_if! {
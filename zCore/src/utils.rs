@@ -11,6 +11,9 @@ pub struct BootOptions {
     pub log_level: String,
     #[cfg(feature = "linux")]
     pub root_proc: String,
+    /// Bytes of contiguous memory set aside for enclaves
+    #[cfg(feature = "linux")]
+    pub epm_reserve: usize,
 }
 
 fn parse_cmdline(cmdline: &str) -> BTreeMap<&str, &str> {
@@ -27,6 +30,17 @@ fn parse_cmdline(cmdline: &str) -> BTreeMap<&str, &str> {
     options
 }
 
+/// Parse a size like "64M", with an optional K/M/G suffix
+fn parse_size(size: &str) -> usize {
+    let (num, shift) = match size.chars().last() {
+        Some('K') | Some('k') => (&size[..size.len() - 1], 10),
+        Some('M') | Some('m') => (&size[..size.len() - 1], 20),
+        Some('G') | Some('g') => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    num.parse::<usize>().map_or(0, |n| n << shift)
+}
+
 pub fn boot_options() -> BootOptions {
     cfg_if! {
         if #[cfg(feature = "libos")] {
@@ -52,6 +66,8 @@ pub fn boot_options() -> BootOptions {
                 log_level,
                 #[cfg(feature = "linux")]
                 root_proc: args[1..].join("?"),
+                #[cfg(feature = "linux")]
+                epm_reserve: parse_size(&std::env::var("EPM_RESERVE").unwrap_or_default()),
             }
         } else {
             let cmdline = kernel_hal::boot::cmdline();
@@ -61,6 +77,8 @@ pub fn boot_options() -> BootOptions {
                 log_level: String::from(*options.get("LOG").unwrap_or(&"")),
                 #[cfg(feature = "linux")]
                 root_proc: String::from(*options.get("ROOTPROC").unwrap_or(&"/bin/busybox?sh")),
+                #[cfg(feature = "linux")]
+                epm_reserve: parse_size(options.get("EPM_RESERVE").unwrap_or(&"")),
             }
         }
     }
@@ -70,16 +70,7 @@ impl Futex {
     ///
     /// The owner of the futex is set to nothing, regardless of the wake count.
     pub fn wake(&self, wake_count: usize) -> usize {
-        let mut inner = self.inner.lock();
-        inner.set_owner(None);
-        for i in 0..wake_count {
-            if let Some(waiter) = inner.waiter_queue.pop_front() {
-                waiter.wake();
-            } else {
-                return i;
-            }
-        }
-        wake_count
+        self.wake_bitset(wake_count, u32::MAX)
     }
 
     // ------ Advanced APIs on Zircon ------
@@ -117,10 +108,22 @@ impl Futex {
         current_value: i32,
         thread: Option<Arc<Thread>>,
         new_owner: Option<Arc<Thread>>,
+    ) -> impl Future<Output = ZxResult> {
+        self.wait_inner(self.value, current_value, thread, new_owner, u32::MAX)
+    }
+
+    fn wait_inner(
+        self: &Arc<Self>,
+        value: &'static AtomicI32,
+        current_value: i32,
+        thread: Option<Arc<Thread>>,
+        new_owner: Option<Arc<Thread>>,
+        bitset: u32,
     ) -> impl Future<Output = ZxResult> {
         #[must_use = "wait does nothing unless polled/`await`-ed"]
         struct FutexFuture {
             waiter: Arc<Waiter>,
+            value: &'static AtomicI32,
             current_value: i32,
             new_owner: Option<Arc<Thread>>,
         }
@@ -138,7 +141,7 @@ impl Futex {
                 // first time?
                 if inner.waker.is_none() {
                     // check value
-                    let value = inner.futex.value.load(Ordering::SeqCst);
+                    let value = self.value.load(Ordering::SeqCst);
                     if value != self.current_value {
                         return Poll::Ready(Err(ZxError::BAD_STATE));
                     }
@@ -172,12 +175,14 @@ impl Futex {
         FutexFuture {
             waiter: Arc::new(Waiter {
                 thread,
+                bitset,
                 inner: Mutex::new(WaiterInner {
                     waker: None,
                     woken: false,
                     futex: self.clone(),
                 }),
             }),
+            value,
             current_value,
             new_owner,
         }
@@ -248,6 +253,87 @@ impl Futex {
         new_inner.set_owner(new_requeue_owner);
         Ok(())
     }
+
+    // ------ Extensions for Linux ------
+
+    /// Wait on a futex, only woken by the wakes whose bitset has a bit in common with `bitset`.
+    ///
+    /// It is [`wait_with_owner`] without an owner, except that `value` is checked instead of the
+    /// value the futex was created with. The futex of a word shared by processes is found by the
+    /// memory holding the word, while each process sees the word at an address of its own.
+    ///
+    /// [`wait_with_owner`]: Futex::wait_with_owner
+    pub fn wait_bitset(
+        self: &Arc<Self>,
+        value: &'static AtomicI32,
+        current_value: i32,
+        thread: Option<Arc<Thread>>,
+        bitset: u32,
+    ) -> impl Future<Output = ZxResult> {
+        self.wait_inner(value, current_value, thread, None, bitset)
+    }
+
+    /// Wake at most `wake_count` waiters whose bitset has a bit in common with `bitset`.
+    ///
+    /// Return the number of waiters that were woken up.
+    /// The owner of the futex is set to nothing like [`wake`].
+    ///
+    /// [`wake`]: Futex::wake
+    pub fn wake_bitset(&self, wake_count: usize, bitset: u32) -> usize {
+        let mut inner = self.inner.lock();
+        inner.set_owner(None);
+        let mut woken = 0;
+        let mut i = 0;
+        while woken < wake_count && i < inner.waiter_queue.len() {
+            if inner.waiter_queue[i].bitset & bitset == 0 {
+                i += 1;
+                continue;
+            }
+            let waiter = inner.waiter_queue.remove(i).unwrap();
+            waiter.wake();
+            woken += 1;
+        }
+        woken
+    }
+
+    /// Wake at most `wake_count` waiters and move at most `requeue_count` others to
+    /// `requeue_futex`, like [`requeue`] leaving both futexes without an owner.
+    ///
+    /// If `expected` is given, first verifies that the word holds the value and reports
+    /// `ZxError::BAD_STATE` if not.
+    /// Return the number of waiters that were woken up or moved.
+    ///
+    /// [`requeue`]: Futex::requeue
+    pub fn wake_requeue(
+        &self,
+        expected: Option<(&AtomicI32, i32)>,
+        wake_count: usize,
+        requeue_count: usize,
+        requeue_futex: &Arc<Futex>,
+    ) -> ZxResult<usize> {
+        let mut inner = self.inner.lock();
+        if let Some((value, current_value)) = expected {
+            if value.load(Ordering::SeqCst) != current_value {
+                return Err(ZxError::BAD_STATE);
+            }
+        }
+        inner.set_owner(None);
+        let wake_count = wake_count.min(inner.waiter_queue.len());
+        for waiter in inner.waiter_queue.drain(..wake_count) {
+            waiter.wake();
+        }
+        let requeue_count = requeue_count.min(inner.waiter_queue.len());
+        // waiters moved to the same futex stay where they are
+        if !core::ptr::eq(self, requeue_futex.as_ref()) {
+            let mut new_inner = requeue_futex.inner.lock();
+            for waiter in inner.waiter_queue.drain(..requeue_count) {
+                waiter.reset_futex(requeue_futex.clone());
+                new_inner.waiter_queue.push_back(waiter);
+            }
+            new_inner.set_owner(None);
+        }
+        Ok(wake_count + requeue_count)
+    }
 }
 
 impl FutexInner {
@@ -275,6 +361,8 @@ impl FutexInner {
 struct Waiter {
     /// The thread waiting on the futex.
     thread: Option<Arc<Thread>>,
+    /// Only the wakes with a bit in common wake the waiter.
+    bitset: u32,
     inner: Mutex<WaiterInner>,
 }
 
@@ -384,6 +472,59 @@ mod tests {
         assert_eq!(VALUE.load(Ordering::SeqCst), 2);
     }
 
+    #[async_std::test]
+    async fn bitset() {
+        static VALUE: AtomicI32 = AtomicI32::new(1);
+        let futex = Futex::new(&VALUE);
+
+        // inconsistent value should fail.
+        assert_eq!(
+            futex.wait_bitset(&VALUE, 0, None, 1).await,
+            Err(ZxError::BAD_STATE)
+        );
+
+        // spawn a new task to wake me up.
+        {
+            let futex = futex.clone();
+            async_std::task::spawn(async move {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+                // no bit in common
+                assert_eq!(futex.wake_bitset(1, 0b10), 0);
+                assert_eq!(futex.wake_bitset(1, 0b11), 1);
+            });
+        }
+        futex.wait_bitset(&VALUE, 1, None, 0b01).await.unwrap();
+        assert_eq!(futex.wake(1), 0);
+    }
+
+    #[async_std::test]
+    async fn wake_requeue() {
+        static VALUE: AtomicI32 = AtomicI32::new(1);
+        let futex = Futex::new(&VALUE);
+        static REQUEUE_VALUE: AtomicI32 = AtomicI32::new(100);
+        let requeue_futex = Futex::new(&REQUEUE_VALUE);
+
+        for _ in 0..2 {
+            let futex = futex.clone();
+            async_std::task::spawn(async move {
+                futex.wait(1).await.unwrap();
+            });
+        }
+        async_std::task::sleep(Duration::from_millis(10)).await;
+        assert_eq!(futex.inner.lock().waiter_queue.len(), 2);
+
+        // inconsistent value should fail.
+        assert_eq!(
+            futex.wake_requeue(Some((&VALUE, 2)), 1, 1, &requeue_futex),
+            Err(ZxError::BAD_STATE)
+        );
+        // 1 waiter waken, 1 waiter moved into `requeue_futex`.
+        assert_eq!(futex.wake_requeue(None, 1, 5, &requeue_futex), Ok(2));
+        assert_eq!(futex.inner.lock().waiter_queue.len(), 0);
+        assert_eq!(requeue_futex.inner.lock().waiter_queue.len(), 1);
+        assert_eq!(requeue_futex.wake(1), 1);
+    }
+
     #[async_std::test]
     async fn owner() {
         let root_job = Job::root();
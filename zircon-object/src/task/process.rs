@@ -58,7 +58,7 @@ pub struct Process {
     _counter: CountHelper,
     job: Arc<Job>,
     policy: JobPolicy,
-    vmar: Arc<VmAddressRegion>,
+    vmar: Mutex<Arc<VmAddressRegion>>,
     ext: Box<dyn Any + Send + Sync>,
     exceptionate: Arc<Exceptionate>,
     debug_exceptionate: Arc<Exceptionate>,
@@ -119,13 +119,24 @@ impl Process {
         job: &Arc<Job>,
         name: &str,
         ext: impl Any + Send + Sync,
+    ) -> ZxResult<Arc<Self>> {
+        Self::create_with_vmar(job, name, ext, VmAddressRegion::new_root())
+    }
+
+    /// Create a new process with extension info, running in the address space `vmar`
+    /// which may be shared with other processes.
+    pub fn create_with_vmar(
+        job: &Arc<Job>,
+        name: &str,
+        ext: impl Any + Send + Sync,
+        vmar: Arc<VmAddressRegion>,
     ) -> ZxResult<Arc<Self>> {
         let proc = Arc::new(Process {
             base: KObjectBase::with_name(name),
             _counter: CountHelper::new(),
             job: job.clone(),
             policy: job.policy(),
-            vmar: VmAddressRegion::new_root(),
+            vmar: Mutex::new(vmar),
             ext: Box::new(ext),
             exceptionate: Exceptionate::new(ExceptionChannelType::Process),
             debug_exceptionate: Exceptionate::new(ExceptionChannelType::Debugger),
@@ -310,7 +321,19 @@ impl Process {
 
     /// Get the `VmAddressRegion` of the process.
     pub fn vmar(&self) -> Arc<VmAddressRegion> {
-        self.vmar.clone()
+        self.vmar.lock().clone()
+    }
+
+    /// Move the process to a new empty address space and return it.
+    ///
+    /// The old one is cleared, unless other processes still run in it.
+    pub fn replace_vmar(&self) -> ZxResult<Arc<VmAddressRegion>> {
+        let vmar = VmAddressRegion::new_root();
+        let old = core::mem::replace(&mut *self.vmar.lock(), vmar.clone());
+        if Arc::strong_count(&old) == 1 {
+            old.clear()?;
+        }
+        Ok(vmar)
     }
 
     /// Get the job of the process.
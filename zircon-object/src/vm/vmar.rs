@@ -308,58 +308,120 @@ impl VmAddressRegion {
     }
 
     /// Change protections on a subset of the region of memory in the containing
-    /// address space.  If the requested range overlaps with a subregion,
-    /// protect() will fail.
+    /// address space, including the mappings of its subregions.
+    ///
+    /// The flags are kept per page, so a mapping only partially in the range is split
+    /// between the old and the new protections.
+    /// Every page in the range must be mapped, otherwise protect() will fail.
     pub fn protect(&self, addr: usize, len: usize, flags: MMUFlags) -> ZxResult {
         if !page_aligned(addr) || !page_aligned(len) {
             return Err(ZxError::INVALID_ARGS);
         }
-        let mut guard = self.inner.lock();
-        let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
         let end_addr = addr + len;
-        // check if there are overlapping subregion
-        if inner
-            .children
-            .iter()
-            .any(|child| child.end_addr() >= addr && child.addr() <= end_addr)
-        {
+        self.check_mapped_range(addr, end_addr)?;
+        // check if protect flags is valid
+        let mut valid = true;
+        self.for_each_mapping_in(addr, end_addr, &mut |map, _, _| {
+            valid &= map.is_valid_mapping_flags(flags);
+        });
+        if !valid {
+            return Err(ZxError::ACCESS_DENIED);
+        }
+        let mut result = Ok(());
+        self.for_each_mapping_in(addr, end_addr, &mut |map, start_index, end_index| {
+            if result.is_ok() {
+                result = map.protect(flags, start_index, end_index);
+            }
+        });
+        result
+    }
+
+    /// Decommit the pages of the range from the VMOs mapped there,
+    /// they are zero-filled on the next access.
+    ///
+    /// Every page in the range must be mapped.
+    pub fn decommit(&self, addr: usize, len: usize) -> ZxResult {
+        if !page_aligned(addr) || !page_aligned(len) {
             return Err(ZxError::INVALID_ARGS);
         }
-        let length: usize = inner
-            .mappings
-            .iter()
-            .filter_map(|map| {
-                if map.end_addr() >= addr && map.addr() <= end_addr {
-                    Some(end_addr.min(map.end_addr()) - addr.max(map.addr()))
-                } else {
-                    None
-                }
-            })
-            .sum();
-        if length != len {
-            return Err(ZxError::NOT_FOUND);
+        let end_addr = addr + len;
+        self.check_mapped_range(addr, end_addr)?;
+        let mut result = Ok(());
+        self.for_each_mapping_in(addr, end_addr, &mut |map, start_index, end_index| {
+            if result.is_ok() {
+                result = map.decommit(start_index, end_index);
+            }
+        });
+        result
+    }
+
+    /// Remove the pages of the range from the page table, the VMOs mapped there keep
+    /// their content and the pages are mapped again on the next access.
+    ///
+    /// Every page in the range must be mapped.
+    pub fn unmap_pages(&self, addr: usize, len: usize) -> ZxResult {
+        if !page_aligned(addr) || !page_aligned(len) {
+            return Err(ZxError::INVALID_ARGS);
         }
-        // check if protect flags is valid
-        if inner
-            .mappings
-            .iter()
-            .filter(|map| map.end_addr() >= addr && map.addr() <= end_addr) // get mappings in range: [addr, end_addr]
-            .any(|map| !map.is_valid_mapping_flags(flags))
-        {
-            return Err(ZxError::ACCESS_DENIED);
+        let end_addr = addr + len;
+        self.check_mapped_range(addr, end_addr)?;
+        self.for_each_mapping_in(addr, end_addr, &mut |map, start_index, end_index| {
+            map.unmap_pages(start_index, end_index);
+        });
+        Ok(())
+    }
+
+    /// Commit and map the pages of the range ahead of their first access.
+    ///
+    /// Pages which cannot be read are left alone. Every page in the range must be mapped.
+    pub fn commit(&self, addr: usize, len: usize) -> ZxResult {
+        if !page_aligned(addr) || !page_aligned(len) {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let end_addr = addr + len;
+        self.check_mapped_range(addr, end_addr)?;
+        let mut result = Ok(());
+        self.for_each_mapping_in(addr, end_addr, &mut |map, start_index, end_index| {
+            if result.is_ok() {
+                result = map.commit(start_index, end_index);
+            }
+        });
+        result
+    }
+
+    /// Check that all pages in `[begin, end)` are mapped in this VMAR or its subregions.
+    fn check_mapped_range(&self, begin: VirtAddr, end: VirtAddr) -> ZxResult {
+        if self.is_dead() {
+            return Err(ZxError::BAD_STATE);
+        }
+        let mut length = 0;
+        self.for_each_mapping_in(begin, end, &mut |_, start_index, end_index| {
+            length += (end_index - start_index) * PAGE_SIZE;
+        });
+        if length != end - begin {
+            return Err(ZxError::NOT_FOUND);
         }
-        inner
-            .mappings
-            .iter()
-            .filter(|map| map.end_addr() >= addr && map.addr() <= end_addr)
-            .for_each(|map| {
-                let start_index = pages(addr.max(map.addr()) - map.addr());
-                let end_index = pages(end_addr.min(map.end_addr()) - map.addr());
-                map.protect(flags, start_index, end_index);
-            });
         Ok(())
     }
 
+    /// Call `f` with every mapping overlapping `[begin, end)` in this VMAR or its subregions,
+    /// and the range of its pages in there.
+    fn for_each_mapping_in(
+        &self,
+        begin: VirtAddr,
+        end: VirtAddr,
+        f: &mut impl FnMut(&Arc<VmMapping>, usize, usize),
+    ) {
+        self.for_each_mapping(&mut |map| {
+            if map.overlap(begin, end) {
+                let (addr, end_addr) = (map.addr(), map.end_addr());
+                let start_index = pages(begin.max(addr) - addr);
+                let end_index = pages(end.min(end_addr) - addr);
+                f(map, start_index, end_index);
+            }
+        });
+    }
+
     /// Unmap all mappings within the VMAR, and destroy all sub-regions of the region.
     pub fn destroy(self: &Arc<Self>) -> ZxResult {
         self.destroy_internal()?;
@@ -593,6 +655,15 @@ impl VmAddressRegion {
         }
     }
 
+    /// Get the mapped ranges of this VMAR and its subregions sorted by address,
+    /// split where the flags of the pages change.
+    pub fn mappings_info(&self) -> Vec<MappingInfo> {
+        let mut infos = Vec::new();
+        self.for_each_mapping(&mut |map| map.fill_in_mappings_info(&mut infos));
+        infos.sort_unstable_by_key(|info| info.addr);
+        infos
+    }
+
     /// Clone the entire address space and VMOs from source VMAR. (For Linux fork)
     pub fn fork_from(&self, src: &Arc<Self>) -> ZxResult {
         let mut guard = self.inner.lock();
@@ -695,6 +766,21 @@ pub struct VmarInfo {
     // pg_token: usize,
 }
 
+/// Information of a range of pages mapped from a VMO with the same flags.
+#[derive(Debug, Clone)]
+pub struct MappingInfo {
+    /// Start address of the range.
+    pub addr: VirtAddr,
+    /// Size of the range in bytes.
+    pub size: usize,
+    /// Flags of the pages.
+    pub flags: MMUFlags,
+    /// The mapped VMO.
+    pub vmo: Arc<VmObject>,
+    /// Offset in the VMO of the first page.
+    pub vmo_offset: usize,
+}
+
 /// Virtual Memory Mapping
 pub struct VmMapping {
     /// The permission limitation of the vmar
@@ -723,6 +809,13 @@ pub struct TaskStatsInfo {
     scaled_shared_bytes: u64,
 }
 
+impl TaskStatsInfo {
+    /// Bytes of committed memory mapped by the task.
+    pub fn committed_bytes(&self) -> u64 {
+        self.private_bytes + self.shared_bytes
+    }
+}
+
 impl core::fmt::Debug for VmMapping {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let inner = self.inner.lock();
@@ -773,6 +866,10 @@ impl VmMapping {
             let page_num = inner.size / PAGE_SIZE;
             let vmo_offset = inner.vmo_offset / PAGE_SIZE;
             for i in 0..page_num {
+                // inaccessible pages are left unmapped until they are protected again
+                if !inner.flags[i].intersects(MMUFlags::RXW) {
+                    continue;
+                }
                 let paddr = commit(vmo_offset + i, inner.flags[i])?;
                 //通过GenericPageTable的hal_pt_map进行页表映射
                 page_table
@@ -891,7 +988,13 @@ impl VmMapping {
         self.permissions.contains(flags & MMUFlags::RXW)
     }
 
-    fn protect(&self, flags: MMUFlags, start_index: usize, end_index: usize) {
+    /// Change the flags of pages in `[start_index, end_index)`.
+    ///
+    /// Only the pages already mapped are updated, the others get the new flags when they
+    /// are faulted in. Inaccessible pages are unmapped, and a page not mapped writable yet
+    /// still takes a write fault, so that it gets its own copy if it is shared for
+    /// copy-on-write.
+    fn protect(&self, flags: MMUFlags, start_index: usize, end_index: usize) -> ZxResult {
         let mut inner = self.inner.lock();
         let mut pg_table = self.page_table.lock();
         for i in start_index..end_index {
@@ -899,10 +1002,79 @@ impl VmMapping {
             new_flags.remove(MMUFlags::RXW);
             new_flags.insert(flags & MMUFlags::RXW);
             inner.flags[i] = new_flags;
+            let vaddr = inner.addr + i * PAGE_SIZE;
+            let mapped_flags = match pg_table.query(vaddr) {
+                Ok((_, mapped_flags, _)) => mapped_flags,
+                Err(_) => continue,
+            };
+            if !new_flags.intersects(MMUFlags::RXW) {
+                pg_table
+                    .unmap(vaddr)
+                    .ignore()
+                    .map_err(|_| ZxError::ACCESS_DENIED)?;
+                continue;
+            }
+            if !mapped_flags.contains(MMUFlags::WRITE) {
+                new_flags.remove(MMUFlags::WRITE);
+            }
             pg_table
-                .update(inner.addr + i * PAGE_SIZE, None, Some(new_flags))
+                .update(vaddr, None, Some(new_flags))
                 .ignore()
-                .unwrap();
+                .map_err(|_| ZxError::ACCESS_DENIED)?;
+        }
+        Ok(())
+    }
+
+    /// Unmap pages in `[start_index, end_index)` from the page table only.
+    fn unmap_pages(&self, start_index: usize, end_index: usize) {
+        let inner = self.inner.lock();
+        let mut pg_table = self.page_table.lock();
+        for i in start_index..end_index {
+            pg_table.unmap(inner.addr + i * PAGE_SIZE).ignore().unwrap();
+        }
+    }
+
+    /// Unmap pages in `[start_index, end_index)` and release their frames in the VMO.
+    fn decommit(&self, start_index: usize, end_index: usize) -> ZxResult {
+        self.unmap_pages(start_index, end_index);
+        let offset = self.inner.lock().vmo_offset + start_index * PAGE_SIZE;
+        let len = (end_index - start_index) * PAGE_SIZE;
+        // a child VMO cannot decommit, zeroing whole pages drops them the same way
+        match self.vmo.decommit(offset, len) {
+            Err(ZxError::NOT_SUPPORTED) => self.vmo.zero(offset, len),
+            result => result,
+        }
+    }
+
+    /// Fault in readable pages in `[start_index, end_index)` which are not mapped yet.
+    fn commit(&self, start_index: usize, end_index: usize) -> ZxResult {
+        for i in start_index..end_index {
+            let (vaddr, flags) = {
+                let inner = self.inner.lock();
+                (inner.addr + i * PAGE_SIZE, inner.flags[i])
+            };
+            if !flags.contains(MMUFlags::READ) || self.query_vaddr(vaddr).is_ok() {
+                continue;
+            }
+            self.handle_page_fault(vaddr, MMUFlags::READ)?;
+        }
+        Ok(())
+    }
+
+    fn fill_in_mappings_info(&self, infos: &mut Vec<MappingInfo>) {
+        let inner = self.inner.lock();
+        let mut start = 0;
+        for (i, flags) in inner.flags.iter().enumerate() {
+            if i + 1 == inner.flags.len() || inner.flags[i + 1] != *flags {
+                infos.push(MappingInfo {
+                    addr: inner.addr + start * PAGE_SIZE,
+                    size: (i + 1 - start) * PAGE_SIZE,
+                    flags: *flags,
+                    vmo: self.vmo.clone(),
+                    vmo_offset: inner.vmo_offset + start * PAGE_SIZE,
+                });
+                start = i + 1;
+            }
         }
     }
 
@@ -1222,6 +1394,141 @@ mod tests {
         assert_eq!(vmar.used_size(), 0x1000);
     }
 
+    #[test]
+    fn protect_mapping() {
+        let vmar = VmAddressRegion::new_root();
+        let base = vmar.addr();
+        let child = vmar
+            .allocate_at(0x2000, 0x2000, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)
+            .unwrap();
+        let flags = MMUFlags::READ | MMUFlags::WRITE;
+        vmar.map_at(0, VmObject::new_paged(2), 0, 0x2000, flags)
+            .unwrap();
+        child
+            .map_at(0, VmObject::new_paged(2), 0, 0x2000, flags)
+            .unwrap();
+
+        let flags_at = |addr| vmar.find_mapping(addr).unwrap().get_flags(addr).unwrap();
+
+        // a range across the subregion is split page by page
+        vmar.protect(base + 0x1000, 0x2000, MMUFlags::READ).unwrap();
+        assert_eq!(flags_at(base), flags);
+        assert_eq!(flags_at(base + 0x1000), MMUFlags::READ);
+        assert_eq!(flags_at(base + 0x2000), MMUFlags::READ);
+        assert_eq!(flags_at(base + 0x3000), flags);
+
+        // unmapped pages in the range
+        assert_eq!(
+            vmar.protect(base + 0x3000, 0x2000, MMUFlags::READ).err(),
+            Some(ZxError::NOT_FOUND)
+        );
+        assert_eq!(flags_at(base + 0x3000), flags);
+    }
+
+    #[test]
+    fn protect_lazy_mapping() {
+        let vmar = VmAddressRegion::new_root();
+        let vmo = VmObject::new_paged(2);
+        let addr = vmar
+            .map_ext(
+                None,
+                vmo,
+                0,
+                0x2000,
+                MMUFlags::RXW,
+                MMUFlags::READ,
+                false,
+                false,
+            )
+            .unwrap();
+        let mapping = vmar.find_mapping(addr).unwrap();
+        vmar.handle_page_fault(addr, MMUFlags::READ).unwrap();
+        vmar.protect(addr, 0x2000, MMUFlags::READ | MMUFlags::WRITE)
+            .unwrap();
+
+        // the page never accessed is left to the page fault
+        assert!(mapping.query_vaddr(addr + PAGE_SIZE).is_err());
+        vmar.handle_page_fault(addr + PAGE_SIZE, MMUFlags::WRITE)
+            .unwrap();
+        let (_, flags, _) = mapping.query_vaddr(addr + PAGE_SIZE).unwrap();
+        assert!(flags.contains(MMUFlags::WRITE));
+
+        // the mapped page becomes writable on its first write
+        let (_, flags, _) = mapping.query_vaddr(addr).unwrap();
+        assert!(!flags.contains(MMUFlags::WRITE));
+        vmar.handle_page_fault(addr, MMUFlags::WRITE).unwrap();
+        let (_, flags, _) = mapping.query_vaddr(addr).unwrap();
+        assert!(flags.contains(MMUFlags::WRITE));
+
+        vmar.protect(addr, 0x2000, MMUFlags::empty()).unwrap();
+        assert!(mapping.query_vaddr(addr).is_err());
+    }
+
+    #[test]
+    fn decommit_mapping() {
+        let vmar = VmAddressRegion::new_root();
+        let vmo = VmObject::new_paged(2);
+        vmar.map_at(0, vmo.clone(), 0, 0x2000, MMUFlags::RXW)
+            .unwrap();
+        vmo.test_write(0, 1);
+        vmo.test_write(1, 2);
+        vmar.decommit(vmar.addr() + PAGE_SIZE, PAGE_SIZE).unwrap();
+        assert_eq!(vmo.test_read(0), 1);
+        assert_eq!(vmo.test_read(1), 0);
+        assert_eq!(
+            vmar.decommit(vmar.addr(), 0x3000).err(),
+            Some(ZxError::NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn unmap_pages_keeps_content() {
+        let vmar = VmAddressRegion::new_root();
+        let vmo = VmObject::new_paged(2);
+        vmar.map_at(0, vmo.clone(), 0, 0x2000, MMUFlags::RXW)
+            .unwrap();
+        vmo.test_write(0, 1);
+        vmar.handle_page_fault(vmar.addr(), MMUFlags::READ).unwrap();
+        vmar.unmap_pages(vmar.addr(), 0x2000).unwrap();
+        assert_eq!(vmo.test_read(0), 1);
+        assert_eq!(
+            vmar.unmap_pages(vmar.addr(), 0x3000).err(),
+            Some(ZxError::NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn mappings_info() {
+        let vmar = VmAddressRegion::new_root();
+        let base = vmar.addr();
+        let child = vmar
+            .allocate_at(0x4000, 0x1000, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)
+            .unwrap();
+        let vmo = VmObject::new_paged(3);
+        vmar.map_at(0, vmo.clone(), 0, 0x3000, MMUFlags::RXW)
+            .unwrap();
+        child
+            .map_at(0, VmObject::new_paged(1), 0, 0x1000, MMUFlags::READ)
+            .unwrap();
+        vmar.protect(base + 0x1000, 0x1000, MMUFlags::READ).unwrap();
+
+        let infos = vmar.mappings_info();
+        let ranges: Vec<_> = infos
+            .iter()
+            .map(|info| (info.addr - base, info.size, info.flags, info.vmo_offset))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (0, 0x1000, MMUFlags::RXW, 0),
+                (0x1000, 0x1000, MMUFlags::READ, 0x1000),
+                (0x2000, 0x1000, MMUFlags::RXW, 0x2000),
+                (0x4000, 0x1000, MMUFlags::READ, 0),
+            ]
+        );
+        assert!(Arc::ptr_eq(&infos[0].vmo, &vmo));
+    }
+
     #[test]
     #[allow(unsafe_code)]
     fn copy_on_write_update_mapping() {